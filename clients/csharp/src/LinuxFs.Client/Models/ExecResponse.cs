@@ -19,6 +19,39 @@ public sealed class ExecResponse
     [JsonPropertyName("duration_ms")]
     public ulong DurationMs { get; set; }
 
+    [JsonPropertyName("user_cpu_ms")]
+    public ulong UserCpuMs { get; set; }
+
+    [JsonPropertyName("system_cpu_ms")]
+    public ulong SystemCpuMs { get; set; }
+
+    [JsonPropertyName("max_rss_bytes")]
+    public ulong MaxRssBytes { get; set; }
+
+    [JsonPropertyName("block_reads")]
+    public ulong BlockReads { get; set; }
+
+    [JsonPropertyName("block_writes")]
+    public ulong BlockWrites { get; set; }
+
+    [JsonPropertyName("signal")]
+    public int? Signal { get; set; }
+
+    [JsonPropertyName("killed_by")]
+    public string? KilledBy { get; set; }
+
     [JsonPropertyName("truncated")]
     public bool Truncated { get; set; }
+
+    [JsonPropertyName("seccomp_violation")]
+    public bool SeccompViolation { get; set; }
+
+    [JsonPropertyName("memory_peak_bytes")]
+    public ulong? MemoryPeakBytes { get; set; }
+
+    [JsonPropertyName("cpu_time_ms")]
+    public ulong? CpuTimeMs { get; set; }
+
+    [JsonPropertyName("oom_killed")]
+    public bool OomKilled { get; set; }
 }
@@ -0,0 +1,24 @@
+use crate::services::access_service;
+use crate::state::AppState;
+use std::time::Duration;
+use tokio::sync::watch;
+
+pub async fn run(state: AppState, mut shutdown: watch::Receiver<bool>) {
+    let interval = Duration::from_secs(state.config.access_flush_interval_secs);
+
+    loop {
+        tokio::select! {
+            _ = tokio::time::sleep(interval) => {}
+            _ = shutdown.changed() => {
+                tracing::info!("Access flusher shutting down");
+                access_service::flush(&state).await;
+                return;
+            }
+        }
+
+        let flushed = access_service::flush(&state).await;
+        if flushed > 0 {
+            tracing::debug!(repos = flushed, "Access stats flushed");
+        }
+    }
+}
@@ -0,0 +1,23 @@
+use crate::services::change_log_service;
+use crate::state::AppState;
+use std::time::Duration;
+use tokio::sync::watch;
+
+pub async fn run(state: AppState, mut shutdown: watch::Receiver<bool>) {
+    let interval = Duration::from_secs(state.config.ttl_sweep_interval_secs);
+
+    loop {
+        tokio::select! {
+            _ = tokio::time::sleep(interval) => {}
+            _ = shutdown.changed() => {
+                tracing::info!("Change log pruner shutting down");
+                return;
+            }
+        }
+
+        let dropped = change_log_service::prune(&state);
+        if dropped > 0 {
+            tracing::info!(count = dropped, "Change log prune completed");
+        }
+    }
+}
@@ -16,5 +16,6 @@ pub async fn run(state: AppState, mut shutdown: watch::Receiver<bool>) {
         }
 
         eviction_service::evict_over_limit_repos(&state).await;
+        eviction_service::evict_over_budget(&state).await;
     }
 }
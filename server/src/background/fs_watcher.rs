@@ -0,0 +1,106 @@
+use crate::services::watch_service::{self, Change};
+use crate::state::AppState;
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+use tokio::sync::{mpsc, watch};
+use uuid::Uuid;
+
+/// Debounce filesystem events and reconcile each touched path once it
+/// has been quiet for `fs_watch_debounce_ms`.
+pub async fn run(
+    state: AppState,
+    mut events: mpsc::UnboundedReceiver<notify::Event>,
+    mut shutdown: watch::Receiver<bool>,
+) {
+    let debounce = Duration::from_millis(state.config.fs_watch_debounce_ms);
+    let tick = (debounce / 2).max(Duration::from_millis(10));
+    let mut dirty: HashMap<(Uuid, String), Instant> = HashMap::new();
+    let mut renames: Vec<(Instant, Uuid, String, String)> = Vec::new();
+
+    loop {
+        tokio::select! {
+            event = events.recv() => {
+                let Some(event) = event else {
+                    tracing::info!("Filesystem watcher channel closed");
+                    return;
+                };
+                let now = Instant::now();
+                for change in watch_service::classify(&state, &event) {
+                    match change {
+                        Change::Dirty(repo_id, path) => {
+                            dirty.insert((repo_id, path), now);
+                        }
+                        Change::Renamed(repo_id, from, to) => {
+                            renames.push((now, repo_id, from, to));
+                        }
+                    }
+                }
+            }
+            _ = tokio::time::sleep(tick) => {}
+            _ = shutdown.changed() => {
+                tracing::info!("Filesystem watcher shutting down");
+                return;
+            }
+        }
+
+        let now = Instant::now();
+
+        // Renames first, in arrival order, so moved files keep their
+        // metadata before the touched paths are rehashed.
+        let ready = renames
+            .iter()
+            .take_while(|(at, ..)| now - *at >= debounce)
+            .count();
+        for (_, repo_id, from, to) in renames.drain(..ready) {
+            if let Err(e) = watch_service::apply_rename(&state, repo_id, &from, &to).await {
+                tracing::warn!(
+                    repo_id = %repo_id,
+                    from = %from,
+                    to = %to,
+                    error = %e,
+                    "Failed to apply rename"
+                );
+            }
+            // Already quiet; sync in this pass
+            let settled = now.checked_sub(debounce).unwrap_or(now);
+            dirty.insert((repo_id, from), settled);
+            dirty.insert((repo_id, to), settled);
+        }
+
+        // Paths a pending rename will move wait for it, so their metadata
+        // is carried over rather than recorded afresh
+        let ready: Vec<(Uuid, String)> = dirty
+            .iter()
+            .filter(|(_, at)| now - **at >= debounce)
+            .filter(|((repo_id, path), _)| {
+                !renames.iter().any(|(_, r, from, to)| {
+                    r == repo_id && (overlaps(path, from) || overlaps(path, to))
+                })
+            })
+            .map(|(key, _)| key.clone())
+            .collect();
+        for key in ready {
+            dirty.remove(&key);
+            let (repo_id, path) = key;
+            if let Err(e) = watch_service::sync_path(&state, repo_id, &path).await {
+                tracing::warn!(
+                    repo_id = %repo_id,
+                    path = %path,
+                    error = %e,
+                    "Failed to sync watched path"
+                );
+            }
+        }
+        watch_service::prune_api_writes(&state, debounce * 4 + Duration::from_secs(5));
+    }
+}
+
+/// Whether one path is the other or lies under it.
+fn overlaps(a: &str, b: &str) -> bool {
+    let under = |child: &str, parent: &str| {
+        child
+            .strip_prefix(parent)
+            .is_some_and(|rest| rest.starts_with('/'))
+    };
+    a == b || under(a, b) || under(b, a)
+}
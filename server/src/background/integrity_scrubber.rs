@@ -0,0 +1,56 @@
+use crate::services::scrub_service;
+use crate::state::AppState;
+use std::time::Duration;
+use tokio::sync::watch;
+
+/// Files checked between progress saves and rate-limit pauses.
+const BATCH_FILES: usize = 16;
+
+pub async fn run(state: AppState, mut shutdown: watch::Receiver<bool>) {
+    if state.config.scrub_interval_secs == 0 {
+        tracing::info!("Integrity scrubber disabled");
+        return;
+    }
+    let interval = chrono::Duration::seconds(state.config.scrub_interval_secs as i64);
+    let rate = state.config.scrub_rate_bytes_per_sec;
+
+    loop {
+        let wait = {
+            let progress = state.scrub.read().await;
+            match (progress.pass_started_at, progress.last_pass_completed_at) {
+                (Some(_), _) | (None, None) => None,
+                (None, Some(last)) => (last + interval - chrono::Utc::now())
+                    .to_std()
+                    .ok()
+                    .filter(|d| !d.is_zero()),
+            }
+        };
+
+        if let Some(wait) = wait {
+            tokio::select! {
+                _ = tokio::time::sleep(wait) => {}
+                _ = state.scrub_wake.notified() => {}
+                _ = shutdown.changed() => {
+                    tracing::info!("Integrity scrubber shutting down");
+                    return;
+                }
+            }
+            continue;
+        }
+
+        let batch = scrub_service::scrub_batch(&state, BATCH_FILES).await;
+        let pause = if rate > 0 {
+            Duration::from_secs_f64(batch.bytes_hashed as f64 / rate as f64)
+        } else {
+            Duration::ZERO
+        };
+
+        tokio::select! {
+            _ = tokio::time::sleep(pause) => {}
+            _ = shutdown.changed() => {
+                tracing::info!("Integrity scrubber shutting down");
+                return;
+            }
+        }
+    }
+}
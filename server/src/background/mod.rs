@@ -1,3 +1,10 @@
+pub mod access_flusher;
+pub mod change_log_pruner;
 pub mod eviction_monitor;
+pub mod fs_watcher;
+pub mod integrity_scrubber;
+pub mod repo_reaper;
 pub mod snapshot_writer;
+pub mod trash_purger;
 pub mod ttl_reaper;
+pub mod webhook_dispatcher;
@@ -0,0 +1,66 @@
+use crate::models::trash::DeleteCause;
+use crate::services::repo_service;
+use crate::state::AppState;
+use chrono::{Duration as ChronoDuration, Utc};
+use std::time::Duration;
+use tokio::sync::watch;
+
+pub async fn run(state: AppState, mut shutdown: watch::Receiver<bool>) {
+    let interval = Duration::from_secs(state.config.ttl_sweep_interval_secs);
+
+    loop {
+        tokio::select! {
+            _ = tokio::time::sleep(interval) => {}
+            _ = shutdown.changed() => {
+                tracing::info!("Repo reaper shutting down");
+                return;
+            }
+        }
+
+        let deleted = sweep(&state).await;
+        if deleted > 0 {
+            tracing::info!(count = deleted, "Repo reaper sweep completed");
+        }
+    }
+}
+
+/// Delete every repository whose absolute or idle expiry, plus the
+/// configured grace period, has passed. Returns the number deleted.
+pub async fn sweep(state: &AppState) -> u64 {
+    let now = Utc::now();
+    let grace = i64::try_from(state.config.repo_expiry_grace_secs)
+        .ok()
+        .and_then(ChronoDuration::try_seconds)
+        .unwrap_or(ChronoDuration::MAX);
+
+    let expired: Vec<uuid::Uuid> = state
+        .repos
+        .iter()
+        .filter(|r| {
+            r.value()
+                .effective_expiry()
+                .and_then(|exp| exp.checked_add_signed(grace))
+                .is_some_and(|deadline| deadline <= now)
+        })
+        .map(|r| *r.key())
+        .collect();
+
+    let mut deleted = 0u64;
+    for repo_id in expired {
+        match repo_service::delete_repo(state, repo_id, DeleteCause::Expiry).await {
+            Ok(()) => {
+                deleted += 1;
+                tracing::info!(repo_id = %repo_id, "Expired repository removed");
+            }
+            Err(e) => {
+                tracing::warn!(
+                    repo_id = %repo_id,
+                    error = %e,
+                    "Failed to remove expired repository"
+                );
+            }
+        }
+    }
+
+    deleted
+}
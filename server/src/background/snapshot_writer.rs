@@ -43,11 +43,25 @@ pub async fn write_snapshot(state: &AppState) {
         })
         .collect();
 
+    let trash: HashMap<_, _> = state
+        .trash
+        .iter()
+        .map(|e| (*e.key(), e.value().clone()))
+        .collect();
+
+    let checkpoints: HashMap<_, _> = state
+        .checkpoints
+        .iter()
+        .map(|c| (*c.key(), c.value().clone()))
+        .collect();
+
     let snapshot = MetadataSnapshot {
         version: SNAPSHOT_VERSION,
         timestamp: Utc::now(),
         repos,
         files,
+        trash,
+        checkpoints,
     };
 
     let snapshot_path = state.config.snapshot_path();
@@ -0,0 +1,23 @@
+use crate::services::trash_service;
+use crate::state::AppState;
+use std::time::Duration;
+use tokio::sync::watch;
+
+pub async fn run(state: AppState, mut shutdown: watch::Receiver<bool>) {
+    let interval = Duration::from_secs(state.config.ttl_sweep_interval_secs);
+
+    loop {
+        tokio::select! {
+            _ = tokio::time::sleep(interval) => {}
+            _ = shutdown.changed() => {
+                tracing::info!("Trash purger shutting down");
+                return;
+            }
+        }
+
+        let purged = trash_service::purge_expired(&state).await;
+        if purged > 0 {
+            tracing::info!(count = purged, "Trash purge completed");
+        }
+    }
+}
@@ -1,3 +1,5 @@
+use crate::models::trash::DeleteCause;
+use crate::services::pin_service::Pins;
 use crate::state::AppState;
 use chrono::Utc;
 use std::time::Duration;
@@ -15,54 +17,70 @@ pub async fn run(state: AppState, mut shutdown: watch::Receiver<bool>) {
             }
         }
 
-        let now = Utc::now();
-        let repo_ids: Vec<uuid::Uuid> = state.repos.iter().map(|r| *r.key()).collect();
+        let total_expired = sweep(&state).await;
+        if total_expired > 0 {
+            tracing::info!(count = total_expired, "TTL reaper sweep completed");
+        }
+    }
+}
+
+/// Delete every unpinned file whose TTL has passed. Returns the number
+/// removed.
+pub async fn sweep(state: &AppState) -> u64 {
+    let now = Utc::now();
+    let repo_ids: Vec<uuid::Uuid> = state.repos.iter().map(|r| *r.key()).collect();
 
-        let mut total_expired = 0u64;
+    let mut total_expired = 0u64;
 
-        for repo_id in repo_ids {
-            let expired_paths: Vec<String> = state
-                .files
-                .get(&repo_id)
-                .map(|files| {
-                    files
-                        .iter()
-                        .filter(|entry| {
-                            entry
-                                .value()
-                                .expires_at
-                                .map(|exp| exp <= now)
-                                .unwrap_or(false)
-                        })
-                        .map(|entry| entry.key().clone())
-                        .collect()
-                })
-                .unwrap_or_default();
+    for repo_id in repo_ids {
+        let pins = Pins::for_repo(state, repo_id);
+        let expired_paths: Vec<String> = state
+            .files
+            .get(&repo_id)
+            .map(|files| {
+                files
+                    .iter()
+                    .filter(|entry| {
+                        entry
+                            .value()
+                            .expires_at
+                            .map(|exp| exp <= now)
+                            .unwrap_or(false)
+                            && !pins.protects(entry.value())
+                    })
+                    .map(|entry| entry.key().clone())
+                    .collect()
+            })
+            .unwrap_or_default();
 
-            for path in expired_paths {
-                match crate::services::file_service::delete_file(&state, repo_id, &path).await {
-                    Ok(()) => {
-                        total_expired += 1;
-                        tracing::debug!(
-                            repo_id = %repo_id,
-                            path = %path,
-                            "Expired file removed"
-                        );
-                    }
-                    Err(e) => {
-                        tracing::warn!(
-                            repo_id = %repo_id,
-                            path = %path,
-                            error = %e,
-                            "Failed to remove expired file"
-                        );
-                    }
+        for path in expired_paths {
+            match crate::services::file_service::delete_file(
+                state,
+                repo_id,
+                &path,
+                DeleteCause::Expiry,
+            )
+            .await
+            {
+                Ok(()) => {
+                    total_expired += 1;
+                    tracing::debug!(
+                        repo_id = %repo_id,
+                        path = %path,
+                        "Expired file removed"
+                    );
+                }
+                Err(e) => {
+                    tracing::warn!(
+                        repo_id = %repo_id,
+                        path = %path,
+                        error = %e,
+                        "Failed to remove expired file"
+                    );
                 }
             }
         }
-
-        if total_expired > 0 {
-            tracing::info!(count = total_expired, "TTL reaper sweep completed");
-        }
     }
+
+    total_expired
 }
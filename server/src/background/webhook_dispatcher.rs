@@ -0,0 +1,26 @@
+use crate::services::webhook_service;
+use crate::state::AppState;
+use std::time::Duration;
+use tokio::sync::watch;
+
+/// How often queued deliveries are checked for a due retry.
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+pub async fn run(state: AppState, mut shutdown: watch::Receiver<bool>) {
+    loop {
+        let delivered = webhook_service::deliver_due(&state).await;
+        if delivered > 0 {
+            tracing::debug!(count = delivered, "Webhook deliveries sent");
+        }
+
+        tokio::select! {
+            _ = tokio::time::sleep(POLL_INTERVAL) => {}
+            _ = state.webhooks.wake.notified() => {}
+            _ = shutdown.changed() => {
+                webhook_service::persist(&state).await;
+                tracing::info!("Webhook dispatcher shutting down");
+                return;
+            }
+        }
+    }
+}
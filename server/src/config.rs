@@ -11,12 +11,96 @@ pub struct AppConfig {
     pub max_upload_size: u64,
     pub snapshot_interval_secs: u64,
     pub ttl_sweep_interval_secs: u64,
+    pub access_flush_interval_secs: u64,
+    pub repo_expiry_grace_secs: u64,
     pub command_timeout_secs: u64,
     pub command_max_output_bytes: usize,
+    /// Server-wide storage budget across all repos and the trash, which is
+    /// purged oldest first before live data is evicted. 0 disables it; a
+    /// budget below `default_max_repo_size` can evict other repos before a
+    /// single one is full.
     pub cache_max_bytes: u64,
+    /// Order repos give up space in when over `cache_max_bytes`: `idle`
+    /// (longest idle first), `weight` (lowest `eviction_weight` first) or
+    /// `tag` (lowest numeric value of the `global_eviction_tag` tag first).
+    pub global_eviction_order: String,
+    pub global_eviction_tag: String,
     pub max_concurrent_commands: usize,
     pub log_level: String,
     pub cors_allowed_origins: String,
+    /// How long deleted files and repos stay restorable; 0 disables the
+    /// trash.
+    pub trash_retention_secs: u64,
+    /// Comma-separated delete causes (`user`, `eviction`, `expiry`) that
+    /// bypass the trash.
+    pub trash_skip_causes: String,
+    /// Watch repo directories for changes made outside the API.
+    pub fs_watch_enabled: bool,
+    /// Quiet period before a watched change is rehashed and recorded.
+    pub fs_watch_debounce_ms: u64,
+    /// Boot-time reconcile: `fast` (stat only), `full` (rehash) or `off`.
+    pub reconcile_mode: String,
+    /// Time between integrity scrub passes; 0 disables the scrubber.
+    pub scrub_interval_secs: u64,
+    /// Hashing rate cap for the scrubber; 0 is unlimited.
+    pub scrub_rate_bytes_per_sec: u64,
+    /// Move corrupted files to `data_dir/quarantine` and drop their
+    /// metadata instead of only reporting them.
+    pub scrub_quarantine: bool,
+    /// Recent change events kept for feed subscribers resuming with a
+    /// last-seen id.
+    pub event_buffer_size: usize,
+    /// How long change log records are kept for `/changes` cursors.
+    pub change_log_retention_secs: u64,
+    /// Most records kept per repository change log.
+    pub change_log_max_entries: usize,
+    /// Delivery attempts before a webhook call is marked failed.
+    pub webhook_max_attempts: u32,
+    /// First retry delay; doubles per attempt, capped at an hour.
+    pub webhook_backoff_base_secs: u64,
+    pub webhook_timeout_secs: u64,
+    /// How long failed deliveries are kept for a manual retry, counted
+    /// from their last attempt; 0 keeps them until their hook is deleted.
+    pub webhook_failed_retention_secs: u64,
+    /// Comma-separated quota percentages that fire
+    /// `quota_threshold_crossed` when usage rises past them.
+    pub webhook_quota_thresholds: String,
+    /// Run `exec` commands in their own user, mount, PID, IPC, UTS and
+    /// network namespaces, seeing only the repo and read-only system dirs.
+    pub sandbox_namespaces: bool,
+    /// Confine `exec` commands with Landlock to reading the repo and system
+    /// dirs and writing only the repo, where the kernel supports it.
+    pub sandbox_landlock: bool,
+    /// Kill `exec` commands that make system calls the whitelisted tools
+    /// never need (sockets, ptrace, mount, bpf, ...).
+    pub sandbox_seccomp: bool,
+    /// Run each `exec` command in its own cgroup v2 child with the limits
+    /// below; needs a writable cgroup tree at `cgroup_root`.
+    pub sandbox_cgroups: bool,
+    /// Where the cgroup v2 hierarchy is mounted.
+    pub cgroup_root: String,
+    /// Default per-command limits, overridable per repo; 0 is unlimited.
+    pub exec_memory_max_bytes: u64,
+    pub exec_cpu_max_cores: f64,
+    pub exec_pids_max: u64,
+    /// Per-process rlimits applied to every `exec` command, with or
+    /// without cgroups; 0 leaves the server's own limit in place. NPROC
+    /// counts every process and thread of the server's user, not just the
+    /// command's, so it is unset by default and the cgroup's `exec_pids_max`
+    /// bounds the command's processes instead.
+    pub exec_rlimit_cpu_secs: u64,
+    pub exec_rlimit_as_bytes: u64,
+    pub exec_rlimit_fsize_bytes: u64,
+    pub exec_rlimit_nofile: u64,
+    pub exec_rlimit_nproc: u64,
+    /// JSON file adding to or overriding the built-in exec argument
+    /// policy; empty uses the built-in rules alone.
+    pub command_policy_file: String,
+    /// Allow exec invocations the policy classifies as writing to the
+    /// repo, such as `sort -o` or `sed -i`.
+    pub exec_allow_mutating: bool,
+    /// Fork fallback when reflinks are unsupported: `copy` or `hardlink`.
+    pub fork_fallback: String,
 }
 
 impl AppConfig {
@@ -33,13 +117,53 @@ impl AppConfig {
             max_upload_size: parse_env("MAX_UPLOAD_SIZE", 104_857_600),
             snapshot_interval_secs: parse_env("SNAPSHOT_INTERVAL_SECS", 300),
             ttl_sweep_interval_secs: parse_env("TTL_SWEEP_INTERVAL_SECS", 60),
+            access_flush_interval_secs: parse_env("ACCESS_FLUSH_INTERVAL_SECS", 30),
+            repo_expiry_grace_secs: parse_env("REPO_EXPIRY_GRACE_SECS", 0),
             command_timeout_secs: parse_env("COMMAND_TIMEOUT_SECS", 30),
             command_max_output_bytes: parse_env("COMMAND_MAX_OUTPUT_BYTES", 10_485_760),
             cache_max_bytes: parse_env("CACHE_MAX_BYTES", 268_435_456),
+            global_eviction_order: env::var("GLOBAL_EVICTION_ORDER")
+                .unwrap_or_else(|_| "idle".into()),
+            global_eviction_tag: env::var("GLOBAL_EVICTION_TAG")
+                .unwrap_or_else(|_| "eviction-priority".into()),
             max_concurrent_commands: parse_env("MAX_CONCURRENT_COMMANDS", 10),
             log_level: env::var("LOG_LEVEL").unwrap_or_else(|_| "info".into()),
             cors_allowed_origins: env::var("CORS_ALLOWED_ORIGINS")
                 .unwrap_or_else(|_| "*".into()),
+            trash_retention_secs: parse_env("TRASH_RETENTION_SECS", 86_400),
+            trash_skip_causes: env::var("TRASH_SKIP_CAUSES")
+                .unwrap_or_else(|_| "eviction".into()),
+            fs_watch_enabled: parse_env("FS_WATCH_ENABLED", true),
+            fs_watch_debounce_ms: parse_env("FS_WATCH_DEBOUNCE_MS", 500),
+            reconcile_mode: env::var("RECONCILE_MODE").unwrap_or_else(|_| "fast".into()),
+            scrub_interval_secs: parse_env("SCRUB_INTERVAL_SECS", 86_400),
+            scrub_rate_bytes_per_sec: parse_env("SCRUB_RATE_BYTES_PER_SEC", 10_485_760),
+            scrub_quarantine: parse_env("SCRUB_QUARANTINE", false),
+            event_buffer_size: parse_env("EVENT_BUFFER_SIZE", 1024),
+            change_log_retention_secs: parse_env("CHANGE_LOG_RETENTION_SECS", 604_800),
+            change_log_max_entries: parse_env("CHANGE_LOG_MAX_ENTRIES", 100_000),
+            webhook_max_attempts: parse_env("WEBHOOK_MAX_ATTEMPTS", 8),
+            webhook_backoff_base_secs: parse_env("WEBHOOK_BACKOFF_BASE_SECS", 5),
+            webhook_timeout_secs: parse_env("WEBHOOK_TIMEOUT_SECS", 10),
+            webhook_failed_retention_secs: parse_env("WEBHOOK_FAILED_RETENTION_SECS", 604_800),
+            webhook_quota_thresholds: env::var("WEBHOOK_QUOTA_THRESHOLDS")
+                .unwrap_or_else(|_| "80,90,100".into()),
+            sandbox_namespaces: parse_env("SANDBOX_NAMESPACES", true),
+            sandbox_landlock: parse_env("SANDBOX_LANDLOCK", true),
+            sandbox_seccomp: parse_env("SANDBOX_SECCOMP", true),
+            sandbox_cgroups: parse_env("SANDBOX_CGROUPS", true),
+            cgroup_root: env::var("CGROUP_ROOT").unwrap_or_else(|_| "/sys/fs/cgroup".into()),
+            exec_memory_max_bytes: parse_env("EXEC_MEMORY_MAX_BYTES", 536_870_912),
+            exec_cpu_max_cores: parse_env("EXEC_CPU_MAX_CORES", 1.0),
+            exec_pids_max: parse_env("EXEC_PIDS_MAX", 64),
+            exec_rlimit_cpu_secs: parse_env("EXEC_RLIMIT_CPU_SECS", 60),
+            exec_rlimit_as_bytes: parse_env("EXEC_RLIMIT_AS_BYTES", 4_294_967_296),
+            exec_rlimit_fsize_bytes: parse_env("EXEC_RLIMIT_FSIZE_BYTES", 1_073_741_824),
+            exec_rlimit_nofile: parse_env("EXEC_RLIMIT_NOFILE", 1024),
+            exec_rlimit_nproc: parse_env("EXEC_RLIMIT_NPROC", 0),
+            command_policy_file: env::var("COMMAND_POLICY_FILE").unwrap_or_default(),
+            exec_allow_mutating: parse_env("EXEC_ALLOW_MUTATING", false),
+            fork_fallback: env::var("FORK_FALLBACK").unwrap_or_else(|_| "copy".into()),
         }
     }
 
@@ -58,6 +182,30 @@ impl AppConfig {
     pub fn wal_dir(&self) -> std::path::PathBuf {
         self.metadata_dir().join("wal")
     }
+
+    pub fn change_log_dir(&self) -> std::path::PathBuf {
+        self.metadata_dir().join("changes")
+    }
+
+    pub fn scrub_progress_path(&self) -> std::path::PathBuf {
+        self.metadata_dir().join("scrub.bin")
+    }
+
+    pub fn quarantine_dir(&self) -> std::path::PathBuf {
+        std::path::PathBuf::from(&self.data_dir).join("quarantine")
+    }
+
+    pub fn webhooks_dir(&self) -> std::path::PathBuf {
+        std::path::PathBuf::from(&self.data_dir).join("webhooks")
+    }
+
+    pub fn trash_dir(&self) -> std::path::PathBuf {
+        std::path::PathBuf::from(&self.data_dir).join("trash")
+    }
+
+    pub fn tmp_dir(&self) -> std::path::PathBuf {
+        std::path::PathBuf::from(&self.data_dir).join("tmp")
+    }
 }
 
 fn parse_env<T: std::str::FromStr>(key: &str, default: T) -> T {
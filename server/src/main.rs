@@ -4,6 +4,12 @@ use linux_fs::models;
 use linux_fs::persistence;
 use linux_fs::persistence::wal::WalWriter;
 use linux_fs::routes;
+use linux_fs::sandbox;
+use linux_fs::services::change_log_service;
+use linux_fs::services::checkpoint_service;
+use linux_fs::services::reconcile_service::{self, ReconcileMode};
+use linux_fs::services::scrub_service;
+use linux_fs::services::webhook_service;
 use linux_fs::state::AppState;
 use tracing_subscriber::EnvFilter;
 
@@ -24,6 +30,24 @@ async fn main() {
         .init();
 
     tracing::info!("Starting linux-fs v{}", env!("CARGO_PKG_VERSION"));
+    if config.cache_max_bytes != 0 && config.cache_max_bytes < config.default_max_repo_size {
+        tracing::warn!(
+            "CACHE_MAX_BYTES ({}) is below DEFAULT_MAX_REPO_SIZE ({}); filling one repo evicts others",
+            config.cache_max_bytes,
+            config.default_max_repo_size
+        );
+    }
+    if config.sandbox_landlock && sandbox::landlock::abi_version().is_none() {
+        tracing::warn!("Landlock is not supported by this kernel; exec runs without it");
+    }
+    if config.sandbox_seccomp && !sandbox::seccomp::supported() {
+        tracing::warn!("seccomp filters are not supported by this kernel; exec runs without them");
+    }
+    if config.sandbox_cgroups {
+        if let Err(e) = sandbox::cgroup::init(std::path::Path::new(&config.cgroup_root)) {
+            tracing::warn!("Cannot set up exec cgroups, commands run without limits: {}", e);
+        }
+    }
 
     // Ensure data directories exist
     std::fs::create_dir_all(config.repos_dir()).expect("Failed to create repos dir");
@@ -34,7 +58,13 @@ async fn main() {
     let wal_writer =
         WalWriter::open(&config.wal_dir()).expect("Failed to open WAL");
 
-    let state = AppState::new(config.clone(), wal_writer);
+    let state = match AppState::new(config.clone(), wal_writer) {
+        Ok(state) => state,
+        Err(e) => {
+            tracing::error!("Failed to load command policy: {:#}", e);
+            std::process::exit(1);
+        }
+    };
 
     // Load snapshot
     if let Some(snapshot) =
@@ -56,6 +86,12 @@ async fn main() {
             }
             state.files.insert(repo_id, map);
         }
+        for (id, entry) in snapshot.trash {
+            state.trash.insert(id, entry);
+        }
+        for (id, checkpoint) in snapshot.checkpoints {
+            state.checkpoints.insert(id, checkpoint);
+        }
     }
 
     // Replay WAL
@@ -71,12 +107,38 @@ async fn main() {
         }
     }
 
+    // Restore sequence numbers from the change logs
+    change_log_service::load(&state);
+
     // Reconcile with filesystem
-    reconcile_filesystem(&state).await;
+    reconcile_service::reconcile(&state, ReconcileMode::parse(&config.reconcile_mode)).await;
+
+    // Pick up an interrupted integrity scrub
+    scrub_service::load(&state).await;
+
+    // Registered webhooks and undelivered calls
+    webhook_service::load(&state);
 
     // Shutdown signal
     let (shutdown_tx, shutdown_rx) = tokio::sync::watch::channel(false);
 
+    // Watch repo directories for out-of-band changes
+    let watch_handle = if config.fs_watch_enabled {
+        match linux_fs::services::watch_service::start(&state) {
+            Ok(events) => Some(tokio::spawn(background::fs_watcher::run(
+                state.clone(),
+                events,
+                shutdown_rx.clone(),
+            ))),
+            Err(e) => {
+                tracing::error!(error = %e, "Failed to start filesystem watcher");
+                None
+            }
+        }
+    } else {
+        None
+    };
+
     // Start background tasks
     let ttl_handle = tokio::spawn(background::ttl_reaper::run(
         state.clone(),
@@ -90,6 +152,30 @@ async fn main() {
         state.clone(),
         shutdown_rx.clone(),
     ));
+    let access_handle = tokio::spawn(background::access_flusher::run(
+        state.clone(),
+        shutdown_rx.clone(),
+    ));
+    let repo_reaper_handle = tokio::spawn(background::repo_reaper::run(
+        state.clone(),
+        shutdown_rx.clone(),
+    ));
+    let trash_purger_handle = tokio::spawn(background::trash_purger::run(
+        state.clone(),
+        shutdown_rx.clone(),
+    ));
+    let change_log_handle = tokio::spawn(background::change_log_pruner::run(
+        state.clone(),
+        shutdown_rx.clone(),
+    ));
+    let scrubber_handle = tokio::spawn(background::integrity_scrubber::run(
+        state.clone(),
+        shutdown_rx.clone(),
+    ));
+    let webhook_handle = tokio::spawn(background::webhook_dispatcher::run(
+        state.clone(),
+        shutdown_rx.clone(),
+    ));
 
     // Build router
     let app = routes::build_router(state.clone());
@@ -109,7 +195,22 @@ async fn main() {
 
     // Wait for background tasks
     tracing::info!("Waiting for background tasks to finish");
-    let _ = tokio::join!(ttl_handle, snapshot_handle, eviction_handle);
+    let _ = tokio::join!(
+        ttl_handle,
+        snapshot_handle,
+        eviction_handle,
+        access_handle,
+        repo_reaper_handle,
+        trash_purger_handle,
+        scrubber_handle,
+        change_log_handle,
+        webhook_handle
+    );
+    if let Some(handle) = watch_handle {
+        let _ = handle.await;
+    }
+
+    change_log_service::flush(&state).await;
 
     // Final snapshot
     tracing::info!("Writing final snapshot");
@@ -169,9 +270,55 @@ fn replay_wal_entries(state: &AppState, entries: Vec<persistence::wal::WalEntry>
                     last_accessed_at: created_at,
                     default_ttl_seconds,
                     tags: HashMap::new(),
+                    forked_from: None,
+                    forked_at: None,
+                    ttl_seconds: None,
+                    idle_ttl_seconds: None,
+                    expires_at: None,
+                    eviction_policy: Default::default(),
+                    pinned_patterns: Vec::new(),
+                    eviction_weight: models::repo::DEFAULT_EVICTION_WEIGHT,
+                    seq: 0,
+                    exec_limits: Default::default(),
+                    command_profile: None,
                 };
                 state.repos.insert(id, repo);
-                state.files.entry(id).or_insert_with(dashmap::DashMap::new);
+                state.files.entry(id).or_default();
+            }
+            WalEntry::RepoForked {
+                id,
+                source_id,
+                name,
+                max_size_bytes,
+                default_ttl_seconds,
+                tags,
+                created_at,
+            } => {
+                let repo = models::repo::RepoMeta {
+                    id,
+                    name,
+                    max_size_bytes,
+                    current_size_bytes: 0,
+                    file_count: 0,
+                    created_at,
+                    updated_at: created_at,
+                    last_accessed_at: created_at,
+                    default_ttl_seconds,
+                    tags,
+                    forked_from: Some(source_id),
+                    forked_at: Some(created_at),
+                    ttl_seconds: None,
+                    idle_ttl_seconds: None,
+                    expires_at: None,
+                    eviction_policy: Default::default(),
+                    pinned_patterns: Vec::new(),
+                    eviction_weight: models::repo::DEFAULT_EVICTION_WEIGHT,
+                    seq: 0,
+                    exec_limits: Default::default(),
+                    command_profile: None,
+                };
+                state.repos.insert(id, repo);
+                state.files.entry(id).or_default();
             }
             WalEntry::RepoUpdated {
                 id,
@@ -200,6 +347,7 @@ fn replay_wal_entries(state: &AppState, entries: Vec<persistence::wal::WalEntry>
             WalEntry::RepoDeleted { id } => {
                 state.repos.remove(&id);
                 state.files.remove(&id);
+                checkpoint_service::forget_repo(state, id);
             }
             WalEntry::RepoSizeChanged {
                 id,
@@ -220,6 +368,12 @@ fn replay_wal_entries(state: &AppState, entries: Vec<persistence::wal::WalEntry>
                 created_at,
                 expires_at,
             } => {
+                // An overwrite keeps the existing pin
+                let pinned = state
+                    .files
+                    .get(&repo_id)
+                    .and_then(|files| files.get(&path).map(|f| f.pinned))
+                    .unwrap_or(false);
                 let meta = models::file::FileMeta {
                     repo_id,
                     path: path.clone(),
@@ -231,11 +385,13 @@ fn replay_wal_entries(state: &AppState, entries: Vec<persistence::wal::WalEntry>
                     last_accessed_at: created_at,
                     access_count: 0,
                     expires_at,
+                    pinned,
+                    seq: 0,
                 };
                 state
                     .files
                     .entry(repo_id)
-                    .or_insert_with(dashmap::DashMap::new)
+                    .or_default()
                     .insert(path, meta);
 
                 if let Some(mut repo) = state.repos.get_mut(&repo_id) {
@@ -268,64 +424,203 @@ fn replay_wal_entries(state: &AppState, entries: Vec<persistence::wal::WalEntry>
                     }
                 }
             }
+            WalEntry::RepoExpiryChanged {
+                id,
+                ttl_seconds,
+                idle_ttl_seconds,
+                expires_at,
+            } => {
+                if let Some(mut repo) = state.repos.get_mut(&id) {
+                    repo.ttl_seconds = ttl_seconds;
+                    repo.idle_ttl_seconds = idle_ttl_seconds;
+                    repo.expires_at = expires_at;
+                }
+            }
+            WalEntry::RepoEvictionPolicyChanged {
+                id,
+                eviction_policy,
+            } => {
+                if let Some(mut repo) = state.repos.get_mut(&id) {
+                    repo.eviction_policy = eviction_policy;
+                }
+            }
+            WalEntry::FilePinned {
+                repo_id,
+                path,
+                pinned,
+            } => {
+                if let Some(files) = state.files.get(&repo_id) {
+                    if let Some(mut meta) = files.get_mut(&path) {
+                        meta.pinned = pinned;
+                    }
+                }
+            }
+            WalEntry::RepoPinsChanged {
+                id,
+                pinned_patterns,
+            } => {
+                if let Some(mut repo) = state.repos.get_mut(&id) {
+                    repo.pinned_patterns = pinned_patterns;
+                }
+            }
+            WalEntry::RepoEvictionWeightChanged {
+                id,
+                eviction_weight,
+            } => {
+                if let Some(mut repo) = state.repos.get_mut(&id) {
+                    repo.eviction_weight = eviction_weight;
+                }
+            }
+            WalEntry::RepoExecLimitsChanged { id, exec_limits } => {
+                if let Some(mut repo) = state.repos.get_mut(&id) {
+                    repo.exec_limits = exec_limits;
+                }
+            }
+            WalEntry::RepoCommandProfileChanged {
+                id,
+                command_profile,
+            } => {
+                if let Some(mut repo) = state.repos.get_mut(&id) {
+                    repo.command_profile = command_profile;
+                }
+            }
+            WalEntry::Trashed { entry } => {
+                match &entry.item {
+                    models::trash::TrashedItem::File { file } => {
+                        if let Some(files) = state.files.get(&file.repo_id) {
+                            if files.remove(&file.path).is_some() {
+                                if let Some(mut repo) = state.repos.get_mut(&file.repo_id) {
+                                    repo.current_size_bytes =
+                                        repo.current_size_bytes.saturating_sub(file.size_bytes);
+                                    repo.file_count = repo.file_count.saturating_sub(1);
+                                }
+                            }
+                        }
+                    }
+                    models::trash::TrashedItem::Repo { repo, .. } => {
+                        state.repos.remove(&repo.id);
+                        state.files.remove(&repo.id);
+                    }
+                }
+                state.trash.insert(entry.id, *entry);
+            }
+            WalEntry::TrashRestored { id } => replay_trash_restored(state, id),
+            WalEntry::TrashRestoredWithExpiry {
+                id,
+                ttl_seconds,
+                expires_at,
+            } => {
+                if let Some(mut entry) = state.trash.get_mut(&id) {
+                    entry.item.set_expiry(ttl_seconds, expires_at);
+                }
+                replay_trash_restored(state, id);
+            }
+            WalEntry::TrashPurged { id } => {
+                if let Some((_, entry)) = state.trash.remove(&id) {
+                    if let models::trash::TrashedItem::Repo { repo, .. } = entry.item {
+                        checkpoint_service::forget_repo(state, repo.id);
+                    }
+                }
+            }
+            WalEntry::CheckpointCreated { checkpoint } => {
+                state.checkpoints.insert(checkpoint.id, *checkpoint);
+            }
+            WalEntry::CheckpointDeleted { id } => {
+                state.checkpoints.remove(&id);
+            }
+            WalEntry::RepoCreatedWithSettings { repo } => {
+                state.files.entry(repo.id).or_default();
+                state.repos.insert(repo.id, *repo);
+            }
+            WalEntry::RepoForkedWithFiles { repo, files } => {
+                let map = dashmap::DashMap::new();
+                for meta in files {
+                    map.insert(meta.path.clone(), meta);
+                }
+                state.files.insert(repo.id, map);
+                state.repos.insert(repo.id, *repo);
+            }
+            WalEntry::SyncCommitted {
+                repo_id,
+                files,
+                deleted,
+                trashed,
+            } => {
+                let removed = trashed
+                    .iter()
+                    .filter_map(|entry| match &entry.item {
+                        models::trash::TrashedItem::File { file } => Some(file.path.clone()),
+                        models::trash::TrashedItem::Repo { .. } => None,
+                    })
+                    .chain(deleted)
+                    .chain(files.iter().map(|f| f.path.clone()));
+                if let Some(repo_files) = state.files.get(&repo_id) {
+                    for path in removed {
+                        if let Some((_, meta)) = repo_files.remove(&path) {
+                            if let Some(mut repo) = state.repos.get_mut(&repo_id) {
+                                repo.current_size_bytes =
+                                    repo.current_size_bytes.saturating_sub(meta.size_bytes);
+                                repo.file_count = repo.file_count.saturating_sub(1);
+                            }
+                        }
+                    }
+                }
+                for entry in trashed {
+                    state.trash.insert(entry.id, entry);
+                }
+                if let Some(mut repo) = state.repos.get_mut(&repo_id) {
+                    repo.current_size_bytes += files.iter().map(|f| f.size_bytes).sum::<u64>();
+                    repo.file_count += files.len() as u64;
+                }
+                let repo_files = state.files.entry(repo_id).or_default();
+                for meta in files {
+                    repo_files.insert(meta.path.clone(), meta);
+                }
+            }
+            WalEntry::AccessRecorded {
+                repo_id,
+                last_accessed_at,
+                files,
+            } => {
+                if let Some(mut repo) = state.repos.get_mut(&repo_id) {
+                    repo.last_accessed_at = last_accessed_at;
+                }
+                if let Some(repo_files) = state.files.get(&repo_id) {
+                    for (path, access_count, accessed_at) in files {
+                        if let Some(mut meta) = repo_files.get_mut(&path) {
+                            meta.access_count = access_count;
+                            meta.last_accessed_at = accessed_at;
+                        }
+                    }
+                }
+            }
         }
     }
 }
 
-/// Walk the filesystem and reconcile with in-memory state.
-/// Remove metadata for files that don't exist on disk.
-/// Recompute repo sizes.
-async fn reconcile_filesystem(state: &AppState) {
-    let repo_ids: Vec<uuid::Uuid> = state.repos.iter().map(|r| *r.key()).collect();
-
-    for repo_id in repo_ids {
-        let repo_dir = state
-            .config
-            .repos_dir()
-            .join(repo_id.to_string())
-            .join("files");
-
-        if !repo_dir.exists() {
-            tracing::warn!(repo_id = %repo_id, "Repo directory missing, cleaning metadata");
-            state.repos.remove(&repo_id);
-            state.files.remove(&repo_id);
-            continue;
-        }
-
-        // Check for orphaned metadata entries
-        if let Some(files) = state.files.get(&repo_id) {
-            let paths: Vec<String> = files.iter().map(|f| f.key().clone()).collect();
-            for path in paths {
-                let file_path = repo_dir.join(&path);
-                if !file_path.exists() {
-                    tracing::warn!(
-                        repo_id = %repo_id,
-                        path = %path,
-                        "Orphaned metadata entry, removing"
-                    );
-                    files.remove(&path);
+/// Replay a restore: move the trash entry back into the repo maps.
+fn replay_trash_restored(state: &AppState, id: uuid::Uuid) {
+    if let Some((_, entry)) = state.trash.remove(&id) {
+        match entry.item {
+            models::trash::TrashedItem::File { file } => {
+                if let Some(mut repo) = state.repos.get_mut(&file.repo_id) {
+                    repo.current_size_bytes += file.size_bytes;
+                    repo.file_count += 1;
                 }
+                state
+                    .files
+                    .entry(file.repo_id)
+                    .or_default()
+                    .insert(file.path.clone(), file);
             }
-        }
-
-        // Recompute repo size
-        let (total_size, file_count) = state
-            .files
-            .get(&repo_id)
-            .map(|files| {
-                let mut size = 0u64;
-                let mut count = 0u64;
-                for entry in files.iter() {
-                    size += entry.value().size_bytes;
-                    count += 1;
+            models::trash::TrashedItem::Repo { repo, files } => {
+                let map = dashmap::DashMap::new();
+                for meta in files {
+                    map.insert(meta.path.clone(), meta);
                 }
-                (size, count)
-            })
-            .unwrap_or((0, 0));
-
-        if let Some(mut repo) = state.repos.get_mut(&repo_id) {
-            repo.current_size_bytes = total_size;
-            repo.file_count = file_count;
+                state.files.insert(repo.id, map);
+                state.repos.insert(repo.id, *repo);
+            }
         }
     }
 }
@@ -0,0 +1,36 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use super::event::ChangeKind;
+
+/// One entry in a repository's durable change log.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ChangeRecord {
+    /// Per-repo sequence number, increasing by one per change.
+    pub seq: u64,
+    pub kind: ChangeKind,
+    pub path: Option<String>,
+    pub previous_path: Option<String>,
+    /// Content size and etag after the change, for file writes and moves.
+    pub size_bytes: Option<u64>,
+    pub etag: Option<String>,
+    pub timestamp: DateTime<Utc>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ChangesQuery {
+    /// Return changes with a sequence number greater than this.
+    pub since: Option<u64>,
+    pub limit: Option<usize>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ChangesPage {
+    pub repo_id: Uuid,
+    pub since: u64,
+    pub latest_seq: u64,
+    pub changes: Vec<ChangeRecord>,
+    /// Pass the last returned `seq` as `since` to continue.
+    pub has_more: bool,
+}
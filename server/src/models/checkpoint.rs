@@ -0,0 +1,45 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use super::file::FileMeta;
+
+/// A repository's file metadata at one moment, kept to diff against
+/// later. Only metadata is kept, not content.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Checkpoint {
+    pub id: Uuid,
+    pub repo_id: Uuid,
+    pub name: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub files: Vec<FileMeta>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct CreateCheckpointRequest {
+    pub name: Option<String>,
+}
+
+/// A checkpoint as listed, without its files.
+#[derive(Debug, Serialize)]
+pub struct CheckpointInfo {
+    pub id: Uuid,
+    pub repo_id: Uuid,
+    pub name: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub file_count: u64,
+    pub size_bytes: u64,
+}
+
+impl From<&Checkpoint> for CheckpointInfo {
+    fn from(c: &Checkpoint) -> Self {
+        Self {
+            id: c.id,
+            repo_id: c.repo_id,
+            name: c.name.clone(),
+            created_at: c.created_at,
+            file_count: c.files.len() as u64,
+            size_bytes: c.files.iter().map(|f| f.size_bytes).sum(),
+        }
+    }
+}
@@ -0,0 +1,52 @@
+use serde::{Deserialize, Serialize};
+
+use super::file::UploadResult;
+
+#[derive(Debug, Deserialize)]
+pub struct SignatureQuery {
+    pub path: String,
+    pub block_size: Option<usize>,
+}
+
+/// Checksums of one fixed-size block; the last block may be short.
+#[derive(Debug, Clone, Serialize)]
+pub struct BlockSignature {
+    pub index: u64,
+    /// rsync-style rolling checksum, so clients can match blocks at any
+    /// offset of their copy.
+    pub weak: u32,
+    /// Hex sha256 of the block, to confirm a weak match.
+    pub strong: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct FileSignature {
+    pub path: String,
+    /// Send back as `base_etag` with the delta.
+    pub etag: String,
+    pub size_bytes: u64,
+    pub block_size: usize,
+    pub blocks: Vec<BlockSignature>,
+}
+
+/// Parameters of a delta upload. The body is a sequence of instructions:
+/// `0x01, start_block: u64 LE, count: u32 LE` copies blocks of the current
+/// file, and `0x02, len: u32 LE, bytes` inserts literal data.
+#[derive(Debug, Deserialize)]
+pub struct DeltaQuery {
+    pub path: String,
+    /// Etag the delta was computed against.
+    pub base_etag: String,
+    /// Block size of the signature the delta was computed from.
+    pub block_size: usize,
+    /// Expected sha256 of the reconstructed file.
+    pub sha256: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct DeltaResult {
+    #[serde(flatten)]
+    pub upload: UploadResult,
+    pub copied_bytes: u64,
+    pub literal_bytes: u64,
+}
@@ -0,0 +1,66 @@
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+#[derive(Debug, Deserialize)]
+pub struct DiffQuery {
+    /// Repository or checkpoint id to compare against, or `parent` for
+    /// the fork source. Defaults to the repository itself, for comparing
+    /// two prefixes.
+    pub against: Option<String>,
+    pub prefix: Option<String>,
+    pub against_prefix: Option<String>,
+    pub include_text: Option<bool>,
+    pub max_text_bytes: Option<u64>,
+    /// Cap on the text diffs of the whole response.
+    pub max_total_text_bytes: Option<u64>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct DiffSide {
+    pub repo_id: Uuid,
+    /// Set when the side is a checkpoint of `repo_id`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub checkpoint_id: Option<Uuid>,
+    pub prefix: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct DiffFile {
+    pub path: String,
+    pub size_bytes: u64,
+    pub etag: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ModifiedFile {
+    pub path: String,
+    pub old_size_bytes: u64,
+    pub new_size_bytes: u64,
+    pub old_etag: String,
+    pub new_etag: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub unified_diff: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub diff_skipped: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct RenamedFile {
+    pub from: String,
+    pub to: String,
+    pub size_bytes: u64,
+    pub etag: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct RepoDiff {
+    pub base: DiffSide,
+    pub target: DiffSide,
+    pub added: Vec<DiffFile>,
+    pub removed: Vec<DiffFile>,
+    pub modified: Vec<ModifiedFile>,
+    pub renamed: Vec<RenamedFile>,
+    /// Set when text diffs were left out because the response reached
+    /// `max_total_text_bytes`.
+    pub truncated: bool,
+}
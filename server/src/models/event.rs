@@ -0,0 +1,122 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ChangeKind {
+    FileCreated,
+    FileUpdated,
+    FileDeleted,
+    FileMoved,
+    FileExpired,
+    RepoUpdated,
+    RepoDeleted,
+    FileEvicted,
+    RepoExpired,
+}
+
+impl ChangeKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ChangeKind::FileCreated => "file_created",
+            ChangeKind::FileUpdated => "file_updated",
+            ChangeKind::FileDeleted => "file_deleted",
+            ChangeKind::FileMoved => "file_moved",
+            ChangeKind::FileExpired => "file_expired",
+            ChangeKind::RepoUpdated => "repo_updated",
+            ChangeKind::RepoDeleted => "repo_deleted",
+            ChangeKind::FileEvicted => "file_evicted",
+            ChangeKind::RepoExpired => "repo_expired",
+        }
+    }
+
+    pub const ALL: [ChangeKind; 9] = [
+        ChangeKind::FileCreated,
+        ChangeKind::FileUpdated,
+        ChangeKind::FileDeleted,
+        ChangeKind::FileMoved,
+        ChangeKind::FileExpired,
+        ChangeKind::RepoUpdated,
+        ChangeKind::RepoDeleted,
+        ChangeKind::FileEvicted,
+        ChangeKind::RepoExpired,
+    ];
+
+    /// Whether the repository no longer exists after this change.
+    pub fn ends_repo(&self) -> bool {
+        matches!(self, ChangeKind::RepoDeleted | ChangeKind::RepoExpired)
+    }
+}
+
+/// One change pushed to subscribers. Ids increase monotonically for the
+/// life of the process, whose run `epoch` tells them apart from ids of
+/// earlier runs; `seq` is the durable per-repo sequence number.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ChangeEvent {
+    pub id: u64,
+    pub epoch: Uuid,
+    pub repo_id: Uuid,
+    pub seq: u64,
+    pub kind: ChangeKind,
+    /// The affected file; absent for repo events.
+    pub path: Option<String>,
+    /// The source path of a move.
+    pub previous_path: Option<String>,
+    pub timestamp: DateTime<Utc>,
+}
+
+impl ChangeEvent {
+    /// The SSE id, which clients send back as `Last-Event-ID`.
+    pub fn cursor(&self) -> EventCursor {
+        EventCursor {
+            epoch: self.epoch,
+            id: self.id,
+        }
+    }
+
+    /// Whether the event touches a path under `prefix`. Repo events always
+    /// match.
+    pub fn matches_prefix(&self, prefix: &str) -> bool {
+        match (&self.path, &self.previous_path) {
+            (None, None) => true,
+            (path, previous) => [path, previous]
+                .into_iter()
+                .flatten()
+                .any(|p| p.starts_with(prefix)),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SubscribeQuery {
+    pub prefix: Option<String>,
+    /// Replay events after this id; the `Last-Event-ID` header wins when
+    /// both are sent.
+    pub last_event_id: Option<String>,
+}
+
+/// A position in the event feed, written `<epoch>-<id>`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct EventCursor {
+    pub epoch: Uuid,
+    pub id: u64,
+}
+
+impl std::fmt::Display for EventCursor {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}-{}", self.epoch, self.id)
+    }
+}
+
+impl std::str::FromStr for EventCursor {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, ()> {
+        let (epoch, id) = s.rsplit_once('-').ok_or(())?;
+        Ok(Self {
+            epoch: Uuid::parse_str(epoch).map_err(|_| ())?,
+            id: id.parse().map_err(|_| ())?,
+        })
+    }
+}
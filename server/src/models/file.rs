@@ -14,6 +14,19 @@ pub struct FileMeta {
     pub last_accessed_at: DateTime<Utc>,
     pub access_count: u64,
     pub expires_at: Option<DateTime<Utc>>,
+    /// Pinned files are never evicted or expired.
+    pub pinned: bool,
+    /// Sequence number of the last change to this file in the repo's
+    /// change log.
+    pub seq: u64,
+}
+
+/// A stored file plus any files evicted to make room for it.
+#[derive(Debug, Clone, Serialize)]
+pub struct UploadResult {
+    #[serde(flatten)]
+    pub file: FileMeta,
+    pub evicted: Vec<String>,
 }
 
 #[derive(Debug, Deserialize)]
@@ -35,3 +48,10 @@ pub struct CopyFileRequest {
     pub source: String,
     pub destination: String,
 }
+
+#[derive(Debug, Deserialize)]
+pub struct PinFileRequest {
+    pub path: String,
+    /// Defaults to `true`; `false` removes the pin.
+    pub pinned: Option<bool>,
+}
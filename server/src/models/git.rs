@@ -0,0 +1,73 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Deserialize)]
+pub struct GitImportQuery {
+    #[serde(rename = "ref")]
+    pub git_ref: Option<String>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct GitExportRequest {
+    pub branch: Option<String>,
+    pub message: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct GitLogQuery {
+    pub rev: Option<String>,
+    pub limit: Option<usize>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct GitShowQuery {
+    pub rev: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct GitDiffQuery {
+    pub from: String,
+    pub to: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct GitCommit {
+    pub id: String,
+    pub parents: Vec<String>,
+    pub author_name: String,
+    pub author_email: String,
+    pub time: DateTime<Utc>,
+    pub summary: String,
+    pub message: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct GitChangedFile {
+    pub path: String,
+    pub status: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct GitDiff {
+    pub from: String,
+    pub to: String,
+    pub files: Vec<GitChangedFile>,
+    pub patch: String,
+    pub truncated: bool,
+}
+
+#[derive(Debug, Serialize)]
+pub struct GitShow {
+    pub commit: GitCommit,
+    pub diff: GitDiff,
+}
+
+#[derive(Debug, Serialize)]
+pub struct GitImportResult {
+    #[serde(rename = "ref")]
+    pub git_ref: String,
+    pub commit: String,
+    pub files_written: u64,
+    pub bytes_written: u64,
+    pub skipped: Vec<String>,
+}
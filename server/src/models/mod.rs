@@ -1,3 +1,13 @@
+pub mod change;
+pub mod checkpoint;
+pub mod delta;
+pub mod diff;
+pub mod event;
 pub mod file;
+pub mod git;
 pub mod repo;
+pub mod scrub;
 pub mod snapshot;
+pub mod sync;
+pub mod trash;
+pub mod webhook;
@@ -1,5 +1,5 @@
 use chrono::{DateTime, Utc};
-use serde::{Deserialize, Serialize};
+use serde::{Deserialize, Deserializer, Serialize};
 use std::collections::HashMap;
 use uuid::Uuid;
 
@@ -15,6 +15,87 @@ pub struct RepoMeta {
     pub last_accessed_at: DateTime<Utc>,
     pub default_ttl_seconds: Option<u64>,
     pub tags: HashMap<String, String>,
+    pub forked_from: Option<Uuid>,
+    pub forked_at: Option<DateTime<Utc>>,
+    pub ttl_seconds: Option<u64>,
+    pub idle_ttl_seconds: Option<u64>,
+    pub expires_at: Option<DateTime<Utc>>,
+    pub eviction_policy: EvictionPolicy,
+    /// Glob patterns; matching files are treated as pinned.
+    pub pinned_patterns: Vec<String>,
+    /// Relative importance when evicting across repos for the server
+    /// storage budget; lower weights give up space first.
+    pub eviction_weight: u32,
+    /// Sequence number of the repo's latest change; `/changes?since=`
+    /// cursors are compared against it.
+    pub seq: u64,
+    pub exec_limits: ExecLimits,
+    /// The `exec` command profile; `None` uses the default one.
+    pub command_profile: Option<String>,
+}
+
+pub const DEFAULT_EVICTION_WEIGHT: u32 = 1;
+
+/// Per-repo overrides of the server's `exec` resource limits. Unset
+/// fields use the server defaults; 0 removes the limit.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct ExecLimits {
+    pub memory_max_bytes: Option<u64>,
+    pub cpu_max_cores: Option<f64>,
+    pub pids_max: Option<u64>,
+}
+
+impl ExecLimits {
+    /// The fields set here, and `other`'s for the rest.
+    pub fn or(self, other: Self) -> Self {
+        Self {
+            memory_max_bytes: self.memory_max_bytes.or(other.memory_max_bytes),
+            cpu_max_cores: self.cpu_max_cores.or(other.cpu_max_cores),
+            pids_max: self.pids_max.or(other.pids_max),
+        }
+    }
+}
+
+/// How files are chosen for eviction when an upload exceeds the quota.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum EvictionPolicy {
+    /// Least recently accessed first.
+    Lru,
+    /// Lowest access rate (`access_count` per second since creation) first.
+    #[default]
+    Lfu,
+    /// Oldest written first.
+    Fifo,
+    /// Greedy-dual-size-frequency: large, rarely read files first, with
+    /// an aging clock so files left idle since the last eviction go before
+    /// ones read since.
+    Gdsf,
+    /// Never evict; uploads over quota are rejected.
+    Never,
+}
+
+/// The longest repository TTL accepted, a century; longer ones would
+/// push expiries out of the range timestamps can hold.
+pub const MAX_TTL_SECONDS: u64 = 100 * 365 * 86_400;
+
+/// `start` plus `seconds`, or `None` if the result is out of range.
+pub fn checked_deadline(start: DateTime<Utc>, seconds: u64) -> Option<DateTime<Utc>> {
+    let seconds = chrono::Duration::try_seconds(i64::try_from(seconds).ok()?)?;
+    start.checked_add_signed(seconds)
+}
+
+impl RepoMeta {
+    /// The earlier of the absolute expiry and the idle deadline.
+    pub fn effective_expiry(&self) -> Option<DateTime<Utc>> {
+        let idle = self
+            .idle_ttl_seconds
+            .and_then(|s| checked_deadline(self.last_accessed_at, s));
+        match (self.expires_at, idle) {
+            (Some(a), Some(b)) => Some(a.min(b)),
+            (a, b) => a.or(b),
+        }
+    }
 }
 
 #[derive(Debug, Deserialize)]
@@ -22,14 +103,56 @@ pub struct CreateRepoRequest {
     pub name: String,
     pub max_size_bytes: Option<u64>,
     pub default_ttl_seconds: Option<u64>,
+    pub ttl_seconds: Option<u64>,
+    pub idle_ttl_seconds: Option<u64>,
+    pub eviction_policy: Option<EvictionPolicy>,
+    pub pinned_patterns: Option<Vec<String>>,
+    pub eviction_weight: Option<u32>,
+    pub exec_limits: Option<ExecLimits>,
+    pub command_profile: Option<String>,
 }
 
 #[derive(Debug, Deserialize)]
 pub struct UpdateRepoRequest {
     pub name: Option<String>,
     pub max_size_bytes: Option<u64>,
+    #[serde(default, deserialize_with = "double_option")]
     pub default_ttl_seconds: Option<Option<u64>>,
     pub tags: Option<HashMap<String, String>>,
+    #[serde(default, deserialize_with = "double_option")]
+    pub ttl_seconds: Option<Option<u64>>,
+    #[serde(default, deserialize_with = "double_option")]
+    pub idle_ttl_seconds: Option<Option<u64>>,
+    pub eviction_policy: Option<EvictionPolicy>,
+    pub pinned_patterns: Option<Vec<String>>,
+    pub eviction_weight: Option<u32>,
+    pub exec_limits: Option<ExecLimits>,
+    #[serde(default, deserialize_with = "double_option")]
+    pub command_profile: Option<Option<String>>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct ForkRepoRequest {
+    pub name: Option<String>,
+    pub max_size_bytes: Option<u64>,
+    #[serde(default, deserialize_with = "double_option")]
+    pub default_ttl_seconds: Option<Option<u64>>,
+    pub tags: Option<HashMap<String, String>>,
+    #[serde(default, deserialize_with = "double_option")]
+    pub ttl_seconds: Option<Option<u64>>,
+    #[serde(default, deserialize_with = "double_option")]
+    pub idle_ttl_seconds: Option<Option<u64>>,
+    pub eviction_policy: Option<EvictionPolicy>,
+    pub pinned_patterns: Option<Vec<String>>,
+    pub eviction_weight: Option<u32>,
+    pub exec_limits: Option<ExecLimits>,
+    #[serde(default, deserialize_with = "double_option")]
+    pub command_profile: Option<Option<String>>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct RenewRepoRequest {
+    pub ttl_seconds: Option<u64>,
 }
 
 #[derive(Debug, Deserialize)]
@@ -38,3 +161,13 @@ pub struct ListReposQuery {
     pub per_page: Option<u64>,
     pub sort: Option<String>,
 }
+
+/// Distinguish an absent field (`None`) from an explicit `null`
+/// (`Some(None)`), so PATCH-style requests can clear a value.
+fn double_option<'de, T, D>(deserializer: D) -> Result<Option<Option<T>>, D::Error>
+where
+    T: Deserialize<'de>,
+    D: Deserializer<'de>,
+{
+    Option::<T>::deserialize(deserializer).map(Some)
+}
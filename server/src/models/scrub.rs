@@ -0,0 +1,45 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ScrubIssue {
+    /// The stored content no longer hashes to the recorded etag.
+    Corrupted,
+    /// Metadata exists but the file is gone from disk.
+    Missing,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ScrubFinding {
+    pub repo_id: Uuid,
+    pub path: String,
+    pub issue: ScrubIssue,
+    pub expected_etag: String,
+    pub actual_etag: Option<String>,
+    pub detected_at: DateTime<Utc>,
+    /// Where the bad copy was moved when quarantine is enabled.
+    pub quarantine_path: Option<String>,
+}
+
+/// Integrity scrubber progress, saved to `metadata/scrub.bin` after every
+/// batch so a pass resumes from `cursor` after a restart.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ScrubProgress {
+    /// Set while a pass is running.
+    pub pass_started_at: Option<DateTime<Utc>>,
+    /// Last file checked in the current pass, in (repo id, path) order.
+    pub cursor: Option<(Uuid, String)>,
+    pub last_pass_completed_at: Option<DateTime<Utc>>,
+    pub passes_completed: u64,
+    pub pass_files_checked: u64,
+    pub pass_bytes_checked: u64,
+    pub files_checked_total: u64,
+    pub bytes_checked_total: u64,
+    pub corrupted_total: u64,
+    pub missing_total: u64,
+    /// Outstanding problems; an entry is dropped once the file checks out
+    /// again.
+    pub findings: Vec<ScrubFinding>,
+}
@@ -2,10 +2,17 @@ use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use uuid::Uuid;
 
+use super::checkpoint::Checkpoint;
 use super::file::FileMeta;
 use super::repo::RepoMeta;
+use super::trash::TrashEntry;
 
-pub const SNAPSHOT_VERSION: u32 = 1;
+/// Snapshots are bincode, which cannot skip or default fields, so bump
+/// this whenever the encoding of any type below changes and keep the old
+/// layout in a module like [`v1`] with a migration. Snapshots of any
+/// other version are refused rather than discarded, since the WAL before
+/// them is gone.
+pub const SNAPSHOT_VERSION: u32 = 11;
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct MetadataSnapshot {
@@ -13,4 +20,156 @@ pub struct MetadataSnapshot {
     pub timestamp: chrono::DateTime<chrono::Utc>,
     pub repos: HashMap<Uuid, RepoMeta>,
     pub files: HashMap<Uuid, HashMap<String, FileMeta>>,
+    pub trash: HashMap<Uuid, TrashEntry>,
+    pub checkpoints: HashMap<Uuid, Checkpoint>,
+}
+
+/// The layout from before checkpoints.
+pub mod v10 {
+    use crate::models::file::FileMeta;
+    use crate::models::repo::RepoMeta;
+    use crate::models::trash::TrashEntry;
+    use chrono::{DateTime, Utc};
+    use serde::{Deserialize, Serialize};
+    use std::collections::HashMap;
+    use uuid::Uuid;
+
+    pub const VERSION: u32 = 10;
+
+    #[derive(Debug, Serialize, Deserialize)]
+    pub struct MetadataSnapshot {
+        pub version: u32,
+        pub timestamp: DateTime<Utc>,
+        pub repos: HashMap<Uuid, RepoMeta>,
+        pub files: HashMap<Uuid, HashMap<String, FileMeta>>,
+        pub trash: HashMap<Uuid, TrashEntry>,
+    }
+
+    impl From<MetadataSnapshot> for super::MetadataSnapshot {
+        fn from(s: MetadataSnapshot) -> Self {
+            Self {
+                version: super::SNAPSHOT_VERSION,
+                timestamp: s.timestamp,
+                repos: s.repos,
+                files: s.files,
+                trash: s.trash,
+                checkpoints: HashMap::new(),
+            }
+        }
+    }
+}
+
+/// The first released layout, from before forks, TTLs, eviction policies,
+/// pins, the trash and the change log.
+pub mod v1 {
+    use crate::models::repo::{EvictionPolicy, ExecLimits, DEFAULT_EVICTION_WEIGHT};
+    use chrono::{DateTime, Utc};
+    use serde::{Deserialize, Serialize};
+    use std::collections::HashMap;
+    use uuid::Uuid;
+
+    pub const VERSION: u32 = 1;
+
+    #[derive(Debug, Serialize, Deserialize)]
+    pub struct MetadataSnapshot {
+        pub version: u32,
+        pub timestamp: DateTime<Utc>,
+        pub repos: HashMap<Uuid, RepoMeta>,
+        pub files: HashMap<Uuid, HashMap<String, FileMeta>>,
+    }
+
+    #[derive(Debug, Serialize, Deserialize)]
+    pub struct RepoMeta {
+        pub id: Uuid,
+        pub name: String,
+        pub max_size_bytes: u64,
+        pub current_size_bytes: u64,
+        pub file_count: u64,
+        pub created_at: DateTime<Utc>,
+        pub updated_at: DateTime<Utc>,
+        pub last_accessed_at: DateTime<Utc>,
+        pub default_ttl_seconds: Option<u64>,
+        pub tags: HashMap<String, String>,
+    }
+
+    #[derive(Debug, Serialize, Deserialize)]
+    pub struct FileMeta {
+        pub repo_id: Uuid,
+        pub path: String,
+        pub size_bytes: u64,
+        pub etag: String,
+        pub content_type: String,
+        pub created_at: DateTime<Utc>,
+        pub updated_at: DateTime<Utc>,
+        pub last_accessed_at: DateTime<Utc>,
+        pub access_count: u64,
+        pub expires_at: Option<DateTime<Utc>>,
+    }
+
+    impl From<RepoMeta> for super::RepoMeta {
+        fn from(r: RepoMeta) -> Self {
+            Self {
+                id: r.id,
+                name: r.name,
+                max_size_bytes: r.max_size_bytes,
+                current_size_bytes: r.current_size_bytes,
+                file_count: r.file_count,
+                created_at: r.created_at,
+                updated_at: r.updated_at,
+                last_accessed_at: r.last_accessed_at,
+                default_ttl_seconds: r.default_ttl_seconds,
+                tags: r.tags,
+                forked_from: None,
+                forked_at: None,
+                ttl_seconds: None,
+                idle_ttl_seconds: None,
+                expires_at: None,
+                eviction_policy: EvictionPolicy::default(),
+                pinned_patterns: Vec::new(),
+                eviction_weight: DEFAULT_EVICTION_WEIGHT,
+                seq: 0,
+                exec_limits: ExecLimits::default(),
+                command_profile: None,
+            }
+        }
+    }
+
+    impl From<FileMeta> for super::FileMeta {
+        fn from(f: FileMeta) -> Self {
+            Self {
+                repo_id: f.repo_id,
+                path: f.path,
+                size_bytes: f.size_bytes,
+                etag: f.etag,
+                content_type: f.content_type,
+                created_at: f.created_at,
+                updated_at: f.updated_at,
+                last_accessed_at: f.last_accessed_at,
+                access_count: f.access_count,
+                expires_at: f.expires_at,
+                pinned: false,
+                seq: 0,
+            }
+        }
+    }
+
+    impl From<MetadataSnapshot> for super::MetadataSnapshot {
+        fn from(s: MetadataSnapshot) -> Self {
+            Self {
+                version: super::SNAPSHOT_VERSION,
+                timestamp: s.timestamp,
+                repos: s.repos.into_iter().map(|(id, r)| (id, r.into())).collect(),
+                files: s
+                    .files
+                    .into_iter()
+                    .map(|(id, files)| {
+                        let files = files.into_iter().map(|(p, f)| (p, f.into())).collect();
+                        (id, files)
+                    })
+                    .collect(),
+                trash: HashMap::new(),
+                checkpoints: HashMap::new(),
+            }
+        }
+    }
 }
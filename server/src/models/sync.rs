@@ -0,0 +1,69 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use uuid::Uuid;
+
+/// One file of the client's local tree.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ManifestEntry {
+    pub path: String,
+    pub sha256: String,
+    pub size: u64,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SyncPlanRequest {
+    pub files: Vec<ManifestEntry>,
+    /// Delete repo files that are not in the manifest.
+    pub mirror: Option<bool>,
+}
+
+/// Content the client has to send, once for every path that needs it.
+#[derive(Debug, Clone, Serialize)]
+pub struct UploadItem {
+    pub sha256: String,
+    pub size: u64,
+    pub paths: Vec<String>,
+}
+
+/// A path the server fills from an identical file already in the repo.
+#[derive(Debug, Clone, Serialize)]
+pub struct CopyItem {
+    pub path: String,
+    pub source: String,
+}
+
+/// What it takes to make a repo match a manifest. The commit body is the
+/// content of every `upload` item, concatenated in order.
+#[derive(Debug, Clone, Serialize)]
+pub struct SyncPlan {
+    pub plan_id: Uuid,
+    pub repo_id: Uuid,
+    pub mirror: bool,
+    pub upload: Vec<UploadItem>,
+    pub copy: Vec<CopyItem>,
+    pub delete: Vec<String>,
+    pub unchanged: usize,
+    pub upload_bytes: u64,
+    pub expires_at: DateTime<Utc>,
+    /// Etag of every path the plan reads or writes, as seen when planning;
+    /// `None` for paths that did not exist. The commit fails if any moved.
+    #[serde(skip)]
+    pub observed: HashMap<String, Option<String>>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SyncCommitQuery {
+    pub plan_id: Uuid,
+}
+
+#[derive(Debug, Serialize)]
+pub struct SyncResult {
+    pub plan_id: Uuid,
+    pub uploaded: usize,
+    pub copied: usize,
+    pub deleted: Vec<String>,
+    pub unchanged: usize,
+    /// The repo's change sequence number after the commit.
+    pub seq: u64,
+}
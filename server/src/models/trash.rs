@@ -0,0 +1,86 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use super::file::FileMeta;
+use super::repo::RepoMeta;
+
+/// Why a file or repository was deleted. Each cause can be configured to
+/// bypass the trash via `TRASH_SKIP_CAUSES`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DeleteCause {
+    /// An explicit API request.
+    User,
+    /// Freed to make room under a repo quota or the server budget.
+    Eviction,
+    /// A file or repository TTL ran out.
+    Expiry,
+}
+
+impl DeleteCause {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DeleteCause::User => "user",
+            DeleteCause::Eviction => "eviction",
+            DeleteCause::Expiry => "expiry",
+        }
+    }
+}
+
+/// A deleted file or repository held until `purge_at`. The content lives
+/// under `data_dir/trash/<id>`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TrashEntry {
+    pub id: Uuid,
+    pub repo_id: Uuid,
+    pub cause: DeleteCause,
+    pub deleted_at: DateTime<Utc>,
+    pub purge_at: DateTime<Utc>,
+    pub size_bytes: u64,
+    pub item: TrashedItem,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TrashedItem {
+    File {
+        file: FileMeta,
+    },
+    Repo {
+        repo: Box<RepoMeta>,
+        files: Vec<FileMeta>,
+    },
+}
+
+impl TrashedItem {
+    /// When the file or repository was due to expire before it was deleted.
+    pub fn expires_at(&self) -> Option<DateTime<Utc>> {
+        match self {
+            TrashedItem::File { file } => file.expires_at,
+            TrashedItem::Repo { repo, .. } => repo.expires_at,
+        }
+    }
+
+    /// Give the item a new expiry to restore it with.
+    pub fn set_expiry(&mut self, ttl_seconds: u64, expires_at: DateTime<Utc>) {
+        match self {
+            TrashedItem::File { file } => file.expires_at = Some(expires_at),
+            TrashedItem::Repo { repo, .. } => {
+                repo.ttl_seconds = Some(ttl_seconds);
+                repo.expires_at = Some(expires_at);
+            }
+        }
+    }
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct RestoreTrashRequest {
+    /// A new TTL, required when the item's expiry has already passed.
+    pub ttl_seconds: Option<u64>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ListTrashQuery {
+    pub repo_id: Option<Uuid>,
+}
@@ -0,0 +1,96 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+/// Event name sent when a repo's usage rises past a configured percentage
+/// of its quota.
+pub const QUOTA_THRESHOLD_EVENT: &str = "quota_threshold_crossed";
+
+/// A registered webhook. Stored with its secret in
+/// `data_dir/webhooks/hooks.json`; the API only returns the secret on
+/// creation.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Webhook {
+    pub id: Uuid,
+    /// Limits the hook to one repository; `None` receives every repo.
+    pub repo_id: Option<Uuid>,
+    pub url: String,
+    pub secret: String,
+    /// Event names to deliver; empty means all.
+    pub events: Vec<String>,
+    pub created_at: DateTime<Utc>,
+}
+
+impl Webhook {
+    pub fn wants(&self, repo_id: Uuid, event: &str) -> bool {
+        self.repo_id.is_none_or(|id| id == repo_id)
+            && (self.events.is_empty() || self.events.iter().any(|e| e == event))
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct WebhookInfo {
+    pub id: Uuid,
+    pub repo_id: Option<Uuid>,
+    pub url: String,
+    pub events: Vec<String>,
+    pub created_at: DateTime<Utc>,
+}
+
+impl From<&Webhook> for WebhookInfo {
+    fn from(hook: &Webhook) -> Self {
+        Self {
+            id: hook.id,
+            repo_id: hook.repo_id,
+            url: hook.url.clone(),
+            events: hook.events.clone(),
+            created_at: hook.created_at,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CreateWebhookRequest {
+    pub url: String,
+    pub repo_id: Option<Uuid>,
+    pub events: Option<Vec<String>>,
+    /// Generated when omitted.
+    pub secret: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DeliveryStatus {
+    /// Waiting for its first or next attempt.
+    Pending,
+    /// Gave up after `webhook_max_attempts`; can be retried by hand until
+    /// `webhook_failed_retention_secs` passes.
+    Failed,
+}
+
+/// One queued webhook call, persisted as
+/// `data_dir/webhooks/queue/<id>.json` until it succeeds.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Delivery {
+    pub id: Uuid,
+    pub webhook_id: Uuid,
+    pub event: String,
+    pub payload: serde_json::Value,
+    pub status: DeliveryStatus,
+    pub attempts: u32,
+    pub created_at: DateTime<Utc>,
+    pub next_attempt_at: DateTime<Utc>,
+    pub last_status: Option<u16>,
+    pub last_error: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ListDeliveriesQuery {
+    pub status: Option<DeliveryStatus>,
+    pub webhook_id: Option<Uuid>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ListWebhooksQuery {
+    pub repo_id: Option<Uuid>,
+}
@@ -0,0 +1,254 @@
+use crate::models::change::ChangeRecord;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::fs::File;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use uuid::Uuid;
+
+/// Per-repo change logs under `metadata/changes/<repo_id>.log`, framed
+/// like the WAL (u32 length prefix + bincode). Records are also kept in
+/// memory for queries. Unlike the WAL this is never truncated by
+/// snapshots; only `prune` drops records.
+///
+/// Records are written as they are appended, before the change is
+/// published, so a sequence number anyone has seen survives a crash as
+/// far as the WAL entry behind it does. A repo whose log failed to write
+/// is marked broken until a full rewrite succeeds; its followers are
+/// told to resync meanwhile.
+pub struct ChangeLog {
+    dir: PathBuf,
+    repos: HashMap<Uuid, RepoLog>,
+    files: HashMap<Uuid, File>,
+    broken: HashSet<Uuid>,
+}
+
+#[derive(Default)]
+struct RepoLog {
+    records: VecDeque<ChangeRecord>,
+}
+
+impl ChangeLog {
+    pub fn new(dir: &Path) -> Self {
+        Self {
+            dir: dir.to_path_buf(),
+            repos: HashMap::new(),
+            files: HashMap::new(),
+            broken: HashSet::new(),
+        }
+    }
+
+    /// Read every log in the directory. Returns the number of repos loaded.
+    pub fn load(&mut self) -> anyhow::Result<usize> {
+        if !self.dir.exists() {
+            return Ok(0);
+        }
+        for entry in std::fs::read_dir(&self.dir)? {
+            let path = entry?.path();
+            let Some(repo_id) = path
+                .file_name()
+                .and_then(|n| n.to_str())
+                .and_then(|n| n.strip_suffix(".log"))
+                .and_then(|n| Uuid::parse_str(n).ok())
+            else {
+                continue;
+            };
+            let records = read_records(&path)?;
+            self.repos.insert(
+                repo_id,
+                RepoLog {
+                    records: records.into(),
+                },
+            );
+        }
+        Ok(self.repos.len())
+    }
+
+    /// Write `record` to the repo's log. A repo whose log is broken gets
+    /// its whole log rewritten instead, which clears the mark once it
+    /// lands.
+    pub fn append(&mut self, repo_id: Uuid, record: ChangeRecord) -> anyhow::Result<()> {
+        let data = frame(&bincode::serialize(&record)?);
+        // Keep the record in memory even if the write fails, so sequence
+        // numbers are never reused in this run
+        self.repos
+            .entry(repo_id)
+            .or_default()
+            .records
+            .push_back(record);
+        if self.broken.contains(&repo_id) {
+            return self.rewrite(repo_id);
+        }
+        let result = self.write_append(repo_id, &data);
+        self.check(repo_id, result)
+    }
+
+    fn write_append(&mut self, repo_id: Uuid, data: &[u8]) -> std::io::Result<()> {
+        let file = match self.files.entry(repo_id) {
+            std::collections::hash_map::Entry::Occupied(e) => e.into_mut(),
+            std::collections::hash_map::Entry::Vacant(e) => {
+                std::fs::create_dir_all(&self.dir)?;
+                e.insert(
+                    std::fs::OpenOptions::new()
+                        .create(true)
+                        .append(true)
+                        .open(log_path(&self.dir, repo_id))?,
+                )
+            }
+        };
+        file.write_all(data)?;
+        file.flush()
+    }
+
+    /// Replace the repo's log file with its in-memory records.
+    fn rewrite(&mut self, repo_id: Uuid) -> anyhow::Result<()> {
+        self.files.remove(&repo_id);
+        let data = match self.repos.get(&repo_id) {
+            Some(log) => encode_records(&log.records)?,
+            None => return Ok(()),
+        };
+        let path = log_path(&self.dir, repo_id);
+        let tmp_path = path.with_extension("log.tmp");
+        let result = std::fs::create_dir_all(&self.dir)
+            .and_then(|_| {
+                let mut file = File::create(&tmp_path)?;
+                file.write_all(&data)?;
+                file.sync_data()
+            })
+            .and_then(|_| std::fs::rename(&tmp_path, &path));
+        if result.is_ok() {
+            self.broken.remove(&repo_id);
+        }
+        self.check(repo_id, result)
+    }
+
+    /// Mark the repo broken if a write failed; its file may now end in a
+    /// torn record, so nothing more is appended to it.
+    fn check(&mut self, repo_id: Uuid, result: std::io::Result<()>) -> anyhow::Result<()> {
+        if let Err(e) = result {
+            self.files.remove(&repo_id);
+            self.broken.insert(repo_id);
+            return Err(e.into());
+        }
+        Ok(())
+    }
+
+    /// Whether writes to the repo's log have failed since it was last
+    /// written in full, so the file may be missing records.
+    pub fn is_broken(&self, repo_id: Uuid) -> bool {
+        self.broken.contains(&repo_id)
+    }
+
+    /// Sync every log written to so far to disk.
+    pub fn sync(&self) -> std::io::Result<()> {
+        for file in self.files.values() {
+            file.sync_data()?;
+        }
+        Ok(())
+    }
+
+    pub fn records(&self, repo_id: Uuid) -> Option<&VecDeque<ChangeRecord>> {
+        self.repos.get(&repo_id).map(|log| &log.records)
+    }
+
+    pub fn latest_seq(&self, repo_id: Uuid) -> u64 {
+        self.repos
+            .get(&repo_id)
+            .and_then(|log| log.records.back())
+            .map(|r| r.seq)
+            .unwrap_or(0)
+    }
+
+    pub fn repo_ids(&self) -> Vec<Uuid> {
+        self.repos.keys().copied().collect()
+    }
+
+    /// Drop records before the first one `keep` accepts, always keeping the
+    /// newest so the sequence survives a restart. Returns how many were
+    /// dropped.
+    pub fn prune(
+        &mut self,
+        repo_id: Uuid,
+        keep: impl Fn(&ChangeRecord) -> bool,
+    ) -> anyhow::Result<usize> {
+        let Some(log) = self.repos.get_mut(&repo_id) else {
+            return Ok(0);
+        };
+        let mut dropped = 0;
+        while log.records.len() > 1 && !keep(&log.records[0]) {
+            log.records.pop_front();
+            dropped += 1;
+        }
+        if dropped > 0 {
+            self.rewrite(repo_id)?;
+        }
+        Ok(dropped)
+    }
+
+    /// Delete a repository's log entirely.
+    pub fn remove(&mut self, repo_id: Uuid) -> anyhow::Result<()> {
+        self.repos.remove(&repo_id);
+        self.files.remove(&repo_id);
+        self.broken.remove(&repo_id);
+        match std::fs::remove_file(log_path(&self.dir, repo_id)) {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e.into()),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Read a log, cutting the file back to its last whole record so later
+/// appends do not land behind a torn or corrupt one.
+fn read_records(path: &Path) -> anyhow::Result<Vec<ChangeRecord>> {
+    let data = std::fs::read(path)?;
+    let mut records = Vec::new();
+    let mut cursor = 0;
+    let mut good = 0;
+    while cursor + 4 <= data.len() {
+        let len = u32::from_le_bytes(data[cursor..cursor + 4].try_into().unwrap()) as usize;
+        cursor += 4;
+        if cursor + len > data.len() {
+            tracing::warn!(path = %path.display(), "Change log truncated, stopping read");
+            break;
+        }
+        match bincode::deserialize::<ChangeRecord>(&data[cursor..cursor + len]) {
+            Ok(record) => records.push(record),
+            Err(e) => {
+                tracing::warn!(path = %path.display(), "Change log record corrupt: {}", e);
+                break;
+            }
+        }
+        cursor += len;
+        good = cursor;
+    }
+    if good < data.len() {
+        tracing::warn!(
+            path = %path.display(),
+            dropped_bytes = data.len() - good,
+            "Truncating change log to its last whole record"
+        );
+        std::fs::OpenOptions::new()
+            .write(true)
+            .open(path)?
+            .set_len(good as u64)?;
+    }
+    Ok(records)
+}
+
+fn frame(data: &[u8]) -> Vec<u8> {
+    let mut framed = Vec::with_capacity(4 + data.len());
+    framed.extend_from_slice(&(data.len() as u32).to_le_bytes());
+    framed.extend_from_slice(data);
+    framed
+}
+
+fn encode_records(records: &VecDeque<ChangeRecord>) -> anyhow::Result<Vec<u8>> {
+    let mut data = Vec::new();
+    for record in records {
+        data.extend(frame(&bincode::serialize(record)?));
+    }
+    Ok(data)
+}
+
+fn log_path(dir: &Path, repo_id: Uuid) -> PathBuf {
+    dir.join(format!("{}.log", repo_id))
+}
@@ -1,2 +1,4 @@
+pub mod change_log;
+pub mod scrub;
 pub mod snapshot;
 pub mod wal;
@@ -0,0 +1,24 @@
+use crate::models::scrub::ScrubProgress;
+use std::path::Path;
+
+pub fn save_progress(path: &Path, progress: &ScrubProgress) -> anyhow::Result<()> {
+    let tmp_path = path.with_extension("bin.tmp");
+    let data = bincode::serialize(progress)?;
+    std::fs::write(&tmp_path, &data)?;
+    std::fs::rename(&tmp_path, path)?;
+    Ok(())
+}
+
+pub fn load_progress(path: &Path) -> anyhow::Result<Option<ScrubProgress>> {
+    if !path.exists() {
+        return Ok(None);
+    }
+    let data = std::fs::read(path)?;
+    match bincode::deserialize::<ScrubProgress>(&data) {
+        Ok(progress) => Ok(Some(progress)),
+        Err(e) => {
+            tracing::error!("Failed to deserialize scrub progress: {}", e);
+            Ok(None)
+        }
+    }
+}
@@ -1,4 +1,5 @@
-use crate::models::snapshot::{MetadataSnapshot, SNAPSHOT_VERSION};
+use crate::models::snapshot::{v1, v10, MetadataSnapshot, SNAPSHOT_VERSION};
+use anyhow::Context;
 use std::path::Path;
 
 pub fn save_snapshot(path: &Path, snapshot: &MetadataSnapshot) -> anyhow::Result<()> {
@@ -9,26 +10,40 @@ pub fn save_snapshot(path: &Path, snapshot: &MetadataSnapshot) -> anyhow::Result
     Ok(())
 }
 
+/// Load the snapshot, migrating older layouts. A snapshot that cannot be
+/// read is an error: the WAL was truncated when it was written, so
+/// starting without it would lose every repo and file.
 pub fn load_snapshot(path: &Path) -> anyhow::Result<Option<MetadataSnapshot>> {
     if !path.exists() {
         return Ok(None);
     }
     let data = std::fs::read(path)?;
-    match bincode::deserialize::<MetadataSnapshot>(&data) {
-        Ok(snapshot) => {
-            if snapshot.version != SNAPSHOT_VERSION {
-                tracing::warn!(
-                    "Snapshot version mismatch: expected {}, got {}",
-                    SNAPSHOT_VERSION,
-                    snapshot.version
-                );
-                return Ok(None);
-            }
-            Ok(Some(snapshot))
+    // Every layout starts with its version
+    let version: u32 = bincode::deserialize(&data).context("reading snapshot version")?;
+    let snapshot = match version {
+        SNAPSHOT_VERSION => {
+            bincode::deserialize::<MetadataSnapshot>(&data).context("decoding snapshot")?
         }
-        Err(e) => {
-            tracing::error!("Failed to deserialize snapshot: {}", e);
-            Ok(None)
+        v10::VERSION => {
+            tracing::info!("Migrating version {} snapshot", version);
+            bincode::deserialize::<v10::MetadataSnapshot>(&data)
+                .context("decoding version 10 snapshot")?
+                .into()
         }
-    }
+        v1::VERSION => {
+            tracing::info!("Migrating version {} snapshot", version);
+            bincode::deserialize::<v1::MetadataSnapshot>(&data)
+                .context("decoding version 1 snapshot")?
+                .into()
+        }
+        other => anyhow::bail!(
+            "{} has snapshot version {}, which this server cannot read (it reads {}, {} and {})",
+            path.display(),
+            other,
+            v1::VERSION,
+            v10::VERSION,
+            SNAPSHOT_VERSION
+        ),
+    };
+    Ok(Some(snapshot))
 }
@@ -1,3 +1,7 @@
+use crate::models::checkpoint::Checkpoint;
+use crate::models::file::FileMeta;
+use crate::models::repo::{EvictionPolicy, ExecLimits, RepoMeta};
+use crate::models::trash::TrashEntry;
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
@@ -49,6 +53,95 @@ pub enum WalEntry {
         destination: String,
         updated_at: DateTime<Utc>,
     },
+    RepoForked {
+        id: Uuid,
+        source_id: Uuid,
+        name: String,
+        max_size_bytes: u64,
+        default_ttl_seconds: Option<u64>,
+        tags: HashMap<String, String>,
+        created_at: DateTime<Utc>,
+    },
+    AccessRecorded {
+        repo_id: Uuid,
+        last_accessed_at: DateTime<Utc>,
+        /// `(path, access_count, last_accessed_at)` for files read since
+        /// the previous flush.
+        files: Vec<(String, u64, DateTime<Utc>)>,
+    },
+    RepoExpiryChanged {
+        id: Uuid,
+        ttl_seconds: Option<u64>,
+        idle_ttl_seconds: Option<u64>,
+        expires_at: Option<DateTime<Utc>>,
+    },
+    RepoEvictionPolicyChanged {
+        id: Uuid,
+        eviction_policy: EvictionPolicy,
+    },
+    FilePinned {
+        repo_id: Uuid,
+        path: String,
+        pinned: bool,
+    },
+    RepoPinsChanged {
+        id: Uuid,
+        pinned_patterns: Vec<String>,
+    },
+    RepoEvictionWeightChanged {
+        id: Uuid,
+        eviction_weight: u32,
+    },
+    /// A file or repository was moved into the trash; replaces
+    /// `FileDeleted`/`RepoDeleted` for soft deletes.
+    Trashed {
+        entry: Box<TrashEntry>,
+    },
+    TrashRestored {
+        id: Uuid,
+    },
+    TrashPurged {
+        id: Uuid,
+    },
+    /// A sync plan applied as a unit: `deleted` paths are removed
+    /// outright, `trashed` ones moved to the trash, then `files` written.
+    SyncCommitted {
+        repo_id: Uuid,
+        files: Vec<FileMeta>,
+        deleted: Vec<String>,
+        trashed: Vec<TrashEntry>,
+    },
+    RepoExecLimitsChanged {
+        id: Uuid,
+        exec_limits: ExecLimits,
+    },
+    RepoCommandProfileChanged {
+        id: Uuid,
+        command_profile: Option<String>,
+    },
+    /// `TrashRestored` with a new expiry, for items whose own had passed.
+    TrashRestoredWithExpiry {
+        id: Uuid,
+        ttl_seconds: u64,
+        expires_at: DateTime<Utc>,
+    },
+    CheckpointCreated {
+        checkpoint: Box<Checkpoint>,
+    },
+    CheckpointDeleted {
+        id: Uuid,
+    },
+    /// A new repository with all its settings; replaces `RepoCreated`
+    /// and the settings entries that followed it.
+    RepoCreatedWithSettings {
+        repo: Box<RepoMeta>,
+    },
+    /// A fork and the files cloned into it, recorded as a unit; replaces
+    /// `RepoForked` and the per-file entries that followed it.
+    RepoForkedWithFiles {
+        repo: Box<RepoMeta>,
+        files: Vec<FileMeta>,
+    },
 }
 
 pub struct WalWriter {
@@ -0,0 +1,43 @@
+use axum::extract::{Path, Query, State};
+use axum::http::StatusCode;
+use axum::Json;
+use serde_json::{json, Value};
+use uuid::Uuid;
+
+use crate::error::AppError;
+use crate::models::webhook::ListDeliveriesQuery;
+use crate::services::{scrub_service, webhook_service};
+use crate::state::AppState;
+
+pub async fn scrub_status(State(state): State<AppState>) -> Json<Value> {
+    let progress = state.scrub.read().await.clone();
+    Json(json!({ "data": progress, "error": null }))
+}
+
+pub async fn start_scrub(State(state): State<AppState>) -> (StatusCode, Json<Value>) {
+    let progress = scrub_service::request_pass(&state).await;
+    tracing::info!("Integrity scrub requested");
+
+    (
+        StatusCode::ACCEPTED,
+        Json(json!({ "data": progress, "error": null })),
+    )
+}
+
+pub async fn list_deliveries(
+    State(state): State<AppState>,
+    Query(query): Query<ListDeliveriesQuery>,
+) -> Json<Value> {
+    let deliveries = webhook_service::list_deliveries(&state, &query);
+    Json(json!({ "data": deliveries, "error": null }))
+}
+
+pub async fn retry_delivery(
+    State(state): State<AppState>,
+    Path(delivery_id): Path<Uuid>,
+) -> Result<Json<Value>, AppError> {
+    let delivery = webhook_service::retry(&state, delivery_id)?;
+    tracing::info!(delivery_id = %delivery_id, "Webhook delivery retry requested");
+
+    Ok(Json(json!({ "data": delivery, "error": null })))
+}
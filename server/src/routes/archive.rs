@@ -9,6 +9,7 @@ use uuid::Uuid;
 
 use crate::error::AppError;
 use crate::sandbox::path_validator;
+use crate::services::access_service;
 use crate::state::AppState;
 
 #[derive(Debug, Deserialize)]
@@ -28,6 +29,7 @@ pub async fn create_archive(
             repo_id
         )));
     }
+    access_service::touch_repo(&state, repo_id);
 
     let format = req.format.unwrap_or_else(|| "tar.gz".into());
     if format != "tar.gz" {
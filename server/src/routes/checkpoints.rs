@@ -0,0 +1,44 @@
+use axum::extract::{Path, State};
+use axum::http::StatusCode;
+use axum::Json;
+use serde_json::{json, Value};
+use uuid::Uuid;
+
+use crate::error::AppError;
+use crate::models::checkpoint::CreateCheckpointRequest;
+use crate::services::checkpoint_service;
+use crate::state::AppState;
+
+pub async fn create_checkpoint(
+    State(state): State<AppState>,
+    Path(repo_id): Path<Uuid>,
+    body: Option<Json<CreateCheckpointRequest>>,
+) -> Result<(StatusCode, Json<Value>), AppError> {
+    let req = body.map(|Json(r)| r).unwrap_or_default();
+    let checkpoint = checkpoint_service::create(&state, repo_id, req).await?;
+    tracing::info!(repo_id = %repo_id, checkpoint_id = %checkpoint.id, "Checkpoint created");
+
+    Ok((
+        StatusCode::CREATED,
+        Json(json!({ "data": checkpoint, "error": null })),
+    ))
+}
+
+pub async fn list_checkpoints(
+    State(state): State<AppState>,
+    Path(repo_id): Path<Uuid>,
+) -> Result<Json<Value>, AppError> {
+    let checkpoints = checkpoint_service::list(&state, repo_id)?;
+
+    Ok(Json(json!({ "data": checkpoints, "error": null })))
+}
+
+pub async fn delete_checkpoint(
+    State(state): State<AppState>,
+    Path((repo_id, checkpoint_id)): Path<(Uuid, Uuid)>,
+) -> Result<StatusCode, AppError> {
+    checkpoint_service::delete(&state, repo_id, checkpoint_id).await?;
+    tracing::info!(repo_id = %repo_id, checkpoint_id = %checkpoint_id, "Checkpoint deleted");
+
+    Ok(StatusCode::NO_CONTENT)
+}
@@ -0,0 +1,19 @@
+use axum::extract::{Path, Query, State};
+use axum::Json;
+use serde_json::{json, Value};
+use uuid::Uuid;
+
+use crate::error::AppError;
+use crate::models::diff::DiffQuery;
+use crate::services::diff_service;
+use crate::state::AppState;
+
+pub async fn diff_repo(
+    State(state): State<AppState>,
+    Path(repo_id): Path<Uuid>,
+    Query(query): Query<DiffQuery>,
+) -> Result<Json<Value>, AppError> {
+    let diff = diff_service::diff_repo(&state, repo_id, query).await?;
+
+    Ok(Json(json!({ "data": diff, "error": null })))
+}
@@ -0,0 +1,120 @@
+use std::collections::VecDeque;
+use std::time::Duration;
+
+use axum::extract::{Path, Query, State};
+use axum::http::HeaderMap;
+use axum::response::sse::{Event, KeepAlive, Sse};
+use futures_util::stream::{self, Stream};
+use serde_json::json;
+use tokio::sync::broadcast;
+use uuid::Uuid;
+
+use crate::error::AppError;
+use crate::models::event::{ChangeEvent, EventCursor, SubscribeQuery};
+use crate::services::event_service::Replay;
+use crate::state::AppState;
+
+struct Feed {
+    repo_id: Uuid,
+    prefix: Option<String>,
+    backlog: VecDeque<ChangeEvent>,
+    resync: bool,
+    receiver: broadcast::Receiver<ChangeEvent>,
+    done: bool,
+}
+
+impl Feed {
+    fn wants(&self, event: &ChangeEvent) -> bool {
+        event.repo_id == self.repo_id
+            && self
+                .prefix
+                .as_deref()
+                .is_none_or(|p| event.matches_prefix(p))
+    }
+
+    async fn next_event(&mut self) -> Option<Result<Event, axum::Error>> {
+        if self.done {
+            return None;
+        }
+        if self.resync {
+            self.resync = false;
+            return Some(resync_event(self.repo_id));
+        }
+        loop {
+            let event = match self.backlog.pop_front() {
+                Some(event) => event,
+                None => match self.receiver.recv().await {
+                    Ok(event) => event,
+                    Err(broadcast::error::RecvError::Lagged(skipped)) => {
+                        tracing::warn!(repo_id = %self.repo_id, skipped, "Change feed subscriber lagged");
+                        return Some(resync_event(self.repo_id));
+                    }
+                    Err(broadcast::error::RecvError::Closed) => return None,
+                },
+            };
+            if !self.wants(&event) {
+                continue;
+            }
+            // Nothing follows a repo delete
+            self.done = event.kind.ends_repo();
+            return Some(
+                Event::default()
+                    .id(event.cursor().to_string())
+                    .event(event.kind.as_str())
+                    .json_data(&event),
+            );
+        }
+    }
+}
+
+/// Tells the client that events were missed and it should relist.
+fn resync_event(repo_id: Uuid) -> Result<Event, axum::Error> {
+    Event::default()
+        .event("resync")
+        .json_data(json!({ "repo_id": repo_id }))
+}
+
+pub async fn subscribe(
+    State(state): State<AppState>,
+    Path(repo_id): Path<Uuid>,
+    Query(query): Query<SubscribeQuery>,
+    headers: HeaderMap,
+) -> Result<Sse<impl Stream<Item = Result<Event, axum::Error>>>, AppError> {
+    if !state.repos.contains_key(&repo_id) {
+        return Err(AppError::NotFound(format!(
+            "Repository {} not found",
+            repo_id
+        )));
+    }
+
+    let last_event_id = match headers.get("last-event-id") {
+        // Not visible ASCII, so it fails to parse below
+        Some(value) => Some(value.to_str().unwrap_or_default()),
+        None => query.last_event_id.as_deref(),
+    };
+    let last_event_id = last_event_id
+        .map(|v| {
+            v.parse::<EventCursor>()
+                .map_err(|()| AppError::BadRequest("Invalid Last-Event-ID".into()))
+        })
+        .transpose()?;
+
+    let (replay, receiver) = state.events.subscribe(last_event_id);
+    let (backlog, resync) = match replay {
+        Replay::Events(events) => (events.into(), false),
+        Replay::Gap => (VecDeque::new(), true),
+    };
+    let feed = Feed {
+        repo_id,
+        prefix: query.prefix,
+        backlog,
+        resync,
+        receiver,
+        done: false,
+    };
+
+    let stream = stream::unfold(feed, |mut feed| async move {
+        feed.next_event().await.map(|event| (event, feed))
+    });
+    Ok(Sse::new(stream).keep_alive(KeepAlive::new().interval(Duration::from_secs(15))))
+}
@@ -8,9 +8,11 @@ use tokio_util::io::ReaderStream;
 use uuid::Uuid;
 
 use crate::error::AppError;
-use crate::models::file::{CopyFileRequest, ListFilesQuery, MoveFileRequest};
+use crate::models::trash::DeleteCause;
+use crate::models::delta::{DeltaQuery, SignatureQuery};
+use crate::models::file::{CopyFileRequest, ListFilesQuery, MoveFileRequest, PinFileRequest};
 use crate::sandbox::path_validator;
-use crate::services::file_service;
+use crate::services::{access_service, delta_service, file_service, pin_service};
 use crate::state::AppState;
 
 pub async fn upload_file(
@@ -26,22 +28,26 @@ pub async fn upload_file(
         .and_then(|v| v.to_str().ok())
         .and_then(|v| v.parse().ok());
 
-    let meta = file_service::upload_file(&state, repo_id, &rel_path, body, ttl).await?;
+    let result = file_service::upload_file(&state, repo_id, &rel_path, body, ttl).await?;
 
     tracing::info!(
         repo_id = %repo_id,
         path = %rel_path,
-        size = meta.size_bytes,
+        size = result.file.size_bytes,
+        evicted = result.evicted.len(),
         "File uploaded"
     );
 
     let mut resp_headers = HeaderMap::new();
-    resp_headers.insert("ETag", format!("\"{}\"", meta.etag).parse().unwrap());
+    resp_headers.insert(
+        "ETag",
+        format!("\"{}\"", result.file.etag).parse().unwrap(),
+    );
 
     Ok((
         StatusCode::CREATED,
         resp_headers,
-        Json(json!({ "data": meta, "error": null })),
+        Json(json!({ "data": result, "error": null })),
     ))
 }
 
@@ -113,7 +119,8 @@ pub async fn delete_file(
     Path((repo_id, file_path)): Path<(Uuid, String)>,
 ) -> Result<StatusCode, AppError> {
     let rel_path = path_validator::validate_relative_path(&file_path)?;
-    file_service::delete_file(&state, repo_id, &rel_path).await?;
+    file_service::delete_file(&state, repo_id, &rel_path, DeleteCause::User).await?;
+    access_service::touch_repo(&state, repo_id);
     tracing::info!(repo_id = %repo_id, path = %rel_path, "File deleted");
     Ok(StatusCode::NO_CONTENT)
 }
@@ -178,3 +185,67 @@ pub async fn copy_file(
 
     Ok(Json(json!({ "data": meta, "error": null })))
 }
+
+pub async fn pin_file(
+    State(state): State<AppState>,
+    Path(repo_id): Path<Uuid>,
+    Json(req): Json<PinFileRequest>,
+) -> Result<Json<Value>, AppError> {
+    let path = path_validator::validate_relative_path(&req.path)?;
+    let pinned = req.pinned.unwrap_or(true);
+
+    let meta = pin_service::pin_file(&state, repo_id, &path, pinned).await?;
+    access_service::touch_repo(&state, repo_id);
+    tracing::info!(repo_id = %repo_id, path = %path, pinned, "File pin updated");
+
+    Ok(Json(json!({ "data": meta, "error": null })))
+}
+
+pub async fn file_signature(
+    State(state): State<AppState>,
+    Path(repo_id): Path<Uuid>,
+    Query(query): Query<SignatureQuery>,
+) -> Result<Json<Value>, AppError> {
+    let path = path_validator::validate_relative_path(&query.path)?;
+    let signature = delta_service::signature(&state, repo_id, &path, query.block_size).await?;
+
+    Ok(Json(json!({ "data": signature, "error": null })))
+}
+
+pub async fn upload_delta(
+    State(state): State<AppState>,
+    Path(repo_id): Path<Uuid>,
+    Query(query): Query<DeltaQuery>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Result<(StatusCode, HeaderMap, Json<Value>), AppError> {
+    let rel_path = path_validator::validate_relative_path(&query.path)?;
+
+    let ttl: Option<u64> = headers
+        .get("X-File-TTL")
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.parse().ok());
+
+    let result = delta_service::apply_delta(&state, repo_id, &rel_path, query, body, ttl).await?;
+
+    tracing::info!(
+        repo_id = %repo_id,
+        path = %rel_path,
+        size = result.upload.file.size_bytes,
+        copied = result.copied_bytes,
+        literal = result.literal_bytes,
+        "File updated from delta"
+    );
+
+    let mut resp_headers = HeaderMap::new();
+    resp_headers.insert(
+        "ETag",
+        format!("\"{}\"", result.upload.file.etag).parse().unwrap(),
+    );
+
+    Ok((
+        StatusCode::OK,
+        resp_headers,
+        Json(json!({ "data": result, "error": null })),
+    ))
+}
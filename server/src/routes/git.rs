@@ -0,0 +1,89 @@
+use axum::body::Body;
+use axum::extract::{Path, Query, State};
+use axum::http::StatusCode;
+use axum::Json;
+use bytes::Bytes;
+use serde_json::{json, Value};
+use uuid::Uuid;
+
+use crate::error::AppError;
+use crate::models::git::{
+    GitDiffQuery, GitExportRequest, GitImportQuery, GitLogQuery, GitShowQuery,
+};
+use crate::services::git_service;
+use crate::state::AppState;
+
+pub async fn import_bundle(
+    State(state): State<AppState>,
+    Path(repo_id): Path<Uuid>,
+    Query(query): Query<GitImportQuery>,
+    body: Bytes,
+) -> Result<Json<Value>, AppError> {
+    let result = git_service::import_bundle(&state, repo_id, body, query.git_ref).await?;
+    tracing::info!(
+        repo_id = %repo_id,
+        git_ref = %result.git_ref,
+        commit = %result.commit,
+        files = result.files_written,
+        "Git bundle imported"
+    );
+
+    Ok(Json(json!({ "data": result, "error": null })))
+}
+
+pub async fn export_bundle(
+    State(state): State<AppState>,
+    Path(repo_id): Path<Uuid>,
+    Json(req): Json<GitExportRequest>,
+) -> Result<axum::response::Response, AppError> {
+    let (commit, data) = git_service::export_bundle(&state, repo_id, req).await?;
+    tracing::info!(repo_id = %repo_id, commit = %commit, "Git bundle exported");
+
+    let response = axum::response::Response::builder()
+        .status(StatusCode::OK)
+        .header("Content-Type", "application/x-git-bundle")
+        .header("Content-Length", data.len().to_string())
+        .header("X-Git-Commit", commit)
+        .header(
+            "Content-Disposition",
+            format!("attachment; filename=\"{}.bundle\"", repo_id),
+        )
+        .body(Body::from(data))
+        .unwrap();
+
+    Ok(response)
+}
+
+pub async fn log(
+    State(state): State<AppState>,
+    Path(repo_id): Path<Uuid>,
+    Query(query): Query<GitLogQuery>,
+) -> Result<Json<Value>, AppError> {
+    let limit = query.limit.unwrap_or(50).clamp(1, 1000);
+    let commits = git_service::log(&state, repo_id, query.rev, limit).await?;
+
+    Ok(Json(json!({
+        "data": { "commits": commits },
+        "error": null
+    })))
+}
+
+pub async fn show(
+    State(state): State<AppState>,
+    Path(repo_id): Path<Uuid>,
+    Query(query): Query<GitShowQuery>,
+) -> Result<Json<Value>, AppError> {
+    let show = git_service::show(&state, repo_id, query.rev).await?;
+
+    Ok(Json(json!({ "data": show, "error": null })))
+}
+
+pub async fn diff(
+    State(state): State<AppState>,
+    Path(repo_id): Path<Uuid>,
+    Query(query): Query<GitDiffQuery>,
+) -> Result<Json<Value>, AppError> {
+    let diff = git_service::diff(&state, repo_id, query.from, query.to).await?;
+
+    Ok(Json(json!({ "data": diff, "error": null })))
+}
@@ -3,6 +3,8 @@ use axum::http::StatusCode;
 use axum::Json;
 use serde_json::{json, Value};
 
+use crate::sandbox::{cgroup, landlock, namespace, seccomp};
+use crate::services::{eviction_service, trash_service};
 use crate::state::AppState;
 
 pub async fn health() -> (StatusCode, Json<Value>) {
@@ -11,19 +13,37 @@ pub async fn health() -> (StatusCode, Json<Value>) {
 
 pub async fn status(State(state): State<AppState>) -> Json<Value> {
     let repo_count = state.repos.len();
-    let total_size: u64 = state
-        .repos
-        .iter()
-        .map(|r| r.value().current_size_bytes)
-        .sum();
+    let total_size = eviction_service::total_size_bytes(&state);
+    // A budget of 0 is unlimited
+    let budget = Some(state.config.cache_max_bytes).filter(|b| *b > 0);
+    let trash_size = trash_service::size_bytes(&state);
+    let scrub = state.scrub.read().await;
     let uptime = chrono::Utc::now()
         .signed_duration_since(state.start_time)
         .num_seconds();
+    let landlock_abi = landlock::abi_version();
 
     Json(json!({
         "data": {
             "repo_count": repo_count,
             "total_size_bytes": total_size,
+            "cache_max_bytes": budget,
+            "cache_available_bytes": budget.map(|b| b.saturating_sub(total_size)),
+            "cache_usage_ratio": budget.map(|b| total_size as f64 / b as f64),
+            "trash_entries": state.trash.len(),
+            "trash_size_bytes": trash_size,
+            "scrub_running": scrub.pass_started_at.is_some(),
+            "scrub_files_checked_total": scrub.files_checked_total,
+            "scrub_bytes_checked_total": scrub.bytes_checked_total,
+            "scrub_corrupted_total": scrub.corrupted_total,
+            "scrub_missing_total": scrub.missing_total,
+            "scrub_open_findings": scrub.findings.len(),
+            "scrub_last_pass_completed_at": scrub.last_pass_completed_at,
+            "sandbox_namespaces": state.config.sandbox_namespaces && namespace::supported(),
+            "sandbox_landlock": state.config.sandbox_landlock && landlock_abi.is_some(),
+            "sandbox_landlock_abi": landlock_abi,
+            "sandbox_seccomp": state.config.sandbox_seccomp && seccomp::supported(),
+            "sandbox_cgroups": state.config.sandbox_cgroups && cgroup::exec_root().is_some(),
             "uptime_seconds": uptime,
             "version": env!("CARGO_PKG_VERSION"),
         },
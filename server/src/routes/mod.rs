@@ -1,8 +1,16 @@
+pub mod admin;
 pub mod archive;
+pub mod checkpoints;
+pub mod diff;
+pub mod events;
 pub mod files;
+pub mod git;
 pub mod health;
 pub mod repos;
 pub mod shell;
+pub mod sync;
+pub mod trash;
+pub mod webhooks;
 
 use axum::routing::{delete, get, head, patch, post};
 use axum::Router;
@@ -31,6 +39,23 @@ pub fn build_router(state: AppState) -> Router {
         .route("/repos/{repo_id}", get(repos::get_repo))
         .route("/repos/{repo_id}", patch(repos::update_repo))
         .route("/repos/{repo_id}", delete(repos::delete_repo))
+        .route("/repos/{repo_id}/fork", post(repos::fork_repo))
+        .route("/repos/{repo_id}/renew", post(repos::renew_repo))
+        .route("/repos/{repo_id}/diff", get(diff::diff_repo))
+        .route(
+            "/repos/{repo_id}/checkpoints",
+            post(checkpoints::create_checkpoint),
+        )
+        .route(
+            "/repos/{repo_id}/checkpoints",
+            get(checkpoints::list_checkpoints),
+        )
+        .route(
+            "/repos/{repo_id}/checkpoints/{checkpoint_id}",
+            delete(checkpoints::delete_checkpoint),
+        )
+        .route("/repos/{repo_id}/events", get(events::subscribe))
+        .route("/repos/{repo_id}/changes", get(repos::list_changes))
         // Files
         .route("/repos/{repo_id}/files", get(files::list_files))
         .route(
@@ -51,10 +76,40 @@ pub fn build_router(state: AppState) -> Router {
         )
         .route("/repos/{repo_id}/files-move", post(files::move_file))
         .route("/repos/{repo_id}/files-copy", post(files::copy_file))
+        .route("/repos/{repo_id}/files-pin", post(files::pin_file))
+        .route("/repos/{repo_id}/files-signature", get(files::file_signature))
+        .route("/repos/{repo_id}/files-delta", post(files::upload_delta))
+        // Sync
+        .route("/repos/{repo_id}/sync/plan", post(sync::plan))
+        .route("/repos/{repo_id}/sync/commit", post(sync::commit))
         // Shell
         .route("/repos/{repo_id}/exec", post(shell::exec_command))
+        .route("/repos/{repo_id}/commands", get(shell::list_commands))
         // Archive
         .route("/repos/{repo_id}/archive", post(archive::create_archive))
+        // Git
+        .route("/repos/{repo_id}/git/import", post(git::import_bundle))
+        .route("/repos/{repo_id}/git/export", post(git::export_bundle))
+        .route("/repos/{repo_id}/git/log", get(git::log))
+        .route("/repos/{repo_id}/git/show", get(git::show))
+        .route("/repos/{repo_id}/git/diff", get(git::diff))
+        // Trash
+        .route("/repos/{repo_id}/trash", get(trash::list_repo_trash))
+        .route("/trash", get(trash::list_trash))
+        .route("/trash/{trash_id}", delete(trash::purge))
+        .route("/trash/{trash_id}/restore", post(trash::restore))
+        // Admin
+        .route("/admin/scrub", get(admin::scrub_status))
+        .route("/admin/scrub", post(admin::start_scrub))
+        .route("/admin/webhooks/deliveries", get(admin::list_deliveries))
+        .route(
+            "/admin/webhooks/deliveries/{delivery_id}/retry",
+            post(admin::retry_delivery),
+        )
+        // Webhooks
+        .route("/webhooks", post(webhooks::create_webhook))
+        .route("/webhooks", get(webhooks::list_webhooks))
+        .route("/webhooks/{webhook_id}", delete(webhooks::delete_webhook))
         .layer(ApiKeyLayer::new(api_key));
 
     // CORS
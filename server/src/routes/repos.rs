@@ -5,8 +5,12 @@ use serde_json::{json, Value};
 use uuid::Uuid;
 
 use crate::error::AppError;
-use crate::models::repo::{CreateRepoRequest, ListReposQuery, UpdateRepoRequest};
-use crate::services::repo_service;
+use crate::models::change::ChangesQuery;
+use crate::models::repo::{
+    CreateRepoRequest, ForkRepoRequest, ListReposQuery, RenewRepoRequest, UpdateRepoRequest,
+};
+use crate::models::trash::DeleteCause;
+use crate::services::{change_log_service, repo_service};
 use crate::state::AppState;
 
 pub async fn create_repo(
@@ -78,12 +82,52 @@ pub async fn update_repo(
     Ok(Json(json!({ "data": repo, "error": null })))
 }
 
+pub async fn fork_repo(
+    State(state): State<AppState>,
+    Path(repo_id): Path<Uuid>,
+    Json(req): Json<ForkRepoRequest>,
+) -> Result<(StatusCode, Json<Value>), AppError> {
+    if req.name.as_deref() == Some("") {
+        return Err(AppError::BadRequest("Name must not be empty".into()));
+    }
+
+    let repo = repo_service::fork_repo(&state, repo_id, req).await?;
+    tracing::info!(repo_id = %repo.id, source_id = %repo_id, "Repository forked");
+
+    Ok((
+        StatusCode::CREATED,
+        Json(json!({ "data": repo, "error": null })),
+    ))
+}
+
+pub async fn renew_repo(
+    State(state): State<AppState>,
+    Path(repo_id): Path<Uuid>,
+    body: Option<Json<RenewRepoRequest>>,
+) -> Result<Json<Value>, AppError> {
+    let req = body.map(|Json(r)| r).unwrap_or_default();
+    let repo = repo_service::renew_repo(&state, repo_id, req).await?;
+    tracing::info!(repo_id = %repo_id, expires_at = ?repo.expires_at, "Repository renewed");
+
+    Ok(Json(json!({ "data": repo, "error": null })))
+}
+
 pub async fn delete_repo(
     State(state): State<AppState>,
     Path(repo_id): Path<Uuid>,
 ) -> Result<StatusCode, AppError> {
-    repo_service::delete_repo(&state, repo_id).await?;
+    repo_service::delete_repo(&state, repo_id, DeleteCause::User).await?;
     tracing::info!(repo_id = %repo_id, "Repository deleted");
 
     Ok(StatusCode::NO_CONTENT)
 }
+
+pub async fn list_changes(
+    State(state): State<AppState>,
+    Path(repo_id): Path<Uuid>,
+    Query(query): Query<ChangesQuery>,
+) -> Result<Json<Value>, AppError> {
+    let page =
+        change_log_service::list_changes(&state, repo_id, query.since.unwrap_or(0), query.limit)?;
+    Ok(Json(json!({ "data": page, "error": null })))
+}
@@ -26,3 +26,15 @@ pub async fn exec_command(
         "error": null
     })))
 }
+
+pub async fn list_commands(
+    State(state): State<AppState>,
+    Path(repo_id): Path<Uuid>,
+) -> Result<Json<Value>, AppError> {
+    let commands = shell_service::list_commands(&state, repo_id).await?;
+
+    Ok(Json(json!({
+        "data": commands,
+        "error": null
+    })))
+}
@@ -0,0 +1,39 @@
+use axum::extract::{Path, Query, State};
+use axum::Json;
+use bytes::Bytes;
+use serde_json::{json, Value};
+use uuid::Uuid;
+
+use crate::error::AppError;
+use crate::models::sync::{SyncCommitQuery, SyncPlanRequest};
+use crate::services::sync_service;
+use crate::state::AppState;
+
+pub async fn plan(
+    State(state): State<AppState>,
+    Path(repo_id): Path<Uuid>,
+    Json(req): Json<SyncPlanRequest>,
+) -> Result<Json<Value>, AppError> {
+    let plan = sync_service::plan(&state, repo_id, req)?;
+    tracing::info!(
+        repo_id = %repo_id,
+        plan_id = %plan.plan_id,
+        upload = plan.upload.len(),
+        copy = plan.copy.len(),
+        delete = plan.delete.len(),
+        "Sync plan created"
+    );
+
+    Ok(Json(json!({ "data": plan, "error": null })))
+}
+
+/// The body is the content of the plan's `upload` items, back to back.
+pub async fn commit(
+    State(state): State<AppState>,
+    Path(repo_id): Path<Uuid>,
+    Query(query): Query<SyncCommitQuery>,
+    body: Bytes,
+) -> Result<Json<Value>, AppError> {
+    let result = sync_service::commit(&state, repo_id, query.plan_id, body).await?;
+    Ok(Json(json!({ "data": result, "error": null })))
+}
@@ -0,0 +1,48 @@
+use axum::extract::{Path, Query, State};
+use axum::http::StatusCode;
+use axum::Json;
+use serde_json::{json, Value};
+use uuid::Uuid;
+
+use crate::error::AppError;
+use crate::models::trash::{ListTrashQuery, RestoreTrashRequest};
+use crate::services::trash_service;
+use crate::state::AppState;
+
+pub async fn list_trash(
+    State(state): State<AppState>,
+    Query(query): Query<ListTrashQuery>,
+) -> Json<Value> {
+    let entries = trash_service::list(&state, query.repo_id);
+    Json(json!({ "data": entries, "error": null }))
+}
+
+pub async fn list_repo_trash(
+    State(state): State<AppState>,
+    Path(repo_id): Path<Uuid>,
+) -> Json<Value> {
+    let entries = trash_service::list(&state, Some(repo_id));
+    Json(json!({ "data": entries, "error": null }))
+}
+
+pub async fn restore(
+    State(state): State<AppState>,
+    Path(trash_id): Path<Uuid>,
+    body: Option<Json<RestoreTrashRequest>>,
+) -> Result<Json<Value>, AppError> {
+    let req = body.map(|Json(r)| r).unwrap_or_default();
+    let item = trash_service::restore(&state, trash_id, req).await?;
+    tracing::info!(trash_id = %trash_id, "Trash entry restored");
+
+    Ok(Json(json!({ "data": item, "error": null })))
+}
+
+pub async fn purge(
+    State(state): State<AppState>,
+    Path(trash_id): Path<Uuid>,
+) -> Result<StatusCode, AppError> {
+    trash_service::purge(&state, trash_id).await?;
+    tracing::info!(trash_id = %trash_id, "Trash entry purged");
+
+    Ok(StatusCode::NO_CONTENT)
+}
@@ -0,0 +1,45 @@
+use axum::extract::{Path, Query, State};
+use axum::http::StatusCode;
+use axum::Json;
+use serde_json::{json, Value};
+use uuid::Uuid;
+
+use crate::error::AppError;
+use crate::models::webhook::{CreateWebhookRequest, ListWebhooksQuery, WebhookInfo};
+use crate::services::webhook_service;
+use crate::state::AppState;
+
+/// The secret is only returned here, at creation.
+pub async fn create_webhook(
+    State(state): State<AppState>,
+    Json(req): Json<CreateWebhookRequest>,
+) -> Result<(StatusCode, Json<Value>), AppError> {
+    let hook = webhook_service::create(&state, req)?;
+    tracing::info!(webhook_id = %hook.id, url = %hook.url, "Webhook registered");
+
+    Ok((
+        StatusCode::CREATED,
+        Json(json!({ "data": hook, "error": null })),
+    ))
+}
+
+pub async fn list_webhooks(
+    State(state): State<AppState>,
+    Query(query): Query<ListWebhooksQuery>,
+) -> Json<Value> {
+    let hooks: Vec<WebhookInfo> = webhook_service::list(&state, query.repo_id)
+        .iter()
+        .map(WebhookInfo::from)
+        .collect();
+    Json(json!({ "data": hooks, "error": null }))
+}
+
+pub async fn delete_webhook(
+    State(state): State<AppState>,
+    Path(webhook_id): Path<Uuid>,
+) -> Result<StatusCode, AppError> {
+    webhook_service::delete(&state, webhook_id)?;
+    tracing::info!(webhook_id = %webhook_id, "Webhook deleted");
+
+    Ok(StatusCode::NO_CONTENT)
+}
@@ -0,0 +1,225 @@
+//! cgroup v2 resource limits and accounting for `exec`.
+//!
+//! At startup the server moves itself into a `server` leaf of its own
+//! cgroup and enables the memory, cpu and pids controllers for an `exec`
+//! sibling. Every command then gets a fresh child of `exec` with
+//! `memory.max`, `cpu.max` and `pids.max` set, joins it between fork and
+//! exec, and is read for usage and killed wholesale once it is done.
+//! Without a writable cgroup v2 tree commands run without limits.
+
+use crate::config::AppConfig;
+use crate::models::repo::ExecLimits;
+use std::io;
+use std::os::fd::{AsRawFd, RawFd};
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+use std::time::Duration;
+
+const CONTROLLERS: &[&str] = &["memory", "cpu", "pids"];
+const CPU_PERIOD_USEC: u64 = 100_000;
+const MIN_CPU_QUOTA_USEC: u64 = 1_000;
+
+static EXEC_ROOT: OnceLock<PathBuf> = OnceLock::new();
+
+/// The cgroup exec cgroups are created under, once [`init`] succeeded.
+pub fn exec_root() -> Option<&'static Path> {
+    EXEC_ROOT.get().map(PathBuf::as_path)
+}
+
+/// Take over the server's cgroup so commands can get children of their
+/// own. `mount` is where the cgroup v2 hierarchy is mounted.
+pub fn init(mount: &Path) -> io::Result<()> {
+    let own = own_cgroup(mount)?;
+    let available = std::fs::read_to_string(own.join("cgroup.controllers"))?;
+    if let Some(missing) = CONTROLLERS
+        .iter()
+        .find(|c| !available.split_whitespace().any(|a| a == **c))
+    {
+        return Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            format!("the {} controller is not available", missing),
+        ));
+    }
+
+    // A cgroup that hands controllers to children may not hold processes
+    // itself, so everything in it moves to a leaf first
+    let server = own.join("server");
+    create_dir(&server)?;
+    for pid in std::fs::read_to_string(own.join("cgroup.procs"))?.lines() {
+        // Processes may exit while we move them
+        let _ = std::fs::write(server.join("cgroup.procs"), pid);
+    }
+    let enable: Vec<String> = CONTROLLERS.iter().map(|c| format!("+{}", c)).collect();
+    std::fs::write(own.join("cgroup.subtree_control"), enable.join(" "))?;
+
+    let exec = own.join("exec");
+    create_dir(&exec)?;
+    std::fs::write(exec.join("cgroup.subtree_control"), enable.join(" "))?;
+    // Left over from a previous run
+    for entry in std::fs::read_dir(&exec)?.flatten() {
+        if entry.file_type().is_ok_and(|t| t.is_dir()) {
+            kill_and_remove(&entry.path());
+        }
+    }
+
+    let _ = EXEC_ROOT.set(exec);
+    Ok(())
+}
+
+fn own_cgroup(mount: &Path) -> io::Result<PathBuf> {
+    let membership = std::fs::read_to_string("/proc/self/cgroup")?;
+    let path = membership
+        .lines()
+        .find_map(|l| l.strip_prefix("0::"))
+        .ok_or_else(|| io::Error::new(io::ErrorKind::Unsupported, "not on cgroup v2"))?;
+    Ok(mount.join(path.trim_start_matches('/')))
+}
+
+fn create_dir(path: &Path) -> io::Result<()> {
+    match std::fs::create_dir(path) {
+        Err(e) if e.kind() != io::ErrorKind::AlreadyExists => Err(e),
+        _ => Ok(()),
+    }
+}
+
+/// Limits for one command; `None` is unlimited.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Limits {
+    pub memory_max_bytes: Option<u64>,
+    pub cpu_max_cores: Option<f64>,
+    pub pids_max: Option<u64>,
+}
+
+impl Limits {
+    /// The server defaults with a repo's overrides applied; 0 in either
+    /// removes the limit.
+    pub fn resolve(config: &AppConfig, repo: &ExecLimits) -> Self {
+        Self {
+            memory_max_bytes: Some(
+                repo.memory_max_bytes
+                    .unwrap_or(config.exec_memory_max_bytes),
+            )
+            .filter(|v| *v > 0),
+            cpu_max_cores: Some(repo.cpu_max_cores.unwrap_or(config.exec_cpu_max_cores))
+                .filter(|v| *v > 0.0),
+            pids_max: Some(repo.pids_max.unwrap_or(config.exec_pids_max)).filter(|v| *v > 0),
+        }
+    }
+}
+
+/// What a command used, read from its cgroup after it finished.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Usage {
+    /// `None` on kernels before 5.19, which do not track the peak.
+    pub memory_peak_bytes: Option<u64>,
+    pub cpu_time_ms: Option<u64>,
+    pub oom_killed: bool,
+}
+
+/// The cgroup of a single command.
+pub struct ExecCgroup {
+    path: PathBuf,
+    /// Opened before fork, so joining is a single write in the child.
+    procs: std::fs::File,
+}
+
+impl ExecCgroup {
+    /// Create a cgroup with `limits` under [`exec_root`], or `None` when
+    /// cgroups are not set up.
+    pub fn create(limits: &Limits) -> io::Result<Option<Self>> {
+        let Some(root) = exec_root() else {
+            return Ok(None);
+        };
+        let path = root.join(uuid::Uuid::new_v4().to_string());
+        std::fs::create_dir(&path)?;
+        let procs = std::fs::OpenOptions::new()
+            .write(true)
+            .open(path.join("cgroup.procs"));
+        let cgroup = match procs {
+            Ok(procs) => Self { path, procs },
+            Err(e) => {
+                let _ = std::fs::remove_dir(&path);
+                return Err(e);
+            }
+        };
+        if let Err(e) = cgroup.apply(limits) {
+            let _ = std::fs::remove_dir(&cgroup.path);
+            return Err(e);
+        }
+        Ok(Some(cgroup))
+    }
+
+    fn apply(&self, limits: &Limits) -> io::Result<()> {
+        let max = |v: Option<u64>| v.map_or_else(|| "max".to_string(), |v| v.to_string());
+        self.write("memory.max", &max(limits.memory_max_bytes))?;
+        if limits.memory_max_bytes.is_some() {
+            // Otherwise the limit only pushes the command into swap
+            let _ = self.write("memory.swap.max", "0");
+        }
+        let quota = limits
+            .cpu_max_cores
+            .map(|cores| ((cores * CPU_PERIOD_USEC as f64) as u64).max(MIN_CPU_QUOTA_USEC));
+        self.write("cpu.max", &format!("{} {}", max(quota), CPU_PERIOD_USEC))?;
+        self.write("pids.max", &max(limits.pids_max))
+    }
+
+    fn write(&self, file: &str, value: &str) -> io::Result<()> {
+        std::fs::write(self.path.join(file), value)
+    }
+
+    fn read(&self, file: &str) -> Option<String> {
+        std::fs::read_to_string(self.path.join(file)).ok()
+    }
+
+    /// The open `cgroup.procs`, for [`join`] in the child.
+    pub fn procs_fd(&self) -> RawFd {
+        self.procs.as_raw_fd()
+    }
+
+    pub fn usage(&self) -> Usage {
+        let stat = |file: &str, key: &str| -> Option<u64> {
+            self.read(file)?.lines().find_map(|l| {
+                let (k, v) = l.split_once(' ')?;
+                (k == key).then(|| v.trim().parse().ok()).flatten()
+            })
+        };
+        Usage {
+            memory_peak_bytes: self.read("memory.peak").and_then(|v| v.trim().parse().ok()),
+            cpu_time_ms: stat("cpu.stat", "usage_usec").map(|us| us / 1000),
+            oom_killed: stat("memory.events", "oom_kill").is_some_and(|n| n > 0),
+        }
+    }
+
+    /// Kill whatever the command left running and delete the cgroup.
+    pub async fn remove(self) {
+        let path = self.path;
+        let _ = tokio::task::spawn_blocking(move || kill_and_remove(&path)).await;
+    }
+}
+
+/// Move the calling process into the cgroup whose `cgroup.procs` is open
+/// as `procs`.
+///
+/// # Safety
+/// Must only be called between fork and exec.
+pub unsafe fn join(procs: RawFd) -> io::Result<()> {
+    // "0" is the writing process itself
+    if libc::write(procs, b"0".as_ptr().cast(), 1) != 1 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+fn kill_and_remove(path: &Path) {
+    let _ = std::fs::write(path.join("cgroup.kill"), "1");
+    // The kernel empties the cgroup asynchronously after the kill
+    for _ in 0..50 {
+        match std::fs::remove_dir(path) {
+            Err(e) if e.raw_os_error() == Some(libc::EBUSY) => {
+                std::thread::sleep(Duration::from_millis(20));
+            }
+            _ => return,
+        }
+    }
+    tracing::warn!(path = %path.display(), "exec cgroup still busy, leaving it");
+}
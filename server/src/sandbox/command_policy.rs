@@ -0,0 +1,642 @@
+//! Declarative argument policy for `exec`.
+//!
+//! Every allowed command has a rule saying which of its options are
+//! forbidden, which take a value, which take a path, and which make the
+//! command write to the repo. Path arguments must resolve inside the repo,
+//! and sed scripts and awk programs are scanned for the commands that run
+//! programs or write files.
+//!
+//! Rules are grouped into named profiles, and each repo picks one. The
+//! `default` profile holds every command in the top-level `commands`
+//! catalogue; other profiles `include` commands from the catalogue and may
+//! define their own, with tighter limits or fixed arguments. The built-in
+//! rules and profiles ship in `command_policy.json`; operators add or
+//! replace commands and profiles with a file of the same shape, where
+//! `null` removes a built-in entry.
+
+use crate::config::AppConfig;
+use crate::error::AppError;
+use crate::models::repo::ExecLimits;
+use crate::sandbox::path_validator;
+use anyhow::Context;
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::iter::Peekable;
+use std::path::{Path, PathBuf};
+use std::str::Chars;
+
+const BUILT_IN: &str = include_str!("command_policy.json");
+
+/// The profile of repos that do not choose one.
+pub const DEFAULT_PROFILE: &str = "default";
+
+/// Script files passed with a flag like `sed -f` are scanned too, up to
+/// this size.
+const MAX_SCRIPT_FILE_BYTES: u64 = 64 * 1024;
+
+/// Which operands name files.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Operands {
+    #[default]
+    Paths,
+    /// The first operand is a pattern or program unless a script flag
+    /// supplied it; the rest are paths.
+    ScriptThenPaths,
+    /// Paths up to the first option, then an expression (`find`).
+    LeadingPaths,
+    /// None of them.
+    Text,
+}
+
+/// Script languages whose programs are checked.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Script {
+    Sed,
+    Awk,
+}
+
+impl Script {
+    fn check(self, text: &str) -> Result<(), String> {
+        match self {
+            Script::Sed => check_sed(text),
+            Script::Awk => check_awk(text),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct CommandRule {
+    /// Every invocation writes to the repo.
+    pub mutating: bool,
+    /// The command runs arbitrary code or its arguments are not policed,
+    /// so only the kernel sandbox keeps it in the repo. It is refused
+    /// unless namespaces, Landlock and seccomp are all enforced.
+    pub requires_sandbox: bool,
+    pub operands: Operands,
+    /// Whether `-abc` means `-a -b -c`; off for tools like `find` whose
+    /// options are single-dash words.
+    pub short_clusters: bool,
+    /// When set, options outside this list and the lists below are
+    /// rejected.
+    pub allowed_flags: Option<Vec<String>>,
+    /// Option to the reason it is refused.
+    pub forbidden_flags: BTreeMap<String, String>,
+    /// Options taking a value.
+    pub value_flags: Vec<String>,
+    /// Options taking a path, which must resolve inside the repo.
+    pub path_flags: Vec<String>,
+    /// Options that make the command write to the repo.
+    pub mutating_flags: Vec<String>,
+    /// Options that supply the script or pattern, or make it unnecessary.
+    pub script_flags: Vec<String>,
+    /// Options naming a library the script loads (`awk -i`), which is
+    /// scanned like a script file but does not replace the script.
+    pub include_flags: Vec<String>,
+    /// Options that may come before the paths without starting the
+    /// expression (`find -L`).
+    pub pre_path_flags: Vec<String>,
+    pub script: Option<Script>,
+    /// Operands from this index on are written to (`uniq IN OUT`).
+    pub writes_operands_from: Option<usize>,
+    /// Arguments always passed first, such as `python3 -I`.
+    pub fixed_args: Vec<String>,
+    /// Default and maximum for the request's timeout.
+    pub timeout_seconds: Option<u64>,
+    /// Default and maximum for the request's output cap.
+    pub max_output_bytes: Option<usize>,
+    /// Overrides the repo's and the server's cgroup limits.
+    pub exec_limits: ExecLimits,
+    /// How to ask the binary for its version.
+    pub version_args: Vec<String>,
+}
+
+impl Default for CommandRule {
+    fn default() -> Self {
+        Self {
+            mutating: false,
+            requires_sandbox: false,
+            operands: Operands::default(),
+            short_clusters: true,
+            allowed_flags: None,
+            forbidden_flags: BTreeMap::new(),
+            value_flags: Vec::new(),
+            path_flags: Vec::new(),
+            mutating_flags: Vec::new(),
+            script_flags: Vec::new(),
+            include_flags: Vec::new(),
+            pre_path_flags: Vec::new(),
+            script: None,
+            writes_operands_from: None,
+            fixed_args: Vec::new(),
+            timeout_seconds: None,
+            max_output_bytes: None,
+            exec_limits: ExecLimits::default(),
+            version_args: vec!["--version".into()],
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct PolicyFile {
+    #[serde(default)]
+    commands: BTreeMap<String, Option<CommandRule>>,
+    #[serde(default)]
+    profiles: BTreeMap<String, Option<ProfileFile>>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct ProfileFile {
+    /// Catalogue commands, with their rules as they are.
+    include: Vec<String>,
+    /// Commands only this profile has, or its own rules for catalogue ones.
+    commands: BTreeMap<String, CommandRule>,
+    /// Overrides `exec_allow_mutating` for repos on this profile.
+    allow_mutating: Option<bool>,
+}
+
+#[derive(Debug, Clone)]
+pub struct CommandPolicy {
+    profiles: BTreeMap<String, Profile>,
+}
+
+/// The commands a repo may run, and how.
+#[derive(Debug, Clone)]
+pub struct Profile {
+    commands: BTreeMap<String, CommandRule>,
+    allow_mutating: bool,
+}
+
+impl CommandPolicy {
+    /// The built-in rules merged with `command_policy_file`, if set.
+    pub fn load(config: &AppConfig) -> anyhow::Result<Self> {
+        let mut catalogue = BTreeMap::new();
+        let mut profiles = BTreeMap::new();
+        let built_in: PolicyFile =
+            serde_json::from_str(BUILT_IN).context("built-in command policy")?;
+        merge(&mut catalogue, &mut profiles, built_in);
+        if !config.command_policy_file.is_empty() {
+            let path = &config.command_policy_file;
+            let text =
+                std::fs::read_to_string(path).with_context(|| format!("reading {}", path))?;
+            let file = serde_json::from_str(&text).with_context(|| format!("parsing {}", path))?;
+            merge(&mut catalogue, &mut profiles, file);
+        }
+
+        let mut resolved = BTreeMap::new();
+        for (name, profile) in profiles {
+            let mut commands = BTreeMap::new();
+            for command in profile.include {
+                let rule = catalogue.get(&command).with_context(|| {
+                    format!("profile '{}' includes unknown command '{}'", name, command)
+                })?;
+                commands.insert(command, rule.clone());
+            }
+            commands.extend(profile.commands);
+            let allow_mutating = profile.allow_mutating.unwrap_or(config.exec_allow_mutating);
+            resolved.insert(
+                name,
+                Profile {
+                    commands,
+                    allow_mutating,
+                },
+            );
+        }
+        resolved
+            .entry(DEFAULT_PROFILE.to_string())
+            .or_insert(Profile {
+                commands: catalogue,
+                allow_mutating: config.exec_allow_mutating,
+            });
+        Ok(Self { profiles: resolved })
+    }
+
+    pub fn has_profile(&self, name: &str) -> bool {
+        self.profiles.contains_key(name)
+    }
+
+    /// The profile a repo selected, or the default one.
+    pub fn profile(&self, name: Option<&str>) -> Result<&Profile, AppError> {
+        let name = name.unwrap_or(DEFAULT_PROFILE);
+        self.profiles.get(name).ok_or_else(|| {
+            AppError::Forbidden(format!("Command profile '{}' is not configured", name))
+        })
+    }
+}
+
+fn merge(
+    catalogue: &mut BTreeMap<String, CommandRule>,
+    profiles: &mut BTreeMap<String, ProfileFile>,
+    file: PolicyFile,
+) {
+    for (name, rule) in file.commands {
+        match rule {
+            Some(rule) => catalogue.insert(name, rule),
+            None => catalogue.remove(&name),
+        };
+    }
+    for (name, profile) in file.profiles {
+        match profile {
+            Some(profile) => profiles.insert(name, profile),
+            None => profiles.remove(&name),
+        };
+    }
+}
+
+impl Profile {
+    pub fn rule(&self, command: &str) -> Option<&CommandRule> {
+        self.commands.get(command)
+    }
+
+    pub fn commands(&self) -> impl Iterator<Item = (&str, &CommandRule)> {
+        self.commands
+            .iter()
+            .map(|(name, rule)| (name.as_str(), rule))
+    }
+
+    pub fn allow_mutating(&self) -> bool {
+        self.allow_mutating
+    }
+
+    /// Check an invocation against the command's rule and return whether
+    /// it writes to the repository. Paths are relative to `repo_root`;
+    /// absolute ones must lie under `visible_root`, where the command sees
+    /// the repo.
+    pub fn check(
+        &self,
+        command: &str,
+        args: &[String],
+        repo_root: &Path,
+        visible_root: &Path,
+    ) -> Result<bool, AppError> {
+        let Some(rule) = self.commands.get(command) else {
+            return Err(AppError::Forbidden(format!(
+                "Command '{}' is not allowed",
+                command
+            )));
+        };
+        let deny = |reason: String| AppError::Forbidden(format!("{}: {}", command, reason));
+        let paths = RepoPaths {
+            root: repo_root,
+            visible: visible_root,
+        };
+
+        let mut writes = rule
+            .mutating
+            .then(|| "the command writes to the repository".to_string());
+        let mut script_given = false;
+        let mut in_expression = false;
+        // (operand, seen after the first option)
+        let mut operands = Vec::new();
+        let mut only_operands = false;
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            if only_operands || arg == "-" || !arg.starts_with('-') {
+                if rule.operands == Operands::LeadingPaths && (arg == "(" || arg == "!") {
+                    in_expression = true;
+                }
+                operands.push((arg.as_str(), in_expression));
+                continue;
+            }
+            if arg == "--" {
+                only_operands = true;
+                continue;
+            }
+            let pre_path = !in_expression && rule.pre_path_flags.iter().any(|f| f == arg);
+            if !pre_path {
+                in_expression = true;
+            }
+
+            for (flag, attached) in rule.split(arg) {
+                let flag = rule.resolve(&flag).map_err(deny)?;
+                if let Some(reason) = rule.forbidden_flags.get(flag) {
+                    return Err(deny(format!(
+                        "option '{}' is not allowed: {}",
+                        flag, reason
+                    )));
+                }
+                if rule.allowed_flags.is_some() && !rule.knows(flag) {
+                    return Err(deny(format!("option '{}' is not allowed", flag)));
+                }
+                let value = match rule.takes_value(flag) {
+                    true => attached.or_else(|| args.next().cloned()),
+                    false => None,
+                };
+                if let Some(value) = &value {
+                    if rule.path_flags.iter().any(|f| f == flag) {
+                        let resolved = paths.check(value).map_err(deny)?;
+                        if let (Some(script), Some(file)) = (rule.script, resolved) {
+                            if rule.script_flags.iter().any(|f| f == flag)
+                                || rule.include_flags.iter().any(|f| f == flag)
+                            {
+                                check_script_file(script, &file)
+                                    .map_err(|e| deny(format!("script file '{}': {}", value, e)))?;
+                            }
+                        }
+                    } else if rule.script_flags.iter().any(|f| f == flag) {
+                        if let Some(script) = rule.script {
+                            script
+                                .check(value)
+                                .map_err(|e| deny(format!("script '{}': {}", value, e)))?;
+                        }
+                    }
+                }
+                if rule.script_flags.iter().any(|f| f == flag) {
+                    script_given = true;
+                }
+                if rule.mutating_flags.iter().any(|f| f == flag) {
+                    writes = Some(format!("option '{}' writes to the repository", flag));
+                }
+            }
+        }
+
+        let mut operands = operands.into_iter();
+        if rule.operands == Operands::ScriptThenPaths && !script_given {
+            if let (Some((text, _)), Some(script)) = (operands.next(), rule.script) {
+                script
+                    .check(text)
+                    .map_err(|e| deny(format!("script '{}': {}", text, e)))?;
+            }
+        }
+        for (index, (operand, in_expression)) in operands.enumerate() {
+            let is_path = match rule.operands {
+                Operands::Text => false,
+                Operands::LeadingPaths => !in_expression,
+                Operands::Paths | Operands::ScriptThenPaths => true,
+            };
+            if !is_path {
+                continue;
+            }
+            paths.check(operand).map_err(deny)?;
+            if rule.writes_operands_from.is_some_and(|from| index >= from) {
+                writes = Some(format!("operand '{}' is written to", operand));
+            }
+        }
+
+        match writes {
+            Some(what) if !self.allow_mutating => Err(deny(format!(
+                "{}, but exec is read-only on this server",
+                what
+            ))),
+            writes => Ok(writes.is_some()),
+        }
+    }
+}
+
+impl CommandRule {
+    fn takes_value(&self, flag: &str) -> bool {
+        self.value_flags.iter().any(|f| f == flag) || self.path_flags.iter().any(|f| f == flag)
+    }
+
+    /// Whether any list mentions `flag`.
+    fn knows(&self, flag: &str) -> bool {
+        self.known_flags().any(|f| f == flag)
+    }
+
+    fn known_flags(&self) -> impl Iterator<Item = &str> {
+        self.forbidden_flags
+            .keys()
+            .chain(&self.value_flags)
+            .chain(&self.path_flags)
+            .chain(&self.mutating_flags)
+            .chain(&self.script_flags)
+            .chain(&self.include_flags)
+            .chain(&self.pre_path_flags)
+            .chain(self.allowed_flags.iter().flatten())
+            .map(String::as_str)
+    }
+
+    /// One argument as `(option, attached value)` pairs: `--opt=value`,
+    /// or a cluster like `-rnA3` whose value-taking option ends it.
+    fn split(&self, arg: &str) -> Vec<(String, Option<String>)> {
+        if arg.starts_with("--") {
+            return match arg.split_once('=') {
+                Some((flag, value)) => vec![(flag.to_string(), Some(value.to_string()))],
+                None => vec![(arg.to_string(), None)],
+            };
+        }
+        if !self.short_clusters || arg.len() == 2 {
+            return vec![(arg.to_string(), None)];
+        }
+        let mut flags = Vec::new();
+        for (i, c) in arg.char_indices().skip(1) {
+            let flag = format!("-{}", c);
+            if self.takes_value(&flag) {
+                let rest = &arg[i + c.len_utf8()..];
+                flags.push((flag, Some(rest.to_string()).filter(|r| !r.is_empty())));
+                break;
+            }
+            flags.push((flag, None));
+        }
+        flags
+    }
+
+    /// The option `flag` stands for. GNU tools accept any unambiguous
+    /// prefix of a long option, so `--out` is `--output`.
+    fn resolve<'a>(&'a self, flag: &'a str) -> Result<&'a str, String> {
+        if !flag.starts_with("--") || self.knows(flag) {
+            return Ok(flag);
+        }
+        let mut matches: Vec<&str> = self.known_flags().filter(|f| f.starts_with(flag)).collect();
+        matches.sort_unstable();
+        matches.dedup();
+        if let Some(forbidden) = matches
+            .iter()
+            .find(|f| self.forbidden_flags.contains_key(**f))
+        {
+            return Err(format!(
+                "option '{}' is not allowed: it abbreviates '{}', which {}",
+                flag, forbidden, self.forbidden_flags[*forbidden]
+            ));
+        }
+        match matches.as_slice() {
+            [only] => Ok(only),
+            _ => Ok(flag),
+        }
+    }
+}
+
+struct RepoPaths<'a> {
+    root: &'a Path,
+    visible: &'a Path,
+}
+
+impl RepoPaths<'_> {
+    /// The host path `value` names, or `None` for stdin.
+    fn check(&self, value: &str) -> Result<Option<PathBuf>, String> {
+        if value == "-" {
+            return Ok(None);
+        }
+        let path = Path::new(value);
+        let relative = match path.is_absolute() {
+            true => path
+                .strip_prefix(self.visible)
+                .map_err(|_| format!("'{}' is outside the repository", value))?,
+            false => path,
+        };
+        let resolved = self.root.join(relative);
+        path_validator::ensure_within_root(self.root, &resolved)
+            .map_err(|_| format!("'{}' resolves outside the repository", value))?;
+        Ok(Some(resolved))
+    }
+}
+
+fn check_script_file(script: Script, path: &Path) -> Result<(), String> {
+    // A missing file is for the command to report
+    let Ok(meta) = std::fs::metadata(path) else {
+        return Ok(());
+    };
+    if meta.len() > MAX_SCRIPT_FILE_BYTES {
+        return Err(format!("larger than {} bytes", MAX_SCRIPT_FILE_BYTES));
+    }
+    let text = std::fs::read(path).map_err(|e| e.to_string())?;
+    script.check(&String::from_utf8_lossy(&text))
+}
+
+/// Consume up to and including the next unescaped `delimiter`.
+fn skip_delimited(chars: &mut Peekable<Chars>, delimiter: char) {
+    while let Some(c) = chars.next() {
+        if c == '\\' {
+            chars.next();
+        } else if c == delimiter {
+            return;
+        }
+    }
+}
+
+fn skip_until(chars: &mut Peekable<Chars>, end: impl Fn(char) -> bool) {
+    while chars.next_if(|c| !end(*c)).is_some() {}
+}
+
+fn check_sed(script: &str) -> Result<(), String> {
+    let mut chars = script.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            // Separators, blocks and addresses
+            ' ' | '\t' | '\n' | ';' | '{' | '}' | '!' | ',' | '~' | '+' | '$' | 'I' | 'M' => {}
+            '0'..='9' => {}
+            '/' => skip_delimited(&mut chars, '/'),
+            '\\' => {
+                if let Some(delimiter) = chars.next() {
+                    skip_delimited(&mut chars, delimiter);
+                }
+            }
+            // Text and labels
+            'a' | 'i' | 'c' | '#' => skip_until(&mut chars, |c| c == '\n'),
+            ':' | 'b' | 't' | 'T' | 'v' => skip_until(&mut chars, |c| c == '\n' || c == ';'),
+            's' | 'y' => {
+                let Some(delimiter) = chars.next() else {
+                    return Ok(());
+                };
+                skip_delimited(&mut chars, delimiter);
+                skip_delimited(&mut chars, delimiter);
+                if c == 's' {
+                    while let Some(flag) = chars.next_if(char::is_ascii_alphanumeric) {
+                        match flag {
+                            'e' => {
+                                return Err(
+                                    "the 'e' flag of 's' runs the result as a command".into()
+                                )
+                            }
+                            'w' => return Err("the 'w' flag of 's' writes to a file".into()),
+                            _ => {}
+                        }
+                    }
+                }
+            }
+            'e' => return Err("the 'e' command runs shell commands".into()),
+            'w' | 'W' => return Err(format!("the '{}' command writes to a file", c)),
+            'r' | 'R' => return Err(format!("the '{}' command reads another file", c)),
+            'p' | 'P' | 'd' | 'D' | 'n' | 'N' | 'g' | 'G' | 'h' | 'H' | 'x' | 'l' | '=' | 'q'
+            | 'Q' | 'z' | 'F' => {}
+            other => return Err(format!("unknown command '{}'", other)),
+        }
+    }
+    Ok(())
+}
+
+/// Scripts from files never pass through `validate_args`, so this rejects
+/// pipes itself rather than relying on the argument check.
+fn check_awk(program: &str) -> Result<(), String> {
+    let mut chars = program.chars().peekable();
+    // Inside a print statement, where an unparenthesised '>' redirects
+    let mut in_print = false;
+    let mut depth = 0i32;
+    // The nesting depth of a getline whose '<' would name a file to read
+    let mut getline: Option<i32> = None;
+    // Whether a '/' here divides rather than starts a regex
+    let mut after_operand = false;
+    while let Some(c) = chars.next() {
+        match c {
+            '"' => skip_delimited(&mut chars, '"'),
+            '/' if !after_operand => skip_delimited(&mut chars, '/'),
+            '#' => skip_until(&mut chars, |c| c == '\n'),
+            '@' => {
+                let mut word = String::new();
+                while let Some(c) = chars.next_if(|c| c.is_ascii_alphanumeric() || *c == '_') {
+                    word.push(c);
+                }
+                match word.as_str() {
+                    "load" => return Err("@load loads native extensions".into()),
+                    "include" => return Err("@include reads another file".into()),
+                    _ => {}
+                }
+            }
+            c if c.is_ascii_alphabetic() || c == '_' => {
+                let mut word = c.to_string();
+                while let Some(c) = chars.next_if(|c| c.is_ascii_alphanumeric() || *c == '_') {
+                    word.push(c);
+                }
+                match word.as_str() {
+                    "system" => return Err("system() runs shell commands".into()),
+                    "getline" => getline = Some(depth),
+                    "print" | "printf" => {
+                        in_print = true;
+                        depth = 0;
+                        after_operand = false;
+                        continue;
+                    }
+                    _ => {}
+                }
+            }
+            c if c.is_ascii_digit() || c == '.' => {}
+            ')' | ']' => {
+                depth -= 1;
+                if getline.is_some_and(|d| depth < d) {
+                    getline = None;
+                }
+            }
+            c if c.is_whitespace() && c != '\n' => continue,
+            '(' | '[' => {
+                depth += 1;
+                after_operand = false;
+                continue;
+            }
+            '{' | '}' | ';' | '\n' => {
+                in_print = false;
+                getline = None;
+                after_operand = false;
+                continue;
+            }
+            '|' if chars.next_if_eq(&'|').is_some() => {
+                after_operand = false;
+                continue;
+            }
+            '|' => return Err("pipes run shell commands".into()),
+            '<' if getline.is_some() => return Err("getline with '<' reads another file".into()),
+            '>' if in_print && depth <= 0 => {
+                return Err("output redirection with '>' writes to a file".into())
+            }
+            _ => {
+                after_operand = false;
+                continue;
+            }
+        }
+        after_operand = true;
+    }
+    Ok(())
+}
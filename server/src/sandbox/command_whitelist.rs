@@ -1,12 +1,21 @@
 use crate::error::AppError;
 
-const ALLOWED_COMMANDS: &[&str] = &[
-    "rg", "grep", "head", "tail", "cat", "wc", "find", "ls", "sort", "uniq", "sed", "awk", "tr",
-    "cut", "diff", "file", "stat", "du", "tree",
+/// Options that make a command start other programs. Without one of
+/// them the seccomp filter denies the command any process of its own.
+const SPAWNING_OPTIONS: &[(&str, &[&str])] = &[
+    ("find", &["-exec", "-execdir", "-ok", "-okdir"]),
+    ("rg", &["--pre", "-z", "--search-zip"]),
+    ("sort", &["--compress-program"]),
 ];
 
-pub fn is_allowed(command: &str) -> bool {
-    ALLOWED_COMMANDS.contains(&command)
+pub fn may_spawn(command: &str, args: &[String]) -> bool {
+    let Some((_, options)) = SPAWNING_OPTIONS.iter().find(|(c, _)| *c == command) else {
+        return false;
+    };
+    args.iter().any(|arg| {
+        let name = arg.split_once('=').map_or(arg.as_str(), |(name, _)| name);
+        options.contains(&name)
+    })
 }
 
 /// Reject arguments that could enable shell injection or path traversal.
@@ -1,78 +1,551 @@
+use crate::config::AppConfig;
 use crate::error::AppError;
+use crate::models::repo::ExecLimits;
+use crate::sandbox::cgroup::{self, ExecCgroup, Limits};
+use crate::sandbox::command_whitelist;
+use crate::sandbox::landlock::{self, Ruleset};
+use crate::sandbox::namespace::{self, Isolation};
+use crate::sandbox::rlimits::ResourceLimits;
+use crate::sandbox::seccomp::{self, Filter};
 use crate::services::shell_service::ExecResponse;
-use std::path::Path;
-use std::time::Instant;
-use tokio::process::Command;
+use dashmap::DashMap;
+use std::ffi::CString;
+use std::future::Future;
+use std::io;
+use std::os::unix::process::{CommandExt, ExitStatusExt};
+use std::path::{Path, PathBuf};
+use std::process::{Command, ExitStatus};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::{Duration, Instant};
+use tokio::io::AsyncReadExt;
+use tokio::net::unix::pipe;
 
+/// How long a command gets between SIGTERM and SIGKILL.
+const KILL_GRACE: Duration = Duration::from_secs(2);
+
+/// How long a binary gets to print its version.
+const VERSION_TIMEOUT: Duration = Duration::from_secs(5);
+
+const SEARCH_PATH: &str = "/usr/bin:/bin:/usr/local/bin";
+
+/// The whole environment a command sees.
+const ENV: &[(&str, &str)] = &[
+    ("PATH", SEARCH_PATH),
+    ("HOME", "/tmp"),
+    ("LC_ALL", "C.UTF-8"),
+];
+
+/// Which sandboxing layers wrap a command. Namespaces fail the command
+/// when the kernel refuses them; Landlock and seccomp are skipped on
+/// kernels without them, and cgroup limits where the server could not
+/// set up a cgroup tree.
+#[derive(Debug, Clone, Copy)]
+pub struct SandboxOptions {
+    pub namespaces: bool,
+    pub landlock: bool,
+    pub seccomp: bool,
+    /// `None` when cgroup limits are disabled.
+    pub limits: Option<Limits>,
+    pub rlimits: ResourceLimits,
+}
+
+impl SandboxOptions {
+    pub fn from_config(config: &AppConfig, repo_limits: &ExecLimits) -> Self {
+        Self {
+            namespaces: config.sandbox_namespaces,
+            landlock: config.sandbox_landlock,
+            seccomp: config.sandbox_seccomp,
+            limits: config
+                .sandbox_cgroups
+                .then(|| Limits::resolve(config, repo_limits)),
+            rlimits: ResourceLimits::from_config(config),
+        }
+    }
+
+    /// Whether namespaces, Landlock and seccomp are all enabled and
+    /// supported here, so none of them would be skipped.
+    pub fn fully_enforced(&self) -> bool {
+        self.namespaces
+            && namespace::supported()
+            && self.landlock
+            && landlock::abi_version().is_some()
+            && self.seccomp
+            && seccomp::supported()
+    }
+}
+
+/// Argument and environment vectors for an `execve` made from `pre_exec`
+/// rather than by `Command`, so the seccomp filter can pin the program
+/// pointer as the only one allowed.
+struct Image {
+    program: CString,
+    _strings: Vec<CString>,
+    argv: Vec<*const libc::c_char>,
+    envp: Vec<*const libc::c_char>,
+}
+
+// SAFETY: the pointers refer into the owned, never mutated `CString`s.
+unsafe impl Send for Image {}
+unsafe impl Sync for Image {}
+
+fn cstring(s: impl Into<Vec<u8>>) -> io::Result<CString> {
+    CString::new(s).map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))
+}
+
+/// The lookup execvp would do, with symlinks resolved so the binary is
+/// still found where `/etc/alternatives` and friends are not visible.
+pub fn resolve_program(command: &str) -> io::Result<PathBuf> {
+    SEARCH_PATH
+        .split(':')
+        .map(|dir| Path::new(dir).join(command))
+        .find(|p| p.is_file())
+        .ok_or_else(|| io::Error::from_raw_os_error(libc::ENOENT))?
+        .canonicalize()
+}
+
+/// The first line `program` prints when run with `args`, from stdout or
+/// else stderr, if it exits successfully. Binaries only change with the image, so answers are
+/// kept for the life of the process.
+pub async fn binary_version(program: &Path, args: &[String]) -> Option<String> {
+    type Probe = (PathBuf, Vec<String>);
+    static VERSIONS: OnceLock<DashMap<Probe, Option<String>>> = OnceLock::new();
+    let versions = VERSIONS.get_or_init(DashMap::new);
+    let key = (program.to_path_buf(), args.to_vec());
+    if let Some(version) = versions.get(&key) {
+        return version.clone();
+    }
+
+    let output = tokio::process::Command::new(program)
+        .args(args)
+        .env_clear()
+        .envs(ENV.iter().copied())
+        .current_dir("/")
+        .stdin(std::process::Stdio::null())
+        .kill_on_drop(true)
+        .output();
+    // A probe that failed to run may succeed next time, so it is not kept
+    let Ok(Ok(output)) = tokio::time::timeout(VERSION_TIMEOUT, output).await else {
+        return None;
+    };
+    let version = output
+        .status
+        .success()
+        .then(|| {
+            [output.stdout, output.stderr].iter().find_map(|out| {
+                String::from_utf8_lossy(out)
+                    .lines()
+                    .map(str::trim)
+                    .find(|line| !line.is_empty())
+                    .map(str::to_string)
+            })
+        })
+        .flatten();
+    versions.insert(key, version.clone());
+    version
+}
+
+impl Image {
+    fn new(program: &Path, command: &str, args: &[String]) -> io::Result<Self> {
+        let argv: Vec<CString> = std::iter::once(command)
+            .chain(args.iter().map(String::as_str))
+            .map(cstring)
+            .collect::<io::Result<_>>()?;
+        let envp: Vec<CString> = ENV
+            .iter()
+            .map(|(k, v)| cstring(format!("{}={}", k, v)))
+            .collect::<io::Result<_>>()?;
+        let pointers = |strings: &[CString]| {
+            strings
+                .iter()
+                .map(|s| s.as_ptr())
+                .chain(std::iter::once(std::ptr::null()))
+                .collect()
+        };
+        Ok(Self {
+            program: cstring(program.as_os_str().as_encoded_bytes())?,
+            argv: pointers(&argv),
+            envp: pointers(&envp),
+            _strings: argv.into_iter().chain(envp).collect(),
+        })
+    }
+
+    /// Only returns if the exec failed.
+    unsafe fn exec(&self) -> io::Error {
+        libc::execve(
+            self.program.as_ptr(),
+            self.argv.as_ptr(),
+            self.envp.as_ptr(),
+        );
+        io::Error::last_os_error()
+    }
+}
+
+/// Run `command` in `working_dir`, the repo. Unless `writable`, the
+/// sandbox leaves the repo read-only to it.
 pub async fn run_command(
     command: &str,
     args: &[String],
     working_dir: &Path,
+    writable: bool,
     timeout_secs: u64,
     max_output_bytes: usize,
+    sandbox: SandboxOptions,
 ) -> Result<ExecResponse, AppError> {
     let start = Instant::now();
 
-    let mut cmd = Command::new(command);
-    cmd.args(args)
+    let program = resolve_program(command)
+        .map_err(|e| AppError::Internal(format!("Failed to spawn command: {}", e)))?;
+    let mut cmd = Command::new(&program);
+    cmd.process_group(0);
+    cmd.arg0(command)
+        .args(args)
         .current_dir(working_dir)
         .env_clear()
-        .env("PATH", "/usr/bin:/bin:/usr/local/bin")
-        .env("HOME", "/tmp")
-        .env("LC_ALL", "C.UTF-8")
+        .envs(ENV.iter().copied())
         .stdin(std::process::Stdio::null())
         .stdout(std::process::Stdio::piped())
         .stderr(std::process::Stdio::piped());
 
-    let child = cmd
-        .spawn()
-        .map_err(|e| AppError::Internal(format!("Failed to spawn command: {}", e)))?;
+    let prepare_err = |e| AppError::Internal(format!("Failed to prepare sandbox: {}", e));
+    let isolation = sandbox
+        .namespaces
+        .then(|| Isolation::new(working_dir, writable))
+        .transpose()
+        .map_err(prepare_err)?;
+    // Landlock goes on after the namespaces, so it sees the repo where the
+    // new root put it
+    let (visible_root, scratch_dir) = match isolation {
+        Some(_) => (
+            Path::new(namespace::REPO_MOUNT),
+            Some(Path::new(namespace::SCRATCH_MOUNT)),
+        ),
+        None => (working_dir, None),
+    };
+    let ruleset = match sandbox.landlock {
+        true => Ruleset::new(visible_root, writable, scratch_dir).map_err(prepare_err)?,
+        false => None,
+    };
+    let filter = match sandbox.seccomp && seccomp::supported() {
+        true => {
+            let image = Image::new(&program, command, args)
+                .map_err(|e| AppError::Internal(format!("Failed to spawn command: {}", e)))?;
+            let spawn = command_whitelist::may_spawn(command, args);
+            Some((Filter::new(spawn, &image.program), image))
+        }
+        false => None,
+    };
+    let cgroup = match &sandbox.limits {
+        Some(limits) => ExecCgroup::create(limits).map_err(prepare_err)?,
+        None => None,
+    };
+    // The cgroup itself stays here for accounting; it outlives the spawn
+    let join_fd = cgroup.as_ref().map(ExecCgroup::procs_fd);
+    let filtered = filter.is_some();
+    let sandboxed = isolation.is_some() || ruleset.is_some() || filtered || join_fd.is_some();
+    let rlimits = sandbox.rlimits;
+    // SAFETY: `join`, `enter`, `apply`, `restrict_self`, `install` and
+    // `exec` only make async-signal-safe calls on memory allocated before
+    // the fork.
+    unsafe {
+        cmd.pre_exec(move || {
+            // Join first, so the namespace relay is accounted too
+            if let Some(fd) = join_fd {
+                cgroup::join(fd)?;
+            }
+            if let Some(isolation) = &isolation {
+                isolation.enter()?;
+            }
+            rlimits.apply()?;
+            if let Some(ruleset) = &ruleset {
+                ruleset.restrict_self()?;
+            }
+            if let Some((filter, image)) = &filter {
+                filter.install()?;
+                return Err(image.exec());
+            }
+            Ok(())
+        });
+    }
+
+    let mut child = match cmd.spawn() {
+        Ok(child) => child,
+        Err(e) => {
+            if let Some(cgroup) = cgroup {
+                cgroup.remove().await;
+            }
+            let what = if sandboxed {
+                "Failed to start sandboxed command"
+            } else {
+                "Failed to spawn command"
+            };
+            return Err(AppError::Internal(format!("{}: {}", what, e)));
+        }
+    };
 
-    // Wait with timeout
-    let result = tokio::time::timeout(
-        std::time::Duration::from_secs(timeout_secs),
-        child.wait_with_output(),
-    )
-    .await;
+    // Reaped here rather than by tokio, for the rusage only wait4 gives
+    let pid = child.id() as libc::pid_t;
+    let group = Group::new(pid);
+    let reaper = group.clone();
+    let mut waiter = tokio::task::spawn_blocking(move || reaper.wait());
+    let running = Running {
+        group: group.clone(),
+        cgroup,
+        finished: false,
+    };
+    let (mut stdout_pipe, mut stderr_pipe) = child
+        .stdout
+        .take()
+        .zip(child.stderr.take())
+        .ok_or_else(|| io::Error::other("missing output pipes"))
+        .and_then(|(out, err)| {
+            Ok((
+                pipe::Receiver::from_owned_fd(out.into())?,
+                pipe::Receiver::from_owned_fd(err.into())?,
+            ))
+        })
+        .map_err(|e| AppError::Internal(format!("Command execution failed: {}", e)))?;
+
+    // Output read before a timeout stays in these buffers
+    let mut stdout = Vec::new();
+    let mut stderr = Vec::new();
+    let mut timed_out = false;
+    let result = {
+        let collect = async {
+            let (out, err) = tokio::join!(
+                read_into(&mut stdout_pipe, &mut stdout),
+                read_into(&mut stderr_pipe, &mut stderr)
+            );
+            out.and(err)?;
+            (&mut waiter).await.map_err(io::Error::other)?
+        };
+        tokio::pin!(collect);
+        match tokio::time::timeout(Duration::from_secs(timeout_secs), &mut collect).await {
+            Ok(result) => Some(result),
+            Err(_) => {
+                timed_out = true;
+                terminate(&group, collect).await
+            }
+        }
+    };
+    let result = match result {
+        Some(result) => result,
+        // The command is dead but something that left its group still
+        // holds the pipes; stop reading and only reap
+        None => (&mut waiter).await.map_err(io::Error::other).and_then(|r| r),
+    };
 
     let duration_ms = start.elapsed().as_millis() as u64;
 
-    match result {
-        Ok(Ok(output)) => {
-            let mut truncated = false;
+    // Read usage before the cgroup goes; removing it also kills anything
+    // still running in it
+    let cgroup = running.finish();
+    let usage = cgroup.as_ref().map(ExecCgroup::usage).unwrap_or_default();
+    if let Some(cgroup) = cgroup {
+        cgroup.remove().await;
+    }
 
-            let stdout = if output.stdout.len() > max_output_bytes {
-                truncated = true;
-                String::from_utf8_lossy(&output.stdout[..max_output_bytes]).to_string()
-            } else {
-                String::from_utf8_lossy(&output.stdout).to_string()
-            };
+    let (status, rusage) =
+        result.map_err(|e| AppError::Internal(format!("Command execution failed: {}", e)))?;
+    let mut truncated = false;
 
-            let stderr = if output.stderr.len() > max_output_bytes {
-                truncated = true;
-                String::from_utf8_lossy(&output.stderr[..max_output_bytes]).to_string()
-            } else {
-                String::from_utf8_lossy(&output.stderr).to_string()
+    let stdout = if stdout.len() > max_output_bytes {
+        truncated = true;
+        String::from_utf8_lossy(&stdout[..max_output_bytes]).to_string()
+    } else {
+        String::from_utf8_lossy(&stdout).to_string()
+    };
+
+    let stderr = if stderr.len() > max_output_bytes {
+        truncated = true;
+        String::from_utf8_lossy(&stderr[..max_output_bytes]).to_string()
+    } else {
+        String::from_utf8_lossy(&stderr).to_string()
+    };
+
+    let user_cpu_ms = millis(rusage.ru_utime);
+    let system_cpu_ms = millis(rusage.ru_stime);
+    let killed_by = match timed_out {
+        true => Some("timeout"),
+        false => status.signal().and_then(|signal| {
+            killed_by(
+                signal,
+                filtered,
+                &rlimits,
+                user_cpu_ms + system_cpu_ms,
+                usage.oom_killed,
+            )
+        }),
+    };
+
+    Ok(ExecResponse {
+        exit_code: status.code().unwrap_or(-1),
+        stdout,
+        stderr,
+        duration_ms,
+        user_cpu_ms,
+        system_cpu_ms,
+        // Linux reports kilobytes
+        max_rss_bytes: rusage.ru_maxrss as u64 * 1024,
+        block_reads: rusage.ru_inblock as u64,
+        block_writes: rusage.ru_oublock as u64,
+        signal: status.signal(),
+        killed_by: killed_by.map(String::from),
+        truncated,
+        seccomp_violation: killed_by == Some("seccomp"),
+        memory_peak_bytes: usage.memory_peak_bytes,
+        cpu_time_ms: usage.cpu_time_ms,
+        oom_killed: usage.oom_killed,
+    })
+}
+
+/// A started command's process group and cgroup. If it is dropped before
+/// [`Running::finish`], because the client disconnected and the request
+/// was cancelled, the group is terminated in the background.
+struct Running {
+    group: Group,
+    cgroup: Option<ExecCgroup>,
+    finished: bool,
+}
+
+impl Running {
+    fn finish(mut self) -> Option<ExecCgroup> {
+        self.finished = true;
+        self.cgroup.take()
+    }
+}
+
+impl Drop for Running {
+    fn drop(&mut self) {
+        if self.finished {
+            return;
+        }
+        let group = self.group.clone();
+        let cgroup = self.cgroup.take();
+        tokio::spawn(async move {
+            group.kill(libc::SIGTERM);
+            tokio::time::sleep(KILL_GRACE).await;
+            group.kill(libc::SIGKILL);
+            if let Some(cgroup) = cgroup {
+                cgroup.remove().await;
+            }
+        });
+    }
+}
+
+/// SIGTERM the group, then SIGKILL it if `done` has not finished after
+/// [`KILL_GRACE`]. `None` when `done` still has not finished after the
+/// kill.
+async fn terminate<F: Future + Unpin>(group: &Group, mut done: F) -> Option<F::Output> {
+    group.kill(libc::SIGTERM);
+    if let Ok(output) = tokio::time::timeout(KILL_GRACE, &mut done).await {
+        return Some(output);
+    }
+    group.kill(libc::SIGKILL);
+    tokio::time::timeout(KILL_GRACE, done).await.ok()
+}
+
+/// The process group a command leads, so it and everything it started can
+/// be signalled together. Once the leader is reaped its pid, and with it
+/// the group id, may be handed to an unrelated process, so signals stop
+/// there; anything left behind is killed with the command's cgroup.
+#[derive(Clone)]
+struct Group {
+    pgid: libc::pid_t,
+    reaped: Arc<Mutex<bool>>,
+}
+
+impl Group {
+    fn new(pgid: libc::pid_t) -> Self {
+        Group {
+            pgid,
+            reaped: Arc::new(Mutex::new(false)),
+        }
+    }
+
+    fn kill(&self, signal: libc::c_int) {
+        let reaped = self.reaped.lock().unwrap();
+        if !*reaped {
+            // SAFETY: kill takes no pointers.
+            unsafe { libc::kill(-self.pgid, signal) };
+        }
+    }
+
+    /// Block until the leader exits, then reap it. It stays a zombie,
+    /// holding its pid, until `reaped` is set under the lock.
+    fn wait(&self) -> io::Result<(ExitStatus, libc::rusage)> {
+        loop {
+            // SAFETY: siginfo_t is plain data; all-zero is a valid value.
+            let mut info: libc::siginfo_t = unsafe { std::mem::zeroed() };
+            // SAFETY: `info` is valid for the call.
+            let ret = unsafe {
+                libc::waitid(
+                    libc::P_PID,
+                    self.pgid as libc::id_t,
+                    &mut info,
+                    libc::WEXITED | libc::WNOWAIT,
+                )
             };
+            if ret == 0 {
+                break;
+            }
+            let err = io::Error::last_os_error();
+            if err.kind() != io::ErrorKind::Interrupted {
+                return Err(err);
+            }
+        }
+        let mut reaped = self.reaped.lock().unwrap();
+        let result = wait_for(self.pgid);
+        *reaped = true;
+        result
+    }
+}
 
-            Ok(ExecResponse {
-                exit_code: output.status.code().unwrap_or(-1),
-                stdout,
-                stderr,
-                duration_ms,
-                truncated,
-            })
+/// Append everything from `pipe` to `buf`. What was read stays in `buf`
+/// if the future is dropped part way.
+async fn read_into(pipe: &mut pipe::Receiver, buf: &mut Vec<u8>) -> io::Result<()> {
+    while pipe.read_buf(buf).await? > 0 {}
+    Ok(())
+}
+
+fn wait_for(pid: libc::pid_t) -> io::Result<(ExitStatus, libc::rusage)> {
+    let mut status = 0;
+    // SAFETY: rusage is plain data; all-zero is a valid value.
+    let mut rusage: libc::rusage = unsafe { std::mem::zeroed() };
+    loop {
+        // SAFETY: both out pointers are valid for the call.
+        if unsafe { libc::wait4(pid, &mut status, 0, &mut rusage) } == pid {
+            return Ok((ExitStatus::from_raw(status), rusage));
         }
-        Ok(Err(e)) => Err(AppError::Internal(format!("Command execution failed: {}", e))),
-        Err(_) => {
-            // Timeout
-            Ok(ExecResponse {
-                exit_code: -1,
-                stdout: String::new(),
-                stderr: "Command timed out".to_string(),
-                duration_ms,
-                truncated: false,
-            })
+        let err = io::Error::last_os_error();
+        if err.kind() != io::ErrorKind::Interrupted {
+            return Err(err);
+        }
+    }
+}
+
+fn millis(t: libc::timeval) -> u64 {
+    t.tv_sec as u64 * 1000 + t.tv_usec as u64 / 1000
+}
+
+/// The limit that explains a command dying from `signal`, if any. Running
+/// out of address space is not reported: allocations fail and the command
+/// decides how to die.
+fn killed_by(
+    signal: libc::c_int,
+    filtered: bool,
+    rlimits: &ResourceLimits,
+    cpu_ms: u64,
+    oom_killed: bool,
+) -> Option<&'static str> {
+    match signal {
+        libc::SIGSYS if filtered => Some("seccomp"),
+        libc::SIGXCPU => Some("cpu_time"),
+        libc::SIGXFSZ => Some("file_size"),
+        libc::SIGKILL if oom_killed => Some("memory"),
+        // Past the soft limit the kernel sends SIGKILL at the hard one
+        libc::SIGKILL if rlimits.cpu_secs.is_some_and(|s| cpu_ms >= s * 1000) => {
+            Some("cpu_time")
         }
+        _ => None,
     }
 }
@@ -0,0 +1,197 @@
+//! Landlock filesystem confinement for `exec`.
+//!
+//! A lighter alternative to [`super::namespace`]: the command keeps the
+//! host's view of the filesystem, but the kernel only lets it read the
+//! system binary and library directories, read the repo, write it too
+//! when the invocation was found to write to it and, inside the
+//! namespaces, write the private `/tmp`. Nothing in the repo or `/tmp`
+//! can be executed.
+//! Landlock is unprivileged and stacks with the namespaces, so both can be
+//! on at once. Kernels without it (before 5.13, or with the LSM disabled)
+//! run commands unconfined.
+
+use std::ffi::CString;
+use std::io;
+use std::path::Path;
+use std::sync::OnceLock;
+
+const CREATE_RULESET_VERSION: u32 = 1 << 0;
+const RULE_PATH_BENEATH: libc::c_int = 1;
+
+const ACCESS_EXECUTE: u64 = 1 << 0;
+const ACCESS_WRITE_FILE: u64 = 1 << 1;
+const ACCESS_READ_FILE: u64 = 1 << 2;
+const ACCESS_READ_DIR: u64 = 1 << 3;
+/// Everything up to `MAKE_SYM`; understood by every ABI.
+const ACCESS_ABI_1: u64 = (1 << 13) - 1;
+const ACCESS_REFER: u64 = 1 << 13;
+const ACCESS_TRUNCATE: u64 = 1 << 14;
+const ACCESS_IOCTL_DEV: u64 = 1 << 15;
+
+/// Rights that make sense on a single file rather than a directory.
+const FILE_ACCESS: u64 =
+    ACCESS_EXECUTE | ACCESS_WRITE_FILE | ACCESS_READ_FILE | ACCESS_TRUNCATE | ACCESS_IOCTL_DEV;
+const READ_ONLY: u64 = ACCESS_EXECUTE | ACCESS_READ_FILE | ACCESS_READ_DIR;
+/// Everything but running what is there.
+const READ_WRITE: u64 = !ACCESS_EXECUTE;
+
+/// Host paths commands may read and execute from.
+const SYSTEM_PATHS: &[&str] = &["/usr", "/bin", "/sbin", "/lib", "/lib32", "/lib64"];
+
+const DEVICES: &[&str] = &["/dev/null", "/dev/zero", "/dev/urandom"];
+
+#[repr(C)]
+struct RulesetAttr {
+    handled_access_fs: u64,
+}
+
+#[repr(C, packed)]
+struct PathBeneathAttr {
+    allowed_access: u64,
+    parent_fd: i32,
+}
+
+/// The Landlock ABI version the kernel speaks, or `None` if Landlock is
+/// unavailable.
+pub fn abi_version() -> Option<u32> {
+    static ABI: OnceLock<Option<u32>> = OnceLock::new();
+    *ABI.get_or_init(|| {
+        // SAFETY: a null attr with size 0 only asks for the version.
+        let ret = unsafe {
+            libc::syscall(
+                libc::SYS_landlock_create_ruleset,
+                std::ptr::null::<RulesetAttr>(),
+                0usize,
+                CREATE_RULESET_VERSION,
+            )
+        };
+        (ret > 0).then_some(ret as u32)
+    })
+}
+
+fn handled_access(abi: u32) -> u64 {
+    let mut access = ACCESS_ABI_1;
+    if abi >= 2 {
+        access |= ACCESS_REFER;
+    }
+    if abi >= 3 {
+        access |= ACCESS_TRUNCATE;
+    }
+    if abi >= 5 {
+        access |= ACCESS_IOCTL_DEV;
+    }
+    access
+}
+
+/// A ruleset prepared before fork and applied in the child.
+pub struct Ruleset {
+    handled: u64,
+    /// `(path, allowed access)`; paths missing at apply time are skipped.
+    rules: Vec<(CString, u64)>,
+}
+
+fn cstring(s: impl Into<Vec<u8>>) -> io::Result<CString> {
+    CString::new(s).map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))
+}
+
+impl Ruleset {
+    /// Rules confining a command to `repo_root`, writable only when
+    /// `writable`, and `scratch_dir` as the child will see them. Returns
+    /// `None` when the kernel has no Landlock support.
+    pub fn new(
+        repo_root: &Path,
+        writable: bool,
+        scratch_dir: Option<&Path>,
+    ) -> io::Result<Option<Self>> {
+        let Some(abi) = abi_version() else {
+            return Ok(None);
+        };
+        let handled = handled_access(abi);
+
+        let repo_access = match writable {
+            true => READ_WRITE,
+            false => READ_ONLY & !ACCESS_EXECUTE,
+        };
+        let mut rules = vec![(
+            cstring(repo_root.as_os_str().as_encoded_bytes())?,
+            repo_access & handled,
+        )];
+        if let Some(dir) = scratch_dir {
+            rules.push((cstring(dir.as_os_str().as_encoded_bytes())?, READ_WRITE & handled));
+        }
+        for path in SYSTEM_PATHS {
+            rules.push((cstring(*path)?, READ_ONLY & handled));
+        }
+        for path in DEVICES {
+            rules.push((
+                cstring(*path)?,
+                (ACCESS_READ_FILE | ACCESS_WRITE_FILE | ACCESS_IOCTL_DEV) & handled,
+            ));
+        }
+        Ok(Some(Self { handled, rules }))
+    }
+
+    /// Confine the calling process and everything it execs.
+    ///
+    /// # Safety
+    /// Must only be called between fork and exec.
+    pub unsafe fn restrict_self(&self) -> io::Result<()> {
+        let attr = RulesetAttr {
+            handled_access_fs: self.handled,
+        };
+        let ruleset = check(libc::syscall(
+            libc::SYS_landlock_create_ruleset,
+            &attr as *const RulesetAttr,
+            std::mem::size_of::<RulesetAttr>(),
+            0u32,
+        ))? as libc::c_int;
+
+        let result = self.add_rules(ruleset).and_then(|()| {
+            check(libc::prctl(libc::PR_SET_NO_NEW_PRIVS, 1, 0, 0, 0) as libc::c_long)?;
+            check(libc::syscall(
+                libc::SYS_landlock_restrict_self,
+                ruleset,
+                0u32,
+            ))?;
+            Ok(())
+        });
+        libc::close(ruleset);
+        result
+    }
+
+    unsafe fn add_rules(&self, ruleset: libc::c_int) -> io::Result<()> {
+        for (path, access) in &self.rules {
+            let fd = libc::open(path.as_ptr(), libc::O_PATH | libc::O_CLOEXEC);
+            if fd < 0 {
+                continue;
+            }
+            let mut st: libc::stat = std::mem::zeroed();
+            let mut allowed = *access;
+            if libc::fstat(fd, &mut st) == 0 && st.st_mode & libc::S_IFMT != libc::S_IFDIR {
+                allowed &= FILE_ACCESS;
+            }
+            let rule = PathBeneathAttr {
+                allowed_access: allowed,
+                parent_fd: fd,
+            };
+            let ret = libc::syscall(
+                libc::SYS_landlock_add_rule,
+                ruleset,
+                RULE_PATH_BENEATH,
+                &rule as *const PathBeneathAttr,
+                0u32,
+            );
+            libc::close(fd);
+            check(ret)?;
+        }
+        Ok(())
+    }
+}
+
+fn check(ret: libc::c_long) -> io::Result<libc::c_long> {
+    if ret < 0 {
+        Err(io::Error::last_os_error())
+    } else {
+        Ok(ret)
+    }
+}
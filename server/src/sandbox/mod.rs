@@ -1,3 +1,9 @@
+pub mod cgroup;
+pub mod command_policy;
 pub mod command_whitelist;
 pub mod executor;
+pub mod landlock;
+pub mod namespace;
 pub mod path_validator;
+pub mod rlimits;
+pub mod seccomp;
@@ -0,0 +1,369 @@
+//! Per-command namespaces for `exec`.
+//!
+//! The child unshares user, mount, PID, IPC, UTS and network namespaces
+//! between fork and exec, builds a fresh root on a tmpfs that holds only
+//! the repo (at [`REPO_MOUNT`], read-only unless the invocation was found
+//! to write to it), read-only `/usr`, `/bin` and the library directories,
+//! a private `/tmp` and a few `/dev` nodes, and pivots into it. Everything works without privileges: the user
+//! namespace grants the capabilities needed for the mounts, and the
+//! command itself runs as an unprivileged id inside it.
+
+use std::ffi::CString;
+use std::io;
+use std::os::fd::AsRawFd;
+use std::path::Path;
+use std::sync::OnceLock;
+
+/// Where the repo appears inside the sandbox; also the working directory.
+pub const REPO_MOUNT: &str = "/repo";
+
+/// The private tmpfs commands write scratch files to.
+pub const SCRATCH_MOUNT: &str = "/tmp";
+
+/// The id the command runs as inside the user namespace.
+const SANDBOX_ID: u32 = 1000;
+
+/// Host directories exposed read-only. Symlinks (merged-/usr layouts) are
+/// recreated as symlinks instead of mounted.
+const SYSTEM_DIRS: &[&str] = &["usr", "bin", "lib", "lib64"];
+
+const DEVICES: &[&str] = &["null", "zero", "urandom"];
+
+/// Scratch mount point for the new root. Only this mount namespace sees
+/// the tmpfs placed over it.
+const NEW_ROOT: &str = "/tmp";
+
+const NAMESPACES: libc::c_int = libc::CLONE_NEWUSER
+    | libc::CLONE_NEWNS
+    | libc::CLONE_NEWPID
+    | libc::CLONE_NEWIPC
+    | libc::CLONE_NEWUTS
+    | libc::CLONE_NEWNET;
+
+/// Whether this kernel and container let an unprivileged process create
+/// the namespaces. Probed once in a throwaway child.
+pub fn supported() -> bool {
+    static SUPPORTED: OnceLock<bool> = OnceLock::new();
+    *SUPPORTED.get_or_init(|| {
+        // SAFETY: the child only makes async-signal-safe calls before
+        // exiting.
+        unsafe {
+            match libc::fork() {
+                -1 => false,
+                0 => libc::_exit((libc::unshare(NAMESPACES) != 0) as libc::c_int),
+                pid => {
+                    let mut status = 0;
+                    libc::waitpid(pid, &mut status, 0) == pid
+                        && libc::WIFEXITED(status)
+                        && libc::WEXITSTATUS(status) == 0
+                }
+            }
+        }
+    })
+}
+
+enum SystemEntry {
+    Dir {
+        name: CString,
+        source: CString,
+        flags: libc::c_ulong,
+    },
+    Symlink {
+        name: CString,
+        target: CString,
+    },
+}
+
+/// Everything the child needs, allocated before fork: only
+/// async-signal-safe calls may run between fork and exec.
+pub struct Isolation {
+    /// Reserves the fd number named by `repo_source`; the child reopens
+    /// the repo onto it after unsharing, since a bind mount cannot take
+    /// its source from the old mount namespace.
+    repo_fd: std::fs::File,
+    repo_path: CString,
+    repo_source: CString,
+    repo_flags: libc::c_ulong,
+    system: Vec<SystemEntry>,
+    /// `(host node, mount point)` pairs.
+    devices: Vec<(CString, CString)>,
+    uid_map: Vec<u8>,
+    gid_map: Vec<u8>,
+}
+
+fn cstring(s: impl Into<Vec<u8>>) -> io::Result<CString> {
+    CString::new(s).map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))
+}
+
+/// Mount flags a bind remount has to keep, since the kernel refuses to
+/// clear flags inherited from a more privileged namespace.
+fn locked_flags(path: &Path) -> io::Result<libc::c_ulong> {
+    let c_path = cstring(path.as_os_str().as_encoded_bytes())?;
+    let mut st: libc::statvfs = unsafe { std::mem::zeroed() };
+    // SAFETY: `c_path` is NUL-terminated and `st` is a valid out pointer.
+    if unsafe { libc::statvfs(c_path.as_ptr(), &mut st) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    let mut flags = libc::MS_NOSUID | libc::MS_NODEV;
+    for (st_flag, ms_flag) in [
+        (libc::ST_NOEXEC, libc::MS_NOEXEC),
+        (libc::ST_NOATIME, libc::MS_NOATIME),
+        (libc::ST_NODIRATIME, libc::MS_NODIRATIME),
+        (libc::ST_RELATIME, libc::MS_RELATIME),
+    ] {
+        if st.f_flag & st_flag != 0 {
+            flags |= ms_flag;
+        }
+    }
+    Ok(flags)
+}
+
+impl Isolation {
+    /// Prepare a sandbox around `repo_root`, mounted read-write only when
+    /// `writable`.
+    pub fn new(repo_root: &Path, writable: bool) -> io::Result<Self> {
+        let repo = std::fs::File::open(repo_root)?;
+        let repo_source = cstring(format!("/proc/self/fd/{}", repo.as_raw_fd()))?;
+        let mut repo_flags = locked_flags(repo_root)?;
+        if !writable {
+            repo_flags |= libc::MS_RDONLY;
+        }
+
+        let mut system = Vec::new();
+        for name in SYSTEM_DIRS {
+            let host = Path::new("/").join(name);
+            let Ok(meta) = std::fs::symlink_metadata(&host) else {
+                continue;
+            };
+            if meta.file_type().is_symlink() {
+                let target = std::fs::read_link(&host)?;
+                system.push(SystemEntry::Symlink {
+                    name: cstring(format!("{}/{}", NEW_ROOT, name))?,
+                    target: cstring(target.as_os_str().as_encoded_bytes())?,
+                });
+            } else if meta.is_dir() {
+                system.push(SystemEntry::Dir {
+                    name: cstring(format!("{}/{}", NEW_ROOT, name))?,
+                    source: cstring(host.as_os_str().as_encoded_bytes())?,
+                    flags: locked_flags(&host)?,
+                });
+            }
+        }
+
+        let devices = DEVICES
+            .iter()
+            .map(|d| {
+                Ok((
+                    cstring(format!("/dev/{}", d))?,
+                    cstring(format!("{}/dev/{}", NEW_ROOT, d))?,
+                ))
+            })
+            .collect::<io::Result<_>>()?;
+
+        // SAFETY: getuid/getgid cannot fail.
+        let (uid, gid) = unsafe { (libc::getuid(), libc::getgid()) };
+        Ok(Self {
+            repo_path: cstring(repo_root.as_os_str().as_encoded_bytes())?,
+            repo_fd: repo,
+            repo_source,
+            repo_flags,
+            system,
+            devices,
+            uid_map: format!("{} {} 1\n", SANDBOX_ID, uid).into_bytes(),
+            gid_map: format!("{} {} 1\n", SANDBOX_ID, gid).into_bytes(),
+        })
+    }
+
+    /// Enter the sandbox. Called in the forked child before exec; on
+    /// success the caller continues as PID 1 of the new PID namespace
+    /// while the original child waits for it and relays its exit status.
+    ///
+    /// # Safety
+    /// Must only be called between fork and exec.
+    pub unsafe fn enter(&self) -> io::Result<()> {
+        check(libc::unshare(NAMESPACES))?;
+        write_file(c"/proc/self/setgroups", b"deny")?;
+        write_file(c"/proc/self/uid_map", &self.uid_map)?;
+        write_file(c"/proc/self/gid_map", &self.gid_map)?;
+        check(libc::sethostname(c"sandbox".as_ptr(), 7))?;
+
+        // Pin the repo before the tmpfs below can cover its path
+        let fd = check(libc::open(
+            self.repo_path.as_ptr(),
+            libc::O_PATH | libc::O_DIRECTORY | libc::O_CLOEXEC,
+        ))?;
+        check(libc::dup3(fd, self.repo_fd.as_raw_fd(), libc::O_CLOEXEC))?;
+        libc::close(fd);
+
+        self.build_root()?;
+
+        // The new PID namespace only applies to children, so fork once
+        // more and stay behind as a relay for the exit status.
+        match check(libc::fork())? {
+            0 => {
+                // Take the command down if the relay is killed
+                check(libc::prctl(
+                    libc::PR_SET_PDEATHSIG,
+                    libc::SIGKILL as libc::c_ulong,
+                ))?;
+                Ok(())
+            }
+            pid => relay_exit(pid),
+        }
+    }
+
+    unsafe fn build_root(&self) -> io::Result<()> {
+        let no_data = std::ptr::null();
+        check(libc::mount(
+            std::ptr::null(),
+            c"/".as_ptr(),
+            std::ptr::null(),
+            libc::MS_REC | libc::MS_PRIVATE,
+            no_data,
+        ))?;
+        check(libc::mount(
+            c"tmpfs".as_ptr(),
+            c"/tmp".as_ptr(),
+            c"tmpfs".as_ptr(),
+            libc::MS_NOSUID | libc::MS_NODEV,
+            c"size=1m,mode=755".as_ptr().cast(),
+        ))?;
+
+        for entry in &self.system {
+            match entry {
+                SystemEntry::Symlink { name, target } => {
+                    check(libc::symlink(target.as_ptr(), name.as_ptr()))?;
+                }
+                SystemEntry::Dir {
+                    name,
+                    source,
+                    flags,
+                } => {
+                    check(libc::mkdir(name.as_ptr(), 0o755))?;
+                    bind(source, name, *flags | libc::MS_RDONLY)?;
+                }
+            }
+        }
+
+        check(libc::mkdir(c"/tmp/repo".as_ptr(), 0o755))?;
+        bind(&self.repo_source, c"/tmp/repo", self.repo_flags)?;
+
+        check(libc::mkdir(c"/tmp/tmp".as_ptr(), 0o1777))?;
+        check(libc::mount(
+            c"tmpfs".as_ptr(),
+            c"/tmp/tmp".as_ptr(),
+            c"tmpfs".as_ptr(),
+            libc::MS_NOSUID | libc::MS_NODEV,
+            c"size=64m,mode=1777".as_ptr().cast(),
+        ))?;
+
+        check(libc::mkdir(c"/tmp/dev".as_ptr(), 0o755))?;
+        for (source, target) in &self.devices {
+            // Best effort: commands run without them, just less happily
+            let fd = libc::open(
+                target.as_ptr(),
+                libc::O_CREAT | libc::O_WRONLY | libc::O_CLOEXEC,
+                0o666,
+            );
+            if fd >= 0 {
+                libc::close(fd);
+                libc::mount(
+                    source.as_ptr(),
+                    target.as_ptr(),
+                    std::ptr::null(),
+                    libc::MS_BIND,
+                    no_data,
+                );
+            }
+        }
+
+        // Swap roots and drop the host tree entirely
+        check(libc::chdir(c"/tmp".as_ptr()))?;
+        check(libc::syscall(libc::SYS_pivot_root, c".".as_ptr(), c".".as_ptr()) as libc::c_int)?;
+        check(libc::umount2(c".".as_ptr(), libc::MNT_DETACH))?;
+        check(libc::mount(
+            std::ptr::null(),
+            c"/".as_ptr(),
+            std::ptr::null(),
+            libc::MS_REMOUNT | libc::MS_BIND | libc::MS_RDONLY | libc::MS_NOSUID | libc::MS_NODEV,
+            no_data,
+        ))?;
+        check(libc::chdir(c"/repo".as_ptr()))?;
+        Ok(())
+    }
+}
+
+unsafe fn bind(source: &CString, target: &std::ffi::CStr, flags: libc::c_ulong) -> io::Result<()> {
+    check(libc::mount(
+        source.as_ptr(),
+        target.as_ptr(),
+        std::ptr::null(),
+        libc::MS_BIND | libc::MS_REC,
+        std::ptr::null(),
+    ))?;
+    check(libc::mount(
+        std::ptr::null(),
+        target.as_ptr(),
+        std::ptr::null(),
+        libc::MS_BIND | libc::MS_REMOUNT | flags,
+        std::ptr::null(),
+    ))?;
+    Ok(())
+}
+
+unsafe fn write_file(path: &std::ffi::CStr, data: &[u8]) -> io::Result<()> {
+    let fd = check(libc::open(path.as_ptr(), libc::O_WRONLY | libc::O_CLOEXEC))?;
+    let written = libc::write(fd, data.as_ptr().cast(), data.len());
+    libc::close(fd);
+    if written != data.len() as isize {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+/// Wait for the command and exit the same way, dying from the same signal
+/// if it was killed so the executor sees what actually happened.
+unsafe fn relay_exit(pid: libc::pid_t) -> ! {
+    // The relay never execs, so its copy of the channel `Command` uses to
+    // detect the exec would hold `spawn` until the command exits; the
+    // output pipes go too, so they close with the command
+    if libc::syscall(libc::SYS_close_range, 0u32, u32::MAX, 0u32) != 0 {
+        for fd in 0..4096 {
+            libc::close(fd);
+        }
+    }
+    let mut status = 0;
+    loop {
+        if libc::waitpid(pid, &mut status, 0) == pid {
+            break;
+        }
+        if io::Error::last_os_error().raw_os_error() != Some(libc::EINTR) {
+            libc::_exit(127);
+        }
+    }
+    if libc::WIFEXITED(status) {
+        libc::_exit(libc::WEXITSTATUS(status));
+    }
+    let sig = libc::WTERMSIG(status);
+    let no_core = libc::rlimit {
+        rlim_cur: 0,
+        rlim_max: 0,
+    };
+    libc::setrlimit(libc::RLIMIT_CORE, &no_core);
+    libc::signal(sig, libc::SIG_DFL);
+    let mut set: libc::sigset_t = std::mem::zeroed();
+    libc::sigemptyset(&mut set);
+    libc::sigaddset(&mut set, sig);
+    libc::sigprocmask(libc::SIG_UNBLOCK, &set, std::ptr::null_mut());
+    libc::kill(libc::getpid(), sig);
+    // Only reached for signals that do not terminate by default
+    libc::_exit(128 + sig)
+}
+
+fn check<T: Default + PartialOrd>(ret: T) -> io::Result<T> {
+    if ret < T::default() {
+        Err(io::Error::last_os_error())
+    } else {
+        Ok(ret)
+    }
+}
@@ -52,13 +52,10 @@ pub fn validate_relative_path(rel_path: &str) -> Result<String, AppError> {
 }
 
 /// Validate that a resolved path is within the given root directory.
-#[allow(dead_code)]
+/// Symlinks are followed as far as the path exists.
 pub fn ensure_within_root(root: &Path, resolved: &Path) -> Result<(), AppError> {
-    // Use canonical comparison if possible, fall back to starts_with
-    let canon_root = root.canonicalize().unwrap_or_else(|_| root.to_path_buf());
-    let canon_resolved = resolved
-        .canonicalize()
-        .unwrap_or_else(|_| resolved.to_path_buf());
+    let canon_root = canonicalize_existing(root);
+    let canon_resolved = canonicalize_existing(resolved);
 
     if !canon_resolved.starts_with(&canon_root) {
         return Err(AppError::Forbidden(
@@ -67,3 +64,21 @@ pub fn ensure_within_root(root: &Path, resolved: &Path) -> Result<(), AppError>
     }
     Ok(())
 }
+
+/// Canonicalize the longest existing prefix of `path` and append the rest.
+fn canonicalize_existing(path: &Path) -> PathBuf {
+    let mut existing = path;
+    let mut rest = Vec::new();
+    loop {
+        if let Ok(canon) = existing.canonicalize() {
+            return rest.iter().rev().fold(canon, |p, name| p.join(name));
+        }
+        match (existing.parent(), existing.file_name()) {
+            (Some(parent), Some(name)) => {
+                rest.push(name);
+                existing = parent;
+            }
+            _ => return path.to_path_buf(),
+        }
+    }
+}
@@ -0,0 +1,72 @@
+//! Per-process resource limits for `exec`.
+//!
+//! Unlike the cgroup limits these need no setup and work everywhere, but
+//! they bound each process rather than the command as a whole. A `None`
+//! limit is inherited from the server unchanged.
+
+use crate::config::AppConfig;
+use std::io;
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ResourceLimits {
+    /// `SIGXCPU` at the limit, `SIGKILL` a second later.
+    pub cpu_secs: Option<u64>,
+    /// Allocations beyond it fail; most tools then exit with an error.
+    pub address_space_bytes: Option<u64>,
+    /// `SIGXFSZ` when a write would grow a file past it.
+    pub file_size_bytes: Option<u64>,
+    pub open_files: Option<u64>,
+    /// Counted across every process and thread of the server's user.
+    pub processes: Option<u64>,
+}
+
+impl ResourceLimits {
+    pub fn from_config(config: &AppConfig) -> Self {
+        let limit = |v: u64| Some(v).filter(|v| *v > 0);
+        Self {
+            cpu_secs: limit(config.exec_rlimit_cpu_secs),
+            address_space_bytes: limit(config.exec_rlimit_as_bytes),
+            file_size_bytes: limit(config.exec_rlimit_fsize_bytes),
+            open_files: limit(config.exec_rlimit_nofile),
+            processes: limit(config.exec_rlimit_nproc),
+        }
+    }
+
+    /// Apply the limits, and disable core dumps, for the calling process.
+    ///
+    /// # Safety
+    /// Must only be called between fork and exec.
+    pub unsafe fn apply(&self) -> io::Result<()> {
+        set(libc::RLIMIT_CORE, 0, 0)?;
+        if let Some(secs) = self.cpu_secs {
+            set(libc::RLIMIT_CPU, secs, secs + 1)?;
+        }
+        for (resource, limit) in [
+            (libc::RLIMIT_AS, self.address_space_bytes),
+            (libc::RLIMIT_FSIZE, self.file_size_bytes),
+            (libc::RLIMIT_NOFILE, self.open_files),
+            (libc::RLIMIT_NPROC, self.processes),
+        ] {
+            if let Some(limit) = limit {
+                set(resource, limit, limit)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Lower a limit; one already below the requested value is kept.
+unsafe fn set(resource: libc::__rlimit_resource_t, soft: u64, hard: u64) -> io::Result<()> {
+    let mut current: libc::rlimit = std::mem::zeroed();
+    if libc::getrlimit(resource, &mut current) != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    let new = libc::rlimit {
+        rlim_cur: soft.min(current.rlim_max),
+        rlim_max: hard.min(current.rlim_max),
+    };
+    if libc::setrlimit(resource, &new) != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
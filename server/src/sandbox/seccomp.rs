@@ -0,0 +1,240 @@
+//! seccomp-bpf syscall filter for `exec`.
+//!
+//! The whitelisted tools read files and write to pipes; they never need
+//! sockets, tracing, mounts, module loading or the other calls denied
+//! here. A denied call kills the command with `SIGSYS`, which the
+//! executor reports as a violation. Commands whose arguments do not ask
+//! for a child process also lose `fork` and any `execve` after their own.
+
+use std::ffi::CStr;
+use std::io;
+use std::sync::OnceLock;
+
+#[cfg(target_arch = "x86_64")]
+const AUDIT_ARCH: u32 = 0xc000_003e;
+#[cfg(target_arch = "aarch64")]
+const AUDIT_ARCH: u32 = 0xc000_00b7;
+
+/// x32 syscalls share the x86_64 arch token; refuse the whole range.
+#[cfg(target_arch = "x86_64")]
+const X32_SYSCALL_BIT: u32 = 0x4000_0000;
+
+const OFFSET_NR: u32 = 0;
+const OFFSET_ARCH: u32 = 4;
+const OFFSET_ARGS: u32 = 16;
+
+const DENIED: &[libc::c_long] = &[
+    libc::SYS_ptrace,
+    libc::SYS_process_vm_readv,
+    libc::SYS_process_vm_writev,
+    libc::SYS_mount,
+    libc::SYS_umount2,
+    libc::SYS_pivot_root,
+    libc::SYS_chroot,
+    libc::SYS_unshare,
+    libc::SYS_setns,
+    libc::SYS_kexec_load,
+    libc::SYS_kexec_file_load,
+    libc::SYS_reboot,
+    libc::SYS_bpf,
+    libc::SYS_perf_event_open,
+    libc::SYS_init_module,
+    libc::SYS_finit_module,
+    libc::SYS_delete_module,
+    libc::SYS_keyctl,
+    libc::SYS_add_key,
+    libc::SYS_request_key,
+    libc::SYS_userfaultfd,
+    libc::SYS_open_by_handle_at,
+    libc::SYS_name_to_handle_at,
+    libc::SYS_io_uring_setup,
+    libc::SYS_swapon,
+    libc::SYS_swapoff,
+    libc::SYS_acct,
+    libc::SYS_quotactl,
+    libc::SYS_sethostname,
+    libc::SYS_setdomainname,
+    libc::SYS_settimeofday,
+    libc::SYS_clock_settime,
+    libc::SYS_adjtimex,
+];
+
+#[cfg(target_arch = "x86_64")]
+const SPAWN: &[libc::c_long] = &[libc::SYS_fork, libc::SYS_vfork, libc::SYS_execveat];
+#[cfg(target_arch = "aarch64")]
+const SPAWN: &[libc::c_long] = &[libc::SYS_execveat];
+
+/// Whether the kernel can load a filter that kills the whole process.
+pub fn supported() -> bool {
+    static SUPPORTED: OnceLock<bool> = OnceLock::new();
+    *SUPPORTED.get_or_init(|| {
+        let action = libc::SECCOMP_RET_KILL_PROCESS;
+        // SAFETY: `action` outlives the call, which only reads it.
+        unsafe {
+            libc::syscall(
+                libc::SYS_seccomp,
+                libc::SECCOMP_GET_ACTION_AVAIL,
+                0u32,
+                &action as *const u32,
+            ) == 0
+        }
+    })
+}
+
+/// A compiled filter, built before fork and loaded in the child.
+pub struct Filter {
+    program: Vec<libc::sock_filter>,
+}
+
+fn stmt(code: u32, k: u32) -> libc::sock_filter {
+    jump(code, k, 0, 0)
+}
+
+fn jump(code: u32, k: u32, jt: u8, jf: u8) -> libc::sock_filter {
+    libc::sock_filter {
+        code: code as u16,
+        jt,
+        jf,
+        k,
+    }
+}
+
+fn load(offset: u32) -> libc::sock_filter {
+    stmt(libc::BPF_LD | libc::BPF_W | libc::BPF_ABS, offset)
+}
+
+fn ret(action: u32) -> libc::sock_filter {
+    stmt(libc::BPF_RET | libc::BPF_K, action)
+}
+
+/// Skip the next `skip` instructions unless the syscall number is `nr`.
+fn if_nr(nr: libc::c_long, skip: u8) -> libc::sock_filter {
+    jump(
+        libc::BPF_JMP | libc::BPF_JEQ | libc::BPF_K,
+        nr as u32,
+        0,
+        skip,
+    )
+}
+
+const KILL: u32 = libc::SECCOMP_RET_KILL_PROCESS;
+const ALLOW: u32 = libc::SECCOMP_RET_ALLOW;
+
+fn errno(code: libc::c_int) -> u32 {
+    libc::SECCOMP_RET_ERRNO | (code as u32 & libc::SECCOMP_RET_DATA)
+}
+
+impl Filter {
+    /// Build the filter. Unless `spawn` is set, `execve` is only allowed
+    /// for `program`, the exact pointer the executor execs with, so the
+    /// command cannot replace itself or start anything else.
+    pub fn new(spawn: bool, program: &CStr) -> Self {
+        let mut p = vec![
+            load(OFFSET_ARCH),
+            jump(
+                libc::BPF_JMP | libc::BPF_JEQ | libc::BPF_K,
+                AUDIT_ARCH,
+                1,
+                0,
+            ),
+            ret(KILL),
+            load(OFFSET_NR),
+        ];
+        #[cfg(target_arch = "x86_64")]
+        p.extend([
+            jump(
+                libc::BPF_JMP | libc::BPF_JGE | libc::BPF_K,
+                X32_SYSCALL_BIT,
+                0,
+                1,
+            ),
+            ret(KILL),
+        ]);
+        for nr in DENIED {
+            p.extend([if_nr(*nr, 1), ret(KILL)]);
+        }
+
+        // glibc probes the name service cache over a unix socket and
+        // copes with a refusal; any other socket is a violation
+        p.extend([
+            if_nr(libc::SYS_socket, 4),
+            load(OFFSET_ARGS),
+            jump(
+                libc::BPF_JMP | libc::BPF_JEQ | libc::BPF_K,
+                libc::AF_UNIX as u32,
+                0,
+                1,
+            ),
+            ret(errno(libc::EACCES)),
+            ret(KILL),
+        ]);
+
+        if !spawn {
+            for nr in SPAWN {
+                p.extend([if_nr(*nr, 1), ret(KILL)]);
+            }
+            // Threads are fine, new processes are not. clone3 hides its
+            // flags behind a pointer, so send libc back to clone.
+            p.extend([
+                if_nr(libc::SYS_clone3, 1),
+                ret(errno(libc::ENOSYS)),
+                if_nr(libc::SYS_clone, 4),
+                load(OFFSET_ARGS),
+                jump(
+                    libc::BPF_JMP | libc::BPF_JSET | libc::BPF_K,
+                    libc::CLONE_THREAD as u32,
+                    0,
+                    1,
+                ),
+                ret(ALLOW),
+                ret(KILL),
+            ]);
+            let ptr = program.as_ptr() as u64;
+            p.extend([
+                if_nr(libc::SYS_execve, 6),
+                load(OFFSET_ARGS),
+                jump(
+                    libc::BPF_JMP | libc::BPF_JEQ | libc::BPF_K,
+                    ptr as u32,
+                    0,
+                    3,
+                ),
+                load(OFFSET_ARGS + 4),
+                jump(
+                    libc::BPF_JMP | libc::BPF_JEQ | libc::BPF_K,
+                    (ptr >> 32) as u32,
+                    0,
+                    1,
+                ),
+                ret(ALLOW),
+                ret(KILL),
+            ]);
+        }
+        p.push(ret(ALLOW));
+        Self { program: p }
+    }
+
+    /// Load the filter into the calling process; it survives exec.
+    ///
+    /// # Safety
+    /// Must only be called between fork and exec.
+    pub unsafe fn install(&self) -> io::Result<()> {
+        if libc::prctl(libc::PR_SET_NO_NEW_PRIVS, 1, 0, 0, 0) != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let prog = libc::sock_fprog {
+            len: self.program.len() as u16,
+            filter: self.program.as_ptr() as *mut libc::sock_filter,
+        };
+        if libc::syscall(
+            libc::SYS_seccomp,
+            libc::SECCOMP_SET_MODE_FILTER,
+            0u32,
+            &prog as *const libc::sock_fprog,
+        ) != 0
+        {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(())
+    }
+}
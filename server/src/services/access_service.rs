@@ -0,0 +1,87 @@
+use crate::persistence::wal::WalEntry;
+use crate::state::AppState;
+use chrono::Utc;
+use uuid::Uuid;
+
+/// Record a read, write or exec against a repository.
+pub fn touch_repo(state: &AppState, repo_id: Uuid) {
+    if let Some(mut repo) = state.repos.get_mut(&repo_id) {
+        repo.last_accessed_at = Utc::now();
+    } else {
+        return;
+    }
+    state.access_dirty.entry(repo_id).or_default();
+}
+
+/// Record a read of a single file, bumping its access stats.
+pub fn record_file_access(state: &AppState, repo_id: Uuid, rel_path: &str) {
+    let now = Utc::now();
+    let found = state
+        .files
+        .get(&repo_id)
+        .and_then(|files| {
+            files.get_mut(rel_path).map(|mut file| {
+                file.last_accessed_at = now;
+                file.access_count += 1;
+            })
+        })
+        .is_some();
+
+    touch_repo(state, repo_id);
+    if found {
+        state
+            .access_dirty
+            .entry(repo_id)
+            .or_default()
+            .insert(rel_path.to_string());
+    }
+}
+
+/// Write aggregated access stats to the WAL so they survive a restart.
+/// Returns the number of repos flushed.
+pub async fn flush(state: &AppState) -> usize {
+    let repo_ids: Vec<Uuid> = state.access_dirty.iter().map(|e| *e.key()).collect();
+    let mut flushed = 0;
+
+    for repo_id in repo_ids {
+        let Some((_, paths)) = state.access_dirty.remove(&repo_id) else {
+            continue;
+        };
+        let Some(last_accessed_at) = state.repos.get(&repo_id).map(|r| r.last_accessed_at) else {
+            continue;
+        };
+        let files: Vec<(String, u64, chrono::DateTime<Utc>)> = state
+            .files
+            .get(&repo_id)
+            .map(|files| {
+                paths
+                    .iter()
+                    .filter_map(|path| {
+                        files
+                            .get(path)
+                            .map(|f| (path.clone(), f.access_count, f.last_accessed_at))
+                    })
+                    .collect()
+            })
+            .unwrap_or_default();
+
+        let result = {
+            let mut wal = state.wal.write().await;
+            wal.append(&WalEntry::AccessRecorded {
+                repo_id,
+                last_accessed_at,
+                files,
+            })
+        };
+
+        match result {
+            Ok(()) => flushed += 1,
+            Err(e) => {
+                tracing::error!(repo_id = %repo_id, error = %e, "Failed to flush access stats");
+                state.access_dirty.entry(repo_id).or_default().extend(paths);
+            }
+        }
+    }
+
+    flushed
+}
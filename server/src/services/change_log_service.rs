@@ -0,0 +1,214 @@
+use crate::error::AppError;
+use crate::models::change::{ChangeRecord, ChangesPage};
+use crate::models::event::ChangeKind;
+use crate::models::trash::TrashedItem;
+use crate::state::AppState;
+use chrono::{Duration, Utc};
+use std::collections::HashMap;
+use uuid::Uuid;
+
+const DEFAULT_PAGE: usize = 1000;
+const MAX_PAGE: usize = 10_000;
+
+/// Load the change logs and bring `seq` on repos and files up to date,
+/// since WAL replay does not carry sequence numbers. Repos are also
+/// raised past every seq their restored files, trash and checkpoints
+/// carry, so a record the log lost is never handed out again.
+pub fn load(state: &AppState) {
+    let mut log = state.changes.lock().unwrap();
+    match log.load() {
+        Ok(0) => {}
+        Ok(count) => tracing::info!(repos = count, "Loaded change logs"),
+        Err(e) => tracing::error!(error = %e, "Failed to load change logs"),
+    }
+
+    for repo_id in log.repo_ids() {
+        let Some(records) = log.records(repo_id) else {
+            continue;
+        };
+        if let Some(mut repo) = state.repos.get_mut(&repo_id) {
+            repo.seq = repo.seq.max(log.latest_seq(repo_id));
+        }
+        if let Some(files) = state.files.get(&repo_id) {
+            for record in records {
+                if let Some(mut meta) = record.path.as_deref().and_then(|p| files.get_mut(p)) {
+                    meta.seq = meta.seq.max(record.seq);
+                }
+            }
+        }
+    }
+    drop(log);
+
+    let mut seen: HashMap<Uuid, u64> = HashMap::new();
+    let mut see = |repo_id: Uuid, seq: u64| {
+        let max = seen.entry(repo_id).or_default();
+        *max = (*max).max(seq);
+    };
+    for files in state.files.iter() {
+        for meta in files.iter() {
+            see(*files.key(), meta.seq);
+        }
+    }
+    for entry in state.trash.iter() {
+        match &entry.item {
+            TrashedItem::File { file } => see(file.repo_id, file.seq),
+            TrashedItem::Repo { repo, .. } => see(repo.id, repo.seq),
+        }
+    }
+    for checkpoint in state.checkpoints.iter() {
+        for meta in &checkpoint.files {
+            see(checkpoint.repo_id, meta.seq);
+        }
+    }
+    for (repo_id, seq) in seen {
+        if let Some(mut repo) = state.repos.get_mut(&repo_id) {
+            repo.seq = repo.seq.max(seq);
+        }
+    }
+}
+
+/// Give a change the repo's next sequence number, append it to the log,
+/// and stamp the repo and file metadata with it.
+pub fn record(
+    state: &AppState,
+    repo_id: Uuid,
+    kind: ChangeKind,
+    path: Option<&str>,
+    previous_path: Option<&str>,
+) -> ChangeRecord {
+    let content = match kind {
+        ChangeKind::FileCreated | ChangeKind::FileUpdated | ChangeKind::FileMoved => {
+            path.and_then(|p| {
+                state
+                    .files
+                    .get(&repo_id)
+                    .and_then(|files| files.get(p).map(|f| (f.size_bytes, f.etag.clone())))
+            })
+        }
+        _ => None,
+    };
+
+    let mut log = state.changes.lock().unwrap();
+    let current = state.repos.get(&repo_id).map(|r| r.seq).unwrap_or(0);
+    let record = ChangeRecord {
+        seq: log.latest_seq(repo_id).max(current) + 1,
+        kind,
+        path: path.map(str::to_string),
+        previous_path: previous_path.map(str::to_string),
+        size_bytes: content.as_ref().map(|(size, _)| *size),
+        etag: content.map(|(_, etag)| etag),
+        timestamp: Utc::now(),
+    };
+    if let Err(e) = log.append(repo_id, record.clone()) {
+        tracing::error!(repo_id = %repo_id, seq = record.seq, error = %e, "Change log write failed");
+    }
+
+    if let Some(mut repo) = state.repos.get_mut(&repo_id) {
+        repo.seq = record.seq;
+    }
+    if let Some(p) = path {
+        if let Some(files) = state.files.get(&repo_id) {
+            if let Some(mut meta) = files.get_mut(p) {
+                meta.seq = record.seq;
+            }
+        }
+    }
+    record
+}
+
+/// Changes after `since`, oldest first. Fails with 410 when records after
+/// `since` have been pruned, or `since` is ahead of the log, and the
+/// caller has to resync from a full listing.
+pub fn list_changes(
+    state: &AppState,
+    repo_id: Uuid,
+    since: u64,
+    limit: Option<usize>,
+) -> Result<ChangesPage, AppError> {
+    let repo_seq = state
+        .repos
+        .get(&repo_id)
+        .map(|r| r.seq)
+        .ok_or_else(|| AppError::NotFound(format!("Repository {} not found", repo_id)))?;
+    let limit = limit.unwrap_or(DEFAULT_PAGE).clamp(1, MAX_PAGE);
+
+    let log = state.changes.lock().unwrap();
+    if log.is_broken(repo_id) {
+        return Err(AppError::Gone(
+            "Resync required: the change log could not be written to disk".into(),
+        ));
+    }
+    let latest_seq = repo_seq.max(log.latest_seq(repo_id));
+    let records = log.records(repo_id);
+    let oldest = records
+        .and_then(|r| r.front())
+        .map(|r| r.seq)
+        .unwrap_or(latest_seq + 1);
+    if since > latest_seq || since + 1 < oldest {
+        return Err(AppError::Gone(format!(
+            "Resync required: changes after seq {} are no longer retained \
+             (oldest retained seq {}, latest seq {})",
+            since, oldest, latest_seq
+        )));
+    }
+
+    let mut changes: Vec<ChangeRecord> = records
+        .map(|r| {
+            r.iter()
+                .filter(|c| c.seq > since)
+                .take(limit + 1)
+                .cloned()
+                .collect()
+        })
+        .unwrap_or_default();
+    let has_more = changes.len() > limit;
+    changes.truncate(limit);
+
+    Ok(ChangesPage {
+        repo_id,
+        since,
+        latest_seq,
+        changes,
+        has_more,
+    })
+}
+
+/// Sync every change recorded so far to disk.
+pub async fn flush(state: &AppState) {
+    if let Err(e) = state.changes.lock().unwrap().sync() {
+        tracing::error!(error = %e, "Change log sync failed");
+    }
+}
+
+/// Apply the retention window and size cap to every log, and delete logs
+/// for repositories that are gone for good. Returns records dropped.
+pub fn prune(state: &AppState) -> u64 {
+    let cutoff = Utc::now() - Duration::seconds(state.config.change_log_retention_secs as i64);
+    let max_entries = state.config.change_log_max_entries.max(1);
+    let trashed: std::collections::HashSet<Uuid> = state.trash.iter().map(|e| e.repo_id).collect();
+
+    let mut log = state.changes.lock().unwrap();
+    let mut dropped = 0u64;
+    for repo_id in log.repo_ids() {
+        let latest = log.latest_seq(repo_id);
+        let result = log.prune(repo_id, |r| {
+            r.timestamp > cutoff && latest - r.seq < max_entries as u64
+        });
+        match result {
+            Ok(n) => dropped += n as u64,
+            Err(e) => tracing::warn!(repo_id = %repo_id, error = %e, "Failed to prune change log"),
+        }
+
+        // A deleted repo keeps its log while it can still be restored
+        let expired = log
+            .records(repo_id)
+            .and_then(|r| r.back())
+            .is_none_or(|r| r.timestamp <= cutoff);
+        if expired && !state.repos.contains_key(&repo_id) && !trashed.contains(&repo_id) {
+            if let Err(e) = log.remove(repo_id) {
+                tracing::warn!(repo_id = %repo_id, error = %e, "Failed to remove change log");
+            }
+        }
+    }
+    dropped
+}
@@ -0,0 +1,107 @@
+use crate::error::AppError;
+use crate::models::checkpoint::{Checkpoint, CheckpointInfo, CreateCheckpointRequest};
+use crate::persistence::wal::WalEntry;
+use crate::services::access_service;
+use crate::state::AppState;
+use chrono::Utc;
+use uuid::Uuid;
+
+/// Checkpoints kept per repository; older ones must be deleted first.
+const MAX_CHECKPOINTS_PER_REPO: usize = 100;
+
+fn require_repo(state: &AppState, repo_id: Uuid) -> Result<(), AppError> {
+    if !state.repos.contains_key(&repo_id) {
+        return Err(AppError::NotFound(format!(
+            "Repository {} not found",
+            repo_id
+        )));
+    }
+    Ok(())
+}
+
+/// Save the metadata of every file in the repo.
+pub async fn create(
+    state: &AppState,
+    repo_id: Uuid,
+    req: CreateCheckpointRequest,
+) -> Result<CheckpointInfo, AppError> {
+    require_repo(state, repo_id)?;
+    let count = state
+        .checkpoints
+        .iter()
+        .filter(|c| c.repo_id == repo_id)
+        .count();
+    if count >= MAX_CHECKPOINTS_PER_REPO {
+        return Err(AppError::Conflict(format!(
+            "Repository {} already has {} checkpoints; delete one first",
+            repo_id, count
+        )));
+    }
+
+    // Writers append to the WAL before touching memory, so holding it
+    // gives a view of the files no write is halfway through.
+    let mut wal = state.wal.write().await;
+    let files = state
+        .files
+        .get(&repo_id)
+        .map(|files| files.iter().map(|f| f.value().clone()).collect())
+        .unwrap_or_default();
+    let checkpoint = Checkpoint {
+        id: Uuid::new_v4(),
+        repo_id,
+        name: req.name,
+        created_at: Utc::now(),
+        files,
+    };
+    wal.append(&WalEntry::CheckpointCreated {
+        checkpoint: Box::new(checkpoint.clone()),
+    })
+    .map_err(|e| AppError::Internal(format!("WAL write failed: {}", e)))?;
+    let info = CheckpointInfo::from(&checkpoint);
+    state.checkpoints.insert(checkpoint.id, checkpoint);
+    drop(wal);
+
+    access_service::touch_repo(state, repo_id);
+    Ok(info)
+}
+
+/// The repo's checkpoints, newest first.
+pub fn list(state: &AppState, repo_id: Uuid) -> Result<Vec<CheckpointInfo>, AppError> {
+    require_repo(state, repo_id)?;
+    let mut checkpoints: Vec<CheckpointInfo> = state
+        .checkpoints
+        .iter()
+        .filter(|c| c.repo_id == repo_id)
+        .map(|c| CheckpointInfo::from(c.value()))
+        .collect();
+    checkpoints.sort_by_key(|c| std::cmp::Reverse(c.created_at));
+    Ok(checkpoints)
+}
+
+pub async fn delete(state: &AppState, repo_id: Uuid, id: Uuid) -> Result<(), AppError> {
+    if state
+        .checkpoints
+        .get(&id)
+        .is_none_or(|c| c.repo_id != repo_id)
+    {
+        return Err(AppError::NotFound(format!("Checkpoint {} not found", id)));
+    }
+
+    {
+        let mut wal = state.wal.write().await;
+        wal.append(&WalEntry::CheckpointDeleted { id })
+            .map_err(|e| AppError::Internal(format!("WAL write failed: {}", e)))?;
+    }
+    state.checkpoints.remove(&id);
+    Ok(())
+}
+
+pub fn get(state: &AppState, id: Uuid) -> Option<Checkpoint> {
+    state.checkpoints.get(&id).map(|c| c.value().clone())
+}
+
+/// Drop the checkpoints of a repository that is gone for good. Replay
+/// does the same when it sees the repo deleted or its trash entry purged.
+pub fn forget_repo(state: &AppState, repo_id: Uuid) {
+    state.checkpoints.retain(|_, c| c.repo_id != repo_id);
+}
@@ -0,0 +1,222 @@
+use crate::error::AppError;
+use crate::models::delta::{BlockSignature, DeltaQuery, DeltaResult, FileSignature};
+use crate::services::{access_service, file_service, repo_service};
+use crate::state::AppState;
+use sha2::{Digest, Sha256};
+use std::io::{Read, Seek, SeekFrom};
+use uuid::Uuid;
+
+pub const DEFAULT_BLOCK_SIZE: usize = 64 * 1024;
+const MIN_BLOCK_SIZE: usize = 512;
+const MAX_BLOCK_SIZE: usize = 16 * 1024 * 1024;
+
+const OP_COPY: u8 = 0x01;
+const OP_LITERAL: u8 = 0x02;
+
+/// rsync's rolling checksum: two 16-bit sums packed into a u32. A client
+/// can slide it along its data one byte at a time.
+pub fn weak_checksum(data: &[u8]) -> u32 {
+    let (mut a, mut b) = (0u32, 0u32);
+    let len = data.len() as u32;
+    for (i, &byte) in data.iter().enumerate() {
+        a = a.wrapping_add(byte as u32);
+        b = b.wrapping_add((len - i as u32).wrapping_mul(byte as u32));
+    }
+    (a & 0xffff) | (b << 16)
+}
+
+fn validate_block_size(block_size: usize) -> Result<usize, AppError> {
+    if !(MIN_BLOCK_SIZE..=MAX_BLOCK_SIZE).contains(&block_size) {
+        return Err(AppError::BadRequest(format!(
+            "block_size must be between {} and {}",
+            MIN_BLOCK_SIZE, MAX_BLOCK_SIZE
+        )));
+    }
+    Ok(block_size)
+}
+
+fn current_etag(state: &AppState, repo_id: Uuid, rel_path: &str) -> Result<String, AppError> {
+    if !state.repos.contains_key(&repo_id) {
+        return Err(AppError::NotFound(format!(
+            "Repository {} not found",
+            repo_id
+        )));
+    }
+    state
+        .files
+        .get(&repo_id)
+        .and_then(|files| files.get(rel_path).map(|f| f.etag.clone()))
+        .ok_or_else(|| AppError::NotFound(format!("File not found: {}", rel_path)))
+}
+
+/// Block checksums of the current version of a file.
+pub async fn signature(
+    state: &AppState,
+    repo_id: Uuid,
+    rel_path: &str,
+    block_size: Option<usize>,
+) -> Result<FileSignature, AppError> {
+    let block_size = validate_block_size(block_size.unwrap_or(DEFAULT_BLOCK_SIZE))?;
+    let etag = current_etag(state, repo_id, rel_path)?;
+    access_service::touch_repo(state, repo_id);
+
+    let disk_path = file_service::resolve_file_path(state, repo_id, rel_path);
+    let (size_bytes, blocks) = tokio::task::spawn_blocking(move || {
+        let mut file = std::fs::File::open(&disk_path)?;
+        let mut buf = vec![0u8; block_size];
+        let mut blocks = Vec::new();
+        let mut size_bytes = 0u64;
+        loop {
+            let n = read_full(&mut file, &mut buf)?;
+            if n == 0 {
+                break;
+            }
+            blocks.push(BlockSignature {
+                index: blocks.len() as u64,
+                weak: weak_checksum(&buf[..n]),
+                strong: hex::encode(Sha256::digest(&buf[..n])),
+            });
+            size_bytes += n as u64;
+            if n < block_size {
+                break;
+            }
+        }
+        Ok::<_, std::io::Error>((size_bytes, blocks))
+    })
+    .await
+    .map_err(|e| AppError::Internal(format!("Signature task failed: {}", e)))??;
+
+    Ok(FileSignature {
+        path: rel_path.to_string(),
+        etag,
+        size_bytes,
+        block_size,
+        blocks,
+    })
+}
+
+/// Fill `buf` unless the file ends first; returns the bytes read.
+fn read_full(file: &mut std::fs::File, buf: &mut [u8]) -> std::io::Result<usize> {
+    let mut filled = 0;
+    while filled < buf.len() {
+        match file.read(&mut buf[filled..])? {
+            0 => break,
+            n => filled += n,
+        }
+    }
+    Ok(filled)
+}
+
+/// Rebuild a file from its current version and a delta, check the result
+/// against the expected sha256, and store it like a normal upload.
+pub async fn apply_delta(
+    state: &AppState,
+    repo_id: Uuid,
+    rel_path: &str,
+    query: DeltaQuery,
+    delta: bytes::Bytes,
+    ttl_seconds: Option<u64>,
+) -> Result<DeltaResult, AppError> {
+    repo_service::validate_ttl("ttl_seconds", ttl_seconds)?;
+    let block_size = validate_block_size(query.block_size)?;
+    let etag = current_etag(state, repo_id, rel_path)?;
+    if etag != query.base_etag {
+        return Err(AppError::Conflict(format!(
+            "{} has changed since the signature was taken; fetch a new signature",
+            rel_path
+        )));
+    }
+
+    let disk_path = file_service::resolve_file_path(state, repo_id, rel_path);
+    let max_size = state.config.max_upload_size;
+    let (data, copied_bytes, literal_bytes) = tokio::task::spawn_blocking(move || {
+        let file = std::fs::File::open(&disk_path)?;
+        rebuild(file, &delta, block_size, max_size)
+    })
+    .await
+    .map_err(|e| AppError::Internal(format!("Delta task failed: {}", e)))??;
+
+    if hex::encode(Sha256::digest(&data)) != query.sha256.to_ascii_lowercase() {
+        return Err(AppError::BadRequest(
+            "Reconstructed file does not match the expected sha256".into(),
+        ));
+    }
+
+    let upload = file_service::upload_file(
+        state,
+        repo_id,
+        rel_path,
+        bytes::Bytes::from(data),
+        ttl_seconds,
+    )
+    .await?;
+    Ok(DeltaResult {
+        upload,
+        copied_bytes,
+        literal_bytes,
+    })
+}
+
+fn rebuild(
+    mut base: std::fs::File,
+    delta: &[u8],
+    block_size: usize,
+    max_size: u64,
+) -> Result<(Vec<u8>, u64, u64), AppError> {
+    let base_len = base.metadata()?.len();
+    let malformed = || AppError::BadRequest("Malformed delta".into());
+    let mut out: Vec<u8> = Vec::new();
+    let (mut copied, mut literal) = (0u64, 0u64);
+    let mut rest = delta;
+
+    while let Some((&op, tail)) = rest.split_first() {
+        match op {
+            OP_COPY => {
+                let (start, tail) = tail.split_at_checked(8).ok_or_else(malformed)?;
+                let (count, tail) = tail.split_at_checked(4).ok_or_else(malformed)?;
+                rest = tail;
+                let start = u64::from_le_bytes(start.try_into().unwrap());
+                let count = u32::from_le_bytes(count.try_into().unwrap()) as u64;
+                let offset = start
+                    .checked_mul(block_size as u64)
+                    .filter(|o| *o < base_len)
+                    .ok_or_else(|| {
+                        AppError::BadRequest(format!("Delta copies missing block {}", start))
+                    })?;
+                let len = (count * block_size as u64).min(base_len - offset);
+                check_size(out.len() as u64 + len, max_size)?;
+                base.seek(SeekFrom::Start(offset))?;
+                let at = out.len();
+                out.resize(at + len as usize, 0);
+                base.read_exact(&mut out[at..])?;
+                copied += len;
+            }
+            OP_LITERAL => {
+                let (len, tail) = tail.split_at_checked(4).ok_or_else(malformed)?;
+                let len = u32::from_le_bytes(len.try_into().unwrap()) as usize;
+                let (data, tail) = tail.split_at_checked(len).ok_or_else(malformed)?;
+                rest = tail;
+                check_size(out.len() as u64 + len as u64, max_size)?;
+                out.extend_from_slice(data);
+                literal += len as u64;
+            }
+            other => {
+                return Err(AppError::BadRequest(format!(
+                    "Unknown delta instruction 0x{:02x}",
+                    other
+                )))
+            }
+        }
+    }
+    Ok((out, copied, literal))
+}
+
+fn check_size(size: u64, max_size: u64) -> Result<(), AppError> {
+    if size > max_size {
+        return Err(AppError::PayloadTooLarge(format!(
+            "Reconstructed file exceeds max upload size {}",
+            max_size
+        )));
+    }
+    Ok(())
+}
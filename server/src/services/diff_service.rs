@@ -0,0 +1,304 @@
+use crate::error::AppError;
+use crate::models::checkpoint::Checkpoint;
+use crate::models::diff::{DiffFile, DiffQuery, DiffSide, ModifiedFile, RenamedFile, RepoDiff};
+use crate::models::file::FileMeta;
+use crate::sandbox::path_validator;
+use crate::services::{access_service, checkpoint_service, file_service};
+use crate::state::AppState;
+use similar::TextDiff;
+use std::collections::{BTreeMap, HashMap};
+use uuid::Uuid;
+
+const DEFAULT_MAX_TEXT_BYTES: u64 = 1_048_576;
+const MAX_TEXT_BYTES_LIMIT: u64 = 10_485_760;
+const DEFAULT_MAX_TOTAL_TEXT_BYTES: u64 = 8_388_608;
+const MAX_TOTAL_TEXT_BYTES_LIMIT: u64 = 52_428_800;
+
+/// Compare the files of `repo_id` against another repository, its fork
+/// source, a checkpoint, or another prefix of itself. Files are matched by
+/// path relative to each side's prefix and compared by etag. Text diffs
+/// are capped per file and for the whole response; past the total cap the
+/// rest are left out and the diff is marked truncated. Checkpoints keep no
+/// content, so modified files get no text diff against one.
+pub async fn diff_repo(
+    state: &AppState,
+    repo_id: Uuid,
+    query: DiffQuery,
+) -> Result<RepoDiff, AppError> {
+    let repo = state
+        .repos
+        .get(&repo_id)
+        .map(|r| r.value().clone())
+        .ok_or_else(|| AppError::NotFound(format!("Repository {} not found", repo_id)))?;
+
+    let (base_id, checkpoint) = match query.against.as_deref() {
+        None => (repo_id, None),
+        Some("parent") => (
+            repo.forked_from.ok_or_else(|| {
+                AppError::BadRequest(format!("Repository {} is not a fork", repo_id))
+            })?,
+            None,
+        ),
+        Some(other) => {
+            let id = Uuid::parse_str(other).map_err(|_| {
+                AppError::BadRequest(format!(
+                    "Invalid 'against' value: {}; expected 'parent', a repository id or a checkpoint id",
+                    other
+                ))
+            })?;
+            match checkpoint_service::get(state, id) {
+                Some(checkpoint) => (checkpoint.repo_id, Some(checkpoint)),
+                None => (id, None),
+            }
+        }
+    };
+    if checkpoint.is_none() && !state.repos.contains_key(&base_id) {
+        return Err(AppError::NotFound(format!(
+            "Repository or checkpoint {} not found",
+            base_id
+        )));
+    }
+
+    let target_prefix = normalize_prefix(query.prefix.as_deref())?;
+    let base_prefix = match query.against_prefix.as_deref() {
+        Some(p) => normalize_prefix(Some(p))?,
+        None => target_prefix.clone(),
+    };
+    if checkpoint.is_none() && base_id == repo_id && base_prefix == target_prefix {
+        return Err(AppError::BadRequest(
+            "Nothing to compare: specify 'against' or a different 'against_prefix'".into(),
+        ));
+    }
+
+    access_service::touch_repo(state, repo_id);
+
+    let base = match &checkpoint {
+        Some(checkpoint) => checkpoint_files(checkpoint, base_prefix.as_deref()),
+        None => collect_files(state, base_id, base_prefix.as_deref()),
+    };
+    let target = collect_files(state, repo_id, target_prefix.as_deref());
+
+    let mut added: Vec<&FileMeta> = Vec::new();
+    let mut removed: Vec<&FileMeta> = Vec::new();
+    let mut changed: Vec<(&FileMeta, &FileMeta)> = Vec::new();
+
+    for (rel, new) in &target {
+        match base.get(rel) {
+            None => added.push(new),
+            Some(old) if old.etag != new.etag => changed.push((old, new)),
+            Some(_) => {}
+        }
+    }
+    for (rel, old) in &base {
+        if !target.contains_key(rel) {
+            removed.push(old);
+        }
+    }
+
+    // Rename detection: pair removed and added files with identical content.
+    let mut removed_by_etag: HashMap<&str, Vec<usize>> = HashMap::new();
+    for (i, old) in removed.iter().enumerate() {
+        removed_by_etag
+            .entry(old.etag.as_str())
+            .or_default()
+            .push(i);
+    }
+    let mut renamed_from = vec![false; removed.len()];
+    let mut renamed = Vec::new();
+    added.retain(|new| {
+        let Some(candidates) = removed_by_etag.get_mut(new.etag.as_str()) else {
+            return true;
+        };
+        let Some(i) = candidates.pop() else {
+            return true;
+        };
+        renamed_from[i] = true;
+        renamed.push(RenamedFile {
+            from: relative(&removed[i].path, base_prefix.as_deref()).to_string(),
+            to: relative(&new.path, target_prefix.as_deref()).to_string(),
+            size_bytes: new.size_bytes,
+            etag: new.etag.clone(),
+        });
+        false
+    });
+    let removed: Vec<&FileMeta> = removed
+        .into_iter()
+        .enumerate()
+        .filter(|(i, _)| !renamed_from[*i])
+        .map(|(_, meta)| meta)
+        .collect();
+
+    let include_text = query.include_text.unwrap_or(false);
+    let max_text_bytes = query
+        .max_text_bytes
+        .unwrap_or(DEFAULT_MAX_TEXT_BYTES)
+        .min(MAX_TEXT_BYTES_LIMIT);
+    let max_total_text_bytes = query
+        .max_total_text_bytes
+        .unwrap_or(DEFAULT_MAX_TOTAL_TEXT_BYTES)
+        .min(MAX_TOTAL_TEXT_BYTES_LIMIT);
+
+    let mut modified = Vec::with_capacity(changed.len());
+    let mut total_text_bytes = 0;
+    let mut truncated = false;
+    for (old, new) in changed {
+        let rel = relative(&new.path, target_prefix.as_deref()).to_string();
+        let (unified_diff, diff_skipped) = if !include_text {
+            (None, None)
+        } else if checkpoint.is_some() {
+            (None, Some("checkpoints keep no content to diff against".to_string()))
+        } else if truncated {
+            (None, Some(total_limit_reason(max_total_text_bytes)))
+        } else {
+            match text_diff(state, old, new, &rel, max_text_bytes).await {
+                // Stop at the first diff that does not fit, so the text
+                // returned is a prefix of the full diff in path order
+                Ok(diff) if total_text_bytes + diff.len() as u64 > max_total_text_bytes => {
+                    truncated = true;
+                    (None, Some(total_limit_reason(max_total_text_bytes)))
+                }
+                Ok(diff) => {
+                    total_text_bytes += diff.len() as u64;
+                    (Some(diff), None)
+                }
+                Err(reason) => (None, Some(reason)),
+            }
+        };
+        modified.push(ModifiedFile {
+            path: rel,
+            old_size_bytes: old.size_bytes,
+            new_size_bytes: new.size_bytes,
+            old_etag: old.etag.clone(),
+            new_etag: new.etag.clone(),
+            unified_diff,
+            diff_skipped,
+        });
+    }
+
+    renamed.sort_by(|a, b| a.to.cmp(&b.to));
+
+    Ok(RepoDiff {
+        base: DiffSide {
+            repo_id: base_id,
+            checkpoint_id: checkpoint.as_ref().map(|c| c.id),
+            prefix: base_prefix.clone(),
+        },
+        target: DiffSide {
+            repo_id,
+            checkpoint_id: None,
+            prefix: target_prefix.clone(),
+        },
+        added: added
+            .into_iter()
+            .map(|m| diff_file(m, target_prefix.as_deref()))
+            .collect(),
+        removed: removed
+            .into_iter()
+            .map(|m| diff_file(m, base_prefix.as_deref()))
+            .collect(),
+        modified,
+        renamed,
+        truncated,
+    })
+}
+
+fn total_limit_reason(max_total_bytes: u64) -> String {
+    format!("response reached its {} byte total diff limit", max_total_bytes)
+}
+
+fn normalize_prefix(prefix: Option<&str>) -> Result<Option<String>, AppError> {
+    match prefix {
+        None | Some("") | Some("/") => Ok(None),
+        Some(p) => Ok(Some(format!(
+            "{}/",
+            path_validator::validate_relative_path(p)?
+        ))),
+    }
+}
+
+fn relative<'a>(path: &'a str, prefix: Option<&str>) -> &'a str {
+    prefix.and_then(|p| path.strip_prefix(p)).unwrap_or(path)
+}
+
+/// Files under `prefix`, keyed by their path relative to it.
+fn collect_files(
+    state: &AppState,
+    repo_id: Uuid,
+    prefix: Option<&str>,
+) -> BTreeMap<String, FileMeta> {
+    state
+        .files
+        .get(&repo_id)
+        .map(|files| {
+            files
+                .iter()
+                .filter_map(|entry| under_prefix(entry.value(), prefix))
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// [`collect_files`] for the files saved in a checkpoint.
+fn checkpoint_files(checkpoint: &Checkpoint, prefix: Option<&str>) -> BTreeMap<String, FileMeta> {
+    checkpoint
+        .files
+        .iter()
+        .filter_map(|meta| under_prefix(meta, prefix))
+        .collect()
+}
+
+fn under_prefix(meta: &FileMeta, prefix: Option<&str>) -> Option<(String, FileMeta)> {
+    let rel = match prefix {
+        Some(p) => meta.path.strip_prefix(p)?,
+        None => meta.path.as_str(),
+    };
+    Some((rel.to_string(), meta.clone()))
+}
+
+fn diff_file(meta: &FileMeta, prefix: Option<&str>) -> DiffFile {
+    DiffFile {
+        path: relative(&meta.path, prefix).to_string(),
+        size_bytes: meta.size_bytes,
+        etag: meta.etag.clone(),
+    }
+}
+
+/// Unified diff of two text files, or the reason it was not produced.
+async fn text_diff(
+    state: &AppState,
+    old: &FileMeta,
+    new: &FileMeta,
+    rel: &str,
+    max_bytes: u64,
+) -> Result<String, String> {
+    if old.size_bytes > max_bytes || new.size_bytes > max_bytes {
+        return Err(format!("file exceeds {} byte diff limit", max_bytes));
+    }
+
+    let old_path = file_service::resolve_file_path(state, old.repo_id, &old.path);
+    let new_path = file_service::resolve_file_path(state, new.repo_id, &new.path);
+    let old_data = tokio::fs::read(&old_path)
+        .await
+        .map_err(|e| format!("failed to read old version: {}", e))?;
+    let new_data = tokio::fs::read(&new_path)
+        .await
+        .map_err(|e| format!("failed to read new version: {}", e))?;
+
+    let (Some(old_text), Some(new_text)) = (as_text(&old_data), as_text(&new_data)) else {
+        return Err("binary file".into());
+    };
+
+    let diff = TextDiff::from_lines(old_text, new_text);
+    Ok(diff
+        .unified_diff()
+        .context_radius(3)
+        .header(&format!("a/{}", rel), &format!("b/{}", rel))
+        .to_string())
+}
+
+fn as_text(data: &[u8]) -> Option<&str> {
+    if data.contains(&0) {
+        return None;
+    }
+    std::str::from_utf8(data).ok()
+}
@@ -0,0 +1,131 @@
+use crate::models::change::ChangeRecord;
+use crate::models::event::{ChangeEvent, ChangeKind, EventCursor};
+use crate::services::{change_log_service, webhook_service};
+use crate::state::AppState;
+use std::collections::VecDeque;
+use std::sync::Mutex;
+use tokio::sync::broadcast;
+use uuid::Uuid;
+
+/// Fan-out of change events to feed subscribers, keeping the most recent
+/// ones so a reconnecting client can catch up.
+pub struct EventBus {
+    /// Drawn at startup, so ids from an earlier run are never mistaken for
+    /// this run's.
+    epoch: Uuid,
+    sender: broadcast::Sender<ChangeEvent>,
+    recent: Mutex<Recent>,
+    capacity: usize,
+}
+
+struct Recent {
+    events: VecDeque<ChangeEvent>,
+    next_id: u64,
+}
+
+/// What a subscriber missed since its last-seen id.
+pub enum Replay {
+    Events(Vec<ChangeEvent>),
+    /// The id is older than the buffer, ahead of it, or from a previous
+    /// run; the client has to relist.
+    Gap,
+}
+
+impl EventBus {
+    pub fn new(capacity: usize) -> Self {
+        let capacity = capacity.max(1);
+        let (sender, _) = broadcast::channel(capacity);
+        Self {
+            epoch: Uuid::new_v4(),
+            sender,
+            recent: Mutex::new(Recent {
+                events: VecDeque::with_capacity(capacity),
+                next_id: 1,
+            }),
+            capacity,
+        }
+    }
+
+    pub fn publish(&self, repo_id: Uuid, record: &ChangeRecord) {
+        let mut recent = self.recent.lock().unwrap();
+        let event = ChangeEvent {
+            id: recent.next_id,
+            epoch: self.epoch,
+            repo_id,
+            seq: record.seq,
+            kind: record.kind,
+            path: record.path.clone(),
+            previous_path: record.previous_path.clone(),
+            timestamp: record.timestamp,
+        };
+        recent.next_id += 1;
+        if recent.events.len() == self.capacity {
+            recent.events.pop_front();
+        }
+        recent.events.push_back(event.clone());
+        // No receivers is fine
+        let _ = self.sender.send(event);
+    }
+
+    /// Subscribe to live events, plus the buffered events after `after`.
+    /// Both are taken under the same lock, so nothing is missed or seen
+    /// twice.
+    pub fn subscribe(
+        &self,
+        after: Option<EventCursor>,
+    ) -> (Replay, broadcast::Receiver<ChangeEvent>) {
+        let recent = self.recent.lock().unwrap();
+        let receiver = self.sender.subscribe();
+        let Some(EventCursor { epoch, id: after }) = after else {
+            return (Replay::Events(Vec::new()), receiver);
+        };
+        if epoch != self.epoch {
+            return (Replay::Gap, receiver);
+        }
+
+        let last_id = recent.next_id - 1;
+        let oldest = recent
+            .events
+            .front()
+            .map(|e| e.id)
+            .unwrap_or(recent.next_id);
+        if after > last_id || after + 1 < oldest {
+            return (Replay::Gap, receiver);
+        }
+        let events = recent
+            .events
+            .iter()
+            .filter(|e| e.id > after)
+            .cloned()
+            .collect();
+        (Replay::Events(events), receiver)
+    }
+}
+
+/// Record a change in the repo's change log and push it to subscribers.
+/// Called after the mutation is in the WAL and in memory.
+fn emit(
+    state: &AppState,
+    repo_id: Uuid,
+    kind: ChangeKind,
+    path: Option<&str>,
+    previous_path: Option<&str>,
+) -> u64 {
+    let record = change_log_service::record(state, repo_id, kind, path, previous_path);
+    state.events.publish(repo_id, &record);
+    webhook_service::on_change(state, repo_id, &record);
+    record.seq
+}
+
+/// Returns the change's sequence number.
+pub fn file_event(state: &AppState, repo_id: Uuid, kind: ChangeKind, path: &str) -> u64 {
+    emit(state, repo_id, kind, Some(path), None)
+}
+
+pub fn file_moved(state: &AppState, repo_id: Uuid, from: &str, to: &str) -> u64 {
+    emit(state, repo_id, ChangeKind::FileMoved, Some(to), Some(from))
+}
+
+pub fn repo_event(state: &AppState, repo_id: Uuid, kind: ChangeKind) -> u64 {
+    emit(state, repo_id, kind, None, None)
+}
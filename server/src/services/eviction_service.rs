@@ -1,85 +1,374 @@
 use crate::error::AppError;
+use crate::models::file::FileMeta;
+use crate::models::repo::EvictionPolicy;
+use crate::models::trash::DeleteCause;
+use crate::services::pin_service::Pins;
+use crate::services::trash_service;
 use crate::state::AppState;
-use chrono::Utc;
+use chrono::{DateTime, Utc};
 use uuid::Uuid;
 
-/// Evict files from a repo to free at least `needed_bytes`.
-/// Returns the number of bytes freed.
+/// Files removed by one eviction pass.
+#[derive(Debug, Default)]
+pub struct Eviction {
+    pub freed_bytes: u64,
+    pub paths: Vec<String>,
+}
+
+/// GDSF's inflation value L for one repo. L rises to the priority of each
+/// file evicted, so a file read since then is prioritised above files that
+/// have sat idle, however often those were read before. The steps L rose
+/// in are kept so each file is ranked with L as it stood when the file was
+/// last used. Held in memory only; after a restart L starts from zero.
+#[derive(Debug, Clone, Default)]
+pub struct GdsfClock {
+    steps: Vec<(DateTime<Utc>, f64)>,
+}
+
+impl GdsfClock {
+    /// L as it stood at `at`.
+    fn at(&self, at: DateTime<Utc>) -> f64 {
+        let i = self.steps.partition_point(|&(t, _)| t <= at);
+        i.checked_sub(1).map_or(0.0, |i| self.steps[i].1)
+    }
+
+    fn current(&self) -> f64 {
+        self.steps.last().map_or(0.0, |&(_, l)| l)
+    }
+
+    /// Raise L to `priority`, the priority of a file just evicted.
+    fn raise(&mut self, now: DateTime<Utc>, priority: f64) {
+        if priority > self.current() {
+            self.steps.push((now, priority));
+        }
+    }
+
+    /// Drop steps no file last used at or after `oldest` can need.
+    fn prune(&mut self, oldest: DateTime<Utc>) {
+        let i = self.steps.partition_point(|&(t, _)| t <= oldest);
+        if i > 1 {
+            self.steps.drain(..i - 1);
+        }
+    }
+
+    /// GDSF priority with unit fetch cost: L when the file was last used
+    /// plus frequency over size.
+    fn priority(&self, meta: &FileMeta) -> f64 {
+        self.at(last_used(meta))
+            + (meta.access_count + 1) as f64 / meta.size_bytes.max(1) as f64
+    }
+}
+
+fn last_used(meta: &FileMeta) -> DateTime<Utc> {
+    meta.last_accessed_at.max(meta.updated_at)
+}
+
+impl EvictionPolicy {
+    /// Order candidates so the first element is evicted first. `clock` is
+    /// the repo's GDSF clock, used only by that policy.
+    /// Returns `None` when the policy forbids eviction.
+    pub fn rank(
+        &self,
+        mut files: Vec<FileMeta>,
+        now: DateTime<Utc>,
+        clock: &GdsfClock,
+    ) -> Option<Vec<FileMeta>> {
+        match self {
+            EvictionPolicy::Never => return None,
+            EvictionPolicy::Lru => files.sort_by_key(|f| f.last_accessed_at),
+            EvictionPolicy::Fifo => files.sort_by_key(|f| f.created_at),
+            EvictionPolicy::Lfu => files.sort_by(|a, b| {
+                access_rate(a, now)
+                    .partial_cmp(&access_rate(b, now))
+                    .unwrap_or(std::cmp::Ordering::Equal)
+                    .then(a.last_accessed_at.cmp(&b.last_accessed_at))
+            }),
+            EvictionPolicy::Gdsf => files.sort_by(|a, b| {
+                clock
+                    .priority(a)
+                    .partial_cmp(&clock.priority(b))
+                    .unwrap_or(std::cmp::Ordering::Equal)
+                    .then(a.last_accessed_at.cmp(&b.last_accessed_at))
+            }),
+        }
+        Some(files)
+    }
+}
+
+fn access_rate(meta: &FileMeta, now: DateTime<Utc>) -> f64 {
+    let age = now
+        .signed_duration_since(meta.created_at)
+        .num_seconds()
+        .max(1) as f64;
+    meta.access_count as f64 / age
+}
+
+/// Unpinned files of a repo other than `keep`, ranked by the repo's
+/// eviction policy. `None` when the repo is missing or never evicts.
+fn ranked_candidates(
+    state: &AppState,
+    repo_id: Uuid,
+    keep: Option<&str>,
+    now: DateTime<Utc>,
+) -> Option<Vec<FileMeta>> {
+    let policy = state.repos.get(&repo_id).map(|r| r.eviction_policy)?;
+    let pins = Pins::for_repo(state, repo_id);
+    let candidates: Vec<FileMeta> = state
+        .files
+        .get(&repo_id)
+        .map(|files| {
+            files
+                .iter()
+                .filter(|f| Some(f.key().as_str()) != keep && !pins.protects(f.value()))
+                .map(|f| f.value().clone())
+                .collect()
+        })
+        .unwrap_or_default();
+    if policy != EvictionPolicy::Gdsf {
+        return policy.rank(candidates, now, &GdsfClock::default());
+    }
+    let mut clock = state.gdsf_clocks.entry(repo_id).or_default();
+    if let Some(oldest) = candidates.iter().map(last_used).min() {
+        clock.prune(oldest);
+    }
+    policy.rank(candidates, now, &clock)
+}
+
+/// Delete `ranked` files in order until `needed_bytes` are freed. Under
+/// GDSF each eviction raises the repo's clock to the evicted priority.
+async fn remove_ranked(
+    state: &AppState,
+    repo_id: Uuid,
+    ranked: Vec<FileMeta>,
+    needed_bytes: u64,
+) -> Result<Eviction, AppError> {
+    let gdsf = state
+        .repos
+        .get(&repo_id)
+        .is_some_and(|r| r.eviction_policy == EvictionPolicy::Gdsf);
+    let mut eviction = Eviction::default();
+    for meta in ranked {
+        if eviction.freed_bytes >= needed_bytes {
+            break;
+        }
+        match crate::services::file_service::delete_file(
+            state,
+            repo_id,
+            &meta.path,
+            DeleteCause::Eviction,
+        )
+        .await
+        {
+            Ok(()) => {
+                if gdsf {
+                    let mut clock = state.gdsf_clocks.entry(repo_id).or_default();
+                    let priority = clock.priority(&meta);
+                    clock.raise(Utc::now(), priority);
+                }
+                eviction.freed_bytes += meta.size_bytes;
+                eviction.paths.push(meta.path);
+            }
+            // Removed concurrently; nothing to free
+            Err(AppError::NotFound(_)) => continue,
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(eviction)
+}
+
+/// Evict files from a repo, in the order chosen by its eviction policy,
+/// until at least `needed_bytes` are freed. Pinned files and `keep`,
+/// typically the path being overwritten by the upload that triggered this,
+/// are never evicted. Nothing is evicted if the unpinned files cannot
+/// free enough space.
 pub async fn evict_bytes(
     state: &AppState,
     repo_id: Uuid,
     needed_bytes: u64,
-) -> Result<u64, AppError> {
-    let files_map = match state.files.get(&repo_id) {
-        Some(f) => f,
-        None => return Ok(0),
-    };
+    keep: Option<&str>,
+) -> Result<Eviction, AppError> {
+    let policy = state
+        .repos
+        .get(&repo_id)
+        .map(|r| r.eviction_policy)
+        .ok_or_else(|| AppError::NotFound(format!("Repository {} not found", repo_id)))?;
 
-    let now = Utc::now();
+    let ranked = ranked_candidates(state, repo_id, keep, Utc::now()).ok_or_else(|| {
+        AppError::PayloadTooLarge(format!(
+            "Repository size limit exceeded by {} bytes and eviction is disabled",
+            needed_bytes
+        ))
+    })?;
+
+    let reclaimable: u64 = ranked.iter().map(|f| f.size_bytes).sum();
+    if reclaimable < needed_bytes {
+        return Err(AppError::PayloadTooLarge(format!(
+            "Repository size limit exceeded by {} bytes but only {} bytes of unpinned data are reclaimable",
+            needed_bytes, reclaimable
+        )));
+    }
+
+    let eviction = remove_ranked(state, repo_id, ranked, needed_bytes).await?;
+
+    if !eviction.paths.is_empty() {
+        tracing::info!(
+            repo_id = %repo_id,
+            policy = ?policy,
+            freed_bytes = eviction.freed_bytes,
+            files = eviction.paths.len(),
+            "Evicted files"
+        );
+    }
 
-    // Score files: score = access_count / age_seconds (higher = more valuable)
-    let mut scored: Vec<(String, f64, u64)> = files_map
+    Ok(eviction)
+}
+
+/// Bytes stored across all repositories and the trash.
+pub fn total_size_bytes(state: &AppState) -> u64 {
+    let live: u64 = state.repos.iter().map(|r| r.current_size_bytes).sum();
+    live + trash_service::size_bytes(state)
+}
+
+/// Repositories in the order they give up space when the server-wide
+/// budget is exceeded, first to be evicted first. See
+/// `AppConfig::global_eviction_order`.
+fn global_order(state: &AppState, now: DateTime<Utc>) -> Vec<Uuid> {
+    let tag_key = &state.config.global_eviction_tag;
+    let mut repos: Vec<(Uuid, i64, u32, i64)> = state
+        .repos
         .iter()
-        .map(|entry| {
-            let meta = entry.value();
-            let age = now
-                .signed_duration_since(meta.created_at)
-                .num_seconds()
-                .max(1) as f64;
-            let score = meta.access_count as f64 / age;
-            (meta.path.clone(), score, meta.size_bytes)
+        .map(|r| {
+            let idle = now.signed_duration_since(r.last_accessed_at).num_seconds();
+            let tag_priority = r
+                .tags
+                .get(tag_key)
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(0);
+            (r.id, idle, r.eviction_weight, tag_priority)
         })
         .collect();
 
-    // Sort by score ascending (evict lowest score first)
-    scored.sort_by(|a, b| a.1.partial_cmp(&b.1).unwrap_or(std::cmp::Ordering::Equal));
+    match state.config.global_eviction_order.as_str() {
+        "weight" => repos.sort_by_key(|&(_, idle, weight, _)| (weight, std::cmp::Reverse(idle))),
+        "tag" => repos.sort_by_key(|&(_, idle, _, tag)| (tag, std::cmp::Reverse(idle))),
+        _ => repos.sort_by_key(|&(_, idle, _, _)| std::cmp::Reverse(idle)),
+    }
+    repos.into_iter().map(|(id, ..)| id).collect()
+}
+
+/// Free at least `needed_bytes`, purging the trash oldest first and then
+/// evicting across repositories in global priority order. `keep` protects
+/// the file being written. When `best_effort` is false nothing is purged
+/// or evicted unless the trash and unpinned data together cover
+/// `needed_bytes`.
+async fn evict_global(
+    state: &AppState,
+    needed_bytes: u64,
+    keep: Option<(Uuid, &str)>,
+    best_effort: bool,
+) -> Result<Vec<(Uuid, Eviction)>, AppError> {
+    let now = Utc::now();
+    let ranked: Vec<(Uuid, Vec<FileMeta>)> = global_order(state, now)
+        .into_iter()
+        .filter_map(|repo_id| {
+            let keep_path = keep.filter(|(id, _)| *id == repo_id).map(|(_, p)| p);
+            ranked_candidates(state, repo_id, keep_path, now).map(|files| (repo_id, files))
+        })
+        .collect();
+
+    let reclaimable: u64 = ranked
+        .iter()
+        .flat_map(|(_, files)| files.iter().map(|f| f.size_bytes))
+        .sum::<u64>()
+        + trash_service::size_bytes(state);
+    if !best_effort && reclaimable < needed_bytes {
+        return Err(AppError::PayloadTooLarge(format!(
+            "Server storage budget exceeded by {} bytes but only {} bytes of trash and unpinned data are reclaimable",
+            needed_bytes, reclaimable
+        )));
+    }
 
-    let mut freed = 0u64;
-    for (path, _score, size) in &scored {
+    let mut freed = trash_service::purge_oldest(state, needed_bytes).await;
+    let mut evictions = Vec::new();
+    for (repo_id, files) in ranked {
         if freed >= needed_bytes {
             break;
         }
-        // Delete the file
-        drop(files_map);
-        crate::services::file_service::delete_file(state, repo_id, path).await?;
-        freed += size;
-        // Re-acquire the map reference
-        if state.files.get(&repo_id).is_none() {
-            break;
+        let eviction = remove_ranked(state, repo_id, files, needed_bytes - freed).await?;
+        if eviction.paths.is_empty() {
+            continue;
         }
-        return evict_continue(state, repo_id, needed_bytes, freed, scored).await;
+        freed += eviction.freed_bytes;
+        tracing::info!(
+            repo_id = %repo_id,
+            freed_bytes = eviction.freed_bytes,
+            files = eviction.paths.len(),
+            "Evicted files for server storage budget"
+        );
+        evictions.push((repo_id, eviction));
+    }
+    // Evicted files kept in the trash still take up space
+    if trash_service::uses_trash(state, DeleteCause::Eviction) {
+        let evicted: u64 = evictions.iter().map(|(_, e)| e.freed_bytes).sum();
+        trash_service::purge_oldest(state, evicted).await;
     }
 
-    Ok(freed)
+    Ok(evictions)
 }
 
-async fn evict_continue(
+/// Make room for `incoming_bytes` more data within `cache_max_bytes`,
+/// evicting across repositories if needed. A budget of 0 is unlimited.
+pub async fn reserve_global(
     state: &AppState,
-    repo_id: Uuid,
-    needed_bytes: u64,
-    mut freed: u64,
-    scored: Vec<(String, f64, u64)>,
-) -> Result<u64, AppError> {
-    for (path, _score, size) in scored.iter().skip(1) {
-        if freed >= needed_bytes {
-            break;
-        }
-        if state.files.get(&repo_id).is_none() {
-            break;
+    incoming_bytes: u64,
+    keep: Option<(Uuid, &str)>,
+) -> Result<Vec<(Uuid, Eviction)>, AppError> {
+    let budget = state.config.cache_max_bytes;
+    let used = total_size_bytes(state);
+    if budget == 0 || used + incoming_bytes <= budget {
+        return Ok(Vec::new());
+    }
+    evict_global(state, used + incoming_bytes - budget, keep, false).await
+}
+
+/// Check the server-wide budget without evicting, for writes that should
+/// not displace other data.
+pub fn check_global(state: &AppState, incoming_bytes: u64) -> Result<(), AppError> {
+    let budget = state.config.cache_max_bytes;
+    let used = total_size_bytes(state);
+    if budget != 0 && used + incoming_bytes > budget {
+        return Err(AppError::PayloadTooLarge(format!(
+            "Server storage budget of {} bytes would be exceeded by {} bytes",
+            budget,
+            used + incoming_bytes - budget
+        )));
+    }
+    Ok(())
+}
+
+/// Bring the server back under `cache_max_bytes`, evicting as much
+/// unpinned data as needed and available.
+pub async fn evict_over_budget(state: &AppState) {
+    let budget = state.config.cache_max_bytes;
+    let used = total_size_bytes(state);
+    if budget == 0 || used <= budget {
+        return;
+    }
+
+    match evict_global(state, used - budget, None, true).await {
+        Ok(evictions) => {
+            let freed: u64 = evictions.iter().map(|(_, e)| e.freed_bytes).sum();
+            if freed < used - budget {
+                tracing::warn!(
+                    over_bytes = used - budget - freed,
+                    "Server storage budget still exceeded after eviction"
+                );
+            }
         }
-        // Check if file still exists (may have been removed already)
-        let exists = state
-            .files
-            .get(&repo_id)
-            .map(|f| f.contains_key(path.as_str()))
-            .unwrap_or(false);
-        if !exists {
-            continue;
+        Err(e) => {
+            tracing::error!(error = %e, "Global eviction failed");
         }
-        crate::services::file_service::delete_file(state, repo_id, path).await?;
-        freed += size;
     }
-    Ok(freed)
 }
 
 /// Proactively check all repos and evict if over limit.
@@ -87,19 +376,28 @@ pub async fn evict_over_limit_repos(state: &AppState) {
     let repo_ids: Vec<Uuid> = state.repos.iter().map(|r| *r.key()).collect();
 
     for repo_id in repo_ids {
-        let (current, max) = match state.repos.get(&repo_id) {
-            Some(r) => (r.current_size_bytes, r.max_size_bytes),
+        let (current, max, policy) = match state.repos.get(&repo_id) {
+            Some(r) => (r.current_size_bytes, r.max_size_bytes, r.eviction_policy),
             None => continue,
         };
 
         if current > max {
+            if policy == EvictionPolicy::Never {
+                tracing::warn!(
+                    repo_id = %repo_id,
+                    over_bytes = current - max,
+                    "Repository over limit with eviction disabled"
+                );
+                continue;
+            }
+
             let needed = current - max;
-            match evict_bytes(state, repo_id, needed).await {
-                Ok(freed) => {
-                    if freed > 0 {
+            match evict_bytes(state, repo_id, needed, None).await {
+                Ok(eviction) => {
+                    if eviction.freed_bytes > 0 {
                         tracing::info!(
                             repo_id = %repo_id,
-                            freed_bytes = freed,
+                            freed_bytes = eviction.freed_bytes,
                             "Proactive eviction completed"
                         );
                     }
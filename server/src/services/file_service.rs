@@ -1,14 +1,21 @@
 use crate::error::AppError;
-use crate::models::file::FileMeta;
+use crate::models::event::ChangeKind;
+use crate::models::file::{FileMeta, UploadResult};
+use crate::models::repo::checked_deadline;
+use crate::models::trash::DeleteCause;
 use crate::persistence::wal::WalEntry;
+use crate::services::{
+    access_service, event_service, eviction_service, repo_service, trash_service,
+    watch_service,
+};
 use crate::state::AppState;
-use chrono::{Duration, Utc};
+use chrono::Utc;
 use sha2::{Digest, Sha256};
 use std::path::{Path, PathBuf};
 use tokio::io::AsyncWriteExt;
 use uuid::Uuid;
 
-fn repo_files_dir(state: &AppState, repo_id: Uuid) -> PathBuf {
+pub(crate) fn repo_files_dir(state: &AppState, repo_id: Uuid) -> PathBuf {
     state
         .config
         .repos_dir()
@@ -20,13 +27,103 @@ pub fn resolve_file_path(state: &AppState, repo_id: Uuid, rel_path: &str) -> Pat
     repo_files_dir(state, repo_id).join(rel_path)
 }
 
+/// A unique scratch path on the data volume, outside every repo tree.
+pub async fn temp_file_path(state: &AppState) -> Result<PathBuf, AppError> {
+    let dir = state.config.tmp_dir();
+    tokio::fs::create_dir_all(&dir).await?;
+    Ok(dir.join(Uuid::new_v4().to_string()))
+}
+
+/// How a file was materialized by [`clone_file`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CloneMethod {
+    Reflink,
+    Hardlink,
+    Copy,
+}
+
+/// Materialize `src` at `dst`, sharing storage where possible.
+/// Tries a `FICLONE` reflink first, then falls back to a hardlink or a
+/// plain copy depending on `allow_hardlink`.
+pub fn clone_file(src: &Path, dst: &Path, allow_hardlink: bool) -> std::io::Result<CloneMethod> {
+    if let Some(parent) = dst.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+
+    if reflink(src, dst).is_ok() {
+        return Ok(CloneMethod::Reflink);
+    }
+
+    if allow_hardlink && std::fs::hard_link(src, dst).is_ok() {
+        return Ok(CloneMethod::Hardlink);
+    }
+
+    std::fs::copy(src, dst)?;
+    Ok(CloneMethod::Copy)
+}
+
+/// Give every hardlinked file under `root` an inode of its own, so a
+/// command writing one in place cannot change another repo's copy.
+/// Copies are staged in `tmp_dir`, which must be on the same filesystem.
+pub fn unshare_links(root: &Path, tmp_dir: &Path) -> std::io::Result<()> {
+    use std::os::unix::fs::MetadataExt;
+
+    let mut dirs = vec![root.to_path_buf()];
+    while let Some(dir) = dirs.pop() {
+        for entry in std::fs::read_dir(&dir)? {
+            let entry = entry?;
+            let meta = entry.metadata()?;
+            if meta.is_dir() {
+                dirs.push(entry.path());
+            } else if meta.is_file() && meta.nlink() > 1 {
+                std::fs::create_dir_all(tmp_dir)?;
+                let tmp = tmp_dir.join(Uuid::new_v4().to_string());
+                std::fs::copy(entry.path(), &tmp)?;
+                if let Err(e) = std::fs::rename(&tmp, entry.path()) {
+                    let _ = std::fs::remove_file(&tmp);
+                    return Err(e);
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(target_os = "linux")]
+fn reflink(src: &Path, dst: &Path) -> std::io::Result<()> {
+    use std::os::fd::AsRawFd;
+
+    let src_file = std::fs::File::open(src)?;
+    let dst_file = std::fs::OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(dst)?;
+
+    // SAFETY: both descriptors are valid for the duration of the call.
+    let ret = unsafe { libc::ioctl(dst_file.as_raw_fd(), libc::FICLONE, src_file.as_raw_fd()) };
+    if ret == -1 {
+        let err = std::io::Error::last_os_error();
+        drop(dst_file);
+        let _ = std::fs::remove_file(dst);
+        return Err(err);
+    }
+    Ok(())
+}
+
+#[cfg(not(target_os = "linux"))]
+fn reflink(_src: &Path, _dst: &Path) -> std::io::Result<()> {
+    Err(std::io::Error::from(std::io::ErrorKind::Unsupported))
+}
+
 pub async fn upload_file(
     state: &AppState,
     repo_id: Uuid,
     rel_path: &str,
     data: bytes::Bytes,
     ttl_seconds: Option<u64>,
-) -> Result<FileMeta, AppError> {
+) -> Result<UploadResult, AppError> {
+    repo_service::validate_ttl("ttl_seconds", ttl_seconds)?;
+
     // Check repo exists
     let default_ttl = {
         let repo = state
@@ -35,38 +132,54 @@ pub async fn upload_file(
             .ok_or_else(|| AppError::NotFound(format!("Repository {} not found", repo_id)))?;
         repo.default_ttl_seconds
     };
+    access_service::touch_repo(state, repo_id);
 
     let file_size = data.len() as u64;
 
     // Check size limits
-    {
-        let repo = state.repos.get(&repo_id).unwrap();
-        if file_size > state.config.max_upload_size {
+    if file_size > state.config.max_upload_size {
+        return Err(AppError::PayloadTooLarge(format!(
+            "File size {} exceeds max upload size {}",
+            file_size, state.config.max_upload_size
+        )));
+    }
+
+    // Check if existing file - we'll subtract its size and keep its pin
+    let (existing_size, pinned) = state
+        .files
+        .get(&repo_id)
+        .and_then(|files| files.get(rel_path).map(|f| (f.size_bytes, f.pinned)))
+        .unwrap_or((0, false));
+
+    let (current_size, max_size) = state
+        .repos
+        .get(&repo_id)
+        .map(|r| (r.current_size_bytes, r.max_size_bytes))
+        .ok_or_else(|| AppError::NotFound(format!("Repository {} not found", repo_id)))?;
+
+    let mut evicted = Vec::new();
+    let new_total = current_size.saturating_sub(existing_size) + file_size;
+    if new_total > max_size {
+        // Try eviction
+        let needed = new_total - max_size;
+        let eviction =
+            eviction_service::evict_bytes(state, repo_id, needed, Some(rel_path)).await?;
+        if eviction.freed_bytes < needed {
             return Err(AppError::PayloadTooLarge(format!(
-                "File size {} exceeds max upload size {}",
-                file_size, state.config.max_upload_size
+                "Repository size limit exceeded. Need {} more bytes",
+                needed - eviction.freed_bytes
             )));
         }
+        evicted = eviction.paths;
+    }
 
-        // Check if existing file - we'll subtract its size
-        let existing_size = state
-            .files
-            .get(&repo_id)
-            .and_then(|files| files.get(rel_path).map(|f| f.size_bytes))
-            .unwrap_or(0);
-
-        let new_total = repo.current_size_bytes - existing_size + file_size;
-        if new_total > repo.max_size_bytes {
-            // Try eviction
-            let needed = new_total - repo.max_size_bytes;
-            let freed =
-                crate::services::eviction_service::evict_bytes(state, repo_id, needed).await?;
-            if freed < needed {
-                return Err(AppError::PayloadTooLarge(format!(
-                    "Repository size limit exceeded. Need {} more bytes",
-                    needed - freed
-                )));
-            }
+    // Then the server-wide budget, which may evict from other repos
+    let incoming = file_size.saturating_sub(existing_size);
+    for (evicted_repo, eviction) in
+        eviction_service::reserve_global(state, incoming, Some((repo_id, rel_path))).await?
+    {
+        if evicted_repo == repo_id {
+            evicted.extend(eviction.paths);
         }
     }
 
@@ -82,18 +195,27 @@ pub async fn upload_file(
 
     let now = Utc::now();
     let ttl = ttl_seconds.or(default_ttl);
-    let expires_at = ttl.map(|s| now + Duration::seconds(s as i64));
+    let expires_at = ttl.and_then(|s| checked_deadline(now, s));
 
-    // Write file to disk
+    // Write file to disk via a temp file and rename, so a path that is
+    // hardlinked into a fork gets a fresh inode instead of being
+    // modified in place.
     let file_path = resolve_file_path(state, repo_id, rel_path);
     if let Some(parent) = file_path.parent() {
         tokio::fs::create_dir_all(parent).await?;
     }
-    let mut file = tokio::fs::File::create(&file_path).await?;
+    let tmp_path = temp_file_path(state).await?;
+    let mut file = tokio::fs::File::create(&tmp_path).await?;
     file.write_all(&data).await?;
     file.flush().await?;
+    drop(file);
+    watch_service::mark_api_write(state, repo_id, rel_path, &tmp_path).await;
+    if let Err(e) = tokio::fs::rename(&tmp_path, &file_path).await {
+        let _ = tokio::fs::remove_file(&tmp_path).await;
+        return Err(e.into());
+    }
 
-    let meta = FileMeta {
+    let mut meta = FileMeta {
         repo_id,
         path: rel_path.to_string(),
         size_bytes: file_size,
@@ -104,6 +226,8 @@ pub async fn upload_file(
         last_accessed_at: now,
         access_count: 0,
         expires_at,
+        pinned,
+        seq: 0,
     };
 
     // WAL
@@ -137,7 +261,7 @@ pub async fn upload_file(
     state
         .files
         .entry(repo_id)
-        .or_insert_with(dashmap::DashMap::new)
+        .or_default()
         .insert(rel_path.to_string(), meta.clone());
 
     // Update repo size
@@ -148,8 +272,17 @@ pub async fn upload_file(
         }
         repo.updated_at = now;
     }
+    let kind = if is_new {
+        ChangeKind::FileCreated
+    } else {
+        ChangeKind::FileUpdated
+    };
+    meta.seq = event_service::file_event(state, repo_id, kind, rel_path);
 
-    Ok(meta)
+    Ok(UploadResult {
+        file: meta,
+        evicted,
+    })
 }
 
 pub async fn download_file(
@@ -171,13 +304,7 @@ pub async fn download_file(
         .and_then(|files| files.get(rel_path).map(|f| f.clone()))
         .ok_or_else(|| AppError::NotFound(format!("File not found: {}", rel_path)))?;
 
-    // Update access stats
-    if let Some(files) = state.files.get(&repo_id) {
-        if let Some(mut file) = files.get_mut(rel_path) {
-            file.last_accessed_at = Utc::now();
-            file.access_count += 1;
-        }
-    }
+    access_service::record_file_access(state, repo_id, rel_path);
 
     let file_path = resolve_file_path(state, repo_id, rel_path);
     if !file_path.exists() {
@@ -198,6 +325,7 @@ pub async fn head_file(
             repo_id
         )));
     }
+    access_service::touch_repo(state, repo_id);
 
     state
         .files
@@ -206,10 +334,13 @@ pub async fn head_file(
         .ok_or_else(|| AppError::NotFound(format!("File not found: {}", rel_path)))
 }
 
+/// Delete a file, moving it to the trash unless `cause` is configured
+/// to skip it.
 pub async fn delete_file(
     state: &AppState,
     repo_id: Uuid,
     rel_path: &str,
+    cause: DeleteCause,
 ) -> Result<(), AppError> {
     if !state.repos.contains_key(&repo_id) {
         return Err(AppError::NotFound(format!(
@@ -218,46 +349,63 @@ pub async fn delete_file(
         )));
     }
 
-    let file_size = state
+    let meta = state
         .files
         .get(&repo_id)
-        .and_then(|files| files.get(rel_path).map(|f| f.size_bytes))
+        .and_then(|files| files.get(rel_path).map(|f| f.clone()))
         .ok_or_else(|| AppError::NotFound(format!("File not found: {}", rel_path)))?;
 
-    // WAL
-    {
-        let mut wal = state.wal.write().await;
-        wal.append(&WalEntry::FileDeleted {
-            repo_id,
-            path: rel_path.to_string(),
-        })
-        .map_err(|e| AppError::Internal(format!("WAL write failed: {}", e)))?;
-    }
+    if trash_service::uses_trash(state, cause) {
+        trash_service::trash_file(state, meta, cause).await?;
+    } else {
+        // WAL
+        {
+            let mut wal = state.wal.write().await;
+            wal.append(&WalEntry::FileDeleted {
+                repo_id,
+                path: rel_path.to_string(),
+            })
+            .map_err(|e| AppError::Internal(format!("WAL write failed: {}", e)))?;
+        }
 
-    // Remove from memory
-    if let Some(files) = state.files.get(&repo_id) {
-        files.remove(rel_path);
+        forget_file(state, repo_id, rel_path);
+
+        // Remove from disk
+        let file_path = resolve_file_path(state, repo_id, rel_path);
+        if file_path.exists() {
+            tokio::fs::remove_file(&file_path).await?;
+            // Clean up empty parent dirs
+            cleanup_empty_dirs(&repo_files_dir(state, repo_id), &file_path).await;
+        }
     }
 
-    // Update repo stats
+    let kind = match cause {
+        DeleteCause::User => ChangeKind::FileDeleted,
+        DeleteCause::Eviction => ChangeKind::FileEvicted,
+        DeleteCause::Expiry => ChangeKind::FileExpired,
+    };
+    event_service::file_event(state, repo_id, kind, rel_path);
+    Ok(())
+}
+
+/// Drop a file's metadata and take it off the repo's size and count.
+pub(crate) fn forget_file(state: &AppState, repo_id: Uuid, rel_path: &str) {
+    let Some(meta) = state
+        .files
+        .get(&repo_id)
+        .and_then(|files| files.remove(rel_path).map(|(_, meta)| meta))
+    else {
+        return;
+    };
+
     if let Some(mut repo) = state.repos.get_mut(&repo_id) {
-        repo.current_size_bytes = repo.current_size_bytes.saturating_sub(file_size);
+        repo.current_size_bytes = repo.current_size_bytes.saturating_sub(meta.size_bytes);
         repo.file_count = repo.file_count.saturating_sub(1);
         repo.updated_at = Utc::now();
     }
-
-    // Remove from disk
-    let file_path = resolve_file_path(state, repo_id, rel_path);
-    if file_path.exists() {
-        tokio::fs::remove_file(&file_path).await?;
-        // Clean up empty parent dirs
-        cleanup_empty_dirs(&repo_files_dir(state, repo_id), &file_path).await;
-    }
-
-    Ok(())
 }
 
-async fn cleanup_empty_dirs(root: &Path, file_path: &Path) {
+pub(crate) async fn cleanup_empty_dirs(root: &Path, file_path: &Path) {
     let mut dir = file_path.parent();
     while let Some(d) = dir {
         if d == root {
@@ -291,6 +439,7 @@ pub async fn list_files(
             repo_id
         )));
     }
+    access_service::touch_repo(state, repo_id);
 
     let files_map = state
         .files
@@ -339,6 +488,7 @@ pub async fn move_file(
             repo_id
         )));
     }
+    access_service::touch_repo(state, repo_id);
 
     let now = Utc::now();
 
@@ -380,6 +530,7 @@ pub async fn move_file(
     if let Some(parent) = dst_path.parent() {
         tokio::fs::create_dir_all(parent).await?;
     }
+    watch_service::mark_api_write(state, repo_id, destination, &src_path).await;
     tokio::fs::rename(&src_path, &dst_path).await?;
 
     // Update in-memory
@@ -391,7 +542,7 @@ pub async fn move_file(
     state
         .files
         .entry(repo_id)
-        .or_insert_with(dashmap::DashMap::new)
+        .or_default()
         .insert(destination.to_string(), meta.clone());
 
     // Cleanup empty dirs
@@ -400,6 +551,7 @@ pub async fn move_file(
         &src_path,
     )
     .await;
+    meta.seq = event_service::file_moved(state, repo_id, source, destination);
 
     Ok(meta)
 }
@@ -416,6 +568,7 @@ pub async fn copy_file(
             repo_id
         )));
     }
+    access_service::touch_repo(state, repo_id);
 
     let now = Utc::now();
 
@@ -448,6 +601,7 @@ pub async fn copy_file(
             ));
         }
     }
+    eviction_service::check_global(state, src_meta.size_bytes)?;
 
     // Copy on disk
     let src_path = resolve_file_path(state, repo_id, source);
@@ -456,8 +610,9 @@ pub async fn copy_file(
         tokio::fs::create_dir_all(parent).await?;
     }
     tokio::fs::copy(&src_path, &dst_path).await?;
+    watch_service::mark_api_write(state, repo_id, destination, &dst_path).await;
 
-    let meta = FileMeta {
+    let mut meta = FileMeta {
         repo_id,
         path: destination.to_string(),
         size_bytes: src_meta.size_bytes,
@@ -468,6 +623,8 @@ pub async fn copy_file(
         last_accessed_at: now,
         access_count: 0,
         expires_at: src_meta.expires_at,
+        pinned: false,
+        seq: 0,
     };
 
     // WAL
@@ -489,7 +646,7 @@ pub async fn copy_file(
     state
         .files
         .entry(repo_id)
-        .or_insert_with(dashmap::DashMap::new)
+        .or_default()
         .insert(destination.to_string(), meta.clone());
 
     // Update repo size
@@ -498,6 +655,7 @@ pub async fn copy_file(
         repo.file_count += 1;
         repo.updated_at = now;
     }
+    meta.seq = event_service::file_event(state, repo_id, ChangeKind::FileCreated, destination);
 
     Ok(meta)
 }
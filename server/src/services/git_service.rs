@@ -0,0 +1,851 @@
+use crate::error::AppError;
+use crate::models::event::ChangeKind;
+use crate::models::file::FileMeta;
+use crate::models::git::{
+    GitChangedFile, GitCommit, GitDiff, GitExportRequest, GitImportResult, GitShow,
+};
+use crate::models::repo::checked_deadline;
+use crate::models::trash::{DeleteCause, TrashEntry, TrashedItem};
+use crate::persistence::wal::WalEntry;
+use crate::sandbox::path_validator;
+use crate::services::{
+    access_service, event_service, eviction_service, file_service, trash_service, watch_service,
+};
+use crate::state::AppState;
+use chrono::{DateTime, Utc};
+use git2::{
+    Delta, DiffFormat, ObjectType, Oid, Repository, Signature, TreeWalkMode, TreeWalkResult,
+};
+use sha2::{Digest, Sha256};
+use std::collections::{HashMap, HashSet};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use uuid::Uuid;
+
+const BUNDLE_V2_SIGNATURE: &str = "# v2 git bundle";
+const BUNDLE_V3_SIGNATURE: &str = "# v3 git bundle";
+const DEFAULT_BRANCH: &str = "main";
+const COMMITTER_NAME: &str = "linux-fs";
+const COMMITTER_EMAIL: &str = "linux-fs@localhost";
+
+/// Object database for a repo. It lives next to `files/` so it is never
+/// visible to exec'd commands or listed as repository content.
+fn git_dir(state: &AppState, repo_id: Uuid) -> PathBuf {
+    state
+        .config
+        .repos_dir()
+        .join(repo_id.to_string())
+        .join("git")
+}
+
+fn ensure_repo_exists(state: &AppState, repo_id: Uuid) -> Result<(), AppError> {
+    if !state.repos.contains_key(&repo_id) {
+        return Err(AppError::NotFound(format!(
+            "Repository {} not found",
+            repo_id
+        )));
+    }
+    Ok(())
+}
+
+fn open_or_init(dir: &PathBuf) -> Result<Repository, AppError> {
+    if dir.exists() {
+        Repository::open_bare(dir).map_err(git_error)
+    } else {
+        let repo = Repository::init_bare(dir).map_err(git_error)?;
+        repo.set_head(&format!("refs/heads/{}", DEFAULT_BRANCH))
+            .map_err(git_error)?;
+        Ok(repo)
+    }
+}
+
+fn open_existing(dir: &PathBuf, repo_id: Uuid) -> Result<Repository, AppError> {
+    if !dir.exists() {
+        return Err(AppError::NotFound(format!(
+            "Repository {} has no git history",
+            repo_id
+        )));
+    }
+    Repository::open_bare(dir).map_err(git_error)
+}
+
+fn git_error(e: git2::Error) -> AppError {
+    match e.code() {
+        git2::ErrorCode::NotFound => AppError::NotFound(e.message().to_string()),
+        git2::ErrorCode::InvalidSpec | git2::ErrorCode::Ambiguous => {
+            AppError::BadRequest(e.message().to_string())
+        }
+        _ => AppError::Internal(format!("Git error: {}", e.message())),
+    }
+}
+
+async fn blocking<T, F>(f: F) -> Result<T, AppError>
+where
+    F: FnOnce() -> Result<T, AppError> + Send + 'static,
+    T: Send + 'static,
+{
+    tokio::task::spawn_blocking(f)
+        .await
+        .map_err(|e| AppError::Internal(format!("Git task failed: {}", e)))?
+}
+
+struct Bundle<'a> {
+    prerequisites: Vec<Oid>,
+    refs: Vec<(String, Oid)>,
+    pack: &'a [u8],
+}
+
+/// Parse the header of a v2 or v3 bundle and locate the packfile.
+fn parse_bundle(data: &[u8]) -> Result<Bundle<'_>, AppError> {
+    let bad = |msg: &str| AppError::BadRequest(format!("Invalid git bundle: {}", msg));
+
+    let mut cursor = 0;
+    let mut next_line = || -> Result<&[u8], AppError> {
+        let rest = &data[cursor..];
+        let end = rest
+            .iter()
+            .position(|b| *b == b'\n')
+            .ok_or_else(|| bad("unterminated header"))?;
+        cursor += end + 1;
+        Ok(&rest[..end])
+    };
+
+    let signature = next_line()?;
+    let is_v3 = match std::str::from_utf8(signature).unwrap_or("") {
+        BUNDLE_V2_SIGNATURE => false,
+        BUNDLE_V3_SIGNATURE => true,
+        _ => return Err(bad("unrecognized signature")),
+    };
+
+    let mut prerequisites = Vec::new();
+    let mut refs = Vec::new();
+    loop {
+        let line = std::str::from_utf8(next_line()?).map_err(|_| bad("non-UTF-8 header"))?;
+        if line.is_empty() {
+            break;
+        }
+        if let Some(capability) = line.strip_prefix('@') {
+            if !is_v3 {
+                return Err(bad("capability in v2 bundle"));
+            }
+            if capability.starts_with("object-format=") && capability != "object-format=sha1" {
+                return Err(bad("only sha1 object format is supported"));
+            }
+            continue;
+        }
+        if let Some(prereq) = line.strip_prefix('-') {
+            let hex = prereq.split(' ').next().unwrap_or("");
+            prerequisites.push(Oid::from_str(hex).map_err(|_| bad("bad prerequisite"))?);
+            continue;
+        }
+        let (hex, name) = line.split_once(' ').ok_or_else(|| bad("bad ref line"))?;
+        refs.push((
+            name.to_string(),
+            Oid::from_str(hex).map_err(|_| bad("bad ref id"))?,
+        ));
+    }
+
+    if refs.is_empty() {
+        return Err(bad("no refs"));
+    }
+
+    Ok(Bundle {
+        prerequisites,
+        refs,
+        pack: &data[cursor..],
+    })
+}
+
+/// Pick the bundle ref to check out: the requested one, else the branch
+/// `HEAD` points at, else `HEAD` itself, else the first ref listed.
+fn select_ref(refs: &[(String, Oid)], requested: Option<&str>) -> Option<(String, Oid)> {
+    let find = |name: &str| refs.iter().find(|(n, _)| n == name).cloned();
+    match requested {
+        Some(r) => find(r)
+            .or_else(|| find(&format!("refs/heads/{}", r)))
+            .or_else(|| find(&format!("refs/tags/{}", r))),
+        None => match find("HEAD") {
+            Some((_, head)) => refs
+                .iter()
+                .find(|(n, oid)| n.starts_with("refs/heads/") && *oid == head)
+                .cloned()
+                .or_else(|| find("HEAD")),
+            None => refs.first().cloned(),
+        },
+    }
+}
+
+/// A blob from the imported tree.
+struct TreeFile {
+    path: String,
+    oid: Oid,
+    size_bytes: u64,
+}
+
+/// A blob written out to the staging directory.
+struct StagedFile {
+    path: String,
+    size_bytes: u64,
+    etag: String,
+}
+
+/// Import a bundle into the repo's object database and make the tree of
+/// the selected ref the repository's files. The tree is written to a
+/// staging directory and swapped in whole, so files it lacks are removed
+/// and a failed import leaves the repository as it was.
+pub async fn import_bundle(
+    state: &AppState,
+    repo_id: Uuid,
+    data: bytes::Bytes,
+    requested_ref: Option<String>,
+) -> Result<GitImportResult, AppError> {
+    let (current_size, max_size, default_ttl) = state
+        .repos
+        .get(&repo_id)
+        .map(|r| (r.current_size_bytes, r.max_size_bytes, r.default_ttl_seconds))
+        .ok_or_else(|| AppError::NotFound(format!("Repository {} not found", repo_id)))?;
+    access_service::touch_repo(state, repo_id);
+
+    // Objects go into the odb now; refs move only once the files are in
+    let dir = git_dir(state, repo_id);
+    let (repo, refs, git_ref, commit_id, tree_files, skipped, pack_bytes) = blocking(move || {
+        let bundle = parse_bundle(&data)?;
+        // Objects are kept outside the repo's files, but still count
+        // against its limit
+        let pack_bytes = bundle.pack.len() as u64;
+        let stored = dir_size(&dir);
+        if stored + pack_bytes > max_size {
+            return Err(AppError::PayloadTooLarge(format!(
+                "Bundle pack of {} bytes would grow the repository's git objects to {} bytes, over its size limit of {}",
+                pack_bytes,
+                stored + pack_bytes,
+                max_size
+            )));
+        }
+        let repo = open_or_init(&dir)?;
+        write_pack(&repo, &bundle)?;
+
+        let (git_ref, target) = match select_ref(&bundle.refs, requested_ref.as_deref()) {
+            Some(found) => found,
+            None => {
+                let requested = requested_ref.unwrap_or_default();
+                let object = repo.revparse_single(&requested).map_err(git_error)?;
+                (requested, object.id())
+            }
+        };
+        let commit_id = repo
+            .find_object(target, None)
+            .and_then(|o| o.peel_to_commit())
+            .map_err(git_error)?
+            .id();
+        let (tree_files, skipped) = list_tree(&repo, commit_id)?;
+        Ok((
+            repo,
+            bundle.refs,
+            git_ref,
+            commit_id,
+            tree_files,
+            skipped,
+            pack_bytes,
+        ))
+    })
+    .await?;
+
+    // The tree replaces the repository, so it has to fit on its own
+    let total: u64 = tree_files.iter().map(|f| f.size_bytes).sum();
+    if let Some(f) = tree_files
+        .iter()
+        .find(|f| f.size_bytes > state.config.max_upload_size)
+    {
+        return Err(AppError::PayloadTooLarge(format!(
+            "{} is {} bytes, over the max upload size {}",
+            f.path, f.size_bytes, state.config.max_upload_size
+        )));
+    }
+    if total + pack_bytes > max_size {
+        return Err(AppError::PayloadTooLarge(format!(
+            "Import of {} bytes plus its {} byte pack exceeds the repository size limit of {}",
+            total, pack_bytes, max_size
+        )));
+    }
+    eviction_service::check_global(state, total.saturating_sub(current_size) + pack_bytes)?;
+
+    let staging = file_service::temp_file_path(state).await?;
+    let staging_dir = staging.clone();
+    let (repo, staged) = blocking(move || {
+        let staged = stage_tree(&repo, &tree_files, &staging_dir);
+        if staged.is_err() {
+            let _ = std::fs::remove_dir_all(&staging_dir);
+        }
+        Ok((repo, staged?))
+    })
+    .await?;
+
+    if let Err(e) = swap_in(state, repo_id, &staging, &staged, default_ttl).await {
+        let _ = tokio::fs::remove_dir_all(&staging).await;
+        return Err(e);
+    }
+
+    let git_ref = blocking(move || {
+        for (name, oid) in &refs {
+            if name != "HEAD" {
+                repo.reference(name, *oid, true, "bundle import")
+                    .map_err(git_error)?;
+            }
+        }
+        if git_ref.starts_with("refs/heads/") {
+            repo.set_head(&git_ref).map_err(git_error)?;
+        } else {
+            repo.set_head_detached(commit_id).map_err(git_error)?;
+        }
+        Ok(git_ref)
+    })
+    .await?;
+
+    Ok(GitImportResult {
+        git_ref,
+        commit: commit_id.to_string(),
+        files_written: staged.len() as u64,
+        bytes_written: total,
+        skipped,
+    })
+}
+
+/// Bytes in the files under `dir`; 0 if it does not exist yet.
+fn dir_size(dir: &Path) -> u64 {
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return 0;
+    };
+    entries
+        .flatten()
+        .map(|entry| match entry.file_type() {
+            Ok(t) if t.is_dir() => dir_size(&entry.path()),
+            Ok(t) if t.is_file() => entry.metadata().map(|m| m.len()).unwrap_or(0),
+            _ => 0,
+        })
+        .sum()
+}
+
+/// Add the bundle's objects to the repository after checking it has the
+/// commits the bundle builds on.
+fn write_pack(repo: &Repository, bundle: &Bundle) -> Result<(), AppError> {
+    let odb = repo.odb().map_err(git_error)?;
+    for prereq in &bundle.prerequisites {
+        if !odb.exists(*prereq) {
+            return Err(AppError::BadRequest(format!(
+                "Bundle requires commit {} which this repository does not have",
+                prereq
+            )));
+        }
+    }
+
+    let mut writer = odb.packwriter().map_err(git_error)?;
+    writer
+        .write_all(bundle.pack)
+        .map_err(|e| AppError::BadRequest(format!("Invalid bundle packfile: {}", e)))?;
+    writer.commit().map_err(git_error)?;
+    Ok(())
+}
+
+/// Blobs in the commit's tree with paths the repository accepts, and the
+/// paths of everything else.
+fn list_tree(repo: &Repository, commit_id: Oid) -> Result<(Vec<TreeFile>, Vec<String>), AppError> {
+    let tree = repo
+        .find_commit(commit_id)
+        .and_then(|c| c.tree())
+        .map_err(git_error)?;
+    let odb = repo.odb().map_err(git_error)?;
+    let mut files = Vec::new();
+    let mut skipped = Vec::new();
+    let mut walk_error = None;
+    tree.walk(TreeWalkMode::PreOrder, |root, entry| {
+        let path = format!("{}{}", root, entry.name().unwrap_or(""));
+        match entry.kind() {
+            Some(ObjectType::Tree) => TreeWalkResult::Ok,
+            Some(ObjectType::Blob) if entry.filemode() != 0o120000 => {
+                let Ok(rel_path) = path_validator::validate_relative_path(&path) else {
+                    skipped.push(path);
+                    return TreeWalkResult::Ok;
+                };
+                match odb.read_header(entry.id()) {
+                    Ok((size, _)) => {
+                        files.push(TreeFile {
+                            path: rel_path,
+                            oid: entry.id(),
+                            size_bytes: size as u64,
+                        });
+                        TreeWalkResult::Ok
+                    }
+                    Err(e) => {
+                        walk_error = Some(git_error(e));
+                        TreeWalkResult::Abort
+                    }
+                }
+            }
+            // Symlinks and submodules have no place in the file index
+            _ => {
+                skipped.push(path);
+                TreeWalkResult::Ok
+            }
+        }
+    })
+    .map_err(git_error)?;
+    match walk_error {
+        Some(e) => Err(e),
+        None => Ok((files, skipped)),
+    }
+}
+
+/// Write each blob under `dir`, one at a time, hashing as it goes.
+fn stage_tree(
+    repo: &Repository,
+    files: &[TreeFile],
+    dir: &std::path::Path,
+) -> Result<Vec<StagedFile>, AppError> {
+    std::fs::create_dir_all(dir)?;
+    let mut staged = Vec::with_capacity(files.len());
+    for file in files {
+        let blob = repo.find_blob(file.oid).map_err(git_error)?;
+        let disk_path = dir.join(&file.path);
+        if let Some(parent) = disk_path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        std::fs::write(&disk_path, blob.content())?;
+        staged.push(StagedFile {
+            path: file.path.clone(),
+            size_bytes: file.size_bytes,
+            etag: hex::encode(Sha256::digest(blob.content())),
+        });
+    }
+    Ok(staged)
+}
+
+/// Replace the repository's files with the staged tree and record the
+/// result as one sync commit. Files the tree lacks go to the trash when
+/// user deletes do; files whose content is unchanged keep their metadata.
+async fn swap_in(
+    state: &AppState,
+    repo_id: Uuid,
+    staging: &std::path::Path,
+    staged: &[StagedFile],
+    default_ttl: Option<u64>,
+) -> Result<(), AppError> {
+    let now = Utc::now();
+    let previous: HashMap<String, FileMeta> = state
+        .files
+        .get(&repo_id)
+        .map(|files| {
+            files
+                .iter()
+                .map(|f| (f.key().clone(), f.value().clone()))
+                .collect()
+        })
+        .unwrap_or_default();
+    let expires_at = default_ttl.and_then(|s| checked_deadline(now, s));
+    let metas: Vec<FileMeta> = staged
+        .iter()
+        .map(|s| match previous.get(&s.path) {
+            Some(old) if old.etag == s.etag => old.clone(),
+            old => FileMeta {
+                repo_id,
+                path: s.path.clone(),
+                size_bytes: s.size_bytes,
+                etag: s.etag.clone(),
+                content_type: mime_guess::from_path(&s.path)
+                    .first_or_octet_stream()
+                    .to_string(),
+                created_at: old.map(|m| m.created_at).unwrap_or(now),
+                updated_at: now,
+                last_accessed_at: now,
+                access_count: 0,
+                expires_at,
+                pinned: old.is_some_and(|m| m.pinned),
+                seq: 0,
+            },
+        })
+        .collect();
+    let kept: HashSet<&str> = staged.iter().map(|s| s.path.as_str()).collect();
+    let mut removed: Vec<FileMeta> = previous
+        .values()
+        .filter(|m| !kept.contains(m.path.as_str()))
+        .cloned()
+        .collect();
+    removed.sort_by(|a, b| a.path.cmp(&b.path));
+
+    let trashed: Vec<TrashEntry> = match trash_service::uses_trash(state, DeleteCause::User) {
+        true => removed
+            .iter()
+            .map(|meta| {
+                trash_service::new_entry(
+                    state,
+                    repo_id,
+                    DeleteCause::User,
+                    meta.size_bytes,
+                    TrashedItem::File { file: meta.clone() },
+                )
+            })
+            .collect(),
+        false => Vec::new(),
+    };
+    let deleted: Vec<String> = match trashed.is_empty() {
+        true => removed.iter().map(|m| m.path.clone()).collect(),
+        false => Vec::new(),
+    };
+
+    let mut wal = state.wal.write().await;
+    let files_dir = file_service::repo_files_dir(state, repo_id);
+    let replaced = file_service::temp_file_path(state).await?;
+    watch_service::unwatch_repo(state, repo_id);
+    tokio::fs::rename(&files_dir, &replaced).await?;
+    if let Err(e) = tokio::fs::rename(staging, &files_dir).await {
+        let _ = tokio::fs::rename(&replaced, &files_dir).await;
+        watch_service::watch_repo(state, repo_id);
+        return Err(e.into());
+    }
+    watch_service::watch_repo(state, repo_id);
+
+    if let Err(e) = wal.append(&WalEntry::SyncCommitted {
+        repo_id,
+        files: metas.clone(),
+        deleted,
+        trashed: trashed.clone(),
+    }) {
+        watch_service::unwatch_repo(state, repo_id);
+        let _ = tokio::fs::rename(&files_dir, staging).await;
+        let _ = tokio::fs::rename(&replaced, &files_dir).await;
+        watch_service::watch_repo(state, repo_id);
+        return Err(AppError::Internal(format!("WAL write failed: {}", e)));
+    }
+
+    let map = dashmap::DashMap::new();
+    for meta in &metas {
+        map.insert(meta.path.clone(), meta.clone());
+    }
+    state.files.insert(repo_id, map);
+    if let Some(mut repo) = state.repos.get_mut(&repo_id) {
+        repo.current_size_bytes = metas.iter().map(|m| m.size_bytes).sum();
+        repo.file_count = metas.len() as u64;
+        repo.updated_at = now;
+    }
+    if !trashed.is_empty() {
+        tokio::fs::create_dir_all(state.config.trash_dir()).await?;
+    }
+    for entry in trashed {
+        if let TrashedItem::File { file } = &entry.item {
+            let dst = state.config.trash_dir().join(entry.id.to_string());
+            if let Err(e) = tokio::fs::rename(replaced.join(&file.path), &dst).await {
+                tracing::warn!(
+                    repo_id = %repo_id,
+                    path = %file.path,
+                    error = %e,
+                    "Import trash move failed on disk"
+                );
+            }
+        }
+        state.trash.insert(entry.id, entry);
+    }
+    drop(wal);
+    let _ = tokio::fs::remove_dir_all(&replaced).await;
+
+    for meta in &removed {
+        event_service::file_event(state, repo_id, ChangeKind::FileDeleted, &meta.path);
+    }
+    for meta in &metas {
+        let kind = match previous.get(&meta.path) {
+            Some(old) if old.etag == meta.etag => continue,
+            Some(_) => ChangeKind::FileUpdated,
+            None => ChangeKind::FileCreated,
+        };
+        event_service::file_event(state, repo_id, kind, &meta.path);
+    }
+    Ok(())
+}
+
+/// Commit the repository's current files onto `branch` and return a
+/// self-contained bundle holding that branch's full history.
+pub async fn export_bundle(
+    state: &AppState,
+    repo_id: Uuid,
+    req: GitExportRequest,
+) -> Result<(String, Vec<u8>), AppError> {
+    ensure_repo_exists(state, repo_id)?;
+    access_service::touch_repo(state, repo_id);
+
+    let mut files: Vec<(String, PathBuf)> = state
+        .files
+        .get(&repo_id)
+        .map(|files| {
+            files
+                .iter()
+                .map(|f| {
+                    let path = f.key().clone();
+                    let disk = file_service::resolve_file_path(state, repo_id, &path);
+                    (path, disk)
+                })
+                .collect()
+        })
+        .unwrap_or_default();
+    files.sort();
+
+    let dir = git_dir(state, repo_id);
+    blocking(move || {
+        let repo = open_or_init(&dir)?;
+
+        let branch = match req.branch {
+            Some(b) => b,
+            None => head_branch(&repo).unwrap_or_else(|| DEFAULT_BRANCH.to_string()),
+        };
+        let refname = format!("refs/heads/{}", branch);
+        if !git2::Reference::is_valid_name(&refname) {
+            return Err(AppError::BadRequest(format!(
+                "Invalid branch name: {}",
+                branch
+            )));
+        }
+
+        let empty_tree = repo
+            .find_tree(
+                repo.treebuilder(None)
+                    .and_then(|b| b.write())
+                    .map_err(git_error)?,
+            )
+            .map_err(git_error)?;
+        let mut update = git2::build::TreeUpdateBuilder::new();
+        for (path, disk) in &files {
+            let content = std::fs::read(disk)?;
+            let oid = repo.blob(&content).map_err(git_error)?;
+            update.upsert(path.as_str(), oid, git2::FileMode::Blob);
+        }
+        let tree_id = update
+            .create_updated(&repo, &empty_tree)
+            .map_err(git_error)?;
+        let tree = repo.find_tree(tree_id).map_err(git_error)?;
+
+        let parent = repo
+            .find_reference(&refname)
+            .ok()
+            .and_then(|r| r.peel_to_commit().ok());
+
+        let commit_id = match parent {
+            Some(ref p) if p.tree_id() == tree_id => p.id(),
+            _ => {
+                let sig = Signature::now(COMMITTER_NAME, COMMITTER_EMAIL).map_err(git_error)?;
+                let message = req
+                    .message
+                    .unwrap_or_else(|| "Export repository contents".to_string());
+                let parents: Vec<&git2::Commit> = parent.iter().collect();
+                repo.commit(Some(&refname), &sig, &sig, &message, &tree, &parents)
+                    .map_err(git_error)?
+            }
+        };
+        repo.set_head(&refname).map_err(git_error)?;
+
+        let mut walk = repo.revwalk().map_err(git_error)?;
+        walk.push(commit_id).map_err(git_error)?;
+        let mut builder = repo.packbuilder().map_err(git_error)?;
+        builder.insert_walk(&mut walk).map_err(git_error)?;
+        let mut pack = git2::Buf::new();
+        builder.write_buf(&mut pack).map_err(git_error)?;
+
+        let mut bundle =
+            format!("{}\n{} {}\n\n", BUNDLE_V2_SIGNATURE, commit_id, refname).into_bytes();
+        bundle.extend_from_slice(&pack);
+
+        Ok((commit_id.to_string(), bundle))
+    })
+    .await
+}
+
+fn head_branch(repo: &Repository) -> Option<String> {
+    let head = repo.find_reference("HEAD").ok()?;
+    head.symbolic_target()?
+        .strip_prefix("refs/heads/")
+        .map(str::to_string)
+}
+
+pub async fn log(
+    state: &AppState,
+    repo_id: Uuid,
+    rev: Option<String>,
+    limit: usize,
+) -> Result<Vec<GitCommit>, AppError> {
+    ensure_repo_exists(state, repo_id)?;
+    access_service::touch_repo(state, repo_id);
+    let dir = git_dir(state, repo_id);
+
+    blocking(move || {
+        let repo = open_existing(&dir, repo_id)?;
+        let start = repo
+            .revparse_single(rev.as_deref().unwrap_or("HEAD"))
+            .and_then(|o| o.peel_to_commit())
+            .map_err(git_error)?;
+
+        let mut walk = repo.revwalk().map_err(git_error)?;
+        walk.set_sorting(git2::Sort::TIME).map_err(git_error)?;
+        walk.push(start.id()).map_err(git_error)?;
+
+        walk.take(limit)
+            .map(|oid| {
+                let commit = repo
+                    .find_commit(oid.map_err(git_error)?)
+                    .map_err(git_error)?;
+                Ok(commit_info(&commit))
+            })
+            .collect()
+    })
+    .await
+}
+
+pub async fn show(
+    state: &AppState,
+    repo_id: Uuid,
+    rev: Option<String>,
+) -> Result<GitShow, AppError> {
+    ensure_repo_exists(state, repo_id)?;
+    access_service::touch_repo(state, repo_id);
+    let dir = git_dir(state, repo_id);
+    let max_bytes = state.config.command_max_output_bytes;
+
+    blocking(move || {
+        let repo = open_existing(&dir, repo_id)?;
+        let commit = repo
+            .revparse_single(rev.as_deref().unwrap_or("HEAD"))
+            .and_then(|o| o.peel_to_commit())
+            .map_err(git_error)?;
+        let parent = commit.parents().next();
+
+        let old_tree = match parent {
+            Some(ref p) => Some(p.tree().map_err(git_error)?),
+            None => None,
+        };
+        let new_tree = commit.tree().map_err(git_error)?;
+        let diff = tree_diff(
+            &repo,
+            old_tree.as_ref(),
+            &new_tree,
+            parent.map(|p| p.id().to_string()).unwrap_or_default(),
+            commit.id().to_string(),
+            max_bytes,
+        )?;
+
+        Ok(GitShow {
+            commit: commit_info(&commit),
+            diff,
+        })
+    })
+    .await
+}
+
+pub async fn diff(
+    state: &AppState,
+    repo_id: Uuid,
+    from: String,
+    to: Option<String>,
+) -> Result<GitDiff, AppError> {
+    ensure_repo_exists(state, repo_id)?;
+    access_service::touch_repo(state, repo_id);
+    let dir = git_dir(state, repo_id);
+    let max_bytes = state.config.command_max_output_bytes;
+
+    blocking(move || {
+        let repo = open_existing(&dir, repo_id)?;
+        let to = to.unwrap_or_else(|| "HEAD".to_string());
+        let old = repo
+            .revparse_single(&from)
+            .and_then(|o| o.peel_to_commit())
+            .map_err(git_error)?;
+        let new = repo
+            .revparse_single(&to)
+            .and_then(|o| o.peel_to_commit())
+            .map_err(git_error)?;
+        let old_tree = old.tree().map_err(git_error)?;
+        let new_tree = new.tree().map_err(git_error)?;
+
+        tree_diff(
+            &repo,
+            Some(&old_tree),
+            &new_tree,
+            old.id().to_string(),
+            new.id().to_string(),
+            max_bytes,
+        )
+    })
+    .await
+}
+
+fn tree_diff(
+    repo: &Repository,
+    old: Option<&git2::Tree>,
+    new: &git2::Tree,
+    from: String,
+    to: String,
+    max_bytes: usize,
+) -> Result<GitDiff, AppError> {
+    let mut diff = repo
+        .diff_tree_to_tree(old, Some(new), None)
+        .map_err(git_error)?;
+    diff.find_similar(None).map_err(git_error)?;
+
+    let files = diff
+        .deltas()
+        .map(|delta| {
+            let path = delta
+                .new_file()
+                .path()
+                .or_else(|| delta.old_file().path())
+                .map(|p| p.to_string_lossy().to_string())
+                .unwrap_or_default();
+            let status = match delta.status() {
+                Delta::Added => "added",
+                Delta::Deleted => "deleted",
+                Delta::Modified => "modified",
+                Delta::Renamed => "renamed",
+                Delta::Copied => "copied",
+                Delta::Typechange => "typechange",
+                _ => "other",
+            };
+            GitChangedFile {
+                path,
+                status: status.to_string(),
+            }
+        })
+        .collect();
+
+    let mut patch = Vec::new();
+    let mut truncated = false;
+    diff.print(DiffFormat::Patch, |_delta, _hunk, line| {
+        if matches!(line.origin(), '+' | '-' | ' ') {
+            patch.push(line.origin() as u8);
+        }
+        patch.extend_from_slice(line.content());
+        if patch.len() > max_bytes {
+            truncated = true;
+            return false;
+        }
+        true
+    })
+    .or_else(|e| if truncated { Ok(()) } else { Err(e) })
+    .map_err(git_error)?;
+    patch.truncate(max_bytes);
+
+    Ok(GitDiff {
+        from,
+        to,
+        files,
+        patch: String::from_utf8_lossy(&patch).to_string(),
+        truncated,
+    })
+}
+
+fn commit_info(commit: &git2::Commit) -> GitCommit {
+    let author = commit.author();
+    GitCommit {
+        id: commit.id().to_string(),
+        parents: commit.parent_ids().map(|p| p.to_string()).collect(),
+        author_name: author.name().unwrap_or("").to_string(),
+        author_email: author.email().unwrap_or("").to_string(),
+        time: DateTime::from_timestamp(commit.time().seconds(), 0).unwrap_or_default(),
+        summary: commit.summary().unwrap_or("").to_string(),
+        message: commit.message().unwrap_or("").to_string(),
+    }
+}
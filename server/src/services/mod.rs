@@ -1,4 +1,18 @@
+pub mod access_service;
+pub mod change_log_service;
+pub mod checkpoint_service;
+pub mod delta_service;
+pub mod diff_service;
+pub mod event_service;
 pub mod eviction_service;
 pub mod file_service;
+pub mod git_service;
+pub mod pin_service;
+pub mod reconcile_service;
 pub mod repo_service;
+pub mod scrub_service;
 pub mod shell_service;
+pub mod sync_service;
+pub mod trash_service;
+pub mod watch_service;
+pub mod webhook_service;
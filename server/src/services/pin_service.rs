@@ -0,0 +1,95 @@
+use crate::error::AppError;
+use crate::models::event::ChangeKind;
+use crate::models::file::FileMeta;
+use crate::persistence::wal::WalEntry;
+use crate::services::event_service;
+use crate::state::AppState;
+use globset::{GlobBuilder, GlobSet, GlobSetBuilder};
+use uuid::Uuid;
+
+/// Decides which files of a repo are protected from eviction and expiry:
+/// explicitly pinned files plus any path matching a repo pin pattern.
+pub struct Pins {
+    patterns: GlobSet,
+}
+
+impl Pins {
+    pub fn for_repo(state: &AppState, repo_id: Uuid) -> Self {
+        let patterns = state
+            .repos
+            .get(&repo_id)
+            .map(|r| r.pinned_patterns.clone())
+            .unwrap_or_default();
+        // Patterns are validated when set; an unparsable one pins nothing.
+        Self {
+            patterns: compile(&patterns).unwrap_or_else(|_| GlobSet::empty()),
+        }
+    }
+
+    pub fn protects(&self, meta: &FileMeta) -> bool {
+        meta.pinned || self.patterns.is_match(&meta.path)
+    }
+}
+
+/// Compile repo pin patterns. `*` stays within one path component and
+/// `**` spans directories.
+pub fn compile(patterns: &[String]) -> Result<GlobSet, AppError> {
+    let mut builder = GlobSetBuilder::new();
+    for pattern in patterns {
+        let glob = GlobBuilder::new(pattern)
+            .literal_separator(true)
+            .build()
+            .map_err(|e| {
+                AppError::BadRequest(format!("Invalid pin pattern '{}': {}", pattern, e))
+            })?;
+        builder.add(glob);
+    }
+    builder
+        .build()
+        .map_err(|e| AppError::BadRequest(format!("Invalid pin patterns: {}", e)))
+}
+
+pub async fn pin_file(
+    state: &AppState,
+    repo_id: Uuid,
+    rel_path: &str,
+    pinned: bool,
+) -> Result<FileMeta, AppError> {
+    if !state.repos.contains_key(&repo_id) {
+        return Err(AppError::NotFound(format!(
+            "Repository {} not found",
+            repo_id
+        )));
+    }
+    if !state
+        .files
+        .get(&repo_id)
+        .map(|f| f.contains_key(rel_path))
+        .unwrap_or(false)
+    {
+        return Err(AppError::NotFound(format!("File not found: {}", rel_path)));
+    }
+
+    {
+        let mut wal = state.wal.write().await;
+        wal.append(&WalEntry::FilePinned {
+            repo_id,
+            path: rel_path.to_string(),
+            pinned,
+        })
+        .map_err(|e| AppError::Internal(format!("WAL write failed: {}", e)))?;
+    }
+
+    let mut meta = state
+        .files
+        .get(&repo_id)
+        .and_then(|files| {
+            files.get_mut(rel_path).map(|mut meta| {
+                meta.pinned = pinned;
+                meta.clone()
+            })
+        })
+        .ok_or_else(|| AppError::NotFound(format!("File not found: {}", rel_path)))?;
+    meta.seq = event_service::file_event(state, repo_id, ChangeKind::FileUpdated, rel_path);
+    Ok(meta)
+}
@@ -0,0 +1,200 @@
+use crate::error::AppError;
+use crate::models::file::FileMeta;
+use crate::sandbox::path_validator;
+use crate::services::{checkpoint_service, file_service, watch_service};
+use crate::state::AppState;
+use serde::Serialize;
+use std::collections::{HashMap, HashSet};
+use std::time::Instant;
+use uuid::Uuid;
+
+/// How thoroughly boot-time reconcile checks files against the disk.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReconcileMode {
+    /// Skip reconcile entirely.
+    Off,
+    /// Stat every file; rehash only untracked files and size mismatches.
+    Fast,
+    /// Rehash every file and fix stale etags.
+    Full,
+}
+
+impl ReconcileMode {
+    pub fn parse(value: &str) -> Self {
+        match value {
+            "off" => ReconcileMode::Off,
+            "full" => ReconcileMode::Full,
+            "fast" => ReconcileMode::Fast,
+            other => {
+                tracing::warn!(mode = %other, "Unknown reconcile mode, using fast");
+                ReconcileMode::Fast
+            }
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone, Serialize)]
+pub struct ReconcileSummary {
+    pub repos_scanned: u64,
+    pub repos_dropped: u64,
+    pub files_scanned: u64,
+    pub files_adopted: u64,
+    pub files_removed: u64,
+    pub sizes_fixed: u64,
+    pub etags_fixed: u64,
+    pub bytes_hashed: u64,
+    pub errors: u64,
+    pub duration_ms: u64,
+}
+
+/// Walk every repo tree and bring metadata in line with the disk: adopt
+/// untracked files, drop metadata for missing ones, fix sizes and, in
+/// full mode, etags. Repo totals are recomputed from the result.
+pub async fn reconcile(state: &AppState, mode: ReconcileMode) -> ReconcileSummary {
+    let mut summary = ReconcileSummary::default();
+    if mode == ReconcileMode::Off {
+        tracing::info!("Reconcile disabled");
+        return summary;
+    }
+
+    let started = Instant::now();
+    let repo_ids: Vec<Uuid> = state.repos.iter().map(|r| *r.key()).collect();
+
+    for repo_id in repo_ids {
+        let repo_dir = file_service::repo_files_dir(state, repo_id);
+        if !repo_dir.exists() {
+            tracing::warn!(repo_id = %repo_id, "Repo directory missing, cleaning metadata");
+            state.repos.remove(&repo_id);
+            state.files.remove(&repo_id);
+            checkpoint_service::forget_repo(state, repo_id);
+            summary.repos_dropped += 1;
+            continue;
+        }
+
+        summary.repos_scanned += 1;
+        if let Err(e) = reconcile_repo(state, repo_id, mode, &mut summary).await {
+            summary.errors += 1;
+            tracing::error!(repo_id = %repo_id, error = %e, "Failed to reconcile repository");
+        }
+        recompute_totals(state, repo_id);
+    }
+
+    summary.duration_ms = started.elapsed().as_millis() as u64;
+    tracing::info!(
+        mode = ?mode,
+        repos_scanned = summary.repos_scanned,
+        repos_dropped = summary.repos_dropped,
+        files_scanned = summary.files_scanned,
+        files_adopted = summary.files_adopted,
+        files_removed = summary.files_removed,
+        sizes_fixed = summary.sizes_fixed,
+        etags_fixed = summary.etags_fixed,
+        bytes_hashed = summary.bytes_hashed,
+        errors = summary.errors,
+        duration_ms = summary.duration_ms,
+        "Reconcile completed"
+    );
+    summary
+}
+
+async fn reconcile_repo(
+    state: &AppState,
+    repo_id: Uuid,
+    mode: ReconcileMode,
+    summary: &mut ReconcileSummary,
+) -> Result<(), AppError> {
+    let repo_dir = file_service::repo_files_dir(state, repo_id);
+    let on_disk = tokio::task::spawn_blocking(move || watch_service::walk_files(&repo_dir))
+        .await
+        .map_err(|e| AppError::Internal(format!("Reconcile task failed: {}", e)))??;
+
+    let known: HashMap<String, FileMeta> = state
+        .files
+        .get(&repo_id)
+        .map(|files| {
+            files
+                .iter()
+                .map(|f| (f.key().clone(), f.value().clone()))
+                .collect()
+        })
+        .unwrap_or_default();
+
+    // Metadata whose file is gone
+    let disk_set: HashSet<&str> = on_disk.iter().map(|p| p.as_str()).collect();
+    for path in known.keys() {
+        if !disk_set.contains(path.as_str()) {
+            tracing::warn!(repo_id = %repo_id, path = %path, "Orphaned metadata entry, removing");
+            watch_service::forget_external_delete(state, repo_id, path).await?;
+            summary.files_removed += 1;
+        }
+    }
+
+    for rel in on_disk {
+        summary.files_scanned += 1;
+        let Ok(path) = path_validator::validate_relative_path(&rel) else {
+            tracing::warn!(repo_id = %repo_id, path = %rel, "Skipping file with invalid path");
+            continue;
+        };
+        let disk_path = file_service::resolve_file_path(state, repo_id, &path);
+        let existing = known.get(&path).cloned();
+
+        if mode == ReconcileMode::Fast {
+            if let Some(meta) = &existing {
+                let size = tokio::fs::metadata(&disk_path).await?.len();
+                if size == meta.size_bytes {
+                    continue;
+                }
+            }
+        }
+
+        let hash_path = disk_path.clone();
+        let (size_bytes, etag) =
+            tokio::task::spawn_blocking(move || watch_service::hash_file(&hash_path))
+                .await
+                .map_err(|e| AppError::Internal(format!("Reconcile task failed: {}", e)))??;
+        summary.bytes_hashed += size_bytes;
+
+        match &existing {
+            None => {
+                tracing::info!(repo_id = %repo_id, path = %path, size = size_bytes, "Adopting untracked file");
+                summary.files_adopted += 1;
+            }
+            Some(meta) if meta.size_bytes != size_bytes => {
+                tracing::warn!(
+                    repo_id = %repo_id,
+                    path = %path,
+                    recorded = meta.size_bytes,
+                    actual = size_bytes,
+                    "Fixing size mismatch"
+                );
+                summary.sizes_fixed += 1;
+            }
+            Some(meta) if meta.etag != etag => {
+                tracing::warn!(repo_id = %repo_id, path = %path, "Fixing stale etag");
+                summary.etags_fixed += 1;
+            }
+            Some(_) => continue,
+        }
+
+        watch_service::record_disk_file(state, repo_id, &path, size_bytes, etag, existing).await?;
+    }
+
+    Ok(())
+}
+
+fn recompute_totals(state: &AppState, repo_id: Uuid) {
+    let (total_size, file_count) = state
+        .files
+        .get(&repo_id)
+        .map(|files| {
+            files.iter().fold((0u64, 0u64), |(size, count), entry| {
+                (size + entry.value().size_bytes, count + 1)
+            })
+        })
+        .unwrap_or((0, 0));
+
+    if let Some(mut repo) = state.repos.get_mut(&repo_id) {
+        repo.current_size_bytes = total_size;
+        repo.file_count = file_count;
+    }
+}
@@ -1,8 +1,19 @@
 use crate::error::AppError;
-use crate::models::repo::{CreateRepoRequest, RepoMeta, UpdateRepoRequest};
+use crate::models::event::ChangeKind;
+use crate::models::file::FileMeta;
+use crate::models::repo::{
+    checked_deadline, CreateRepoRequest, ExecLimits, ForkRepoRequest, RenewRepoRequest, RepoMeta,
+    UpdateRepoRequest, DEFAULT_EVICTION_WEIGHT, MAX_TTL_SECONDS,
+};
+use crate::models::trash::DeleteCause;
 use crate::persistence::wal::WalEntry;
+use crate::services::{
+    access_service, checkpoint_service, event_service, eviction_service, pin_service,
+    trash_service, watch_service,
+};
+use crate::services::file_service::{self, CloneMethod};
 use crate::state::AppState;
-use chrono::Utc;
+use chrono::{DateTime, Utc};
 use std::collections::HashMap;
 use uuid::Uuid;
 
@@ -15,10 +26,21 @@ pub async fn create_repo(
     let max_size = req
         .max_size_bytes
         .unwrap_or(state.config.default_max_repo_size);
+    validate_ttl("ttl_seconds", req.ttl_seconds)?;
+    validate_ttl("idle_ttl_seconds", req.idle_ttl_seconds)?;
+    let expires_at = expiry_from(now, req.ttl_seconds);
+    let eviction_policy = req.eviction_policy.unwrap_or_default();
+    let pinned_patterns = req.pinned_patterns.unwrap_or_default();
+    pin_service::compile(&pinned_patterns)?;
+    let eviction_weight = req.eviction_weight.unwrap_or(DEFAULT_EVICTION_WEIGHT);
+    let exec_limits = req.exec_limits.unwrap_or_default();
+    validate_exec_limits(&exec_limits)?;
+    let command_profile = req.command_profile;
+    validate_command_profile(state, command_profile.as_deref())?;
 
     let repo = RepoMeta {
         id,
-        name: req.name.clone(),
+        name: req.name,
         max_size_bytes: max_size,
         current_size_bytes: 0,
         file_count: 0,
@@ -27,20 +49,28 @@ pub async fn create_repo(
         last_accessed_at: now,
         default_ttl_seconds: req.default_ttl_seconds,
         tags: HashMap::new(),
+        forked_from: None,
+        forked_at: None,
+        ttl_seconds: req.ttl_seconds,
+        idle_ttl_seconds: req.idle_ttl_seconds,
+        expires_at,
+        eviction_policy,
+        pinned_patterns,
+        eviction_weight,
+        seq: 0,
+        exec_limits,
+        command_profile,
     };
 
     // WAL first
-    {
-        let mut wal = state.wal.write().await;
-        wal.append(&WalEntry::RepoCreated {
-            id,
-            name: req.name,
-            max_size_bytes: max_size,
-            default_ttl_seconds: req.default_ttl_seconds,
-            created_at: now,
+    state
+        .wal
+        .write()
+        .await
+        .append(&WalEntry::RepoCreatedWithSettings {
+            repo: Box::new(repo.clone()),
         })
         .map_err(|e| AppError::Internal(format!("WAL write failed: {}", e)))?;
-    }
 
     // Create repo directory
     let repo_dir = state.config.repos_dir().join(id.to_string()).join("files");
@@ -50,10 +80,185 @@ pub async fn create_repo(
 
     state.repos.insert(id, repo.clone());
     state.files.insert(id, dashmap::DashMap::new());
+    watch_service::watch_repo(state, id);
 
     Ok(repo)
 }
 
+/// Create a new repository holding the same files as `source_id`.
+/// Settings are inherited from the source unless overridden in `req`.
+pub async fn fork_repo(
+    state: &AppState,
+    source_id: Uuid,
+    req: ForkRepoRequest,
+) -> Result<RepoMeta, AppError> {
+    let source = state
+        .repos
+        .get(&source_id)
+        .map(|r| r.value().clone())
+        .ok_or_else(|| AppError::NotFound(format!("Repository {} not found", source_id)))?;
+
+    access_service::touch_repo(state, source_id);
+
+    let source_files: Vec<FileMeta> = state
+        .files
+        .get(&source_id)
+        .map(|files| files.iter().map(|f| f.value().clone()).collect())
+        .unwrap_or_default();
+
+    let max_size = req.max_size_bytes.unwrap_or(source.max_size_bytes);
+    let total_size: u64 = source_files.iter().map(|f| f.size_bytes).sum();
+    if total_size > max_size {
+        return Err(AppError::PayloadTooLarge(format!(
+            "Source repository holds {} bytes, exceeding fork size limit {}",
+            total_size, max_size
+        )));
+    }
+    eviction_service::check_global(state, total_size)?;
+
+    let now = Utc::now();
+    let id = Uuid::new_v4();
+    let name = req.name.unwrap_or_else(|| source.name.clone());
+    let default_ttl = req
+        .default_ttl_seconds
+        .unwrap_or(source.default_ttl_seconds);
+    let tags = req.tags.unwrap_or_else(|| source.tags.clone());
+    let ttl_seconds = req.ttl_seconds.unwrap_or(source.ttl_seconds);
+    let idle_ttl_seconds = req.idle_ttl_seconds.unwrap_or(source.idle_ttl_seconds);
+    validate_ttl("ttl_seconds", ttl_seconds)?;
+    validate_ttl("idle_ttl_seconds", idle_ttl_seconds)?;
+    let expires_at = expiry_from(now, ttl_seconds);
+    let eviction_policy = req.eviction_policy.unwrap_or(source.eviction_policy);
+    let pinned_patterns = req
+        .pinned_patterns
+        .unwrap_or_else(|| source.pinned_patterns.clone());
+    pin_service::compile(&pinned_patterns)?;
+    let eviction_weight = req.eviction_weight.unwrap_or(source.eviction_weight);
+    let exec_limits = req.exec_limits.unwrap_or(source.exec_limits);
+    validate_exec_limits(&exec_limits)?;
+    let command_profile = req
+        .command_profile
+        .unwrap_or_else(|| source.command_profile.clone());
+    validate_command_profile(state, command_profile.as_deref())?;
+
+    // Materialize files off the async runtime, before anything records
+    // the fork
+    let allow_hardlink = state.config.fork_fallback == "hardlink";
+    let jobs: Vec<(FileMeta, std::path::PathBuf, std::path::PathBuf)> = source_files
+        .into_iter()
+        .map(|meta| {
+            let src = file_service::resolve_file_path(state, source_id, &meta.path);
+            let dst = file_service::resolve_file_path(state, id, &meta.path);
+            (meta, src, dst)
+        })
+        .collect();
+
+    let repo_root = state.config.repos_dir().join(id.to_string());
+    let cleanup_root = repo_root.clone();
+    let cloned = tokio::task::spawn_blocking(move || {
+        std::fs::create_dir_all(repo_root.join("files"))
+            .map_err(|e| format!("Failed to create repo dir: {}", e))?;
+        jobs.into_iter()
+            .map(|(meta, src, dst)| {
+                match file_service::clone_file(&src, &dst, allow_hardlink) {
+                    Ok(method) => Ok((meta, method)),
+                    Err(e) => Err(format!("Failed to clone {} into fork: {}", meta.path, e)),
+                }
+            })
+            .collect::<Result<Vec<_>, String>>()
+    })
+    .await
+    .map_err(|e| AppError::Internal(format!("Fork task failed: {}", e)))?;
+    // An incomplete fork is not a fork; nothing refers to it yet
+    let cloned = match cloned {
+        Ok(cloned) => cloned,
+        Err(e) => {
+            let _ = tokio::fs::remove_dir_all(&cleanup_root).await;
+            return Err(AppError::Internal(e));
+        }
+    };
+
+    let mut method_counts: HashMap<&'static str, u64> = HashMap::new();
+    let files: Vec<FileMeta> = cloned
+        .into_iter()
+        .map(|(src_meta, method)| {
+            let label = match method {
+                CloneMethod::Reflink => "reflink",
+                CloneMethod::Hardlink => "hardlink",
+                CloneMethod::Copy => "copy",
+            };
+            *method_counts.entry(label).or_default() += 1;
+            FileMeta {
+                repo_id: id,
+                created_at: now,
+                updated_at: now,
+                last_accessed_at: now,
+                access_count: 0,
+                seq: 0,
+                ..src_meta
+            }
+        })
+        .collect();
+    let repo = RepoMeta {
+        id,
+        name,
+        max_size_bytes: max_size,
+        current_size_bytes: total_size,
+        file_count: files.len() as u64,
+        created_at: now,
+        updated_at: now,
+        last_accessed_at: now,
+        default_ttl_seconds: default_ttl,
+        tags,
+        forked_from: Some(source_id),
+        forked_at: Some(now),
+        ttl_seconds,
+        idle_ttl_seconds,
+        expires_at,
+        eviction_policy,
+        pinned_patterns,
+        eviction_weight,
+        seq: 0,
+        exec_limits,
+        command_profile,
+    };
+
+    // WAL first, with every file, so replay never sees half a fork
+    if let Err(e) = state
+        .wal
+        .write()
+        .await
+        .append(&WalEntry::RepoForkedWithFiles {
+            repo: Box::new(repo.clone()),
+            files: files.clone(),
+        })
+    {
+        let _ = tokio::fs::remove_dir_all(&cleanup_root).await;
+        return Err(AppError::Internal(format!("WAL write failed: {}", e)));
+    }
+
+    let paths: Vec<String> = files.iter().map(|f| f.path.clone()).collect();
+    state
+        .files
+        .insert(id, files.into_iter().map(|f| (f.path.clone(), f)).collect());
+    state.repos.insert(id, repo);
+    // A fork's change log starts with its files, so `since=0` is a
+    // complete listing
+    for path in &paths {
+        event_service::file_event(state, id, ChangeKind::FileCreated, path);
+    }
+
+    tracing::info!(
+        repo_id = %id,
+        source_id = %source_id,
+        methods = ?method_counts,
+        "Repository files cloned"
+    );
+    watch_service::watch_repo(state, id);
+
+    get_repo(state, id).await
+}
+
 pub async fn list_repos(
     state: &AppState,
     page: u64,
@@ -64,9 +269,12 @@ pub async fn list_repos(
 
     match sort.as_deref() {
         Some("name") => repos.sort_by(|a, b| a.name.cmp(&b.name)),
-        Some("created_at") => repos.sort_by(|a, b| b.created_at.cmp(&a.created_at)),
-        Some("size") => repos.sort_by(|a, b| b.current_size_bytes.cmp(&a.current_size_bytes)),
-        _ => repos.sort_by(|a, b| b.created_at.cmp(&a.created_at)),
+        Some("created_at") => repos.sort_by_key(|r| std::cmp::Reverse(r.created_at)),
+        Some("size") => repos.sort_by_key(|r| std::cmp::Reverse(r.current_size_bytes)),
+        Some("last_accessed_at") => {
+            repos.sort_by_key(|r| std::cmp::Reverse(r.last_accessed_at))
+        }
+        _ => repos.sort_by_key(|r| std::cmp::Reverse(r.created_at)),
     }
 
     let start = ((page - 1) * per_page) as usize;
@@ -74,14 +282,11 @@ pub async fn list_repos(
 }
 
 pub async fn get_repo(state: &AppState, repo_id: Uuid) -> Result<RepoMeta, AppError> {
+    access_service::touch_repo(state, repo_id);
     state
         .repos
         .get(&repo_id)
-        .map(|r| {
-            let mut repo = r.value().clone();
-            repo.last_accessed_at = Utc::now();
-            repo
-        })
+        .map(|r| r.value().clone())
         .ok_or_else(|| AppError::NotFound(format!("Repository {} not found", repo_id)))
 }
 
@@ -91,6 +296,32 @@ pub async fn update_repo(
     req: UpdateRepoRequest,
 ) -> Result<RepoMeta, AppError> {
     let now = Utc::now();
+    if let Some(patterns) = &req.pinned_patterns {
+        pin_service::compile(patterns)?;
+    }
+    if let Some(exec_limits) = &req.exec_limits {
+        validate_exec_limits(exec_limits)?;
+    }
+    if let Some(command_profile) = &req.command_profile {
+        validate_command_profile(state, command_profile.as_deref())?;
+    }
+    validate_ttl("ttl_seconds", req.ttl_seconds.flatten())?;
+    validate_ttl("idle_ttl_seconds", req.idle_ttl_seconds.flatten())?;
+
+    // Resolve expiry changes against the current settings
+    let expiry = if req.ttl_seconds.is_some() || req.idle_ttl_seconds.is_some() {
+        let (ttl, idle, expires_at) = state
+            .repos
+            .get(&repo_id)
+            .map(|r| (r.ttl_seconds, r.idle_ttl_seconds, r.expires_at))
+            .ok_or_else(|| AppError::NotFound(format!("Repository {} not found", repo_id)))?;
+        Some(match req.ttl_seconds {
+            Some(ttl) => (ttl, req.idle_ttl_seconds.unwrap_or(idle), expiry_from(now, ttl)),
+            None => (ttl, req.idle_ttl_seconds.unwrap_or(idle), expires_at),
+        })
+    } else {
+        None
+    };
 
     // WAL first
     {
@@ -104,6 +335,50 @@ pub async fn update_repo(
             updated_at: now,
         })
         .map_err(|e| AppError::Internal(format!("WAL write failed: {}", e)))?;
+        if let Some((ttl_seconds, idle_ttl_seconds, expires_at)) = expiry {
+            wal.append(&WalEntry::RepoExpiryChanged {
+                id: repo_id,
+                ttl_seconds,
+                idle_ttl_seconds,
+                expires_at,
+            })
+            .map_err(|e| AppError::Internal(format!("WAL write failed: {}", e)))?;
+        }
+        if let Some(eviction_policy) = req.eviction_policy {
+            wal.append(&WalEntry::RepoEvictionPolicyChanged {
+                id: repo_id,
+                eviction_policy,
+            })
+            .map_err(|e| AppError::Internal(format!("WAL write failed: {}", e)))?;
+        }
+        if let Some(pinned_patterns) = &req.pinned_patterns {
+            wal.append(&WalEntry::RepoPinsChanged {
+                id: repo_id,
+                pinned_patterns: pinned_patterns.clone(),
+            })
+            .map_err(|e| AppError::Internal(format!("WAL write failed: {}", e)))?;
+        }
+        if let Some(eviction_weight) = req.eviction_weight {
+            wal.append(&WalEntry::RepoEvictionWeightChanged {
+                id: repo_id,
+                eviction_weight,
+            })
+            .map_err(|e| AppError::Internal(format!("WAL write failed: {}", e)))?;
+        }
+        if let Some(exec_limits) = req.exec_limits {
+            wal.append(&WalEntry::RepoExecLimitsChanged {
+                id: repo_id,
+                exec_limits,
+            })
+            .map_err(|e| AppError::Internal(format!("WAL write failed: {}", e)))?;
+        }
+        if let Some(command_profile) = &req.command_profile {
+            wal.append(&WalEntry::RepoCommandProfileChanged {
+                id: repo_id,
+                command_profile: command_profile.clone(),
+            })
+            .map_err(|e| AppError::Internal(format!("WAL write failed: {}", e)))?;
+        }
     }
 
     let mut entry = state
@@ -124,19 +399,142 @@ pub async fn update_repo(
     if let Some(tags) = req.tags {
         repo.tags = tags;
     }
+    if let Some((ttl_seconds, idle_ttl_seconds, expires_at)) = expiry {
+        repo.ttl_seconds = ttl_seconds;
+        repo.idle_ttl_seconds = idle_ttl_seconds;
+        repo.expires_at = expires_at;
+    }
+    if let Some(eviction_policy) = req.eviction_policy {
+        repo.eviction_policy = eviction_policy;
+    }
+    if let Some(pinned_patterns) = req.pinned_patterns {
+        repo.pinned_patterns = pinned_patterns;
+    }
+    if let Some(eviction_weight) = req.eviction_weight {
+        repo.eviction_weight = eviction_weight;
+    }
+    if let Some(exec_limits) = req.exec_limits {
+        repo.exec_limits = exec_limits;
+    }
+    if let Some(command_profile) = req.command_profile {
+        repo.command_profile = command_profile;
+    }
     repo.updated_at = now;
+    let mut repo = repo.clone();
+    drop(entry);
 
-    Ok(repo.clone())
+    access_service::touch_repo(state, repo_id);
+    repo.seq = event_service::repo_event(state, repo_id, ChangeKind::RepoUpdated);
+    Ok(repo)
 }
 
-pub async fn delete_repo(state: &AppState, repo_id: Uuid) -> Result<(), AppError> {
-    // Check exists
-    if !state.repos.contains_key(&repo_id) {
-        return Err(AppError::NotFound(format!(
-            "Repository {} not found",
+/// Push a repository's expiry out. An absolute TTL restarts from now,
+/// using `req.ttl_seconds` or the repo's configured `ttl_seconds`, and
+/// the idle timer is reset by the access itself.
+pub async fn renew_repo(
+    state: &AppState,
+    repo_id: Uuid,
+    req: RenewRepoRequest,
+) -> Result<RepoMeta, AppError> {
+    let now = Utc::now();
+    let (current_ttl, idle_ttl_seconds) = state
+        .repos
+        .get(&repo_id)
+        .map(|r| (r.ttl_seconds, r.idle_ttl_seconds))
+        .ok_or_else(|| AppError::NotFound(format!("Repository {} not found", repo_id)))?;
+
+    validate_ttl("ttl_seconds", req.ttl_seconds)?;
+    let ttl_seconds = req.ttl_seconds.or(current_ttl);
+    if ttl_seconds.is_none() && idle_ttl_seconds.is_none() {
+        return Err(AppError::BadRequest(format!(
+            "Repository {} has no TTL to renew; specify ttl_seconds",
             repo_id
         )));
     }
+    let expires_at = expiry_from(now, ttl_seconds);
+
+    {
+        let mut wal = state.wal.write().await;
+        wal.append(&WalEntry::RepoExpiryChanged {
+            id: repo_id,
+            ttl_seconds,
+            idle_ttl_seconds,
+            expires_at,
+        })
+        .map_err(|e| AppError::Internal(format!("WAL write failed: {}", e)))?;
+    }
+
+    if let Some(mut repo) = state.repos.get_mut(&repo_id) {
+        repo.ttl_seconds = ttl_seconds;
+        repo.expires_at = expires_at;
+        repo.updated_at = now;
+    }
+    event_service::repo_event(state, repo_id, ChangeKind::RepoUpdated);
+
+    get_repo(state, repo_id).await
+}
+
+fn validate_exec_limits(limits: &ExecLimits) -> Result<(), AppError> {
+    if limits
+        .cpu_max_cores
+        .is_some_and(|c| !c.is_finite() || c < 0.0)
+    {
+        return Err(AppError::BadRequest(
+            "exec_limits.cpu_max_cores must be a non-negative number".into(),
+        ));
+    }
+    Ok(())
+}
+
+fn validate_command_profile(state: &AppState, name: Option<&str>) -> Result<(), AppError> {
+    match name {
+        Some(name) if !state.command_policy.has_profile(name) => Err(AppError::BadRequest(
+            format!("Unknown command profile '{}'", name),
+        )),
+        _ => Ok(()),
+    }
+}
+
+pub(crate) fn validate_ttl(field: &str, ttl_seconds: Option<u64>) -> Result<(), AppError> {
+    match ttl_seconds {
+        Some(s) if s > MAX_TTL_SECONDS => Err(AppError::BadRequest(format!(
+            "{} must be at most {}",
+            field, MAX_TTL_SECONDS
+        ))),
+        _ => Ok(()),
+    }
+}
+
+fn expiry_from(now: DateTime<Utc>, ttl_seconds: Option<u64>) -> Option<DateTime<Utc>> {
+    ttl_seconds.and_then(|s| checked_deadline(now, s))
+}
+
+fn deleted_kind(cause: DeleteCause) -> ChangeKind {
+    match cause {
+        DeleteCause::Expiry => ChangeKind::RepoExpired,
+        DeleteCause::User | DeleteCause::Eviction => ChangeKind::RepoDeleted,
+    }
+}
+
+/// Delete a repository, moving it to the trash unless `cause` is
+/// configured to skip it.
+pub async fn delete_repo(
+    state: &AppState,
+    repo_id: Uuid,
+    cause: DeleteCause,
+) -> Result<(), AppError> {
+    let repo = state
+        .repos
+        .get(&repo_id)
+        .map(|r| r.value().clone())
+        .ok_or_else(|| AppError::NotFound(format!("Repository {} not found", repo_id)))?;
+
+    watch_service::unwatch_repo(state, repo_id);
+    if trash_service::uses_trash(state, cause) {
+        trash_service::trash_repo(state, repo, cause).await?;
+        event_service::repo_event(state, repo_id, deleted_kind(cause));
+        return Ok(());
+    }
 
     // WAL first
     {
@@ -148,6 +546,9 @@ pub async fn delete_repo(state: &AppState, repo_id: Uuid) -> Result<(), AppError
     // Remove from in-memory state
     state.repos.remove(&repo_id);
     state.files.remove(&repo_id);
+    state.gdsf_clocks.remove(&repo_id);
+    checkpoint_service::forget_repo(state, repo_id);
+    event_service::repo_event(state, repo_id, deleted_kind(cause));
 
     // Remove from filesystem
     let repo_dir = state.config.repos_dir().join(repo_id.to_string());
@@ -0,0 +1,276 @@
+use crate::error::AppError;
+use crate::models::event::ChangeKind;
+use crate::models::file::FileMeta;
+use crate::models::scrub::{ScrubFinding, ScrubIssue, ScrubProgress};
+use crate::persistence;
+use crate::persistence::wal::WalEntry;
+use crate::services::{event_service, file_service, watch_service};
+use crate::state::AppState;
+use chrono::Utc;
+use uuid::Uuid;
+
+/// Outcome of one `scrub_batch` call.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ScrubBatch {
+    pub files_checked: u64,
+    pub bytes_hashed: u64,
+    pub pass_complete: bool,
+}
+
+/// Load saved progress into the state so an interrupted pass resumes.
+pub async fn load(state: &AppState) {
+    match persistence::scrub::load_progress(&state.config.scrub_progress_path()) {
+        Ok(Some(progress)) => {
+            if let Some((repo_id, path)) = &progress.cursor {
+                tracing::info!(repo_id = %repo_id, path = %path, "Resuming integrity scrub");
+            }
+            *state.scrub.write().await = progress;
+        }
+        Ok(None) => {}
+        Err(e) => tracing::error!(error = %e, "Failed to load scrub progress"),
+    }
+}
+
+async fn save(state: &AppState, progress: &ScrubProgress) {
+    if let Err(e) = persistence::scrub::save_progress(&state.config.scrub_progress_path(), progress)
+    {
+        tracing::error!(error = %e, "Failed to save scrub progress");
+    }
+}
+
+fn begin_pass(progress: &mut ScrubProgress) {
+    progress.pass_started_at = Some(Utc::now());
+    progress.cursor = None;
+    progress.pass_files_checked = 0;
+    progress.pass_bytes_checked = 0;
+}
+
+/// Start a pass now unless one is already running, and wake the scrubber.
+pub async fn request_pass(state: &AppState) -> ScrubProgress {
+    let progress = {
+        let mut progress = state.scrub.write().await;
+        if progress.pass_started_at.is_none() {
+            begin_pass(&mut progress);
+        }
+        progress.clone()
+    };
+    save(state, &progress).await;
+    state.scrub_wake.notify_one();
+    progress
+}
+
+/// Check up to `max_files` files after the cursor, record what is found,
+/// and save progress. A pass is started if none is running.
+pub async fn scrub_batch(state: &AppState, max_files: usize) -> ScrubBatch {
+    let cursor = {
+        let mut progress = state.scrub.write().await;
+        if progress.pass_started_at.is_none() {
+            begin_pass(&mut progress);
+        }
+        progress.cursor.clone()
+    };
+
+    let queue = next_files(state, cursor.as_ref(), max_files);
+    let mut batch = ScrubBatch {
+        pass_complete: queue.len() < max_files,
+        ..Default::default()
+    };
+
+    for meta in queue {
+        let result = check_file(state, &meta).await;
+        let mut progress = state.scrub.write().await;
+        progress.cursor = Some((meta.repo_id, meta.path.clone()));
+        progress
+            .findings
+            .retain(|f| f.repo_id != meta.repo_id || f.path != meta.path);
+
+        match result {
+            Ok((bytes, finding)) => {
+                batch.files_checked += 1;
+                batch.bytes_hashed += bytes;
+                progress.pass_files_checked += 1;
+                progress.pass_bytes_checked += bytes;
+                progress.files_checked_total += 1;
+                progress.bytes_checked_total += bytes;
+                if let Some(finding) = finding {
+                    match finding.issue {
+                        ScrubIssue::Corrupted => progress.corrupted_total += 1,
+                        ScrubIssue::Missing => progress.missing_total += 1,
+                    }
+                    progress.findings.push(finding);
+                }
+            }
+            Err(e) => {
+                tracing::warn!(
+                    repo_id = %meta.repo_id,
+                    path = %meta.path,
+                    error = %e,
+                    "Failed to scrub file"
+                );
+            }
+        }
+    }
+
+    let progress = {
+        let mut progress = state.scrub.write().await;
+        if batch.pass_complete {
+            progress.pass_started_at = None;
+            progress.cursor = None;
+            progress.last_pass_completed_at = Some(Utc::now());
+            progress.passes_completed += 1;
+            tracing::info!(
+                files = progress.pass_files_checked,
+                bytes = progress.pass_bytes_checked,
+                findings = progress.findings.len(),
+                "Integrity scrub pass completed"
+            );
+        }
+        progress.clone()
+    };
+    save(state, &progress).await;
+    batch
+}
+
+/// The next `limit` files after `cursor`, in (repo id, path) order.
+fn next_files(state: &AppState, cursor: Option<&(Uuid, String)>, limit: usize) -> Vec<FileMeta> {
+    let mut repo_ids: Vec<Uuid> = state.repos.iter().map(|r| *r.key()).collect();
+    repo_ids.sort();
+
+    let mut queue = Vec::new();
+    for repo_id in repo_ids {
+        if cursor.is_some_and(|(c, _)| repo_id < *c) {
+            continue;
+        }
+        let Some(files) = state.files.get(&repo_id) else {
+            continue;
+        };
+        let mut paths: Vec<String> = files
+            .iter()
+            .map(|f| f.key().clone())
+            .filter(|p| cursor.is_none_or(|(c, after)| repo_id != *c || p > after))
+            .collect();
+        paths.sort();
+
+        for path in paths {
+            if queue.len() >= limit {
+                return queue;
+            }
+            if let Some(meta) = files.get(&path) {
+                queue.push(meta.value().clone());
+            }
+        }
+    }
+    queue
+}
+
+fn current_etag(state: &AppState, repo_id: Uuid, path: &str) -> Option<String> {
+    state
+        .files
+        .get(&repo_id)
+        .and_then(|files| files.get(path).map(|f| f.etag.clone()))
+}
+
+/// Rehash one file. Returns the bytes hashed and a finding when the file
+/// is missing or no longer matches its etag. Files rewritten or deleted
+/// while being checked are skipped.
+async fn check_file(
+    state: &AppState,
+    meta: &FileMeta,
+) -> Result<(u64, Option<ScrubFinding>), AppError> {
+    let hash_path = file_service::resolve_file_path(state, meta.repo_id, &meta.path);
+    let hashed = tokio::task::spawn_blocking(move || watch_service::hash_file(&hash_path))
+        .await
+        .map_err(|e| AppError::Internal(format!("Scrub task failed: {}", e)))?;
+
+    let (bytes, actual_etag) = match hashed {
+        Ok((size, etag)) => (size, Some(etag)),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => (0, None),
+        Err(e) => return Err(e.into()),
+    };
+
+    if actual_etag.as_deref() == Some(meta.etag.as_str())
+        || current_etag(state, meta.repo_id, &meta.path).as_deref() != Some(meta.etag.as_str())
+    {
+        return Ok((bytes, None));
+    }
+
+    let issue = if actual_etag.is_some() {
+        ScrubIssue::Corrupted
+    } else {
+        ScrubIssue::Missing
+    };
+    tracing::error!(
+        repo_id = %meta.repo_id,
+        path = %meta.path,
+        issue = ?issue,
+        expected = %meta.etag,
+        actual = ?actual_etag,
+        "Integrity scrub found a bad file"
+    );
+
+    let quarantine_path = if issue == ScrubIssue::Corrupted && state.config.scrub_quarantine {
+        match quarantine(state, meta).await? {
+            Some(path) => Some(path),
+            // Replaced while it was being hashed
+            None => return Ok((bytes, None)),
+        }
+    } else {
+        None
+    };
+
+    Ok((
+        bytes,
+        Some(ScrubFinding {
+            repo_id: meta.repo_id,
+            path: meta.path.clone(),
+            issue,
+            expected_etag: meta.etag.clone(),
+            actual_etag,
+            detected_at: Utc::now(),
+            quarantine_path,
+        }),
+    ))
+}
+
+/// Move a corrupted file to `quarantine/<repo_id>/<path>` and drop its
+/// metadata so it is no longer served. Holds the WAL lock from the etag
+/// re-check to the append, so a write landing meanwhile is left alone;
+/// returns `None` if one already has.
+async fn quarantine(state: &AppState, meta: &FileMeta) -> Result<Option<String>, AppError> {
+    let src = file_service::resolve_file_path(state, meta.repo_id, &meta.path);
+    let dst = state
+        .config
+        .quarantine_dir()
+        .join(meta.repo_id.to_string())
+        .join(&meta.path);
+    if let Some(parent) = dst.parent() {
+        tokio::fs::create_dir_all(parent).await?;
+    }
+
+    {
+        let mut wal = state.wal.write().await;
+        if current_etag(state, meta.repo_id, &meta.path).as_deref() != Some(meta.etag.as_str()) {
+            return Ok(None);
+        }
+        tokio::fs::rename(&src, &dst).await?;
+        if let Err(e) = wal.append(&WalEntry::FileDeleted {
+            repo_id: meta.repo_id,
+            path: meta.path.clone(),
+        }) {
+            let _ = tokio::fs::rename(&dst, &src).await;
+            return Err(AppError::Internal(format!("WAL write failed: {}", e)));
+        }
+        file_service::forget_file(state, meta.repo_id, &meta.path);
+    }
+    event_service::file_event(state, meta.repo_id, ChangeKind::FileDeleted, &meta.path);
+    file_service::cleanup_empty_dirs(&file_service::repo_files_dir(state, meta.repo_id), &src)
+        .await;
+
+    tracing::warn!(
+        repo_id = %meta.repo_id,
+        path = %meta.path,
+        quarantine = %dst.display(),
+        "Corrupted file quarantined"
+    );
+    Ok(Some(dst.to_string_lossy().into_owned()))
+}
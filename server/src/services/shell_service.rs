@@ -1,8 +1,13 @@
 use crate::error::AppError;
+use crate::models::repo::ExecLimits;
+use crate::sandbox::command_policy::DEFAULT_PROFILE;
 use crate::sandbox::command_whitelist;
 use crate::sandbox::executor;
+use crate::sandbox::namespace;
+use crate::services::{access_service, file_service};
 use crate::state::AppState;
 use serde::{Deserialize, Serialize};
+use std::path::Path;
 use uuid::Uuid;
 
 #[derive(Debug, Deserialize)]
@@ -19,7 +24,52 @@ pub struct ExecResponse {
     pub stdout: String,
     pub stderr: String,
     pub duration_ms: u64,
+    /// From `wait4`.
+    pub user_cpu_ms: u64,
+    pub system_cpu_ms: u64,
+    pub max_rss_bytes: u64,
+    pub block_reads: u64,
+    pub block_writes: u64,
+    /// The signal that terminated the command, if any.
+    pub signal: Option<i32>,
+    /// The limit behind `signal`: `timeout`, `seccomp`, `cpu_time`,
+    /// `file_size` or `memory`. Output up to a timeout is still returned.
+    pub killed_by: Option<String>,
     pub truncated: bool,
+    /// The command was killed for making a system call the sandbox denies.
+    pub seccomp_violation: bool,
+    /// Usage from the command's cgroup; `None` without cgroup limits.
+    pub memory_peak_bytes: Option<u64>,
+    pub cpu_time_ms: Option<u64>,
+    /// The kernel killed part of the command for exceeding its memory limit.
+    pub oom_killed: bool,
+}
+
+#[derive(Debug, Serialize)]
+pub struct CommandList {
+    pub profile: String,
+    /// Whether commands may write to the repository.
+    pub allow_mutating: bool,
+    pub commands: Vec<CommandInfo>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct CommandInfo {
+    pub name: String,
+    /// The binary that runs; `None` when it is not installed.
+    pub path: Option<String>,
+    pub version: Option<String>,
+    /// Every invocation writes to the repository.
+    pub mutating: bool,
+    /// Only runs where namespaces, Landlock and seccomp are all enforced.
+    pub requires_sandbox: bool,
+    /// Passed before the request's arguments.
+    pub fixed_args: Vec<String>,
+    /// Defaults, which are also the most a request may ask for.
+    pub timeout_seconds: u64,
+    pub max_output_bytes: usize,
+    /// The repo's limits with the command's overrides applied.
+    pub exec_limits: ExecLimits,
 }
 
 pub async fn execute_command(
@@ -28,20 +78,21 @@ pub async fn execute_command(
     req: ExecRequest,
 ) -> Result<ExecResponse, AppError> {
     // Validate repo exists
-    if !state.repos.contains_key(&repo_id) {
-        return Err(AppError::NotFound(format!(
-            "Repository {} not found",
-            repo_id
-        )));
-    }
+    let (exec_limits, command_profile) = state
+        .repos
+        .get(&repo_id)
+        .map(|r| (r.exec_limits, r.command_profile.clone()))
+        .ok_or_else(|| AppError::NotFound(format!("Repository {} not found", repo_id)))?;
+    access_service::touch_repo(state, repo_id);
 
-    // Validate command is whitelisted
-    if !command_whitelist::is_allowed(&req.command) {
+    // Validate command is in the repo's profile
+    let profile = state.command_policy.profile(command_profile.as_deref())?;
+    let Some(rule) = profile.rule(&req.command) else {
         return Err(AppError::Forbidden(format!(
             "Command '{}' is not allowed",
             req.command
         )));
-    }
+    };
 
     // Validate arguments
     command_whitelist::validate_args(&req.args)?;
@@ -51,13 +102,40 @@ pub async fn execute_command(
         .repos_dir()
         .join(repo_id.to_string())
         .join("files");
+    let visible_root = match state.config.sandbox_namespaces {
+        true => Path::new(namespace::REPO_MOUNT),
+        false => repo_root.as_path(),
+    };
+    let writes = profile.check(&req.command, &req.args, &repo_root, visible_root)?;
+
+    let args: Vec<String> = rule.fixed_args.iter().chain(&req.args).cloned().collect();
+    let timeout = capped(
+        req.timeout_seconds
+            .unwrap_or(state.config.command_timeout_secs),
+        rule.timeout_seconds,
+    );
+    let max_output = capped(
+        req.max_output_bytes
+            .unwrap_or(state.config.command_max_output_bytes),
+        rule.max_output_bytes,
+    );
+    let exec_limits = rule.exec_limits.or(exec_limits);
+    let sandbox = executor::SandboxOptions::from_config(&state.config, &exec_limits);
+    if rule.requires_sandbox && !sandbox.fully_enforced() {
+        return Err(AppError::Forbidden(format!(
+            "{}: the command needs namespaces, Landlock and seccomp, which this server does not enforce",
+            req.command
+        )));
+    }
 
-    let timeout = req
-        .timeout_seconds
-        .unwrap_or(state.config.command_timeout_secs);
-    let max_output = req
-        .max_output_bytes
-        .unwrap_or(state.config.command_max_output_bytes);
+    // Hardlinked forks share inodes with their source, which a command
+    // writing in place would change in both repos
+    if profile.allow_mutating() && state.config.fork_fallback == "hardlink" {
+        let (root, tmp_dir) = (repo_root.clone(), state.config.tmp_dir());
+        tokio::task::spawn_blocking(move || file_service::unshare_links(&root, &tmp_dir))
+            .await
+            .map_err(|e| AppError::Internal(format!("Unshare task failed: {}", e)))??;
+    }
 
     // Acquire semaphore permit
     let _permit = state
@@ -67,5 +145,57 @@ pub async fn execute_command(
         .map_err(|_| AppError::Internal("Command semaphore closed".into()))?;
 
     // Execute
-    executor::run_command(&req.command, &req.args, &repo_root, timeout, max_output).await
+    executor::run_command(
+        &req.command,
+        &args,
+        &repo_root,
+        writes,
+        timeout,
+        max_output,
+        sandbox,
+    )
+    .await
+}
+
+/// The commands a repo's profile allows, with the binaries they run.
+pub async fn list_commands(state: &AppState, repo_id: Uuid) -> Result<CommandList, AppError> {
+    let (exec_limits, command_profile) = state
+        .repos
+        .get(&repo_id)
+        .map(|r| (r.exec_limits, r.command_profile.clone()))
+        .ok_or_else(|| AppError::NotFound(format!("Repository {} not found", repo_id)))?;
+    access_service::touch_repo(state, repo_id);
+
+    let profile = state.command_policy.profile(command_profile.as_deref())?;
+    let commands = profile.commands().map(|(name, rule)| async move {
+        let program = executor::resolve_program(name).ok();
+        let version = match &program {
+            Some(program) => executor::binary_version(program, &rule.version_args).await,
+            None => None,
+        };
+        CommandInfo {
+            name: name.to_string(),
+            path: program.map(|p| p.to_string_lossy().into_owned()),
+            version,
+            mutating: rule.mutating,
+            requires_sandbox: rule.requires_sandbox,
+            fixed_args: rule.fixed_args.clone(),
+            timeout_seconds: capped(state.config.command_timeout_secs, rule.timeout_seconds),
+            max_output_bytes: capped(state.config.command_max_output_bytes, rule.max_output_bytes),
+            exec_limits: rule.exec_limits.or(exec_limits),
+        }
+    });
+
+    Ok(CommandList {
+        profile: command_profile.unwrap_or_else(|| DEFAULT_PROFILE.to_string()),
+        allow_mutating: profile.allow_mutating(),
+        commands: futures_util::future::join_all(commands).await,
+    })
+}
+
+fn capped<T: Ord>(value: T, max: Option<T>) -> T {
+    match max {
+        Some(max) => value.min(max),
+        None => value,
+    }
 }
@@ -0,0 +1,460 @@
+use crate::error::AppError;
+use crate::models::event::ChangeKind;
+use crate::models::file::FileMeta;
+use crate::models::repo::checked_deadline;
+use crate::models::sync::{
+    CopyItem, ManifestEntry, SyncPlan, SyncPlanRequest, SyncResult, UploadItem,
+};
+use crate::models::trash::{DeleteCause, TrashEntry, TrashedItem};
+use crate::persistence::wal::WalEntry;
+use crate::sandbox::path_validator;
+use crate::services::{
+    access_service, event_service, eviction_service, file_service, trash_service, watch_service,
+};
+use crate::state::AppState;
+use chrono::{Duration, Utc};
+use sha2::{Digest, Sha256};
+use std::collections::{HashMap, HashSet};
+use std::path::PathBuf;
+use uuid::Uuid;
+
+/// How long a plan can be committed after it is made.
+const PLAN_TTL_SECS: i64 = 3600;
+
+/// Compare a manifest with the repo and keep the resulting plan for a
+/// later commit.
+pub fn plan(state: &AppState, repo_id: Uuid, req: SyncPlanRequest) -> Result<SyncPlan, AppError> {
+    if !state.repos.contains_key(&repo_id) {
+        return Err(AppError::NotFound(format!(
+            "Repository {} not found",
+            repo_id
+        )));
+    }
+    access_service::touch_repo(state, repo_id);
+
+    let mut manifest: Vec<ManifestEntry> = Vec::with_capacity(req.files.len());
+    let mut seen = HashSet::new();
+    for entry in req.files {
+        let path = path_validator::validate_relative_path(&entry.path)?;
+        let sha256 = entry.sha256.to_ascii_lowercase();
+        if sha256.len() != 64 || !sha256.bytes().all(|b| b.is_ascii_hexdigit()) {
+            return Err(AppError::BadRequest(format!("Invalid sha256 for {}", path)));
+        }
+        if !seen.insert(path.clone()) {
+            return Err(AppError::BadRequest(format!(
+                "Duplicate manifest path: {}",
+                path
+            )));
+        }
+        manifest.push(ManifestEntry {
+            path,
+            sha256,
+            size: entry.size,
+        });
+    }
+
+    let existing: HashMap<String, (String, u64)> = state
+        .files
+        .get(&repo_id)
+        .map(|files| {
+            files
+                .iter()
+                .map(|f| (f.path.clone(), (f.etag.clone(), f.size_bytes)))
+                .collect()
+        })
+        .unwrap_or_default();
+    let mut by_etag: HashMap<&str, &str> = HashMap::new();
+    for (path, (etag, _)) in &existing {
+        // Lowest path wins so plans are deterministic
+        let slot = by_etag.entry(etag.as_str()).or_insert(path.as_str());
+        if path.as_str() < *slot {
+            *slot = path.as_str();
+        }
+    }
+
+    let mirror = req.mirror.unwrap_or(false);
+    let mut observed: HashMap<String, Option<String>> = HashMap::new();
+    let mut uploads: Vec<UploadItem> = Vec::new();
+    let mut upload_index: HashMap<String, usize> = HashMap::new();
+    let mut copy = Vec::new();
+    let mut unchanged = 0;
+
+    for entry in &manifest {
+        let current = existing.get(&entry.path);
+        observed.insert(entry.path.clone(), current.map(|(etag, _)| etag.clone()));
+        if current.is_some_and(|(etag, size)| *etag == entry.sha256 && *size == entry.size) {
+            unchanged += 1;
+        } else if let Some(source) = by_etag.get(entry.sha256.as_str()) {
+            observed.insert(source.to_string(), Some(entry.sha256.clone()));
+            copy.push(CopyItem {
+                path: entry.path.clone(),
+                source: source.to_string(),
+            });
+        } else if let Some(&i) = upload_index.get(&entry.sha256) {
+            uploads[i].paths.push(entry.path.clone());
+        } else {
+            upload_index.insert(entry.sha256.clone(), uploads.len());
+            uploads.push(UploadItem {
+                sha256: entry.sha256.clone(),
+                size: entry.size,
+                paths: vec![entry.path.clone()],
+            });
+        }
+    }
+
+    let mut delete = Vec::new();
+    if mirror {
+        for (path, (etag, _)) in &existing {
+            if !seen.contains(path) {
+                observed.insert(path.clone(), Some(etag.clone()));
+                delete.push(path.clone());
+            }
+        }
+        delete.sort();
+    }
+
+    let upload_bytes = uploads
+        .iter()
+        .try_fold(0u64, |total, u| total.checked_add(u.size))
+        .ok_or_else(|| AppError::BadRequest("Manifest sizes add up to more than u64".into()))?;
+    if upload_bytes > state.config.max_upload_size {
+        return Err(AppError::PayloadTooLarge(format!(
+            "Plan needs {} bytes of uploads, over the {} byte request limit; sync in smaller batches",
+            upload_bytes, state.config.max_upload_size
+        )));
+    }
+
+    let now = Utc::now();
+    state.sync_plans.retain(|_, p| p.expires_at > now);
+    let plan = SyncPlan {
+        plan_id: Uuid::new_v4(),
+        repo_id,
+        mirror,
+        upload: uploads,
+        copy,
+        delete,
+        unchanged,
+        upload_bytes,
+        expires_at: now + Duration::seconds(PLAN_TTL_SECS),
+        observed,
+    };
+    state.sync_plans.insert(plan.plan_id, plan.clone());
+    Ok(plan)
+}
+
+/// A file written to scratch space, waiting to be renamed into the repo.
+struct Staged {
+    tmp_path: PathBuf,
+    path: String,
+    etag: String,
+    size_bytes: u64,
+}
+
+/// Apply a plan: write the uploaded blobs, fill copies from existing
+/// files and, in mirror mode, delete extra files. Everything is staged
+/// and checked first and then recorded as one WAL entry, so the repo
+/// either takes the whole plan or none of it.
+pub async fn commit(
+    state: &AppState,
+    repo_id: Uuid,
+    plan_id: Uuid,
+    body: bytes::Bytes,
+) -> Result<SyncResult, AppError> {
+    let plan = state
+        .sync_plans
+        .remove(&plan_id)
+        .map(|(_, plan)| plan)
+        .filter(|plan| plan.repo_id == repo_id && plan.expires_at > Utc::now())
+        .ok_or_else(|| AppError::NotFound(format!("Sync plan {} not found or expired", plan_id)))?;
+    if body.len() as u64 != plan.upload_bytes {
+        return Err(AppError::BadRequest(format!(
+            "Expected {} bytes of upload content, got {}",
+            plan.upload_bytes,
+            body.len()
+        )));
+    }
+
+    let mut staged: Vec<Staged> = Vec::new();
+    let result = stage(state, &plan, &body, &mut staged).await;
+    let result = match result {
+        Ok(()) => apply(state, &plan, &staged).await,
+        Err(e) => Err(e),
+    };
+    if result.is_err() {
+        for file in &staged {
+            let _ = tokio::fs::remove_file(&file.tmp_path).await;
+        }
+    }
+    result
+}
+
+/// Write every blob and copy into scratch files.
+async fn stage(
+    state: &AppState,
+    plan: &SyncPlan,
+    body: &[u8],
+    staged: &mut Vec<Staged>,
+) -> Result<(), AppError> {
+    let mut offset = 0usize;
+    for item in &plan.upload {
+        let data = usize::try_from(item.size)
+            .ok()
+            .and_then(|size| offset.checked_add(size))
+            .and_then(|end| body.get(offset..end))
+            .ok_or_else(|| {
+                AppError::BadRequest(format!("Upload content for {} is truncated", item.paths[0]))
+            })?;
+        offset += data.len();
+        let etag = hex::encode(Sha256::digest(data));
+        if etag != item.sha256 {
+            return Err(AppError::BadRequest(format!(
+                "Content for {} does not match its sha256",
+                item.paths[0]
+            )));
+        }
+        let first = temp_path(state).await?;
+        tokio::fs::write(&first, data).await?;
+        for (i, path) in item.paths.iter().enumerate() {
+            let tmp_path = if i == 0 {
+                first.clone()
+            } else {
+                let tmp_path = temp_path(state).await?;
+                clone_into(&first, &tmp_path).await?;
+                tmp_path
+            };
+            staged.push(Staged {
+                tmp_path,
+                path: path.clone(),
+                etag: etag.clone(),
+                size_bytes: item.size,
+            });
+        }
+    }
+
+    for item in &plan.copy {
+        let (etag, size_bytes) = state
+            .files
+            .get(&plan.repo_id)
+            .and_then(|files| {
+                files
+                    .get(&item.source)
+                    .map(|f| (f.etag.clone(), f.size_bytes))
+            })
+            .ok_or_else(|| changed_since_plan(&item.source))?;
+        let tmp_path = temp_path(state).await?;
+        let src = file_service::resolve_file_path(state, plan.repo_id, &item.source);
+        clone_into(&src, &tmp_path).await?;
+        staged.push(Staged {
+            tmp_path,
+            path: item.path.clone(),
+            etag,
+            size_bytes,
+        });
+    }
+    Ok(())
+}
+
+async fn temp_path(state: &AppState) -> Result<PathBuf, AppError> {
+    file_service::temp_file_path(state).await
+}
+
+async fn clone_into(src: &std::path::Path, dst: &std::path::Path) -> Result<(), AppError> {
+    let (src, dst) = (src.to_path_buf(), dst.to_path_buf());
+    tokio::task::spawn_blocking(move || file_service::clone_file(&src, &dst, false))
+        .await
+        .map_err(|e| AppError::Internal(format!("Staging task failed: {}", e)))??;
+    Ok(())
+}
+
+fn changed_since_plan(path: &str) -> AppError {
+    AppError::Conflict(format!(
+        "{} changed since the plan was made; request a new plan",
+        path
+    ))
+}
+
+async fn apply(
+    state: &AppState,
+    plan: &SyncPlan,
+    staged: &[Staged],
+) -> Result<SyncResult, AppError> {
+    let repo_id = plan.repo_id;
+    let now = Utc::now();
+
+    // Other writers append to the WAL before touching memory, so holding
+    // it keeps them out until the plan is applied.
+    let mut wal = state.wal.write().await;
+
+    let (current_size, max_size, default_ttl) = state
+        .repos
+        .get(&repo_id)
+        .map(|r| {
+            (
+                r.current_size_bytes,
+                r.max_size_bytes,
+                r.default_ttl_seconds,
+            )
+        })
+        .ok_or_else(|| AppError::NotFound(format!("Repository {} not found", repo_id)))?;
+
+    let mut replaced: HashMap<String, FileMeta> = HashMap::new();
+    {
+        let files = state.files.get(&repo_id);
+        for (path, expected) in &plan.observed {
+            let current = files.as_ref().and_then(|f| f.get(path).map(|m| m.clone()));
+            if current.as_ref().map(|m| &m.etag) != expected.as_ref() {
+                return Err(changed_since_plan(path));
+            }
+            if let Some(meta) = current {
+                replaced.insert(path.clone(), meta);
+            }
+        }
+    }
+
+    let removed_bytes: u64 = staged
+        .iter()
+        .filter_map(|s| replaced.get(&s.path))
+        .chain(plan.delete.iter().filter_map(|p| replaced.get(p)))
+        .map(|m| m.size_bytes)
+        .sum();
+    let added_bytes: u64 = staged.iter().map(|s| s.size_bytes).sum();
+    let new_total = (current_size + added_bytes).saturating_sub(removed_bytes);
+    if new_total > max_size {
+        return Err(AppError::PayloadTooLarge(format!(
+            "Repository size limit exceeded. Need {} more bytes",
+            new_total - max_size
+        )));
+    }
+    eviction_service::check_global(state, added_bytes.saturating_sub(removed_bytes))?;
+
+    let expires_at = default_ttl.and_then(|s| checked_deadline(now, s));
+    let metas: Vec<FileMeta> = staged
+        .iter()
+        .map(|s| {
+            let previous = replaced.get(&s.path);
+            FileMeta {
+                repo_id,
+                path: s.path.clone(),
+                size_bytes: s.size_bytes,
+                etag: s.etag.clone(),
+                content_type: mime_guess::from_path(&s.path)
+                    .first_or_octet_stream()
+                    .to_string(),
+                created_at: previous.map(|m| m.created_at).unwrap_or(now),
+                updated_at: now,
+                last_accessed_at: now,
+                access_count: 0,
+                expires_at,
+                pinned: previous.is_some_and(|m| m.pinned),
+                seq: 0,
+            }
+        })
+        .collect();
+
+    let trash = trash_service::uses_trash(state, DeleteCause::User);
+    let trashed: Vec<TrashEntry> = if trash {
+        plan.delete
+            .iter()
+            .filter_map(|p| replaced.get(p))
+            .map(|meta| {
+                trash_service::new_entry(
+                    state,
+                    repo_id,
+                    DeleteCause::User,
+                    meta.size_bytes,
+                    TrashedItem::File { file: meta.clone() },
+                )
+            })
+            .collect()
+    } else {
+        Vec::new()
+    };
+    let deleted = if trash {
+        Vec::new()
+    } else {
+        plan.delete.clone()
+    };
+
+    wal.append(&WalEntry::SyncCommitted {
+        repo_id,
+        files: metas.clone(),
+        deleted,
+        trashed: trashed.clone(),
+    })
+    .map_err(|e| AppError::Internal(format!("WAL write failed: {}", e)))?;
+
+    // Deletes first, so nothing below is in the way
+    let root = file_service::repo_files_dir(state, repo_id);
+    if !plan.delete.is_empty() {
+        tokio::fs::create_dir_all(state.config.trash_dir()).await?;
+    }
+    for (i, path) in plan.delete.iter().enumerate() {
+        let disk_path = file_service::resolve_file_path(state, repo_id, path);
+        let moved = match trashed.get(i) {
+            Some(entry) => {
+                let dst = state.config.trash_dir().join(entry.id.to_string());
+                tokio::fs::rename(&disk_path, &dst).await
+            }
+            None => tokio::fs::remove_file(&disk_path).await,
+        };
+        if let Err(e) = moved {
+            tracing::warn!(repo_id = %repo_id, path = %path, error = %e, "Sync delete failed on disk");
+        }
+        file_service::forget_file(state, repo_id, path);
+        file_service::cleanup_empty_dirs(&root, &disk_path).await;
+    }
+    for entry in trashed {
+        state.trash.insert(entry.id, entry);
+    }
+
+    for (file, meta) in staged.iter().zip(metas) {
+        let disk_path = file_service::resolve_file_path(state, repo_id, &file.path);
+        if let Some(parent) = disk_path.parent() {
+            tokio::fs::create_dir_all(parent).await?;
+        }
+        watch_service::mark_api_write(state, repo_id, &file.path, &file.tmp_path).await;
+        tokio::fs::rename(&file.tmp_path, &disk_path).await?;
+        file_service::forget_file(state, repo_id, &file.path);
+        state
+            .files
+            .entry(repo_id)
+            .or_default()
+            .insert(file.path.clone(), meta);
+        if let Some(mut repo) = state.repos.get_mut(&repo_id) {
+            repo.current_size_bytes += file.size_bytes;
+            repo.file_count += 1;
+            repo.updated_at = now;
+        }
+    }
+    drop(wal);
+
+    let mut seq = state.repos.get(&repo_id).map(|r| r.seq).unwrap_or(0);
+    for path in &plan.delete {
+        seq = event_service::file_event(state, repo_id, ChangeKind::FileDeleted, path);
+    }
+    for file in staged {
+        let kind = if replaced.contains_key(&file.path) {
+            ChangeKind::FileUpdated
+        } else {
+            ChangeKind::FileCreated
+        };
+        seq = event_service::file_event(state, repo_id, kind, &file.path);
+    }
+
+    tracing::info!(
+        repo_id = %repo_id,
+        plan_id = %plan.plan_id,
+        written = staged.len(),
+        deleted = plan.delete.len(),
+        "Sync plan committed"
+    );
+
+    Ok(SyncResult {
+        plan_id: plan.plan_id,
+        uploaded: plan.upload.iter().map(|u| u.paths.len()).sum(),
+        copied: plan.copy.len(),
+        deleted: plan.delete.clone(),
+        unchanged: plan.unchanged,
+        seq,
+    })
+}
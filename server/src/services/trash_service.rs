@@ -0,0 +1,383 @@
+use crate::error::AppError;
+use crate::models::event::ChangeKind;
+use crate::models::file::FileMeta;
+use crate::models::repo::{checked_deadline, RepoMeta, MAX_TTL_SECONDS};
+use crate::models::trash::{DeleteCause, RestoreTrashRequest, TrashEntry, TrashedItem};
+use crate::persistence::wal::WalEntry;
+use crate::services::{
+    access_service, checkpoint_service, event_service, file_service, repo_service, watch_service,
+};
+use crate::state::AppState;
+use chrono::{DateTime, Utc};
+use std::path::{Path, PathBuf};
+use uuid::Uuid;
+
+/// Whether deletes for `cause` go to the trash instead of being removed
+/// outright. A retention of 0 disables the trash.
+pub fn uses_trash(state: &AppState, cause: DeleteCause) -> bool {
+    state.config.trash_retention_secs > 0
+        && !state
+            .config
+            .trash_skip_causes
+            .split(',')
+            .any(|c| c.trim() == cause.as_str())
+}
+
+fn entry_path(state: &AppState, id: Uuid) -> PathBuf {
+    state.config.trash_dir().join(id.to_string())
+}
+
+/// A trash entry for `item`, purged once the retention period passes.
+pub(crate) fn new_entry(
+    state: &AppState,
+    repo_id: Uuid,
+    cause: DeleteCause,
+    size_bytes: u64,
+    item: TrashedItem,
+) -> TrashEntry {
+    let now = Utc::now();
+    TrashEntry {
+        id: Uuid::new_v4(),
+        repo_id,
+        cause,
+        deleted_at: now,
+        purge_at: checked_deadline(now, state.config.trash_retention_secs.min(MAX_TTL_SECONDS))
+            .unwrap_or(DateTime::<Utc>::MAX_UTC),
+        size_bytes,
+        item,
+    }
+}
+
+/// Move content into the trash, record it in the WAL, and move it back
+/// if the WAL write fails.
+async fn stash(state: &AppState, src: &Path, entry: &TrashEntry) -> Result<(), AppError> {
+    tokio::fs::create_dir_all(state.config.trash_dir()).await?;
+    let dst = entry_path(state, entry.id);
+    tokio::fs::rename(src, &dst).await?;
+
+    let mut wal = state.wal.write().await;
+    if let Err(e) = wal.append(&WalEntry::Trashed {
+        entry: Box::new(entry.clone()),
+    }) {
+        let _ = tokio::fs::rename(&dst, src).await;
+        return Err(AppError::Internal(format!("WAL write failed: {}", e)));
+    }
+    Ok(())
+}
+
+pub async fn trash_file(
+    state: &AppState,
+    meta: FileMeta,
+    cause: DeleteCause,
+) -> Result<TrashEntry, AppError> {
+    let repo_id = meta.repo_id;
+    let path = meta.path.clone();
+    let entry = new_entry(
+        state,
+        repo_id,
+        cause,
+        meta.size_bytes,
+        TrashedItem::File { file: meta },
+    );
+
+    let file_path = file_service::resolve_file_path(state, repo_id, &path);
+    stash(state, &file_path, &entry).await?;
+
+    file_service::forget_file(state, repo_id, &path);
+    file_service::cleanup_empty_dirs(&file_service::repo_files_dir(state, repo_id), &file_path)
+        .await;
+    state.trash.insert(entry.id, entry.clone());
+
+    tracing::info!(
+        repo_id = %repo_id,
+        path = %path,
+        trash_id = %entry.id,
+        cause = cause.as_str(),
+        "File moved to trash"
+    );
+    Ok(entry)
+}
+
+pub async fn trash_repo(
+    state: &AppState,
+    repo: RepoMeta,
+    cause: DeleteCause,
+) -> Result<TrashEntry, AppError> {
+    let repo_id = repo.id;
+    let files: Vec<FileMeta> = state
+        .files
+        .get(&repo_id)
+        .map(|files| files.iter().map(|f| f.value().clone()).collect())
+        .unwrap_or_default();
+    let entry = new_entry(
+        state,
+        repo_id,
+        cause,
+        repo.current_size_bytes,
+        TrashedItem::Repo {
+            repo: Box::new(repo),
+            files,
+        },
+    );
+
+    let repo_dir = state.config.repos_dir().join(repo_id.to_string());
+    stash(state, &repo_dir, &entry).await?;
+
+    state.repos.remove(&repo_id);
+    state.files.remove(&repo_id);
+    state.gdsf_clocks.remove(&repo_id);
+    state.trash.insert(entry.id, entry.clone());
+
+    tracing::info!(
+        repo_id = %repo_id,
+        trash_id = %entry.id,
+        cause = cause.as_str(),
+        "Repository moved to trash"
+    );
+    Ok(entry)
+}
+
+/// Trash entries, newest first, optionally limited to one repository.
+pub fn list(state: &AppState, repo_id: Option<Uuid>) -> Vec<TrashEntry> {
+    let mut entries: Vec<TrashEntry> = state
+        .trash
+        .iter()
+        .filter(|e| repo_id.is_none_or(|id| e.repo_id == id))
+        .map(|e| e.value().clone())
+        .collect();
+    entries.sort_by_key(|e| std::cmp::Reverse(e.deleted_at));
+    entries
+}
+
+/// Put a trashed file back at its original path, or a trashed repository
+/// back under its original id, with the metadata it had when deleted.
+/// An item whose expiry has passed needs `ttl_seconds`, or it would be
+/// reaped again straight away. Its bytes already count toward the server
+/// budget, so restoring never needs room there.
+pub async fn restore(
+    state: &AppState,
+    id: Uuid,
+    req: RestoreTrashRequest,
+) -> Result<TrashedItem, AppError> {
+    let mut entry = state
+        .trash
+        .get(&id)
+        .map(|e| e.value().clone())
+        .ok_or_else(|| AppError::NotFound(format!("Trash entry {} not found", id)))?;
+    let src = entry_path(state, id);
+
+    repo_service::validate_ttl("ttl_seconds", req.ttl_seconds)?;
+    let now = Utc::now();
+    let new_expiry = match req.ttl_seconds {
+        Some(ttl) => {
+            let expires_at = checked_deadline(now, ttl)
+                .ok_or_else(|| AppError::BadRequest("ttl_seconds is out of range".into()))?;
+            entry.item.set_expiry(ttl, expires_at);
+            Some((ttl, expires_at))
+        }
+        None => match entry.item.expires_at() {
+            Some(expires_at) if expires_at <= now => {
+                return Err(AppError::Conflict(format!(
+                    "Trash entry {} expired at {}; pass ttl_seconds to restore it with a new expiry",
+                    id, expires_at
+                )));
+            }
+            _ => None,
+        },
+    };
+
+    match entry.item {
+        TrashedItem::File { file } => {
+            let repo_id = file.repo_id;
+            let (current_size, max_size) = state
+                .repos
+                .get(&repo_id)
+                .map(|r| (r.current_size_bytes, r.max_size_bytes))
+                .ok_or_else(|| {
+                    AppError::NotFound(format!(
+                        "Repository {} not found; restore it first",
+                        repo_id
+                    ))
+                })?;
+            if state
+                .files
+                .get(&repo_id)
+                .map(|f| f.contains_key(&file.path))
+                .unwrap_or(false)
+            {
+                return Err(AppError::Conflict(format!(
+                    "A file already exists at {}",
+                    file.path
+                )));
+            }
+            if current_size + file.size_bytes > max_size {
+                return Err(AppError::PayloadTooLarge(
+                    "Repository size limit would be exceeded by restore".into(),
+                ));
+            }
+
+            let dst = file_service::resolve_file_path(state, repo_id, &file.path);
+            if let Some(parent) = dst.parent() {
+                tokio::fs::create_dir_all(parent).await?;
+            }
+            watch_service::mark_api_write(state, repo_id, &file.path, &src).await;
+            tokio::fs::rename(&src, &dst).await?;
+            append_restored(state, id, new_expiry, &src, &dst).await?;
+
+            state
+                .files
+                .entry(repo_id)
+                .or_default()
+                .insert(file.path.clone(), file.clone());
+            if let Some(mut repo) = state.repos.get_mut(&repo_id) {
+                repo.current_size_bytes += file.size_bytes;
+                repo.file_count += 1;
+                repo.updated_at = Utc::now();
+            }
+            state.trash.remove(&id);
+            access_service::touch_repo(state, repo_id);
+            event_service::file_event(state, repo_id, ChangeKind::FileCreated, &file.path);
+
+            Ok(TrashedItem::File { file })
+        }
+        TrashedItem::Repo { repo, files } => {
+            if state.repos.contains_key(&repo.id) {
+                return Err(AppError::Conflict(format!(
+                    "Repository {} already exists",
+                    repo.id
+                )));
+            }
+
+            let dst = state.config.repos_dir().join(repo.id.to_string());
+            tokio::fs::rename(&src, &dst).await?;
+            append_restored(state, id, new_expiry, &src, &dst).await?;
+
+            let map = dashmap::DashMap::new();
+            for meta in &files {
+                map.insert(meta.path.clone(), meta.clone());
+            }
+            state.files.insert(repo.id, map);
+            state.repos.insert(repo.id, (*repo).clone());
+            state.trash.remove(&id);
+            // Restoring counts as use, so an idle TTL does not reap it again
+            access_service::touch_repo(state, repo.id);
+            watch_service::watch_repo(state, repo.id);
+            // Followers saw the repo deleted; replay its files as new
+            for meta in &files {
+                event_service::file_event(state, repo.id, ChangeKind::FileCreated, &meta.path);
+            }
+
+            Ok(TrashedItem::Repo { repo, files })
+        }
+    }
+}
+
+async fn append_restored(
+    state: &AppState,
+    id: Uuid,
+    new_expiry: Option<(u64, DateTime<Utc>)>,
+    trash_path: &Path,
+    restored_path: &Path,
+) -> Result<(), AppError> {
+    let record = match new_expiry {
+        Some((ttl_seconds, expires_at)) => WalEntry::TrashRestoredWithExpiry {
+            id,
+            ttl_seconds,
+            expires_at,
+        },
+        None => WalEntry::TrashRestored { id },
+    };
+    let mut wal = state.wal.write().await;
+    if let Err(e) = wal.append(&record) {
+        let _ = tokio::fs::rename(restored_path, trash_path).await;
+        return Err(AppError::Internal(format!("WAL write failed: {}", e)));
+    }
+    Ok(())
+}
+
+/// Permanently delete a trash entry and its content.
+pub async fn purge(state: &AppState, id: Uuid) -> Result<(), AppError> {
+    if !state.trash.contains_key(&id) {
+        return Err(AppError::NotFound(format!("Trash entry {} not found", id)));
+    }
+
+    {
+        let mut wal = state.wal.write().await;
+        wal.append(&WalEntry::TrashPurged { id })
+            .map_err(|e| AppError::Internal(format!("WAL write failed: {}", e)))?;
+    }
+
+    if let Some((_, entry)) = state.trash.remove(&id) {
+        if let TrashedItem::Repo { repo, .. } = entry.item {
+            checkpoint_service::forget_repo(state, repo.id);
+        }
+    }
+
+    let path = entry_path(state, id);
+    match tokio::fs::symlink_metadata(&path).await {
+        Ok(m) if m.is_dir() => tokio::fs::remove_dir_all(&path).await?,
+        Ok(_) => tokio::fs::remove_file(&path).await?,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+        Err(e) => return Err(e.into()),
+    }
+    Ok(())
+}
+
+/// Bytes held in the trash. They count toward the server budget until
+/// purged.
+pub fn size_bytes(state: &AppState) -> u64 {
+    state.trash.iter().map(|e| e.size_bytes).sum()
+}
+
+/// Purge entries, oldest first, until at least `needed_bytes` are freed or
+/// the trash is empty. Returns the bytes freed.
+pub async fn purge_oldest(state: &AppState, needed_bytes: u64) -> u64 {
+    let mut entries: Vec<(Uuid, DateTime<Utc>, u64)> = state
+        .trash
+        .iter()
+        .map(|e| (e.id, e.deleted_at, e.size_bytes))
+        .collect();
+    entries.sort_by_key(|&(_, deleted_at, _)| deleted_at);
+
+    let mut freed = 0;
+    for (id, _, size_bytes) in entries {
+        if freed >= needed_bytes {
+            break;
+        }
+        match purge(state, id).await {
+            Ok(()) => freed += size_bytes,
+            Err(AppError::NotFound(_)) => {}
+            Err(e) => {
+                tracing::warn!(trash_id = %id, error = %e, "Failed to purge trash entry");
+            }
+        }
+    }
+    if freed > 0 {
+        tracing::info!(freed_bytes = freed, "Purged trash for server storage budget");
+    }
+    freed
+}
+
+/// Purge every entry whose retention window has ended. Returns the number
+/// purged.
+pub async fn purge_expired(state: &AppState) -> u64 {
+    let now = Utc::now();
+    let expired: Vec<Uuid> = state
+        .trash
+        .iter()
+        .filter(|e| e.purge_at <= now)
+        .map(|e| e.id)
+        .collect();
+
+    let mut purged = 0;
+    for id in expired {
+        match purge(state, id).await {
+            Ok(()) => purged += 1,
+            Err(AppError::NotFound(_)) => {}
+            Err(e) => {
+                tracing::warn!(trash_id = %id, error = %e, "Failed to purge trash entry");
+            }
+        }
+    }
+    purged
+}
@@ -0,0 +1,512 @@
+use crate::error::AppError;
+use crate::models::event::ChangeKind;
+use crate::models::file::FileMeta;
+use crate::models::repo::checked_deadline;
+use crate::models::trash::DeleteCause;
+use crate::persistence::wal::WalEntry;
+use crate::sandbox::path_validator;
+use crate::services::{event_service, eviction_service, file_service};
+use crate::state::AppState;
+use chrono::Utc;
+use notify::event::{AccessKind, AccessMode, ModifyKind, RenameMode};
+use notify::{EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+use sha2::{Digest, Sha256};
+use std::collections::HashSet;
+use std::os::unix::fs::MetadataExt;
+use std::path::{Path, PathBuf};
+use std::time::Instant;
+use tokio::sync::mpsc;
+use uuid::Uuid;
+
+/// Filesystem watches on the `files` directory of every repo, reporting
+/// changes made outside the API (exec'd commands, operators).
+pub struct RepoWatcher {
+    watcher: std::sync::Mutex<RecommendedWatcher>,
+}
+
+/// Identifies a file the API wrote without reading it back. A rename
+/// keeps all three, so the mark can be taken on the temp file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Fingerprint {
+    ino: u64,
+    len: u64,
+    mtime_ns: i128,
+}
+
+impl Fingerprint {
+    fn of(m: &std::fs::Metadata) -> Self {
+        Fingerprint {
+            ino: m.ino(),
+            len: m.len(),
+            mtime_ns: m.mtime() as i128 * 1_000_000_000 + m.mtime_nsec() as i128,
+        }
+    }
+}
+
+/// A change to reconcile, relative to a repo's `files` directory.
+#[derive(Debug, PartialEq, Eq)]
+pub enum Change {
+    /// Created, modified or removed; rehash or drop after debouncing.
+    Dirty(Uuid, String),
+    /// Renamed within one repo.
+    Renamed(Uuid, String, String),
+}
+
+/// Install the watcher on `state` and watch every existing repo. Events
+/// arrive on the returned channel for `background::fs_watcher`.
+pub fn start(state: &AppState) -> notify::Result<mpsc::UnboundedReceiver<notify::Event>> {
+    let (tx, rx) = mpsc::unbounded_channel();
+    let watcher =
+        notify::recommended_watcher(move |res: notify::Result<notify::Event>| match res {
+            Ok(event) => {
+                let _ = tx.send(event);
+            }
+            Err(e) => tracing::warn!(error = %e, "Filesystem watch error"),
+        })?;
+
+    let _ = state.watcher.set(RepoWatcher {
+        watcher: std::sync::Mutex::new(watcher),
+    });
+    let repo_ids: Vec<Uuid> = state.repos.iter().map(|r| *r.key()).collect();
+    for repo_id in repo_ids {
+        watch_repo(state, repo_id);
+    }
+    Ok(rx)
+}
+
+pub fn watch_repo(state: &AppState, repo_id: Uuid) {
+    let Some(repo_watcher) = state.watcher.get() else {
+        return;
+    };
+    let dir = file_service::repo_files_dir(state, repo_id);
+    let mut watcher = repo_watcher.watcher.lock().unwrap();
+    if let Err(e) = watcher.watch(&dir, RecursiveMode::Recursive) {
+        tracing::warn!(repo_id = %repo_id, error = %e, "Failed to watch repository");
+    }
+}
+
+pub fn unwatch_repo(state: &AppState, repo_id: Uuid) {
+    let Some(repo_watcher) = state.watcher.get() else {
+        return;
+    };
+    let dir = file_service::repo_files_dir(state, repo_id);
+    let _ = repo_watcher.watcher.lock().unwrap().unwatch(&dir);
+}
+
+/// Record that the API is about to put `file` at `rel_path`, before it
+/// is renamed or once it is copied there, so the watcher can skip the
+/// events that causes.
+pub async fn mark_api_write(state: &AppState, repo_id: Uuid, rel_path: &str, file: &Path) {
+    if state.watcher.get().is_none() {
+        return;
+    }
+    if let Ok(m) = tokio::fs::symlink_metadata(file).await {
+        state.api_writes.insert(
+            (repo_id, rel_path.to_string()),
+            (Fingerprint::of(&m), Instant::now()),
+        );
+    }
+}
+
+/// Forget API write marks older than `max_age`, by which time their
+/// events have been handled.
+pub fn prune_api_writes(state: &AppState, max_age: std::time::Duration) {
+    state.api_writes.retain(|_, (_, at)| at.elapsed() < max_age);
+}
+
+/// Whether the file at `rel_path` is still the one the API last wrote.
+/// A mark that no longer matches is dropped.
+async fn is_api_write(state: &AppState, repo_id: Uuid, rel_path: &str) -> bool {
+    let key = (repo_id, rel_path.to_string());
+    let Some(marked) = state.api_writes.get(&key).map(|m| m.0) else {
+        return false;
+    };
+    let disk_path = file_service::resolve_file_path(state, repo_id, rel_path);
+    let matches = tokio::fs::symlink_metadata(&disk_path)
+        .await
+        .is_ok_and(|m| Fingerprint::of(&m) == marked);
+    if !matches {
+        state.api_writes.remove_if(&key, |_, (f, _)| *f == marked);
+    }
+    matches
+}
+
+/// Map a raw event to the repo paths it touches.
+pub fn classify(state: &AppState, event: &notify::Event) -> Vec<Change> {
+    match event.kind {
+        EventKind::Modify(ModifyKind::Name(RenameMode::Both)) if event.paths.len() == 2 => {
+            match (
+                repo_relative(state, &event.paths[0]),
+                repo_relative(state, &event.paths[1]),
+            ) {
+                (Some((from_repo, from)), Some((to_repo, to))) if from_repo == to_repo => {
+                    vec![Change::Renamed(from_repo, from, to)]
+                }
+                (from, to) => from
+                    .into_iter()
+                    .chain(to)
+                    .map(|(repo_id, rel)| Change::Dirty(repo_id, rel))
+                    .collect(),
+            }
+        }
+        EventKind::Create(_)
+        | EventKind::Remove(_)
+        | EventKind::Modify(ModifyKind::Data(_))
+        | EventKind::Modify(ModifyKind::Name(_))
+        | EventKind::Modify(ModifyKind::Any)
+        | EventKind::Access(AccessKind::Close(AccessMode::Write)) => event
+            .paths
+            .iter()
+            .filter_map(|p| repo_relative(state, p))
+            .map(|(repo_id, rel)| Change::Dirty(repo_id, rel))
+            .collect(),
+        _ => Vec::new(),
+    }
+}
+
+/// Split `repos/<id>/files/<rel>` into the repo id and validated `rel`.
+fn repo_relative(state: &AppState, path: &Path) -> Option<(Uuid, String)> {
+    let rest = path.strip_prefix(state.config.repos_dir()).ok()?;
+    let mut components = rest.components();
+    let repo_id = Uuid::parse_str(components.next()?.as_os_str().to_str()?).ok()?;
+    if components.next()?.as_os_str() != "files" {
+        return None;
+    }
+    let rel = components.as_path().to_str()?;
+    if rel.is_empty() {
+        return None;
+    }
+    let rel = path_validator::validate_relative_path(rel).ok()?;
+    Some((repo_id, rel))
+}
+
+/// Bring the metadata for `rel_path`, and everything under it if it is a
+/// directory, in line with what is on disk.
+pub async fn sync_path(state: &AppState, repo_id: Uuid, rel_path: &str) -> Result<(), AppError> {
+    if !state.repos.contains_key(&repo_id) {
+        return Ok(());
+    }
+    let disk_path = file_service::resolve_file_path(state, repo_id, rel_path);
+    let metadata = match tokio::fs::symlink_metadata(&disk_path).await {
+        Ok(m) => Some(m),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => None,
+        Err(e) => return Err(e.into()),
+    };
+
+    match metadata {
+        Some(m) if m.is_file() => sync_file(state, repo_id, rel_path).await,
+        Some(m) if m.is_dir() => {
+            let root = disk_path.clone();
+            let found = tokio::task::spawn_blocking(move || walk_files(&root))
+                .await
+                .map_err(|e| AppError::Internal(format!("Watch task failed: {}", e)))??;
+            for rel in &found {
+                let path = format!("{}/{}", rel_path, rel);
+                sync_file(state, repo_id, &path).await?;
+            }
+            forget_missing_under(state, repo_id, rel_path).await
+        }
+        // Gone, or replaced by something we do not track (symlink, fifo)
+        _ => {
+            forget_external_delete(state, repo_id, rel_path).await?;
+            forget_missing_under(state, repo_id, rel_path).await
+        }
+    }
+}
+
+/// Follow a rename inside one repo, carrying metadata to the new path so
+/// pins and access stats survive. Anything that does not line up with
+/// known metadata is left to `sync_path`.
+pub async fn apply_rename(
+    state: &AppState,
+    repo_id: Uuid,
+    from: &str,
+    to: &str,
+) -> Result<(), AppError> {
+    let known: HashSet<String> = state
+        .files
+        .get(&repo_id)
+        .map(|files| files.iter().map(|f| f.key().clone()).collect())
+        .unwrap_or_default();
+    let prefix = format!("{}/", from);
+    let moves: Vec<(String, String)> = known
+        .iter()
+        .filter_map(|path| {
+            if path == from {
+                Some((path.clone(), to.to_string()))
+            } else {
+                path.strip_prefix(&prefix)
+                    .map(|rest| (path.clone(), format!("{}/{}", to, rest)))
+            }
+        })
+        .filter(|(_, dst)| !known.contains(dst))
+        .collect();
+
+    let now = Utc::now();
+    for (source, destination) in moves {
+        // A move the API has made but not yet recorded
+        if is_api_write(state, repo_id, &destination).await {
+            continue;
+        }
+        {
+            let mut wal = state.wal.write().await;
+            wal.append(&WalEntry::FileMoved {
+                repo_id,
+                source: source.clone(),
+                destination: destination.clone(),
+                updated_at: now,
+            })
+            .map_err(|e| AppError::Internal(format!("WAL write failed: {}", e)))?;
+        }
+        if let Some(files) = state.files.get(&repo_id) {
+            if let Some((_, mut meta)) = files.remove(&source) {
+                meta.path = destination.clone();
+                meta.updated_at = now;
+                files.insert(destination.clone(), meta);
+            }
+        }
+        event_service::file_moved(state, repo_id, &source, &destination);
+    }
+    Ok(())
+}
+
+async fn sync_file(state: &AppState, repo_id: Uuid, rel_path: &str) -> Result<(), AppError> {
+    if is_api_write(state, repo_id, rel_path).await {
+        return Ok(());
+    }
+    let disk_path = file_service::resolve_file_path(state, repo_id, rel_path);
+    let hashed = tokio::task::spawn_blocking(move || hash_file(&disk_path))
+        .await
+        .map_err(|e| AppError::Internal(format!("Watch task failed: {}", e)))?;
+    let (size_bytes, etag) = match hashed {
+        Ok(h) => h,
+        // Removed between the event and now; a later event covers it
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(()),
+        Err(e) => return Err(e.into()),
+    };
+
+    let existing = state
+        .files
+        .get(&repo_id)
+        .and_then(|files| files.get(rel_path).map(|f| f.clone()));
+    if existing
+        .as_ref()
+        .is_some_and(|m| m.etag == etag && m.size_bytes == size_bytes)
+    {
+        return Ok(());
+    }
+
+    record_disk_file(state, repo_id, rel_path, size_bytes, etag, existing).await?;
+    tracing::info!(
+        repo_id = %repo_id,
+        path = %rel_path,
+        size = size_bytes,
+        "Out-of-band write recorded"
+    );
+    enforce_limits(state, repo_id, rel_path).await
+}
+
+/// Hold an out-of-band write to the limits an upload would meet: evict
+/// from the repo and then across the server, keeping the file itself.
+/// When that cannot free enough the file is removed, as an upload would
+/// have been refused.
+async fn enforce_limits(state: &AppState, repo_id: Uuid, rel_path: &str) -> Result<(), AppError> {
+    let Some((current, max)) = state
+        .repos
+        .get(&repo_id)
+        .map(|r| (r.current_size_bytes, r.max_size_bytes))
+    else {
+        return Ok(());
+    };
+    let mut result = Ok(());
+    if current > max {
+        result = eviction_service::evict_bytes(state, repo_id, current - max, Some(rel_path))
+            .await
+            .map(|_| ());
+    }
+    if result.is_ok() {
+        result = eviction_service::reserve_global(state, 0, Some((repo_id, rel_path)))
+            .await
+            .map(|_| ());
+    }
+    match result {
+        Err(AppError::PayloadTooLarge(reason)) => {
+            tracing::warn!(
+                repo_id = %repo_id,
+                path = %rel_path,
+                reason = %reason,
+                "Removing out-of-band write over the storage limits"
+            );
+            file_service::delete_file(state, repo_id, rel_path, DeleteCause::Eviction).await
+        }
+        other => other,
+    }
+}
+
+/// Record the on-disk state of a file, keeping what is still valid from
+/// its previous metadata (creation time, pin, access stats, TTL).
+pub(crate) async fn record_disk_file(
+    state: &AppState,
+    repo_id: Uuid,
+    rel_path: &str,
+    size_bytes: u64,
+    etag: String,
+    existing: Option<FileMeta>,
+) -> Result<(), AppError> {
+    let now = Utc::now();
+    let default_ttl = state
+        .repos
+        .get(&repo_id)
+        .and_then(|r| r.default_ttl_seconds);
+    let meta = match &existing {
+        Some(old) => FileMeta {
+            size_bytes,
+            etag: etag.clone(),
+            updated_at: now,
+            ..old.clone()
+        },
+        None => FileMeta {
+            repo_id,
+            path: rel_path.to_string(),
+            size_bytes,
+            etag: etag.clone(),
+            content_type: mime_guess::from_path(rel_path)
+                .first_or_octet_stream()
+                .to_string(),
+            created_at: now,
+            updated_at: now,
+            last_accessed_at: now,
+            access_count: 0,
+            expires_at: default_ttl.and_then(|s| checked_deadline(now, s)),
+            pinned: false,
+            seq: 0,
+        },
+    };
+
+    {
+        let mut wal = state.wal.write().await;
+        wal.append(&WalEntry::FileCreated {
+            repo_id,
+            path: rel_path.to_string(),
+            size_bytes,
+            etag,
+            content_type: meta.content_type.clone(),
+            created_at: meta.created_at,
+            expires_at: meta.expires_at,
+        })
+        .map_err(|e| AppError::Internal(format!("WAL write failed: {}", e)))?;
+    }
+
+    let old_size = existing.as_ref().map(|m| m.size_bytes).unwrap_or(0);
+    state
+        .files
+        .entry(repo_id)
+        .or_default()
+        .insert(rel_path.to_string(), meta);
+    if let Some(mut repo) = state.repos.get_mut(&repo_id) {
+        repo.current_size_bytes = repo.current_size_bytes.saturating_sub(old_size) + size_bytes;
+        if existing.is_none() {
+            repo.file_count += 1;
+        }
+        repo.updated_at = now;
+    }
+    let kind = if existing.is_some() {
+        ChangeKind::FileUpdated
+    } else {
+        ChangeKind::FileCreated
+    };
+    event_service::file_event(state, repo_id, kind, rel_path);
+    Ok(())
+}
+
+/// Drop metadata for a file that is no longer on disk.
+pub(crate) async fn forget_external_delete(
+    state: &AppState,
+    repo_id: Uuid,
+    rel_path: &str,
+) -> Result<(), AppError> {
+    let known = state
+        .files
+        .get(&repo_id)
+        .map(|f| f.contains_key(rel_path))
+        .unwrap_or(false);
+    if !known {
+        return Ok(());
+    }
+
+    {
+        let mut wal = state.wal.write().await;
+        wal.append(&WalEntry::FileDeleted {
+            repo_id,
+            path: rel_path.to_string(),
+        })
+        .map_err(|e| AppError::Internal(format!("WAL write failed: {}", e)))?;
+    }
+    file_service::forget_file(state, repo_id, rel_path);
+    event_service::file_event(state, repo_id, ChangeKind::FileDeleted, rel_path);
+    tracing::info!(repo_id = %repo_id, path = %rel_path, "Out-of-band delete recorded");
+    Ok(())
+}
+
+/// Forget files under directory `rel_dir` that are missing on disk.
+async fn forget_missing_under(
+    state: &AppState,
+    repo_id: Uuid,
+    rel_dir: &str,
+) -> Result<(), AppError> {
+    let prefix = format!("{}/", rel_dir);
+    let candidates: Vec<String> = state
+        .files
+        .get(&repo_id)
+        .map(|files| {
+            files
+                .iter()
+                .filter(|f| f.key().starts_with(&prefix))
+                .map(|f| f.key().clone())
+                .collect()
+        })
+        .unwrap_or_default();
+
+    for path in candidates {
+        let disk_path = file_service::resolve_file_path(state, repo_id, &path);
+        let is_file = tokio::fs::symlink_metadata(&disk_path)
+            .await
+            .map(|m| m.is_file())
+            .unwrap_or(false);
+        if !is_file {
+            forget_external_delete(state, repo_id, &path).await?;
+        }
+    }
+    Ok(())
+}
+
+/// Size and sha256 of a file, streamed.
+pub fn hash_file(path: &Path) -> std::io::Result<(u64, String)> {
+    let mut file = std::fs::File::open(path)?;
+    let mut hasher = Sha256::new();
+    let size = std::io::copy(&mut file, &mut hasher)?;
+    Ok((size, hex::encode(hasher.finalize())))
+}
+
+/// Regular files under `root`, as `/`-separated paths relative to it.
+/// Symlinks are not followed.
+pub fn walk_files(root: &Path) -> std::io::Result<Vec<String>> {
+    let mut found = Vec::new();
+    let mut stack: Vec<PathBuf> = vec![root.to_path_buf()];
+    while let Some(dir) = stack.pop() {
+        for entry in std::fs::read_dir(&dir)? {
+            let entry = entry?;
+            let file_type = entry.file_type()?;
+            let path = entry.path();
+            if file_type.is_dir() {
+                stack.push(path);
+            } else if file_type.is_file() {
+                if let Some(rel) = path.strip_prefix(root).ok().and_then(|p| p.to_str()) {
+                    found.push(rel.replace('\\', "/"));
+                }
+            }
+        }
+    }
+    found.sort();
+    Ok(found)
+}
@@ -0,0 +1,541 @@
+use crate::error::AppError;
+use crate::models::change::ChangeRecord;
+use crate::models::event::ChangeKind;
+use crate::models::repo::checked_deadline;
+use crate::models::webhook::{
+    CreateWebhookRequest, Delivery, DeliveryStatus, ListDeliveriesQuery, Webhook,
+    QUOTA_THRESHOLD_EVENT,
+};
+use crate::state::AppState;
+use chrono::{Duration, Utc};
+use dashmap::{DashMap, DashSet};
+use hmac::{Hmac, Mac};
+use serde_json::{json, Value};
+use sha2::Sha256;
+use std::path::{Path, PathBuf};
+use tokio::sync::Notify;
+use uuid::Uuid;
+
+/// Registered webhooks and their delivery queue.
+pub struct WebhookRegistry {
+    hooks: DashMap<Uuid, Webhook>,
+    deliveries: DashMap<Uuid, Delivery>,
+    /// Deliveries changed in memory since the dispatcher last wrote them
+    /// to the queue dir.
+    unsaved: DashSet<Uuid>,
+    /// Highest quota threshold each repo is currently at or above.
+    quota_levels: DashMap<Uuid, u8>,
+    client: reqwest::Client,
+    pub wake: Notify,
+}
+
+impl Default for WebhookRegistry {
+    fn default() -> Self {
+        Self {
+            hooks: DashMap::new(),
+            deliveries: DashMap::new(),
+            unsaved: DashSet::new(),
+            quota_levels: DashMap::new(),
+            // Receivers answer themselves; a redirect is not followed
+            client: reqwest::Client::builder()
+                .user_agent(concat!("linux-fs/", env!("CARGO_PKG_VERSION")))
+                .redirect(reqwest::redirect::Policy::none())
+                .build()
+                .unwrap_or_default(),
+            wake: Notify::new(),
+        }
+    }
+}
+
+fn hooks_path(state: &AppState) -> PathBuf {
+    state.config.webhooks_dir().join("hooks.json")
+}
+
+fn queue_dir(state: &AppState) -> PathBuf {
+    state.config.webhooks_dir().join("queue")
+}
+
+fn write_json<T: serde::Serialize>(path: &Path, value: &T) -> anyhow::Result<()> {
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    let tmp_path = path.with_extension("json.tmp");
+    std::fs::write(&tmp_path, serde_json::to_vec_pretty(value)?)?;
+    std::fs::rename(&tmp_path, path)?;
+    Ok(())
+}
+
+/// Load registered hooks and queued deliveries, and record each repo's
+/// current quota level so a restart does not re-announce it.
+pub fn load(state: &AppState) {
+    let registry = &state.webhooks;
+    let path = hooks_path(state);
+    if path.exists() {
+        match std::fs::read(&path)
+            .map_err(anyhow::Error::from)
+            .and_then(|data| Ok(serde_json::from_slice::<Vec<Webhook>>(&data)?))
+        {
+            Ok(hooks) => {
+                for hook in hooks {
+                    registry.hooks.insert(hook.id, hook);
+                }
+            }
+            Err(e) => tracing::error!(error = %e, "Failed to load webhooks"),
+        }
+    }
+
+    if let Ok(entries) = std::fs::read_dir(queue_dir(state)) {
+        for entry in entries.flatten() {
+            let path = entry.path();
+            if path.extension().is_none_or(|ext| ext != "json") {
+                continue;
+            }
+            match std::fs::read(&path)
+                .map_err(anyhow::Error::from)
+                .and_then(|data| Ok(serde_json::from_slice::<Delivery>(&data)?))
+            {
+                Ok(delivery) => {
+                    registry.deliveries.insert(delivery.id, delivery);
+                }
+                Err(e) => {
+                    tracing::warn!(path = %path.display(), error = %e, "Skipping unreadable webhook delivery")
+                }
+            }
+        }
+    }
+
+    let repo_ids: Vec<Uuid> = state.repos.iter().map(|r| *r.key()).collect();
+    for repo_id in repo_ids {
+        if let Some(level) = quota_level(state, repo_id) {
+            registry.quota_levels.insert(repo_id, level);
+        }
+    }
+
+    if !registry.hooks.is_empty() {
+        tracing::info!(
+            hooks = registry.hooks.len(),
+            queued = registry.deliveries.len(),
+            "Loaded webhooks"
+        );
+    }
+}
+
+fn save_hooks(state: &AppState) -> Result<(), AppError> {
+    let mut hooks: Vec<Webhook> = state
+        .webhooks
+        .hooks
+        .iter()
+        .map(|h| h.value().clone())
+        .collect();
+    hooks.sort_by_key(|h| h.created_at);
+    write_json(&hooks_path(state), &hooks)?;
+    Ok(())
+}
+
+/// Queue `delivery` to be written by the dispatcher, which keeps file
+/// I/O off the request and event paths.
+fn mark_unsaved(state: &AppState, delivery: Delivery) {
+    state.webhooks.unsaved.insert(delivery.id);
+    state.webhooks.deliveries.insert(delivery.id, delivery);
+}
+
+/// Write deliveries queued or changed since the last call to the queue
+/// dir.
+pub async fn persist(state: &AppState) {
+    let ids: Vec<Uuid> = state.webhooks.unsaved.iter().map(|id| *id).collect();
+    let batch: Vec<Delivery> = ids
+        .into_iter()
+        .filter_map(|id| {
+            state.webhooks.unsaved.remove(&id);
+            state
+                .webhooks
+                .deliveries
+                .get(&id)
+                .map(|d| d.value().clone())
+        })
+        .collect();
+    if batch.is_empty() {
+        return;
+    }
+    let dir = queue_dir(state);
+    let result = tokio::task::spawn_blocking(move || {
+        for delivery in &batch {
+            let path = dir.join(format!("{}.json", delivery.id));
+            if let Err(e) = write_json(&path, delivery) {
+                tracing::error!(delivery_id = %delivery.id, error = %e, "Failed to persist webhook delivery");
+            }
+        }
+    })
+    .await;
+    if let Err(e) = result {
+        tracing::error!(error = %e, "Webhook persist task failed");
+    }
+}
+
+fn remove_delivery(state: &AppState, id: Uuid) {
+    state.webhooks.deliveries.remove(&id);
+    state.webhooks.unsaved.remove(&id);
+    let path = queue_dir(state).join(format!("{}.json", id));
+    if let Err(e) = std::fs::remove_file(&path) {
+        if e.kind() != std::io::ErrorKind::NotFound {
+            tracing::warn!(delivery_id = %id, error = %e, "Failed to remove webhook delivery");
+        }
+    }
+}
+
+fn validate_url(url: &str) -> Result<(), AppError> {
+    let uri: http::Uri = url
+        .parse()
+        .map_err(|e| AppError::BadRequest(format!("Invalid webhook URL: {}", e)))?;
+    if !matches!(uri.scheme_str(), Some("http" | "https")) {
+        return Err(AppError::BadRequest(
+            "Webhook URLs must use http:// or https://".into(),
+        ));
+    }
+    if uri.host().is_none_or(str::is_empty) {
+        return Err(AppError::BadRequest("Webhook URL has no host".into()));
+    }
+    Ok(())
+}
+
+pub fn create(state: &AppState, req: CreateWebhookRequest) -> Result<Webhook, AppError> {
+    validate_url(&req.url)?;
+    if let Some(repo_id) = req.repo_id {
+        if !state.repos.contains_key(&repo_id) {
+            return Err(AppError::NotFound(format!(
+                "Repository {} not found",
+                repo_id
+            )));
+        }
+    }
+    let events = req.events.unwrap_or_default();
+    for event in &events {
+        let known =
+            event == QUOTA_THRESHOLD_EVENT || ChangeKind::ALL.iter().any(|k| k.as_str() == event);
+        if !known {
+            return Err(AppError::BadRequest(format!(
+                "Unknown webhook event: {}",
+                event
+            )));
+        }
+    }
+    let secret = match req.secret {
+        Some(s) if s.is_empty() => {
+            return Err(AppError::BadRequest(
+                "Webhook secret cannot be empty".into(),
+            ))
+        }
+        Some(s) => s,
+        None => format!("{}{}", Uuid::new_v4().simple(), Uuid::new_v4().simple()),
+    };
+
+    let hook = Webhook {
+        id: Uuid::new_v4(),
+        repo_id: req.repo_id,
+        url: req.url,
+        secret,
+        events,
+        created_at: Utc::now(),
+    };
+    state.webhooks.hooks.insert(hook.id, hook.clone());
+    if let Err(e) = save_hooks(state) {
+        state.webhooks.hooks.remove(&hook.id);
+        return Err(e);
+    }
+    Ok(hook)
+}
+
+pub fn list(state: &AppState, repo_id: Option<Uuid>) -> Vec<Webhook> {
+    let mut hooks: Vec<Webhook> = state
+        .webhooks
+        .hooks
+        .iter()
+        .filter(|h| repo_id.is_none_or(|id| h.repo_id == Some(id)))
+        .map(|h| h.value().clone())
+        .collect();
+    hooks.sort_by_key(|h| h.created_at);
+    hooks
+}
+
+/// Unregister a webhook and drop its queued deliveries.
+pub fn delete(state: &AppState, id: Uuid) -> Result<(), AppError> {
+    if state.webhooks.hooks.remove(&id).is_none() {
+        return Err(AppError::NotFound(format!("Webhook {} not found", id)));
+    }
+    save_hooks(state)?;
+    let queued: Vec<Uuid> = state
+        .webhooks
+        .deliveries
+        .iter()
+        .filter(|d| d.webhook_id == id)
+        .map(|d| d.id)
+        .collect();
+    for delivery_id in queued {
+        remove_delivery(state, delivery_id);
+    }
+    Ok(())
+}
+
+/// Queue deliveries for a recorded change, plus a quota event if the
+/// change pushed the repo past a threshold.
+pub fn on_change(state: &AppState, repo_id: Uuid, record: &ChangeRecord) {
+    enqueue(
+        state,
+        repo_id,
+        record.kind.as_str(),
+        serde_json::to_value(record).unwrap_or(Value::Null),
+    );
+
+    if record.kind.ends_repo() {
+        state.webhooks.quota_levels.remove(&repo_id);
+        return;
+    }
+    let level = quota_level(state, repo_id);
+    let previous = match level {
+        Some(level) => state.webhooks.quota_levels.insert(repo_id, level),
+        None => state.webhooks.quota_levels.remove(&repo_id).map(|(_, l)| l),
+    };
+    if let Some(level) = level.filter(|l| previous.is_none_or(|p| *l > p)) {
+        let (current, max) = state
+            .repos
+            .get(&repo_id)
+            .map(|r| (r.current_size_bytes, r.max_size_bytes))
+            .unwrap_or_default();
+        enqueue(
+            state,
+            repo_id,
+            QUOTA_THRESHOLD_EVENT,
+            json!({
+                "threshold_percent": level,
+                "current_size_bytes": current,
+                "max_size_bytes": max,
+            }),
+        );
+    }
+}
+
+/// The highest configured threshold (percent of quota) the repo is at.
+fn quota_level(state: &AppState, repo_id: Uuid) -> Option<u8> {
+    let (current, max) = state
+        .repos
+        .get(&repo_id)
+        .map(|r| (r.current_size_bytes, r.max_size_bytes))?;
+    if max == 0 {
+        return None;
+    }
+    state
+        .config
+        .webhook_quota_thresholds
+        .split(',')
+        .filter_map(|t| t.trim().parse::<u8>().ok())
+        .filter(|t| current as u128 * 100 >= *t as u128 * max as u128)
+        .max()
+}
+
+fn enqueue(state: &AppState, repo_id: Uuid, event: &str, data: Value) {
+    let now = Utc::now();
+    let targets: Vec<Uuid> = state
+        .webhooks
+        .hooks
+        .iter()
+        .filter(|h| h.wants(repo_id, event))
+        .map(|h| h.id)
+        .collect();
+    if targets.is_empty() {
+        return;
+    }
+
+    for webhook_id in targets {
+        let id = Uuid::new_v4();
+        let delivery = Delivery {
+            id,
+            webhook_id,
+            event: event.to_string(),
+            payload: json!({
+                "id": id,
+                "event": event,
+                "repo_id": repo_id,
+                "timestamp": now,
+                "data": data,
+            }),
+            status: DeliveryStatus::Pending,
+            attempts: 0,
+            created_at: now,
+            next_attempt_at: now,
+            last_status: None,
+            last_error: None,
+        };
+        mark_unsaved(state, delivery);
+    }
+    state.webhooks.wake.notify_one();
+}
+
+pub fn list_deliveries(state: &AppState, query: &ListDeliveriesQuery) -> Vec<Delivery> {
+    let mut deliveries: Vec<Delivery> = state
+        .webhooks
+        .deliveries
+        .iter()
+        .filter(|d| query.status.is_none_or(|s| d.status == s))
+        .filter(|d| query.webhook_id.is_none_or(|id| d.webhook_id == id))
+        .map(|d| d.value().clone())
+        .collect();
+    deliveries.sort_by_key(|d| d.created_at);
+    deliveries
+}
+
+/// Put a delivery back in the queue for an immediate attempt.
+pub fn retry(state: &AppState, id: Uuid) -> Result<Delivery, AppError> {
+    let delivery = {
+        let mut delivery = state
+            .webhooks
+            .deliveries
+            .get_mut(&id)
+            .ok_or_else(|| AppError::NotFound(format!("Delivery {} not found", id)))?;
+        delivery.status = DeliveryStatus::Pending;
+        delivery.attempts = 0;
+        delivery.next_attempt_at = Utc::now();
+        delivery.clone()
+    };
+    state.webhooks.unsaved.insert(id);
+    state.webhooks.wake.notify_one();
+    Ok(delivery)
+}
+
+/// Attempt every pending delivery that is due, and drop failed ones
+/// past their retention. Returns how many succeeded.
+pub async fn deliver_due(state: &AppState) -> usize {
+    persist(state).await;
+    let now = Utc::now();
+    drop_expired_failures(state, now);
+    let mut due: Vec<Delivery> = state
+        .webhooks
+        .deliveries
+        .iter()
+        .filter(|d| d.status == DeliveryStatus::Pending && d.next_attempt_at <= now)
+        .map(|d| d.value().clone())
+        .collect();
+    due.sort_by_key(|d| d.created_at);
+
+    let mut delivered = 0;
+    for mut delivery in due {
+        let Some(hook) = state
+            .webhooks
+            .hooks
+            .get(&delivery.webhook_id)
+            .map(|h| h.value().clone())
+        else {
+            remove_delivery(state, delivery.id);
+            continue;
+        };
+
+        let body = serde_json::to_vec(&delivery.payload).unwrap_or_default();
+        let headers = [
+            ("X-Webhook-Id", delivery.id.to_string()),
+            ("X-Webhook-Event", delivery.event.clone()),
+            (
+                "X-Webhook-Signature-256",
+                format!("sha256={}", sign(hook.secret.as_bytes(), &body)),
+            ),
+        ];
+        let timeout = std::time::Duration::from_secs(state.config.webhook_timeout_secs);
+        let result = post(&state.webhooks.client, &hook.url, &headers, body, timeout).await;
+
+        delivery.attempts += 1;
+        match result {
+            Ok(status) if (200..300).contains(&status) => {
+                tracing::debug!(delivery_id = %delivery.id, url = %hook.url, status, "Webhook delivered");
+                remove_delivery(state, delivery.id);
+                delivered += 1;
+                continue;
+            }
+            Ok(status) => {
+                delivery.last_status = Some(status);
+                delivery.last_error = Some(format!("Receiver returned {}", status));
+            }
+            Err(e) => {
+                delivery.last_status = None;
+                delivery.last_error = Some(e);
+            }
+        }
+
+        if delivery.attempts >= state.config.webhook_max_attempts {
+            delivery.status = DeliveryStatus::Failed;
+            tracing::warn!(
+                delivery_id = %delivery.id,
+                url = %hook.url,
+                attempts = delivery.attempts,
+                error = ?delivery.last_error,
+                "Webhook delivery failed permanently"
+            );
+        } else {
+            delivery.next_attempt_at = Utc::now() + backoff(state, delivery.attempts);
+        }
+        // Skip if the delivery was dropped or retried meanwhile
+        if let Some(mut current) = state.webhooks.deliveries.get_mut(&delivery.id) {
+            *current = delivery;
+            state.webhooks.unsaved.insert(current.id);
+        }
+    }
+    persist(state).await;
+    delivered
+}
+
+/// Remove failed deliveries whose last attempt was due longer than
+/// `webhook_failed_retention_secs` ago.
+fn drop_expired_failures(state: &AppState, now: chrono::DateTime<Utc>) {
+    let retention = state.config.webhook_failed_retention_secs;
+    if retention == 0 {
+        return;
+    }
+    let expired: Vec<Uuid> = state
+        .webhooks
+        .deliveries
+        .iter()
+        .filter(|d| d.status == DeliveryStatus::Failed)
+        .filter(|d| checked_deadline(d.next_attempt_at, retention).is_some_and(|t| t <= now))
+        .map(|d| d.id)
+        .collect();
+    for id in expired {
+        remove_delivery(state, id);
+    }
+}
+
+/// Exponential backoff: base * 2^(attempts - 1), capped at an hour.
+fn backoff(state: &AppState, attempts: u32) -> Duration {
+    let base = state.config.webhook_backoff_base_secs;
+    let secs = base
+        .saturating_mul(1u64 << attempts.saturating_sub(1).min(20))
+        .min(3600);
+    Duration::seconds(secs as i64)
+}
+
+/// HMAC-SHA256 of `body` keyed with `secret`, hex encoded. Receivers
+/// compare it with the `X-Webhook-Signature-256` header.
+pub fn sign(secret: &[u8], body: &[u8]) -> String {
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret).expect("HMAC takes keys of any length");
+    mac.update(body);
+    hex::encode(mac.finalize().into_bytes())
+}
+
+/// POST a JSON body and return the response status.
+async fn post(
+    client: &reqwest::Client,
+    url: &str,
+    headers: &[(&str, String)],
+    body: Vec<u8>,
+    timeout: std::time::Duration,
+) -> Result<u16, String> {
+    let mut request = client
+        .post(url)
+        .timeout(timeout)
+        .header(http::header::CONTENT_TYPE, "application/json")
+        .body(body);
+    for (name, value) in headers {
+        request = request.header(*name, value);
+    }
+    match request.send().await {
+        Ok(response) => Ok(response.status().as_u16()),
+        Err(e) if e.is_timeout() => Err(format!("Timed out after {}s", timeout.as_secs())),
+        Err(e) => Err(format!("{:#}", anyhow::Error::from(e))),
+    }
+}
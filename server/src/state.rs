@@ -1,32 +1,87 @@
 use crate::config::AppConfig;
+use crate::models::checkpoint::Checkpoint;
 use crate::models::file::FileMeta;
 use crate::models::repo::RepoMeta;
+use crate::models::scrub::ScrubProgress;
+use crate::models::sync::SyncPlan;
+use crate::models::trash::TrashEntry;
+use crate::persistence::change_log::ChangeLog;
 use crate::persistence::wal::WalWriter;
+use crate::sandbox::command_policy::CommandPolicy;
+use crate::services::event_service::EventBus;
+use crate::services::eviction_service::GdsfClock;
+use crate::services::watch_service::{Fingerprint, RepoWatcher};
+use crate::services::webhook_service::WebhookRegistry;
 use dashmap::DashMap;
-use std::sync::Arc;
-use tokio::sync::{RwLock, Semaphore};
+use std::collections::HashSet;
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::Instant;
+use tokio::sync::{Notify, RwLock, Semaphore};
 use uuid::Uuid;
 
 #[derive(Clone)]
 pub struct AppState {
     pub repos: Arc<DashMap<Uuid, RepoMeta>>,
     pub files: Arc<DashMap<Uuid, DashMap<String, FileMeta>>>,
+    /// Repos whose access stats changed since the last flush, with the
+    /// paths of files that were read.
+    pub access_dirty: Arc<DashMap<Uuid, HashSet<String>>>,
+    /// Soft-deleted files and repos awaiting restore or purge.
+    pub trash: Arc<DashMap<Uuid, TrashEntry>>,
+    /// Saved file metadata of repos, to diff against.
+    pub checkpoints: Arc<DashMap<Uuid, Checkpoint>>,
+    /// Sync plans waiting to be committed; kept in memory only.
+    pub sync_plans: Arc<DashMap<Uuid, SyncPlan>>,
+    /// GDSF inflation clocks of repos using that policy; kept in memory only.
+    pub gdsf_clocks: Arc<DashMap<Uuid, GdsfClock>>,
     pub wal: Arc<RwLock<WalWriter>>,
+    /// Set at boot when `fs_watch_enabled`; absent in tests by default.
+    pub watcher: Arc<OnceLock<RepoWatcher>>,
+    /// Files the API just wrote, so the watcher skips its own events.
+    pub api_writes: Arc<DashMap<(Uuid, String), (Fingerprint, Instant)>>,
+    /// Durable per-repo change logs behind `/changes`.
+    pub changes: Arc<Mutex<ChangeLog>>,
+    /// Change events for feed subscribers.
+    pub events: Arc<EventBus>,
+    /// Integrity scrubber progress and findings.
+    pub scrub: Arc<RwLock<ScrubProgress>>,
+    /// Wakes the scrubber when a pass is requested early.
+    pub scrub_wake: Arc<Notify>,
+    /// Registered webhooks and their delivery queue.
+    pub webhooks: Arc<WebhookRegistry>,
     pub config: Arc<AppConfig>,
     pub command_semaphore: Arc<Semaphore>,
+    /// Argument rules for `exec`, loaded once at startup.
+    pub command_policy: Arc<CommandPolicy>,
     pub start_time: chrono::DateTime<chrono::Utc>,
 }
 
 impl AppState {
-    pub fn new(config: AppConfig, wal: WalWriter) -> Self {
+    /// Fails when the command policy file cannot be loaded.
+    pub fn new(config: AppConfig, wal: WalWriter) -> anyhow::Result<Self> {
         let max_concurrent = config.max_concurrent_commands;
-        Self {
+        let event_buffer_size = config.event_buffer_size;
+        let command_policy = CommandPolicy::load(&config)?;
+        Ok(Self {
             repos: Arc::new(DashMap::new()),
             files: Arc::new(DashMap::new()),
+            access_dirty: Arc::new(DashMap::new()),
+            trash: Arc::new(DashMap::new()),
+            checkpoints: Arc::new(DashMap::new()),
+            sync_plans: Arc::new(DashMap::new()),
+            gdsf_clocks: Arc::new(DashMap::new()),
             wal: Arc::new(RwLock::new(wal)),
+            watcher: Arc::new(OnceLock::new()),
+            api_writes: Arc::new(DashMap::new()),
+            changes: Arc::new(Mutex::new(ChangeLog::new(&config.change_log_dir()))),
+            events: Arc::new(EventBus::new(event_buffer_size)),
+            scrub: Arc::new(RwLock::new(ScrubProgress::default())),
+            scrub_wake: Arc::new(Notify::new()),
+            webhooks: Arc::new(WebhookRegistry::default()),
             config: Arc::new(config),
             command_semaphore: Arc::new(Semaphore::new(max_concurrent)),
+            command_policy: Arc::new(command_policy),
             start_time: chrono::Utc::now(),
-        }
+        })
     }
 }
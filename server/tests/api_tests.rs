@@ -5,7 +5,8 @@ use http::Request;
 use http::StatusCode;
 use http_body_util::BodyExt;
 use linux_fs::config::AppConfig;
-use linux_fs::persistence::wal::WalWriter;
+use linux_fs::models::trash::TrashedItem;
+use linux_fs::persistence::wal::{WalEntry, WalWriter};
 use linux_fs::routes::build_router;
 use linux_fs::state::AppState;
 use serde_json::{json, Value};
@@ -23,12 +24,48 @@ fn test_config(data_dir: &str) -> AppConfig {
         max_upload_size: 104_857_600,
         snapshot_interval_secs: 3600,
         ttl_sweep_interval_secs: 3600,
+        access_flush_interval_secs: 3600,
+        repo_expiry_grace_secs: 0,
         command_timeout_secs: 30,
         command_max_output_bytes: 10_485_760,
         cache_max_bytes: 268_435_456,
+        global_eviction_order: "idle".to_string(),
+        global_eviction_tag: "eviction-priority".to_string(),
         max_concurrent_commands: 10,
         log_level: "error".to_string(),
         cors_allowed_origins: "*".to_string(),
+        trash_retention_secs: 86_400,
+        trash_skip_causes: "eviction".to_string(),
+        fs_watch_enabled: false,
+        fs_watch_debounce_ms: 50,
+        reconcile_mode: "fast".to_string(),
+        scrub_interval_secs: 0,
+        scrub_rate_bytes_per_sec: 0,
+        scrub_quarantine: false,
+        event_buffer_size: 64,
+        change_log_retention_secs: 604_800,
+        change_log_max_entries: 100_000,
+        webhook_max_attempts: 8,
+        webhook_backoff_base_secs: 5,
+        webhook_timeout_secs: 5,
+        webhook_failed_retention_secs: 604_800,
+        webhook_quota_thresholds: "80,90,100".into(),
+        sandbox_namespaces: false,
+        sandbox_landlock: false,
+        sandbox_seccomp: false,
+        sandbox_cgroups: false,
+        cgroup_root: "/sys/fs/cgroup".into(),
+        exec_memory_max_bytes: 536_870_912,
+        exec_cpu_max_cores: 1.0,
+        exec_pids_max: 64,
+        exec_rlimit_cpu_secs: 60,
+        exec_rlimit_as_bytes: 4_294_967_296,
+        exec_rlimit_fsize_bytes: 1_073_741_824,
+        exec_rlimit_nofile: 1024,
+        exec_rlimit_nproc: 0,
+        command_policy_file: String::new(),
+        exec_allow_mutating: false,
+        fork_fallback: "copy".to_string(),
     }
 }
 
@@ -42,7 +79,7 @@ fn setup() -> (AppState, tempfile::TempDir) {
     std::fs::create_dir_all(config.wal_dir()).unwrap();
 
     let wal = WalWriter::open(&config.wal_dir()).unwrap();
-    let state = AppState::new(config, wal);
+    let state = AppState::new(config, wal).unwrap();
     (state, tmp)
 }
 
@@ -62,6 +99,15 @@ async fn body_to_json(body: Body) -> Value {
     serde_json::from_slice(&bytes).unwrap()
 }
 
+// Helper: send a request with the API key and return the status and JSON body
+async fn send_json(state: &AppState, mut req: Request<Body>) -> (StatusCode, Value) {
+    let (key, val) = auth_header();
+    req.headers_mut().insert(key, val);
+    let resp = build_router(state.clone()).oneshot(req).await.unwrap();
+    let status = resp.status();
+    (status, body_to_json(resp.into_body()).await)
+}
+
 // Helper: create a repo and return its UUID
 async fn create_test_repo(state: &AppState, name: &str) -> uuid::Uuid {
     let app = build_router(state.clone());
@@ -302,6 +348,511 @@ async fn test_delete_repo_then_get_returns_404() {
     assert_eq!(resp.status(), StatusCode::NOT_FOUND);
 }
 
+async fn fork_test_repo(state: &AppState, repo_id: uuid::Uuid, body: Value) -> Value {
+    let app = build_router(state.clone());
+    let (key, val) = auth_header();
+    let req = Request::builder()
+        .method("POST")
+        .uri(format!("/api/v1/repos/{}/fork", repo_id))
+        .header(key, val)
+        .header(header::CONTENT_TYPE, "application/json")
+        .body(Body::from(serde_json::to_string(&body).unwrap()))
+        .unwrap();
+    let resp = app.oneshot(req).await.unwrap();
+    assert_eq!(resp.status(), StatusCode::CREATED);
+    body_to_json(resp.into_body()).await
+}
+
+async fn download_test_file(state: &AppState, repo_id: uuid::Uuid, path: &str) -> Bytes {
+    let app = build_router(state.clone());
+    let (key, val) = auth_header();
+    let req = Request::builder()
+        .uri(format!("/api/v1/repos/{}/files/{}", repo_id, path))
+        .header(key, val)
+        .body(Body::empty())
+        .unwrap();
+    let resp = app.oneshot(req).await.unwrap();
+    assert_eq!(resp.status(), StatusCode::OK);
+    body_to_bytes(resp.into_body()).await
+}
+
+#[tokio::test]
+async fn test_fork_repo_copies_files_and_records_lineage() {
+    let (state, _tmp) = setup();
+    let repo_id = create_test_repo(&state, "fork-source").await;
+    upload_test_file(&state, repo_id, "dir/a.txt", b"alpha").await;
+
+    let body = fork_test_repo(&state, repo_id, json!({"tags": {"env": "ci"}})).await;
+    assert_eq!(body["data"]["name"], "fork-source");
+    assert_eq!(body["data"]["forked_from"], repo_id.to_string());
+    assert_eq!(body["data"]["file_count"], 1);
+    assert_eq!(body["data"]["current_size_bytes"], 5);
+    assert_eq!(body["data"]["tags"]["env"], "ci");
+    let fork_id = uuid::Uuid::parse_str(body["data"]["id"].as_str().unwrap()).unwrap();
+
+    // The fork and its files are one WAL entry
+    let entries = WalWriter::read_entries(&state.config.wal_dir()).unwrap();
+    let forks: Vec<&WalEntry> = entries
+        .iter()
+        .filter(|e| match e {
+            WalEntry::RepoForkedWithFiles { repo, .. } => repo.id == fork_id,
+            WalEntry::FileCreated { repo_id, .. } => *repo_id == fork_id,
+            _ => false,
+        })
+        .collect();
+    assert!(matches!(
+        forks[..],
+        [WalEntry::RepoForkedWithFiles { repo, files }]
+            if files.len() == 1 && repo.current_size_bytes == 5 && repo.tags["env"] == "ci"
+    ));
+
+    upload_test_file(&state, fork_id, "dir/a.txt", b"changed").await;
+    let forked = download_test_file(&state, fork_id, "dir/a.txt").await;
+    assert_eq!(&forked[..], b"changed");
+    let original = download_test_file(&state, repo_id, "dir/a.txt").await;
+    assert_eq!(&original[..], b"alpha");
+}
+
+#[tokio::test]
+async fn test_fork_repo_hardlink_fallback_breaks_on_write() {
+    let tmp = tempfile::tempdir().unwrap();
+    let mut config = test_config(tmp.path().to_str().unwrap());
+    config.fork_fallback = "hardlink".to_string();
+    config.exec_allow_mutating = true;
+    let wal = WalWriter::open(&config.wal_dir()).unwrap();
+    let state = AppState::new(config, wal).unwrap();
+
+    let repo_id = create_test_repo(&state, "fork-hardlink").await;
+    upload_test_file(&state, repo_id, "shared.txt", b"original").await;
+    upload_test_file(&state, repo_id, "list.txt", b"b\na\n").await;
+
+    let body = fork_test_repo(&state, repo_id, json!({"name": "child"})).await;
+    assert_eq!(body["data"]["name"], "child");
+    let fork_id = uuid::Uuid::parse_str(body["data"]["id"].as_str().unwrap()).unwrap();
+
+    upload_test_file(&state, repo_id, "shared.txt", b"rewritten").await;
+    let forked = download_test_file(&state, fork_id, "shared.txt").await;
+    assert_eq!(&forked[..], b"original");
+
+    // sort -o truncates and rewrites its output in place
+    let (status, _) =
+        exec_test_command(&state, fork_id, "sort", &["-o", "list.txt", "list.txt"]).await;
+    assert_eq!(status, StatusCode::OK);
+    let source = tmp.path().join(format!("repos/{}/files/list.txt", repo_id));
+    assert_eq!(std::fs::read(source).unwrap(), b"b\na\n");
+    let forked = tmp.path().join(format!("repos/{}/files/list.txt", fork_id));
+    assert_eq!(std::fs::read(forked).unwrap(), b"a\nb\n");
+}
+
+#[tokio::test]
+async fn test_fork_repo_fails_whole_when_a_file_cannot_be_cloned() {
+    let (state, _tmp) = setup();
+    let repo_id = create_test_repo(&state, "fork-broken").await;
+    upload_test_file(&state, repo_id, "a.txt", b"alpha").await;
+    upload_test_file(&state, repo_id, "b.txt", b"beta").await;
+    std::fs::remove_file(state.config.repos_dir().join(format!("{}/files/b.txt", repo_id))).unwrap();
+
+    let app = build_router(state.clone());
+    let (key, val) = auth_header();
+    let req = Request::builder()
+        .method("POST")
+        .uri(format!("/api/v1/repos/{}/fork", repo_id))
+        .header(key, val)
+        .header(header::CONTENT_TYPE, "application/json")
+        .body(Body::from("{}"))
+        .unwrap();
+    let resp = app.oneshot(req).await.unwrap();
+    assert_eq!(resp.status(), StatusCode::INTERNAL_SERVER_ERROR);
+    assert_eq!(state.repos.len(), 1);
+    let dirs = std::fs::read_dir(state.config.repos_dir()).unwrap().count();
+    assert_eq!(dirs, 1);
+}
+
+#[test]
+fn test_version_1_snapshot_is_migrated_and_unknown_versions_refused() {
+    use linux_fs::models::snapshot::v1;
+    use linux_fs::persistence::snapshot::load_snapshot;
+
+    let tmp = tempfile::tempdir().unwrap();
+    let path = tmp.path().join("snapshot.bin");
+    let now = chrono::Utc::now();
+    let repo_id = uuid::Uuid::new_v4();
+    let old = v1::MetadataSnapshot {
+        version: v1::VERSION,
+        timestamp: now,
+        repos: [(
+            repo_id,
+            v1::RepoMeta {
+                id: repo_id,
+                name: "old".into(),
+                max_size_bytes: 100,
+                current_size_bytes: 5,
+                file_count: 1,
+                created_at: now,
+                updated_at: now,
+                last_accessed_at: now,
+                default_ttl_seconds: Some(60),
+                tags: Default::default(),
+            },
+        )]
+        .into(),
+        files: [(
+            repo_id,
+            [(
+                "a.txt".to_string(),
+                v1::FileMeta {
+                    repo_id,
+                    path: "a.txt".into(),
+                    size_bytes: 5,
+                    etag: "e".into(),
+                    content_type: "text/plain".into(),
+                    created_at: now,
+                    updated_at: now,
+                    last_accessed_at: now,
+                    access_count: 3,
+                    expires_at: None,
+                },
+            )]
+            .into(),
+        )]
+        .into(),
+    };
+    std::fs::write(&path, bincode::serialize(&old).unwrap()).unwrap();
+
+    let snapshot = load_snapshot(&path).unwrap().unwrap();
+    let repo = &snapshot.repos[&repo_id];
+    assert_eq!(repo.name, "old");
+    assert_eq!(repo.default_ttl_seconds, Some(60));
+    assert!(repo.forked_from.is_none());
+    assert_eq!(snapshot.files[&repo_id]["a.txt"].access_count, 3);
+    assert!(snapshot.trash.is_empty());
+    assert!(snapshot.checkpoints.is_empty());
+
+    std::fs::write(&path, bincode::serialize(&(7u32, now)).unwrap()).unwrap();
+    let err = load_snapshot(&path).unwrap_err();
+    assert!(err.to_string().contains("snapshot version 7"));
+}
+
+#[tokio::test]
+async fn test_fork_repo_over_size_limit_returns_413() {
+    let (state, _tmp) = setup();
+    let repo_id = create_test_repo(&state, "fork-quota").await;
+    upload_test_file(&state, repo_id, "big.txt", b"0123456789").await;
+
+    let app = build_router(state);
+    let (key, val) = auth_header();
+    let req = Request::builder()
+        .method("POST")
+        .uri(format!("/api/v1/repos/{}/fork", repo_id))
+        .header(key, val)
+        .header(header::CONTENT_TYPE, "application/json")
+        .body(Body::from(r#"{"max_size_bytes":4}"#))
+        .unwrap();
+    let resp = app.oneshot(req).await.unwrap();
+    assert_eq!(resp.status(), StatusCode::PAYLOAD_TOO_LARGE);
+}
+
+#[tokio::test]
+async fn test_diff_against_parent_reports_changes() {
+    let (state, _tmp) = setup();
+    let repo_id = create_test_repo(&state, "diff-source").await;
+    upload_test_file(&state, repo_id, "keep.txt", b"same").await;
+    upload_test_file(&state, repo_id, "edit.txt", b"one\ntwo\n").await;
+    upload_test_file(&state, repo_id, "gone.txt", b"bye").await;
+    upload_test_file(&state, repo_id, "old-name.txt", b"moved content").await;
+
+    let body = fork_test_repo(&state, repo_id, json!({})).await;
+    let fork_id = uuid::Uuid::parse_str(body["data"]["id"].as_str().unwrap()).unwrap();
+
+    upload_test_file(&state, fork_id, "edit.txt", b"one\nthree\n").await;
+    upload_test_file(&state, fork_id, "new.txt", b"fresh").await;
+    let app = build_router(state.clone());
+    let (key, val) = auth_header();
+    let req = Request::builder()
+        .method("DELETE")
+        .uri(format!("/api/v1/repos/{}/files/gone.txt", fork_id))
+        .header(key, val)
+        .body(Body::empty())
+        .unwrap();
+    app.oneshot(req).await.unwrap();
+    let app = build_router(state.clone());
+    let (key, val) = auth_header();
+    let req = Request::builder()
+        .method("POST")
+        .uri(format!("/api/v1/repos/{}/files-move", fork_id))
+        .header(key, val)
+        .header(header::CONTENT_TYPE, "application/json")
+        .body(Body::from(
+            r#"{"source":"old-name.txt","destination":"new-name.txt"}"#,
+        ))
+        .unwrap();
+    app.oneshot(req).await.unwrap();
+
+    let app = build_router(state);
+    let (key, val) = auth_header();
+    let req = Request::builder()
+        .uri(format!(
+            "/api/v1/repos/{}/diff?against=parent&include_text=true",
+            fork_id
+        ))
+        .header(key, val)
+        .body(Body::empty())
+        .unwrap();
+    let resp = app.oneshot(req).await.unwrap();
+    assert_eq!(resp.status(), StatusCode::OK);
+
+    let body: Value = body_to_json(resp.into_body()).await;
+    let data = &body["data"];
+    assert_eq!(data["base"]["repo_id"], repo_id.to_string());
+    assert_eq!(data["added"][0]["path"], "new.txt");
+    assert_eq!(data["removed"][0]["path"], "gone.txt");
+    assert_eq!(data["renamed"][0]["from"], "old-name.txt");
+    assert_eq!(data["renamed"][0]["to"], "new-name.txt");
+    assert_eq!(data["modified"][0]["path"], "edit.txt");
+    let patch = data["modified"][0]["unified_diff"].as_str().unwrap();
+    assert!(patch.contains("-two"));
+    assert!(patch.contains("+three"));
+}
+
+#[tokio::test]
+async fn test_diff_caps_total_text() {
+    let (state, _tmp) = setup();
+    let repo_id = create_test_repo(&state, "diff-cap").await;
+    upload_test_file(&state, repo_id, "a.txt", b"old a\n").await;
+    upload_test_file(&state, repo_id, "b.txt", b"old b\n").await;
+    let body = fork_test_repo(&state, repo_id, json!({})).await;
+    let fork_id = uuid::Uuid::parse_str(body["data"]["id"].as_str().unwrap()).unwrap();
+    upload_test_file(&state, fork_id, "a.txt", b"new a\n").await;
+    upload_test_file(&state, fork_id, "b.txt", b"new b\n").await;
+
+    let req = Request::builder()
+        .uri(format!("/api/v1/repos/{}/diff?against=parent&include_text=true", fork_id))
+        .body(Body::empty())
+        .unwrap();
+    let (status, body) = send_json(&state, req).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["data"]["truncated"], false);
+    let first_len = body["data"]["modified"][0]["unified_diff"]
+        .as_str()
+        .unwrap()
+        .len();
+
+    // Room for the first diff only
+    let req = Request::builder()
+        .uri(format!(
+            "/api/v1/repos/{}/diff?against=parent&include_text=true&max_total_text_bytes={}",
+            fork_id,
+            first_len + 1
+        ))
+        .body(Body::empty())
+        .unwrap();
+    let (status, body) = send_json(&state, req).await;
+    assert_eq!(status, StatusCode::OK);
+    let data = &body["data"];
+    assert_eq!(data["truncated"], true);
+    assert_eq!(data["modified"][0]["path"], "a.txt");
+    assert!(data["modified"][0]["unified_diff"].is_string());
+    assert_eq!(data["modified"][1]["path"], "b.txt");
+    assert!(data["modified"][1]["unified_diff"].is_null());
+    assert!(data["modified"][1]["diff_skipped"]
+        .as_str()
+        .unwrap()
+        .contains("total diff limit"));
+}
+
+#[tokio::test]
+async fn test_diff_against_checkpoint() {
+    let (state, _tmp) = setup();
+    let repo_id = create_test_repo(&state, "checkpointed").await;
+    upload_test_file(&state, repo_id, "edit.txt", b"one\n").await;
+    upload_test_file(&state, repo_id, "gone.txt", b"bye").await;
+    upload_test_file(&state, repo_id, "old-name.txt", b"moved content").await;
+
+    let app = build_router(state.clone());
+    let (key, val) = auth_header();
+    let req = Request::builder()
+        .method("POST")
+        .uri(format!("/api/v1/repos/{}/checkpoints", repo_id))
+        .header(key, val)
+        .header(header::CONTENT_TYPE, "application/json")
+        .body(Body::from(r#"{"name":"before"}"#))
+        .unwrap();
+    let resp = app.oneshot(req).await.unwrap();
+    assert_eq!(resp.status(), StatusCode::CREATED);
+    let body = body_to_json(resp.into_body()).await;
+    assert_eq!(body["data"]["file_count"], 3);
+    let checkpoint_id = body["data"]["id"].as_str().unwrap().to_string();
+    let entries = WalWriter::read_entries(&state.config.wal_dir()).unwrap();
+    assert!(entries
+        .iter()
+        .any(|e| matches!(e, WalEntry::CheckpointCreated { checkpoint } if checkpoint.files.len() == 3)));
+
+    upload_test_file(&state, repo_id, "edit.txt", b"two\n").await;
+    upload_test_file(&state, repo_id, "new.txt", b"fresh").await;
+    delete_test_path(&state, format!("/api/v1/repos/{}/files/gone.txt", repo_id)).await;
+    let app = build_router(state.clone());
+    let (key, val) = auth_header();
+    let req = Request::builder()
+        .method("POST")
+        .uri(format!("/api/v1/repos/{}/files-move", repo_id))
+        .header(key, val)
+        .header(header::CONTENT_TYPE, "application/json")
+        .body(Body::from(
+            r#"{"source":"old-name.txt","destination":"new-name.txt"}"#,
+        ))
+        .unwrap();
+    app.oneshot(req).await.unwrap();
+
+    let req = Request::builder()
+        .uri(format!("/api/v1/repos/{}/diff?against={}&include_text=true", repo_id, checkpoint_id))
+        .body(Body::empty())
+        .unwrap();
+    let (status, body) = send_json(&state, req).await;
+    assert_eq!(status, StatusCode::OK);
+    let data = &body["data"];
+    assert_eq!(data["base"]["repo_id"], repo_id.to_string());
+    assert_eq!(data["base"]["checkpoint_id"], checkpoint_id);
+    assert_eq!(data["added"][0]["path"], "new.txt");
+    assert_eq!(data["removed"][0]["path"], "gone.txt");
+    assert_eq!(data["renamed"][0]["from"], "old-name.txt");
+    assert_eq!(data["renamed"][0]["to"], "new-name.txt");
+    assert_eq!(data["modified"][0]["path"], "edit.txt");
+    assert!(data["modified"][0]["unified_diff"].is_null());
+    assert!(data["modified"][0]["diff_skipped"].is_string());
+
+    let app = build_router(state.clone());
+    let (key, val) = auth_header();
+    let req = Request::builder()
+        .uri(format!("/api/v1/repos/{}/checkpoints", repo_id))
+        .header(key, val)
+        .body(Body::empty())
+        .unwrap();
+    let body = body_to_json(app.oneshot(req).await.unwrap().into_body()).await;
+    assert_eq!(body["data"][0]["name"], "before");
+
+    delete_test_path(
+        &state,
+        format!("/api/v1/repos/{}/checkpoints/{}", repo_id, checkpoint_id),
+    )
+    .await;
+    let req = Request::builder()
+        .uri(format!("/api/v1/repos/{}/diff?against={}", repo_id, checkpoint_id))
+        .body(Body::empty())
+        .unwrap();
+    let (status, _) = send_json(&state, req).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    let req = Request::builder()
+        .uri(format!("/api/v1/repos/{}/diff?against=yesterday", repo_id))
+        .body(Body::empty())
+        .unwrap();
+    let (status, _) = send_json(&state, req).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+}
+
+async fn create_test_repo_with(state: &AppState, body: Value) -> Value {
+    let app = build_router(state.clone());
+    let (key, val) = auth_header();
+    let req = Request::builder()
+        .method("POST")
+        .uri("/api/v1/repos")
+        .header(key, val)
+        .header(header::CONTENT_TYPE, "application/json")
+        .body(Body::from(serde_json::to_string(&body).unwrap()))
+        .unwrap();
+    let resp = app.oneshot(req).await.unwrap();
+    assert_eq!(resp.status(), StatusCode::CREATED);
+    body_to_json(resp.into_body()).await
+}
+
+#[tokio::test]
+async fn test_repo_reaper_deletes_expired_repos() {
+    let (state, _tmp) = setup();
+    let expired = create_test_repo_with(&state, json!({"name": "short", "ttl_seconds": 0})).await;
+    let expired_id = uuid::Uuid::parse_str(expired["data"]["id"].as_str().unwrap()).unwrap();
+    let idle = create_test_repo_with(&state, json!({"name": "idle", "idle_ttl_seconds": 0})).await;
+    let idle_id = uuid::Uuid::parse_str(idle["data"]["id"].as_str().unwrap()).unwrap();
+    let kept = create_test_repo(&state, "forever").await;
+
+    let deleted = linux_fs::background::repo_reaper::sweep(&state).await;
+    assert_eq!(deleted, 2);
+    assert!(!state.repos.contains_key(&expired_id));
+    assert!(!state.repos.contains_key(&idle_id));
+    assert!(state.repos.contains_key(&kept));
+    assert!(!state.config.repos_dir().join(expired_id.to_string()).exists());
+}
+
+#[tokio::test]
+async fn test_out_of_range_ttls_are_rejected() {
+    let (state, _tmp) = setup();
+    for field in ["ttl_seconds", "idle_ttl_seconds"] {
+        let app = build_router(state.clone());
+        let (key, val) = auth_header();
+        let req = Request::builder()
+            .method("POST")
+            .uri("/api/v1/repos")
+            .header(key, val)
+            .header(header::CONTENT_TYPE, "application/json")
+            .body(Body::from(
+                json!({"name": "huge", field: 10_000_000_000_000_000u64}).to_string(),
+            ))
+            .unwrap();
+        let resp = app.oneshot(req).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+        let body = body_to_json(resp.into_body()).await;
+        assert!(body["error"]["message"]
+            .as_str()
+            .unwrap()
+            .starts_with(&format!("{} must be at most", field)));
+    }
+
+    // An idle TTL recovered from older metadata cannot stop the reaper
+    let kept = create_test_repo(&state, "kept").await;
+    state.repos.get_mut(&kept).unwrap().idle_ttl_seconds = Some(u64::MAX);
+    let expired = create_test_repo_with(&state, json!({"name": "short", "ttl_seconds": 0})).await;
+    let expired_id = uuid::Uuid::parse_str(expired["data"]["id"].as_str().unwrap()).unwrap();
+    assert_eq!(linux_fs::background::repo_reaper::sweep(&state).await, 1);
+    assert!(state.repos.contains_key(&kept));
+    assert!(!state.repos.contains_key(&expired_id));
+}
+
+#[tokio::test]
+async fn test_renew_repo_extends_expiry() {
+    let (state, _tmp) = setup();
+    let body = create_test_repo_with(&state, json!({"name": "renew", "ttl_seconds": 60})).await;
+    let repo_id = uuid::Uuid::parse_str(body["data"]["id"].as_str().unwrap()).unwrap();
+    let first_expiry = state.repos.get(&repo_id).unwrap().expires_at.unwrap();
+
+    let app = build_router(state.clone());
+    let (key, val) = auth_header();
+    let req = Request::builder()
+        .method("POST")
+        .uri(format!("/api/v1/repos/{}/renew", repo_id))
+        .header(key, val)
+        .header(header::CONTENT_TYPE, "application/json")
+        .body(Body::from(r#"{"ttl_seconds":86400}"#))
+        .unwrap();
+    let resp = app.oneshot(req).await.unwrap();
+    assert_eq!(resp.status(), StatusCode::OK);
+    let body: Value = body_to_json(resp.into_body()).await;
+    assert_eq!(body["data"]["ttl_seconds"], 86400);
+    assert!(state.repos.get(&repo_id).unwrap().expires_at.unwrap() > first_expiry);
+
+    // An explicit null clears the TTL
+    let app = build_router(state.clone());
+    let (key, val) = auth_header();
+    let req = Request::builder()
+        .method("PATCH")
+        .uri(format!("/api/v1/repos/{}", repo_id))
+        .header(key, val)
+        .header(header::CONTENT_TYPE, "application/json")
+        .body(Body::from(r#"{"ttl_seconds":null}"#))
+        .unwrap();
+    let resp = app.oneshot(req).await.unwrap();
+    assert_eq!(resp.status(), StatusCode::OK);
+    let body: Value = body_to_json(resp.into_body()).await;
+    assert!(body["data"]["expires_at"].is_null());
+}
+
 // ==================== File Tests ====================
 
 #[tokio::test]
@@ -535,62 +1086,2501 @@ async fn test_copy_file() {
     assert_eq!(resp.status(), StatusCode::OK);
 }
 
-// ==================== Shell Tests ====================
+async fn upload_test_file_response(
+    state: &AppState,
+    repo_id: uuid::Uuid,
+    path: &str,
+    content: &[u8],
+) -> (StatusCode, Value) {
+    let req = Request::builder()
+        .method("POST")
+        .uri(format!("/api/v1/repos/{}/files/{}", repo_id, path))
+        .body(Body::from(Bytes::from(content.to_vec())))
+        .unwrap();
+    send_json(state, req).await
+}
 
 #[tokio::test]
-async fn test_exec_allowed_command() {
+async fn test_upload_rejects_file_ttl_out_of_range() {
     let (state, _tmp) = setup();
-    let repo_id = create_test_repo(&state, "exec-test").await;
+    let repo_id = create_test_repo(&state, "file-ttl").await;
 
-    let app = build_router(state);
+    let app = build_router(state.clone());
     let (key, val) = auth_header();
     let req = Request::builder()
         .method("POST")
-        .uri(format!("/api/v1/repos/{}/exec", repo_id))
+        .uri(format!("/api/v1/repos/{}/files/a.txt", repo_id))
         .header(key, val)
-        .header(header::CONTENT_TYPE, "application/json")
-        .body(Body::from(
-            serde_json::to_string(&json!({
-                "command": "ls",
-                "args": []
-            }))
-            .unwrap(),
-        ))
+        .header("X-File-TTL", u64::MAX.to_string())
+        .body(Body::from("alpha"))
         .unwrap();
-
     let resp = app.oneshot(req).await.unwrap();
-    assert_eq!(resp.status(), StatusCode::OK);
+    assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+    assert!(state.files.get(&repo_id).unwrap().is_empty());
+}
 
-    let body: Value = body_to_json(resp.into_body()).await;
-    assert!(body["data"]["exit_code"].is_number());
-    assert!(body["data"]["stdout"].is_string());
-    assert!(body["data"]["stderr"].is_string());
-    assert!(body["data"]["duration_ms"].is_number());
+#[tokio::test]
+async fn test_lru_eviction_reports_evicted_files() {
+    let (state, _tmp) = setup();
+    let body = create_test_repo_with(
+        &state,
+        json!({"name": "lru", "max_size_bytes": 10, "eviction_policy": "lru"}),
+    )
+    .await;
+    let repo_id = uuid::Uuid::parse_str(body["data"]["id"].as_str().unwrap()).unwrap();
+    assert_eq!(body["data"]["eviction_policy"], "lru");
+
+    upload_test_file(&state, repo_id, "a.txt", b"aaaa").await;
+    upload_test_file(&state, repo_id, "b.txt", b"bbbb").await;
+    download_test_file(&state, repo_id, "a.txt").await;
+
+    let (status, body) = upload_test_file_response(&state, repo_id, "c.txt", b"cccc").await;
+    assert_eq!(status, StatusCode::CREATED);
+    assert_eq!(body["data"]["path"], "c.txt");
+    assert_eq!(body["data"]["evicted"], json!(["b.txt"]));
+
+    let files = state.files.get(&repo_id).unwrap();
+    assert!(files.contains_key("a.txt"));
+    assert!(!files.contains_key("b.txt"));
+    assert_eq!(state.repos.get(&repo_id).unwrap().current_size_bytes, 8);
 }
 
 #[tokio::test]
-async fn test_exec_disallowed_command_returns_403() {
+async fn test_gdsf_eviction_ages_out_idle_hot_files() {
     let (state, _tmp) = setup();
-    let repo_id = create_test_repo(&state, "exec-forbidden").await;
+    let body = create_test_repo_with(
+        &state,
+        json!({"name": "gdsf", "max_size_bytes": 30, "eviction_policy": "gdsf"}),
+    )
+    .await;
+    let repo_id = uuid::Uuid::parse_str(body["data"]["id"].as_str().unwrap()).unwrap();
 
-    let app = build_router(state);
+    upload_test_file(&state, repo_id, "hot.txt", b"hhhhhhhhhh").await;
+    for _ in 0..4 {
+        download_test_file(&state, repo_id, "hot.txt").await;
+    }
+    upload_test_file(&state, repo_id, "f0.txt", b"0000000000").await;
+    upload_test_file(&state, repo_id, "f1.txt", b"1111111111").await;
+
+    // Each eviction raises the clock, so fresh files soon outrank the
+    // hot file that has not been read since
+    let mut hot_evicted_at = None;
+    for i in 2..20 {
+        let path = format!("f{}.txt", i);
+        let (status, body) = upload_test_file_response(&state, repo_id, &path, b"xxxxxxxxxx").await;
+        assert_eq!(status, StatusCode::CREATED);
+        if body["data"]["evicted"] == json!(["hot.txt"]) {
+            hot_evicted_at = Some(i);
+            break;
+        }
+    }
+    let hot_evicted_at = hot_evicted_at.expect("idle hot file was never evicted");
+    assert!(hot_evicted_at > 2, "hot file evicted before any aging");
+    assert!(!state.files.get(&repo_id).unwrap().contains_key("hot.txt"));
+}
+
+#[tokio::test]
+async fn test_never_eviction_policy_rejects_over_quota_upload() {
+    let (state, _tmp) = setup();
+    let repo_id = create_test_repo(&state, "never").await;
+
+    let app = build_router(state.clone());
     let (key, val) = auth_header();
     let req = Request::builder()
-        .method("POST")
-        .uri(format!("/api/v1/repos/{}/exec", repo_id))
+        .method("PATCH")
+        .uri(format!("/api/v1/repos/{}", repo_id))
         .header(key, val)
         .header(header::CONTENT_TYPE, "application/json")
         .body(Body::from(
-            serde_json::to_string(&json!({
-                "command": "rm",
-                "args": ["-rf", "/"]
-            }))
-            .unwrap(),
+            r#"{"max_size_bytes":6,"eviction_policy":"never"}"#,
         ))
         .unwrap();
-
     let resp = app.oneshot(req).await.unwrap();
-    assert_eq!(resp.status(), StatusCode::FORBIDDEN);
+    assert_eq!(resp.status(), StatusCode::OK);
+
+    upload_test_file(&state, repo_id, "a.txt", b"aaaa").await;
+    let (status, _) = upload_test_file_response(&state, repo_id, "b.txt", b"bbbb").await;
+    assert_eq!(status, StatusCode::PAYLOAD_TOO_LARGE);
+    assert!(state.files.get(&repo_id).unwrap().contains_key("a.txt"));
+}
+
+async fn pin_test_file(state: &AppState, repo_id: uuid::Uuid, path: &str) {
+    let app = build_router(state.clone());
+    let (key, val) = auth_header();
+    let req = Request::builder()
+        .method("POST")
+        .uri(format!("/api/v1/repos/{}/files-pin", repo_id))
+        .header(key, val)
+        .header(header::CONTENT_TYPE, "application/json")
+        .body(Body::from(json!({ "path": path }).to_string()))
+        .unwrap();
+    let resp = app.oneshot(req).await.unwrap();
+    assert_eq!(resp.status(), StatusCode::OK);
+    let body: Value = body_to_json(resp.into_body()).await;
+    assert_eq!(body["data"]["pinned"], true);
+}
+
+#[tokio::test]
+async fn test_pinned_files_are_not_evicted() {
+    let (state, _tmp) = setup();
+    let body = create_test_repo_with(
+        &state,
+        json!({
+            "name": "pins",
+            "max_size_bytes": 12,
+            "eviction_policy": "lru",
+            "pinned_patterns": ["config/*.toml"]
+        }),
+    )
+    .await;
+    let repo_id = uuid::Uuid::parse_str(body["data"]["id"].as_str().unwrap()).unwrap();
+
+    upload_test_file(&state, repo_id, "config/app.toml", b"conf").await;
+    upload_test_file(&state, repo_id, "keep.txt", b"keep").await;
+    upload_test_file(&state, repo_id, "data.bin", b"data").await;
+    pin_test_file(&state, repo_id, "keep.txt").await;
+
+    let (status, body) = upload_test_file_response(&state, repo_id, "big.bin", b"bigbigbi").await;
+    assert_eq!(status, StatusCode::PAYLOAD_TOO_LARGE);
+    let message = body["error"]["message"].as_str().unwrap();
+    assert!(message.contains("only 4 bytes of unpinned data are reclaimable"));
+    assert_eq!(state.files.get(&repo_id).unwrap().len(), 3);
+
+    let (status, body) = upload_test_file_response(&state, repo_id, "small.bin", b"smal").await;
+    assert_eq!(status, StatusCode::CREATED);
+    assert_eq!(body["data"]["evicted"], json!(["data.bin"]));
+}
+
+#[tokio::test]
+async fn test_ttl_reaper_skips_pinned_files() {
+    let (state, _tmp) = setup();
+    let repo_id = create_test_repo(&state, "pinned-ttl").await;
+    for path in ["pinned.txt", "expired.txt"] {
+        let app = build_router(state.clone());
+        let (key, val) = auth_header();
+        let req = Request::builder()
+            .method("POST")
+            .uri(format!("/api/v1/repos/{}/files/{}", repo_id, path))
+            .header(key, val)
+            .header("X-File-TTL", "0")
+            .body(Body::from("x"))
+            .unwrap();
+        let resp = app.oneshot(req).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::CREATED);
+    }
+    pin_test_file(&state, repo_id, "pinned.txt").await;
+
+    let removed = linux_fs::background::ttl_reaper::sweep(&state).await;
+    assert_eq!(removed, 1);
+    let files = state.files.get(&repo_id).unwrap();
+    assert!(files.contains_key("pinned.txt"));
+    assert!(!files.contains_key("expired.txt"));
+}
+
+#[tokio::test]
+async fn test_global_budget_evicts_from_lowest_weight_repo() {
+    let tmp = tempfile::tempdir().unwrap();
+    let mut config = test_config(tmp.path().to_str().unwrap());
+    config.cache_max_bytes = 12;
+    config.global_eviction_order = "weight".to_string();
+    let wal = WalWriter::open(&config.wal_dir()).unwrap();
+    let state = AppState::new(config, wal).unwrap();
+
+    let low = create_test_repo_with(&state, json!({"name": "low", "eviction_weight": 1})).await;
+    let low_id = uuid::Uuid::parse_str(low["data"]["id"].as_str().unwrap()).unwrap();
+    let high = create_test_repo_with(&state, json!({"name": "high", "eviction_weight": 5})).await;
+    let high_id = uuid::Uuid::parse_str(high["data"]["id"].as_str().unwrap()).unwrap();
+
+    upload_test_file(&state, low_id, "a1.bin", b"aaaa").await;
+    upload_test_file(&state, low_id, "a2.bin", b"aaaa").await;
+    upload_test_file(&state, high_id, "b1.bin", b"bbbb").await;
+
+    let (status, body) = upload_test_file_response(&state, high_id, "b2.bin", b"bbbb").await;
+    assert_eq!(status, StatusCode::CREATED);
+    assert_eq!(body["data"]["evicted"], json!([]));
+    assert_eq!(state.files.get(&low_id).unwrap().len(), 1);
+    assert_eq!(state.files.get(&high_id).unwrap().len(), 2);
+
+    let app = build_router(state.clone());
+    let (key, val) = auth_header();
+    let req = Request::builder()
+        .uri("/api/v1/status")
+        .header(key, val)
+        .body(Body::empty())
+        .unwrap();
+    let resp = app.oneshot(req).await.unwrap();
+    let body: Value = body_to_json(resp.into_body()).await;
+    assert_eq!(body["data"]["total_size_bytes"], 12);
+    assert_eq!(body["data"]["cache_max_bytes"], 12);
+    assert_eq!(body["data"]["cache_available_bytes"], 0);
+
+    // Nothing unpinned is left to give up outside the file being written
+    let (status, _) = upload_test_file_response(&state, high_id, "big.bin", b"0123456789abc").await;
+    assert_eq!(status, StatusCode::PAYLOAD_TOO_LARGE);
+}
+
+#[tokio::test]
+async fn test_access_stats_tracked_and_flushed_to_wal() {
+    let (state, _tmp) = setup();
+    let repo_id = create_test_repo(&state, "access-stats").await;
+    upload_test_file(&state, repo_id, "read-me.txt", b"data").await;
+    let created_access = state.repos.get(&repo_id).unwrap().last_accessed_at;
+
+    download_test_file(&state, repo_id, "read-me.txt").await;
+    download_test_file(&state, repo_id, "read-me.txt").await;
+
+    let app = build_router(state.clone());
+    let (key, val) = auth_header();
+    let req = Request::builder()
+        .uri(format!("/api/v1/repos/{}/files", repo_id))
+        .header(key, val)
+        .body(Body::empty())
+        .unwrap();
+    let resp = app.oneshot(req).await.unwrap();
+    let body: Value = body_to_json(resp.into_body()).await;
+    assert_eq!(body["data"]["files"][0]["access_count"], 2);
+    assert!(state.repos.get(&repo_id).unwrap().last_accessed_at > created_access);
+
+    let flushed = linux_fs::services::access_service::flush(&state).await;
+    assert_eq!(flushed, 1);
+
+    let entries = WalWriter::read_entries(&state.config.wal_dir()).unwrap();
+    let recorded = entries.iter().any(|entry| {
+        matches!(
+            entry,
+            WalEntry::AccessRecorded { files, .. }
+                if files.iter().any(|(path, count, _)| path == "read-me.txt" && *count == 2)
+        )
+    });
+    assert!(recorded);
+}
+
+// ==================== Trash Tests ====================
+
+async fn delete_test_path(state: &AppState, uri: String) {
+    let app = build_router(state.clone());
+    let (key, val) = auth_header();
+    let req = Request::builder()
+        .method("DELETE")
+        .uri(uri)
+        .header(key, val)
+        .body(Body::empty())
+        .unwrap();
+    let resp = app.oneshot(req).await.unwrap();
+    assert_eq!(resp.status(), StatusCode::NO_CONTENT);
+}
+
+async fn restore_trash_entry(state: &AppState, trash_id: &str) -> Value {
+    let app = build_router(state.clone());
+    let (key, val) = auth_header();
+    let req = Request::builder()
+        .method("POST")
+        .uri(format!("/api/v1/trash/{}/restore", trash_id))
+        .header(key, val)
+        .body(Body::empty())
+        .unwrap();
+    let resp = app.oneshot(req).await.unwrap();
+    assert_eq!(resp.status(), StatusCode::OK);
+    body_to_json(resp.into_body()).await
+}
+
+#[tokio::test]
+async fn test_deleted_file_can_be_restored_from_trash() {
+    let (state, _tmp) = setup();
+    let repo_id = create_test_repo(&state, "trash-file").await;
+    upload_test_file(&state, repo_id, "notes/todo.txt", b"keep me").await;
+    let created_at = state.files.get(&repo_id).unwrap().get("notes/todo.txt").unwrap().created_at;
+
+    delete_test_path(&state, format!("/api/v1/repos/{}/files/notes/todo.txt", repo_id)).await;
+    assert_eq!(state.repos.get(&repo_id).unwrap().current_size_bytes, 0);
+
+    let app = build_router(state.clone());
+    let (key, val) = auth_header();
+    let req = Request::builder()
+        .uri(format!("/api/v1/repos/{}/trash", repo_id))
+        .header(key, val)
+        .body(Body::empty())
+        .unwrap();
+    let resp = app.oneshot(req).await.unwrap();
+    let body: Value = body_to_json(resp.into_body()).await;
+    let entry = &body["data"][0];
+    assert_eq!(entry["cause"], "user");
+    assert_eq!(entry["item"]["file"]["file"]["path"], "notes/todo.txt");
+    let trash_id = entry["id"].as_str().unwrap().to_string();
+
+    restore_trash_entry(&state, &trash_id).await;
+    let content = download_test_file(&state, repo_id, "notes/todo.txt").await;
+    assert_eq!(&content[..], b"keep me");
+    let meta = state.files.get(&repo_id).unwrap().get("notes/todo.txt").unwrap().clone();
+    assert_eq!(meta.created_at, created_at);
+    assert_eq!(state.repos.get(&repo_id).unwrap().current_size_bytes, 7);
+    assert!(state.trash.is_empty());
+}
+
+#[tokio::test]
+async fn test_trash_retention_beyond_the_calendar_does_not_overflow() {
+    let tmp = tempfile::tempdir().unwrap();
+    let mut config = test_config(tmp.path().to_str().unwrap());
+    config.trash_retention_secs = u64::MAX;
+    let wal = WalWriter::open(&config.wal_dir()).unwrap();
+    let state = AppState::new(config, wal).unwrap();
+    let repo_id = create_test_repo(&state, "long-trash").await;
+    upload_test_file(&state, repo_id, "a.txt", b"alpha").await;
+
+    delete_test_path(&state, format!("/api/v1/repos/{}/files/a.txt", repo_id)).await;
+    let entry = state.trash.iter().next().unwrap().clone();
+    assert!(entry.purge_at > chrono::Utc::now() + chrono::Duration::days(365 * 99));
+}
+
+#[tokio::test]
+async fn test_deleted_repo_can_be_restored_and_purged() {
+    let (state, _tmp) = setup();
+    let repo_id = create_test_repo(&state, "trash-repo").await;
+    upload_test_file(&state, repo_id, "a.txt", b"alpha").await;
+
+    delete_test_path(&state, format!("/api/v1/repos/{}", repo_id)).await;
+    assert!(!state.repos.contains_key(&repo_id));
+
+    let app = build_router(state.clone());
+    let (key, val) = auth_header();
+    let req = Request::builder()
+        .uri("/api/v1/trash")
+        .header(key, val)
+        .body(Body::empty())
+        .unwrap();
+    let resp = app.oneshot(req).await.unwrap();
+    let body: Value = body_to_json(resp.into_body()).await;
+    assert_eq!(body["data"][0]["item"]["repo"]["repo"]["name"], "trash-repo");
+    let trash_id = body["data"][0]["id"].as_str().unwrap().to_string();
+
+    restore_trash_entry(&state, &trash_id).await;
+    let content = download_test_file(&state, repo_id, "a.txt").await;
+    assert_eq!(&content[..], b"alpha");
+
+    // Delete again and let the retention window lapse
+    delete_test_path(&state, format!("/api/v1/repos/{}", repo_id)).await;
+    let trash_id = *state.trash.iter().next().unwrap().key();
+    state.trash.get_mut(&trash_id).unwrap().purge_at = chrono::Utc::now();
+    let purged = linux_fs::services::trash_service::purge_expired(&state).await;
+    assert_eq!(purged, 1);
+    assert!(state.trash.is_empty());
+    assert!(!state.config.trash_dir().join(trash_id.to_string()).exists());
+}
+
+#[tokio::test]
+async fn test_trash_counts_toward_global_budget_and_is_purged_first() {
+    let tmp = tempfile::tempdir().unwrap();
+    let mut config = test_config(tmp.path().to_str().unwrap());
+    config.cache_max_bytes = 12;
+    let wal = WalWriter::open(&config.wal_dir()).unwrap();
+    let state = AppState::new(config, wal).unwrap();
+    let repo_id = create_test_repo(&state, "trash-budget").await;
+    upload_test_file(&state, repo_id, "old.bin", b"oooo").await;
+    upload_test_file(&state, repo_id, "newer.bin", b"nnnn").await;
+    upload_test_file(&state, repo_id, "live.bin", b"llll").await;
+    delete_test_path(&state, format!("/api/v1/repos/{}/files/old.bin", repo_id)).await;
+    delete_test_path(&state, format!("/api/v1/repos/{}/files/newer.bin", repo_id)).await;
+    assert_eq!(linux_fs::services::eviction_service::total_size_bytes(&state), 12);
+
+    // The oldest trash goes before any live file
+    let (status, body) = upload_test_file_response(&state, repo_id, "next.bin", b"xxxx").await;
+    assert_eq!(status, StatusCode::CREATED);
+    assert_eq!(body["data"]["evicted"], json!([]));
+    assert_eq!(state.files.get(&repo_id).unwrap().len(), 2);
+    assert_eq!(state.trash.len(), 1);
+    let left = state.trash.iter().next().unwrap().value().clone();
+    assert!(matches!(&left.item, TrashedItem::File { file } if file.path == "newer.bin"));
+
+    // Restoring moves bytes out of the trash, so it fits a full budget
+    restore_trash_entry(&state, &left.id.to_string()).await;
+    assert_eq!(linux_fs::services::eviction_service::total_size_bytes(&state), 12);
+    assert_eq!(state.files.get(&repo_id).unwrap().len(), 3);
+}
+
+#[tokio::test]
+async fn test_expired_repo_restores_only_with_a_new_ttl() {
+    let (state, _tmp) = setup();
+    let body = create_test_repo_with(&state, json!({"name": "lapsed", "ttl_seconds": 60})).await;
+    let repo_id = uuid::Uuid::parse_str(body["data"]["id"].as_str().unwrap()).unwrap();
+    upload_test_file(&state, repo_id, "a.txt", b"alpha").await;
+    delete_test_path(&state, format!("/api/v1/repos/{}", repo_id)).await;
+
+    let trash_id = *state.trash.iter().next().unwrap().key();
+    if let TrashedItem::Repo { repo, .. } = &mut state.trash.get_mut(&trash_id).unwrap().item {
+        repo.expires_at = Some(chrono::Utc::now() - chrono::Duration::seconds(1));
+    }
+
+    let restore = |body: Option<Value>| {
+        let app = build_router(state.clone());
+        let (key, val) = auth_header();
+        let req = Request::builder()
+            .method("POST")
+            .uri(format!("/api/v1/trash/{}/restore", trash_id))
+            .header(key, val);
+        let req = match body {
+            Some(body) => req
+                .header(header::CONTENT_TYPE, "application/json")
+                .body(Body::from(body.to_string())),
+            None => req.body(Body::empty()),
+        };
+        app.oneshot(req.unwrap())
+    };
+    let resp = restore(None).await.unwrap();
+    assert_eq!(resp.status(), StatusCode::CONFLICT);
+    let body = body_to_json(resp.into_body()).await;
+    assert!(body["error"]["message"].as_str().unwrap().contains("ttl_seconds"));
+    assert!(!state.repos.contains_key(&repo_id));
+
+    let resp = restore(Some(json!({"ttl_seconds": 3600}))).await.unwrap();
+    assert_eq!(resp.status(), StatusCode::OK);
+    let repo = state.repos.get(&repo_id).unwrap().clone();
+    assert_eq!(repo.ttl_seconds, Some(3600));
+    assert!(repo.expires_at.unwrap() > chrono::Utc::now() + chrono::Duration::seconds(3500));
+
+    let entries = WalWriter::read_entries(&state.config.wal_dir()).unwrap();
+    assert!(entries.iter().any(|e| matches!(
+        e,
+        WalEntry::TrashRestoredWithExpiry { id, ttl_seconds: 3600, .. } if *id == trash_id
+    )));
+}
+
+// ==================== Watcher Tests ====================
+
+#[tokio::test]
+async fn test_sync_path_records_out_of_band_changes() {
+    use linux_fs::services::watch_service;
+
+    let (state, _tmp) = setup();
+    let repo_id = create_test_repo(&state, "watched").await;
+    let files_dir = state.config.repos_dir().join(repo_id.to_string()).join("files");
+
+    std::fs::create_dir_all(files_dir.join("src")).unwrap();
+    std::fs::write(files_dir.join("src/main.c"), b"int main;").unwrap();
+    watch_service::sync_path(&state, repo_id, "src").await.unwrap();
+    let meta = state.files.get(&repo_id).unwrap().get("src/main.c").unwrap().clone();
+    assert_eq!(meta.size_bytes, 9);
+    assert_eq!(state.repos.get(&repo_id).unwrap().file_count, 1);
+
+    std::fs::write(files_dir.join("src/main.c"), b"int main(void);").unwrap();
+    watch_service::sync_path(&state, repo_id, "src/main.c").await.unwrap();
+    let updated = state.files.get(&repo_id).unwrap().get("src/main.c").unwrap().clone();
+    assert_ne!(updated.etag, meta.etag);
+    assert_eq!(updated.created_at, meta.created_at);
+    assert_eq!(state.repos.get(&repo_id).unwrap().current_size_bytes, 15);
+
+    pin_test_file(&state, repo_id, "src/main.c").await;
+    std::fs::rename(files_dir.join("src"), files_dir.join("lib")).unwrap();
+    watch_service::apply_rename(&state, repo_id, "src", "lib").await.unwrap();
+    watch_service::sync_path(&state, repo_id, "src").await.unwrap();
+    watch_service::sync_path(&state, repo_id, "lib").await.unwrap();
+    let moved = state.files.get(&repo_id).unwrap().get("lib/main.c").unwrap().clone();
+    assert!(moved.pinned);
+    assert!(!state.files.get(&repo_id).unwrap().contains_key("src/main.c"));
+
+    std::fs::remove_file(files_dir.join("lib/main.c")).unwrap();
+    watch_service::sync_path(&state, repo_id, "lib/main.c").await.unwrap();
+    assert_eq!(state.repos.get(&repo_id).unwrap().current_size_bytes, 0);
+
+    let entries = WalWriter::read_entries(&state.config.wal_dir()).unwrap();
+    assert!(entries
+        .iter()
+        .any(|e| matches!(e, WalEntry::FileMoved { destination, .. } if destination == "lib/main.c")));
+    assert!(entries
+        .iter()
+        .any(|e| matches!(e, WalEntry::FileDeleted { path, .. } if path == "lib/main.c")));
+}
+
+#[tokio::test]
+async fn test_reconcile_adopts_untracked_files_and_fixes_metadata() {
+    use linux_fs::services::reconcile_service::{reconcile, ReconcileMode};
+
+    let (state, _tmp) = setup();
+    let repo_id = create_test_repo(&state, "reconcile").await;
+    upload_test_file(&state, repo_id, "grown.txt", b"short").await;
+    upload_test_file(&state, repo_id, "same-size.txt", b"aaaa").await;
+    upload_test_file(&state, repo_id, "gone.txt", b"bye").await;
+    let files_dir = state.config.repos_dir().join(repo_id.to_string()).join("files");
+
+    std::fs::write(files_dir.join("grown.txt"), b"much longer").unwrap();
+    std::fs::write(files_dir.join("same-size.txt"), b"bbbb").unwrap();
+    std::fs::remove_file(files_dir.join("gone.txt")).unwrap();
+    std::fs::create_dir_all(files_dir.join("build")).unwrap();
+    std::fs::write(files_dir.join("build/out.o"), b"object").unwrap();
+
+    let fast = reconcile(&state, ReconcileMode::Fast).await;
+    assert_eq!(fast.files_adopted, 1);
+    assert_eq!(fast.files_removed, 1);
+    assert_eq!(fast.sizes_fixed, 1);
+    assert_eq!(fast.etags_fixed, 0);
+    let repo = state.repos.get(&repo_id).unwrap().clone();
+    assert_eq!(repo.file_count, 3);
+    assert_eq!(repo.current_size_bytes, 11 + 4 + 6);
+
+    let full = reconcile(&state, ReconcileMode::Full).await;
+    assert_eq!(full.etags_fixed, 1);
+    assert_eq!(full.files_adopted, 0);
+    let content = download_test_file(&state, repo_id, "build/out.o").await;
+    assert_eq!(&content[..], b"object");
+
+    let off = reconcile(&state, ReconcileMode::Off).await;
+    assert_eq!(off.files_scanned, 0);
+}
+
+#[tokio::test]
+async fn test_scrub_reports_corrupted_and_missing_files() {
+    use linux_fs::services::scrub_service;
+
+    let (state, _tmp) = setup();
+    let repo_id = create_test_repo(&state, "scrub").await;
+    upload_test_file(&state, repo_id, "good.txt", b"fine").await;
+    upload_test_file(&state, repo_id, "rot.txt", b"aaaa").await;
+    upload_test_file(&state, repo_id, "lost.txt", b"gone").await;
+    let files_dir = state.config.repos_dir().join(repo_id.to_string()).join("files");
+    std::fs::write(files_dir.join("rot.txt"), b"aaab").unwrap();
+    std::fs::remove_file(files_dir.join("lost.txt")).unwrap();
+
+    let batch = scrub_service::scrub_batch(&state, 16).await;
+    assert!(batch.pass_complete);
+    assert_eq!(batch.files_checked, 3);
+
+    let app = build_router(state.clone());
+    let (key, val) = auth_header();
+    let req = Request::builder()
+        .uri("/api/v1/admin/scrub")
+        .header(key, val)
+        .body(Body::empty())
+        .unwrap();
+    let resp = app.oneshot(req).await.unwrap();
+    assert_eq!(resp.status(), StatusCode::OK);
+    let body = body_to_json(resp.into_body()).await;
+    assert_eq!(body["data"]["passes_completed"], 1);
+    assert_eq!(body["data"]["corrupted_total"], 1);
+    assert_eq!(body["data"]["missing_total"], 1);
+    let mut findings: Vec<(String, String)> = body["data"]["findings"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|f| {
+            (
+                f["path"].as_str().unwrap().to_string(),
+                f["issue"].as_str().unwrap().to_string(),
+            )
+        })
+        .collect();
+    findings.sort();
+    assert_eq!(
+        findings,
+        vec![
+            ("lost.txt".to_string(), "missing".to_string()),
+            ("rot.txt".to_string(), "corrupted".to_string()),
+        ]
+    );
+
+    // Without quarantine the corrupted file stays in place
+    assert!(state.files.get(&repo_id).unwrap().contains_key("rot.txt"));
+}
+
+#[tokio::test]
+async fn test_scrub_resumes_after_restart_and_quarantines() {
+    use linux_fs::services::scrub_service;
+
+    let tmp = tempfile::tempdir().unwrap();
+    let mut config = test_config(tmp.path().to_str().unwrap());
+    config.scrub_quarantine = true;
+    let wal = WalWriter::open(&config.wal_dir()).unwrap();
+    let state = AppState::new(config, wal).unwrap();
+
+    let repo_id = create_test_repo(&state, "scrub-resume").await;
+    for path in ["a.txt", "b.txt", "c.txt"] {
+        upload_test_file(&state, repo_id, path, b"data").await;
+    }
+    let files_dir = state.config.repos_dir().join(repo_id.to_string()).join("files");
+    std::fs::write(files_dir.join("c.txt"), b"DATA").unwrap();
+
+    let first = scrub_service::scrub_batch(&state, 1).await;
+    assert!(!first.pass_complete);
+
+    // Simulate a restart by dropping in-memory progress and reloading it
+    *state.scrub.write().await = Default::default();
+    scrub_service::load(&state).await;
+    assert_eq!(
+        state.scrub.read().await.cursor,
+        Some((repo_id, "a.txt".to_string()))
+    );
+
+    let rest = scrub_service::scrub_batch(&state, 16).await;
+    assert!(rest.pass_complete);
+    assert_eq!(rest.files_checked, 2);
+
+    let progress = state.scrub.read().await.clone();
+    assert_eq!(progress.pass_files_checked, 3);
+    assert_eq!(progress.findings.len(), 1);
+    let quarantined = progress.findings[0].quarantine_path.clone().unwrap();
+    assert_eq!(std::fs::read(quarantined).unwrap(), b"DATA");
+    assert!(!files_dir.join("c.txt").exists());
+    assert!(!state.files.get(&repo_id).unwrap().contains_key("c.txt"));
+    assert_eq!(state.repos.get(&repo_id).unwrap().file_count, 2);
+}
+
+#[tokio::test]
+async fn test_fs_watcher_picks_up_new_files() {
+    let (state, _tmp) = setup();
+    let events = linux_fs::services::watch_service::start(&state).unwrap();
+    let (_shutdown_tx, shutdown_rx) = tokio::sync::watch::channel(false);
+    tokio::spawn(linux_fs::background::fs_watcher::run(
+        state.clone(),
+        events,
+        shutdown_rx,
+    ));
+
+    let repo_id = create_test_repo(&state, "live").await;
+    let files_dir = state.config.repos_dir().join(repo_id.to_string()).join("files");
+    std::fs::write(files_dir.join("out.txt"), b"from exec").unwrap();
+
+    let mut seen = false;
+    for _ in 0..100 {
+        tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+        if state.files.get(&repo_id).unwrap().contains_key("out.txt") {
+            seen = true;
+            break;
+        }
+    }
+    assert!(seen, "watcher did not record out.txt");
+    assert_eq!(state.repos.get(&repo_id).unwrap().current_size_bytes, 9);
+}
+
+#[tokio::test]
+async fn test_fs_watcher_skips_api_writes_and_enforces_quota() {
+    let (state, _tmp) = setup();
+    let events = linux_fs::services::watch_service::start(&state).unwrap();
+    let (_shutdown_tx, shutdown_rx) = tokio::sync::watch::channel(false);
+    tokio::spawn(linux_fs::background::fs_watcher::run(
+        state.clone(),
+        events,
+        shutdown_rx,
+    ));
+    let body = create_test_repo_with(&state, json!({"name": "quota", "max_size_bytes": 16})).await;
+    let repo_id = uuid::Uuid::parse_str(body["data"]["id"].as_str().unwrap()).unwrap();
+    let files_dir = state.config.repos_dir().join(repo_id.to_string()).join("files");
+    let settle = || tokio::time::sleep(std::time::Duration::from_millis(400));
+
+    upload_test_file(&state, repo_id, "api.txt", b"from api").await;
+    settle().await;
+    let req = Request::builder()
+        .uri(format!("/api/v1/repos/{}/changes?since=0", repo_id))
+        .body(Body::empty())
+        .unwrap();
+    let (_, body) = send_json(&state, req).await;
+    assert_eq!(body["data"]["changes"].as_array().unwrap().len(), 1);
+    let entries = WalWriter::read_entries(&state.config.wal_dir()).unwrap();
+    let created = entries
+        .iter()
+        .filter(|e| matches!(e, WalEntry::FileCreated { path, .. } if path == "api.txt"))
+        .count();
+    assert_eq!(created, 1);
+
+    // An out-of-band overwrite of the same file is still seen
+    std::fs::write(files_dir.join("api.txt"), b"changed!").unwrap();
+    settle().await;
+    let req = Request::builder()
+        .uri(format!("/api/v1/repos/{}/changes?since=0", repo_id))
+        .body(Body::empty())
+        .unwrap();
+    let (_, body) = send_json(&state, req).await;
+    assert_eq!(body["data"]["changes"].as_array().unwrap().len(), 2);
+
+    // Over the repo limit: older files are evicted to keep the new one
+    std::fs::write(files_dir.join("out.txt"), b"written by exec").unwrap();
+    settle().await;
+    let files = state.files.get(&repo_id).unwrap().clone();
+    assert!(files.contains_key("out.txt"));
+    assert!(!files.contains_key("api.txt"));
+    assert_eq!(state.repos.get(&repo_id).unwrap().current_size_bytes, 15);
+
+    // Nothing can make room for this one, so it is removed
+    std::fs::write(files_dir.join("huge.bin"), [0u8; 20]).unwrap();
+    settle().await;
+    assert!(!files_dir.join("huge.bin").exists());
+    assert!(!state.files.get(&repo_id).unwrap().contains_key("huge.bin"));
+    assert_eq!(state.repos.get(&repo_id).unwrap().current_size_bytes, 15);
+}
+
+// ==================== Shell Tests ====================
+
+async fn open_event_feed(state: &AppState, uri: String, last_event_id: Option<&str>) -> Body {
+    let app = build_router(state.clone());
+    let (key, val) = auth_header();
+    let mut req = Request::builder().uri(uri).header(key, val);
+    if let Some(id) = last_event_id {
+        req = req.header("last-event-id", id);
+    }
+    let resp = app.oneshot(req.body(Body::empty()).unwrap()).await.unwrap();
+    assert_eq!(resp.status(), StatusCode::OK);
+    assert_eq!(
+        resp.headers()[header::CONTENT_TYPE],
+        "text/event-stream"
+    );
+    resp.into_body()
+}
+
+// Helper: read `count` SSE events as (id, event name, data)
+async fn read_sse_events(body: &mut Body, count: usize) -> Vec<(String, String, Value)> {
+    let mut buf = String::new();
+    let mut events = Vec::new();
+    while events.len() < count {
+        let frame = tokio::time::timeout(std::time::Duration::from_secs(5), body.frame())
+            .await
+            .expect("timed out waiting for event")
+            .unwrap()
+            .unwrap();
+        let Ok(data) = frame.into_data() else { continue };
+        buf.push_str(std::str::from_utf8(&data).unwrap());
+        while let Some(end) = buf.find("\n\n") {
+            let block: String = buf.drain(..end + 2).collect();
+            let (mut id, mut name, mut data) = (String::new(), String::new(), Value::Null);
+            for line in block.lines() {
+                if let Some(v) = line.strip_prefix("id: ") {
+                    id = v.to_string();
+                } else if let Some(v) = line.strip_prefix("event: ") {
+                    name = v.to_string();
+                } else if let Some(v) = line.strip_prefix("data: ") {
+                    data = serde_json::from_str(v).unwrap();
+                }
+            }
+            if !name.is_empty() {
+                events.push((id, name, data));
+            }
+        }
+    }
+    events
+}
+
+#[tokio::test]
+async fn test_event_feed_streams_filtered_changes() {
+    let (state, _tmp) = setup();
+    let repo_id = create_test_repo(&state, "events").await;
+    let mut feed = open_event_feed(
+        &state,
+        format!("/api/v1/repos/{}/events?prefix=src/", repo_id),
+        None,
+    )
+    .await;
+
+    upload_test_file(&state, repo_id, "docs/readme.md", b"skip").await;
+    upload_test_file(&state, repo_id, "src/a.rs", b"one").await;
+    upload_test_file(&state, repo_id, "src/a.rs", b"two").await;
+    let app = build_router(state.clone());
+    let (key, val) = auth_header();
+    let req = Request::builder()
+        .method("POST")
+        .uri(format!("/api/v1/repos/{}/files-move", repo_id))
+        .header(key, val)
+        .header(header::CONTENT_TYPE, "application/json")
+        .body(Body::from(r#"{"source":"src/a.rs","destination":"src/b.rs"}"#))
+        .unwrap();
+    assert_eq!(app.oneshot(req).await.unwrap().status(), StatusCode::OK);
+    delete_test_path(&state, format!("/api/v1/repos/{}/files/src/b.rs", repo_id)).await;
+    delete_test_path(&state, format!("/api/v1/repos/{}", repo_id)).await;
+
+    let events = read_sse_events(&mut feed, 5).await;
+    let names: Vec<&str> = events.iter().map(|(_, name, _)| name.as_str()).collect();
+    assert_eq!(
+        names,
+        vec!["file_created", "file_updated", "file_moved", "file_deleted", "repo_deleted"]
+    );
+    assert_eq!(events[0].2["path"], "src/a.rs");
+    assert_eq!(events[2].2["previous_path"], "src/a.rs");
+    assert_eq!(events[2].2["path"], "src/b.rs");
+
+    // The feed ends after the repo is deleted
+    let end = tokio::time::timeout(std::time::Duration::from_secs(5), feed.frame())
+        .await
+        .unwrap();
+    assert!(end.is_none());
+}
+
+#[tokio::test]
+async fn test_event_feed_resumes_from_last_event_id() {
+    let (state, _tmp) = setup();
+    let repo_id = create_test_repo(&state, "events-resume").await;
+    let uri = format!("/api/v1/repos/{}/events", repo_id);
+
+    let mut feed = open_event_feed(&state, uri.clone(), None).await;
+    upload_test_file(&state, repo_id, "one.txt", b"1").await;
+    let first = read_sse_events(&mut feed, 1).await;
+    drop(feed);
+
+    // Missed while disconnected
+    upload_test_file(&state, repo_id, "two.txt", b"2").await;
+    upload_test_file(&state, repo_id, "three.txt", b"3").await;
+
+    let mut feed = open_event_feed(&state, uri.clone(), Some(&first[0].0)).await;
+    let replayed = read_sse_events(&mut feed, 2).await;
+    assert_eq!(replayed[0].2["path"], "two.txt");
+    assert_eq!(replayed[1].2["path"], "three.txt");
+
+    // An id this run never issued asks the client to relist
+    let (epoch, _) = first[0].0.rsplit_once('-').unwrap();
+    let mut feed =
+        open_event_feed(&state, format!("{}?last_event_id={}-999999", uri, epoch), None).await;
+    let resync = read_sse_events(&mut feed, 1).await;
+    assert_eq!(resync[0].1, "resync");
+
+    // So does one from an earlier run, even if this run has reached it
+    let earlier = format!("{}-1", uuid::Uuid::new_v4());
+    let mut feed = open_event_feed(&state, uri.clone(), Some(&earlier)).await;
+    let resync = read_sse_events(&mut feed, 1).await;
+    assert_eq!(resync[0].1, "resync");
+}
+
+#[tokio::test]
+async fn test_change_log_returns_ordered_changes_since_cursor() {
+    let (state, _tmp) = setup();
+    let repo_id = create_test_repo(&state, "changes").await;
+    let (_, first) = upload_test_file_response(&state, repo_id, "a.txt", b"one").await;
+    assert_eq!(first["data"]["seq"], 1);
+    upload_test_file(&state, repo_id, "a.txt", b"two").await;
+    let app = build_router(state.clone());
+    let (key, val) = auth_header();
+    let req = Request::builder()
+        .method("POST")
+        .uri(format!("/api/v1/repos/{}/files-move", repo_id))
+        .header(key, val)
+        .header(header::CONTENT_TYPE, "application/json")
+        .body(Body::from(r#"{"source":"a.txt","destination":"b.txt"}"#))
+        .unwrap();
+    assert_eq!(app.oneshot(req).await.unwrap().status(), StatusCode::OK);
+    delete_test_path(&state, format!("/api/v1/repos/{}/files/b.txt", repo_id)).await;
+
+    let req = Request::builder()
+        .uri(format!("/api/v1/repos/{}/changes?since=0", repo_id))
+        .body(Body::empty())
+        .unwrap();
+    let (status, body) = send_json(&state, req).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["data"]["latest_seq"], 4);
+    let kinds: Vec<(u64, &str)> = body["data"]["changes"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|c| (c["seq"].as_u64().unwrap(), c["kind"].as_str().unwrap()))
+        .collect();
+    assert_eq!(
+        kinds,
+        vec![
+            (1, "file_created"),
+            (2, "file_updated"),
+            (3, "file_moved"),
+            (4, "file_deleted")
+        ]
+    );
+    assert_eq!(body["data"]["changes"][2]["previous_path"], "a.txt");
+    assert_eq!(body["data"]["changes"][1]["size_bytes"], 3);
+
+    let req = Request::builder()
+        .uri(format!("/api/v1/repos/{}/changes?since=1&limit=1", repo_id))
+        .body(Body::empty())
+        .unwrap();
+    let (_, page) = send_json(&state, req).await;
+    assert_eq!(page["data"]["changes"].as_array().unwrap().len(), 1);
+    assert_eq!(page["data"]["changes"][0]["seq"], 2);
+    assert_eq!(page["data"]["has_more"], true);
+    assert_eq!(state.repos.get(&repo_id).unwrap().seq, 4);
+}
+
+#[tokio::test]
+async fn test_change_log_retention_requires_resync_and_survives_restart() {
+    use linux_fs::services::change_log_service;
+
+    let tmp = tempfile::tempdir().unwrap();
+    let mut config = test_config(tmp.path().to_str().unwrap());
+    config.change_log_max_entries = 2;
+    let wal = WalWriter::open(&config.wal_dir()).unwrap();
+    let state = AppState::new(config.clone(), wal).unwrap();
+
+    let repo_id = create_test_repo(&state, "changes-retention").await;
+    for path in ["1.txt", "2.txt", "3.txt", "4.txt"] {
+        upload_test_file(&state, repo_id, path, b"x").await;
+    }
+    assert_eq!(change_log_service::prune(&state), 2);
+
+    let req = Request::builder()
+        .uri(format!("/api/v1/repos/{}/changes?since=1", repo_id))
+        .body(Body::empty())
+        .unwrap();
+    let (status, body) = send_json(&state, req).await;
+    assert_eq!(status, StatusCode::GONE);
+    assert!(body["error"]["message"]
+        .as_str()
+        .unwrap()
+        .contains("Resync required"));
+    let req = Request::builder()
+        .uri(format!("/api/v1/repos/{}/changes?since=2", repo_id))
+        .body(Body::empty())
+        .unwrap();
+    let (status, body) = send_json(&state, req).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["data"]["changes"].as_array().unwrap().len(), 2);
+
+    // A fresh process picks the sequence up from the log on disk
+    change_log_service::flush(&state).await;
+    let mut repo = state.repos.get(&repo_id).unwrap().clone();
+    repo.seq = 0;
+    let wal = WalWriter::open(&config.wal_dir()).unwrap();
+    let restarted = AppState::new(config, wal).unwrap();
+    restarted.repos.insert(repo_id, repo);
+    change_log_service::load(&restarted);
+    assert_eq!(restarted.repos.get(&repo_id).unwrap().seq, 4);
+
+    upload_test_file(&restarted, repo_id, "5.txt", b"x").await;
+    let req = Request::builder()
+        .uri(format!("/api/v1/repos/{}/changes?since=4", repo_id))
+        .body(Body::empty())
+        .unwrap();
+    let (_, body) = send_json(&restarted, req).await;
+    assert_eq!(body["data"]["changes"][0]["seq"], 5);
+    assert_eq!(body["data"]["changes"][0]["path"], "5.txt");
+
+    // A torn record at the end is cut off, so later appends survive
+    change_log_service::flush(&restarted).await;
+    let log_path = restarted
+        .config
+        .change_log_dir()
+        .join(format!("{}.log", repo_id));
+    let mut file = std::fs::OpenOptions::new().append(true).open(&log_path).unwrap();
+    std::io::Write::write_all(&mut file, &[200, 0, 0, 0, 1, 2]).unwrap();
+    drop(file);
+    let mut repo = restarted.repos.get(&repo_id).unwrap().clone();
+    repo.seq = 0;
+    let wal = WalWriter::open(&restarted.config.wal_dir()).unwrap();
+    let again = AppState::new((*restarted.config).clone(), wal).unwrap();
+    again.repos.insert(repo_id, repo.clone());
+    change_log_service::load(&again);
+    upload_test_file(&again, repo_id, "6.txt", b"x").await;
+    change_log_service::flush(&again).await;
+
+    let wal = WalWriter::open(&again.config.wal_dir()).unwrap();
+    let last = AppState::new((*again.config).clone(), wal).unwrap();
+    last.repos.insert(repo_id, repo);
+    change_log_service::load(&last);
+    let req = Request::builder()
+        .uri(format!("/api/v1/repos/{}/changes?since=5", repo_id))
+        .body(Body::empty())
+        .unwrap();
+    let (_, body) = send_json(&last, req).await;
+    assert_eq!(body["data"]["changes"][0]["path"], "6.txt");
+}
+
+
+#[tokio::test]
+async fn test_change_log_is_written_before_the_response_and_seq_outlives_it() {
+    use linux_fs::services::{change_log_service, checkpoint_service};
+
+    let (state, _tmp) = setup();
+    let repo_id = create_test_repo(&state, "changes-crash").await;
+    upload_test_file(&state, repo_id, "a.txt", b"x").await;
+    upload_test_file(&state, repo_id, "a.txt", b"y").await;
+    let seq = state.repos.get(&repo_id).unwrap().seq;
+    checkpoint_service::create(&state, repo_id, Default::default())
+        .await
+        .unwrap();
+
+    // Restart without flushing: every record is already on disk
+    let mut repo = state.repos.get(&repo_id).unwrap().clone();
+    repo.seq = 0;
+    let wal = WalWriter::open(&state.config.wal_dir()).unwrap();
+    let restarted = AppState::new((*state.config).clone(), wal).unwrap();
+    restarted.repos.insert(repo_id, repo.clone());
+    change_log_service::load(&restarted);
+    assert_eq!(restarted.repos.get(&repo_id).unwrap().seq, seq);
+
+    // A lost log still cannot hand out a seq the checkpoint recorded
+    std::fs::remove_file(
+        state
+            .config
+            .change_log_dir()
+            .join(format!("{}.log", repo_id)),
+    )
+    .unwrap();
+    let wal = WalWriter::open(&state.config.wal_dir()).unwrap();
+    let again = AppState::new((*state.config).clone(), wal).unwrap();
+    again.repos.insert(repo_id, repo);
+    for checkpoint in state.checkpoints.iter() {
+        again.checkpoints.insert(*checkpoint.key(), checkpoint.value().clone());
+    }
+    change_log_service::load(&again);
+    assert_eq!(again.repos.get(&repo_id).unwrap().seq, seq);
+}
+
+// ==================== Sync Tests ====================
+
+fn manifest_entry(path: &str, content: &[u8]) -> Value {
+    use sha2::{Digest, Sha256};
+    json!({
+        "path": path,
+        "sha256": hex::encode(Sha256::digest(content)),
+        "size": content.len(),
+    })
+}
+
+#[tokio::test]
+async fn test_sync_plan_and_mirror_commit() {
+    let (state, _tmp) = setup();
+    let repo_id = create_test_repo(&state, "sync").await;
+    upload_test_file(&state, repo_id, "a.txt", b"alpha").await;
+    upload_test_file(&state, repo_id, "b.txt", b"beta").await;
+    upload_test_file(&state, repo_id, "old.txt", b"stale").await;
+
+    let manifest = json!({
+        "mirror": true,
+        "files": [
+            manifest_entry("a.txt", b"alpha"),
+            manifest_entry("b.txt", b"beta2"),
+            manifest_entry("copy/a.txt", b"alpha"),
+            manifest_entry("d.txt", b"new"),
+            manifest_entry("e.txt", b"new"),
+        ],
+    });
+    let req = Request::builder()
+        .method("POST")
+        .uri(format!("/api/v1/repos/{}/sync/plan", repo_id))
+        .header(header::CONTENT_TYPE, "application/json")
+        .body(Body::from(manifest.to_string()))
+        .unwrap();
+    let (status, plan) = send_json(&state, req).await;
+    assert_eq!(status, StatusCode::OK);
+    let plan = &plan["data"];
+    assert_eq!(plan["unchanged"], 1);
+    assert_eq!(plan["copy"], json!([{"path": "copy/a.txt", "source": "a.txt"}]));
+    assert_eq!(plan["delete"], json!(["old.txt"]));
+    assert_eq!(plan["upload"].as_array().unwrap().len(), 2);
+    assert_eq!(plan["upload"][1]["paths"], json!(["d.txt", "e.txt"]));
+    assert_eq!(plan["upload_bytes"], 8);
+    let plan_id = plan["plan_id"].as_str().unwrap().to_string();
+
+    let commit_uri = format!(
+        "/api/v1/repos/{}/sync/commit?plan_id={}",
+        repo_id, plan_id
+    );
+    let req = Request::builder()
+        .method("POST")
+        .uri(commit_uri.clone())
+        .header(header::CONTENT_TYPE, "application/json")
+        .body(Body::from("beta2new"))
+        .unwrap();
+    let (status, result) = send_json(&state, req).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(result["data"]["uploaded"], 3);
+    assert_eq!(result["data"]["copied"], 1);
+    assert_eq!(result["data"]["deleted"], json!(["old.txt"]));
+
+    assert_eq!(download_test_file(&state, repo_id, "b.txt").await, "beta2");
+    assert_eq!(download_test_file(&state, repo_id, "copy/a.txt").await, "alpha");
+    assert_eq!(download_test_file(&state, repo_id, "e.txt").await, "new");
+    let repo = state.repos.get(&repo_id).unwrap().clone();
+    assert_eq!(repo.file_count, 5);
+    assert_eq!(repo.current_size_bytes, 5 + 5 + 5 + 3 + 3);
+    assert!(!state.files.get(&repo_id).unwrap().contains_key("old.txt"));
+    assert_eq!(state.trash.len(), 1);
+    assert_eq!(result["data"]["seq"], repo.seq);
+
+    // A plan is single-use
+    let req = Request::builder()
+        .method("POST")
+        .uri(commit_uri)
+        .header(header::CONTENT_TYPE, "application/json")
+        .body(Body::from("beta2new"))
+        .unwrap();
+    let (status, _) = send_json(&state, req).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn test_sync_commit_rejects_stale_plan_and_bad_content() {
+    let (state, _tmp) = setup();
+    let repo_id = create_test_repo(&state, "sync-stale").await;
+    upload_test_file(&state, repo_id, "a.txt", b"one").await;
+
+    let plan_uri = format!("/api/v1/repos/{}/sync/plan", repo_id);
+    let manifest = json!({"files": [manifest_entry("a.txt", b"two"), manifest_entry("b.txt", b"one")]});
+    let req = Request::builder()
+        .method("POST")
+        .uri(plan_uri.clone())
+        .header(header::CONTENT_TYPE, "application/json")
+        .body(Body::from(manifest.to_string()))
+        .unwrap();
+    let (_, plan) = send_json(&state, req).await;
+    let plan_id = plan["data"]["plan_id"].as_str().unwrap().to_string();
+
+    // The copy source changes before the commit
+    upload_test_file(&state, repo_id, "a.txt", b"three").await;
+    let req = Request::builder()
+        .method("POST")
+        .uri(format!("/api/v1/repos/{}/sync/commit?plan_id={}", repo_id, plan_id))
+        .header(header::CONTENT_TYPE, "application/json")
+        .body(Body::from("two"))
+        .unwrap();
+    let (status, body) = send_json(&state, req).await;
+    assert_eq!(status, StatusCode::CONFLICT);
+    assert!(body["error"]["message"].as_str().unwrap().contains("new plan"));
+    assert!(!state.files.get(&repo_id).unwrap().contains_key("b.txt"));
+    assert_eq!(download_test_file(&state, repo_id, "a.txt").await, "three");
+
+    let req = Request::builder()
+        .method("POST")
+        .uri(plan_uri)
+        .header(header::CONTENT_TYPE, "application/json")
+        .body(Body::from(manifest.to_string()))
+        .unwrap();
+    let (_, plan) = send_json(&state, req).await;
+    let plan_id = plan["data"]["plan_id"].as_str().unwrap().to_string();
+    let req = Request::builder()
+        .method("POST")
+        .uri(format!("/api/v1/repos/{}/sync/commit?plan_id={}", repo_id, plan_id))
+        .header(header::CONTENT_TYPE, "application/json")
+        .body(Body::from("TWO"))
+        .unwrap();
+    let (status, _) = send_json(&state, req).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert_eq!(download_test_file(&state, repo_id, "a.txt").await, "three");
+    assert!(std::fs::read_dir(state.config.tmp_dir()).unwrap().next().is_none());
+
+    // Sizes that wrap around to a small total
+    let mut huge = manifest_entry("c.txt", b"c");
+    huge["size"] = json!(u64::MAX);
+    let mut small = manifest_entry("d.txt", b"dd");
+    small["size"] = json!(2);
+    let manifest = json!({"files": [huge, small]});
+    let req = Request::builder()
+        .method("POST")
+        .uri(format!("/api/v1/repos/{}/sync/plan", repo_id))
+        .header(header::CONTENT_TYPE, "application/json")
+        .body(Body::from(manifest.to_string()))
+        .unwrap();
+    let (status, body) = send_json(&state, req).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert!(body["error"]["message"].as_str().unwrap().contains("u64"));
+}
+
+// ==================== Delta Upload Tests ====================
+
+fn delta_copy(start: u64, count: u32) -> Vec<u8> {
+    let mut op = vec![0x01];
+    op.extend_from_slice(&start.to_le_bytes());
+    op.extend_from_slice(&count.to_le_bytes());
+    op
+}
+
+fn delta_literal(data: &[u8]) -> Vec<u8> {
+    let mut op = vec![0x02];
+    op.extend_from_slice(&(data.len() as u32).to_le_bytes());
+    op.extend_from_slice(data);
+    op
+}
+
+#[tokio::test]
+async fn test_delta_upload_rebuilds_file_from_signature() {
+    use linux_fs::services::delta_service;
+    use sha2::{Digest, Sha256};
+
+    let (state, _tmp) = setup();
+    let repo_id = create_test_repo(&state, "delta").await;
+    let original: Vec<u8> = (0..2500u32).map(|i| (i % 251) as u8).collect();
+    upload_test_file(&state, repo_id, "big.bin", &original).await;
+
+    let app = build_router(state.clone());
+    let (key, val) = auth_header();
+    let req = Request::builder()
+        .uri(format!(
+            "/api/v1/repos/{}/files-signature?path=big.bin&block_size=1024",
+            repo_id
+        ))
+        .header(key, val)
+        .body(Body::empty())
+        .unwrap();
+    let resp = app.oneshot(req).await.unwrap();
+    assert_eq!(resp.status(), StatusCode::OK);
+    let sig = body_to_json(resp.into_body()).await["data"].clone();
+    let blocks = sig["blocks"].as_array().unwrap();
+    assert_eq!(blocks.len(), 3);
+    assert_eq!(sig["size_bytes"], 2500);
+    assert_eq!(
+        blocks[2]["weak"],
+        delta_service::weak_checksum(&original[2048..])
+    );
+    assert_eq!(
+        blocks[1]["strong"],
+        hex::encode(Sha256::digest(&original[1024..2048]))
+    );
+
+    // Replace the middle block and append a tail
+    let mut updated = original[..1024].to_vec();
+    updated.extend_from_slice(b"patched middle");
+    updated.extend_from_slice(&original[2048..]);
+    updated.extend_from_slice(b"tail");
+    let delta = [
+        delta_copy(0, 1),
+        delta_literal(b"patched middle"),
+        delta_copy(2, 1),
+        delta_literal(b"tail"),
+    ]
+    .concat();
+    let sha = hex::encode(Sha256::digest(&updated));
+    let base = sig["etag"].as_str().unwrap();
+
+    let req = Request::builder()
+        .method("POST")
+        .uri(format!(
+            "/api/v1/repos/{}/files-delta?path=big.bin&block_size=1024&base_etag={}&sha256={}",
+            repo_id,
+            base,
+            "0".repeat(64)
+        ))
+        .body(Body::from(delta.clone()))
+        .unwrap();
+    let (status, body) = send_json(&state, req).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert!(body["error"]["message"].as_str().unwrap().contains("sha256"));
+
+    let query = format!("path=big.bin&block_size=1024&base_etag={}&sha256={}", base, sha);
+    let req = Request::builder()
+        .method("POST")
+        .uri(format!("/api/v1/repos/{}/files-delta?{}", repo_id, query))
+        .body(Body::from(delta.clone()))
+        .unwrap();
+    let (status, body) = send_json(&state, req).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["data"]["etag"], sha);
+    assert_eq!(body["data"]["copied_bytes"], 1024 + 452);
+    assert_eq!(body["data"]["literal_bytes"], 18);
+    assert_eq!(download_test_file(&state, repo_id, "big.bin").await, updated);
+    assert_eq!(
+        state.repos.get(&repo_id).unwrap().current_size_bytes,
+        updated.len() as u64
+    );
+
+    // The base has moved on
+    let req = Request::builder()
+        .method("POST")
+        .uri(format!("/api/v1/repos/{}/files-delta?{}", repo_id, query))
+        .body(Body::from(delta))
+        .unwrap();
+    let (status, _) = send_json(&state, req).await;
+    assert_eq!(status, StatusCode::CONFLICT);
+}
+
+// ==================== Webhook Tests ====================
+
+type Received = std::sync::Arc<std::sync::Mutex<Vec<(http::HeaderMap, Bytes)>>>;
+
+/// Local stand-in receiver answering every POST with `status`.
+async fn spawn_webhook_receiver(
+    status: std::sync::Arc<std::sync::atomic::AtomicU16>,
+) -> (String, Received) {
+    use axum::extract::State;
+
+    let received: Received = Default::default();
+    let app = axum::Router::new()
+        .route(
+            "/hook",
+            axum::routing::post(
+                |State((status, received)): State<(
+                    std::sync::Arc<std::sync::atomic::AtomicU16>,
+                    Received,
+                )>,
+                 headers: http::HeaderMap,
+                 body: Bytes| async move {
+                    received.lock().unwrap().push((headers, body));
+                    StatusCode::from_u16(status.load(std::sync::atomic::Ordering::SeqCst)).unwrap()
+                },
+            ),
+        )
+        .with_state((status, received.clone()));
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+    (format!("http://{}/hook", addr), received)
+}
+
+#[tokio::test]
+async fn test_webhook_delivers_signed_change_and_quota_events() {
+    use linux_fs::services::webhook_service;
+
+    assert_eq!(
+        webhook_service::sign(b"key", b"The quick brown fox jumps over the lazy dog"),
+        "f7bc83f430538424b13298e6aa6fb143ef4d59a14946175997479dbc2d1a3cd8"
+    );
+
+    let (state, _tmp) = setup();
+    let status = std::sync::Arc::new(std::sync::atomic::AtomicU16::new(200));
+    let (url, received) = spawn_webhook_receiver(status).await;
+    let body = create_test_repo_with(&state, json!({"name": "hooked", "max_size_bytes": 10})).await;
+    let repo_id = uuid::Uuid::parse_str(body["data"]["id"].as_str().unwrap()).unwrap();
+
+    let req = Request::builder()
+        .method("POST")
+        .uri("/api/v1/webhooks")
+        .header(header::CONTENT_TYPE, "application/json")
+        .body(Body::from(json!({"url": "ftp://example.com/hook"}).to_string()))
+        .unwrap();
+    let (code, _) = send_json(&state, req).await;
+    assert_eq!(code, StatusCode::BAD_REQUEST);
+    let req = Request::builder()
+        .method("POST")
+        .uri("/api/v1/webhooks")
+        .header(header::CONTENT_TYPE, "application/json")
+        .body(Body::from(json!({"url": url, "repo_id": repo_id, "secret": "s3cret"}).to_string()))
+        .unwrap();
+    let (code, hook) = send_json(&state, req).await;
+    assert_eq!(code, StatusCode::CREATED);
+    assert_eq!(hook["data"]["secret"], "s3cret");
+
+    // 9 of 10 bytes crosses the 80% and 90% thresholds at once
+    upload_test_file(&state, repo_id, "a.txt", b"123456789").await;
+    let other = create_test_repo(&state, "unhooked").await;
+    upload_test_file(&state, other, "b.txt", b"x").await;
+    assert_eq!(webhook_service::deliver_due(&state).await, 2);
+
+    let received = received.lock().unwrap().clone();
+    assert_eq!(received.len(), 2);
+    for (headers, body) in &received {
+        let signature = headers["x-webhook-signature-256"].to_str().unwrap();
+        assert_eq!(
+            signature,
+            format!("sha256={}", webhook_service::sign(b"s3cret", body))
+        );
+    }
+    let change: Value = serde_json::from_slice(&received[0].1).unwrap();
+    assert_eq!(received[0].0["x-webhook-event"], "file_created");
+    assert_eq!(change["repo_id"], repo_id.to_string());
+    assert_eq!(change["data"]["path"], "a.txt");
+    assert_eq!(change["data"]["seq"], 1);
+    let quota: Value = serde_json::from_slice(&received[1].1).unwrap();
+    assert_eq!(quota["event"], "quota_threshold_crossed");
+    assert_eq!(quota["data"]["threshold_percent"], 90);
+
+    // Same level again does not re-fire
+    upload_test_file(&state, repo_id, "a.txt", b"987654321").await;
+    let pending = webhook_service::list_deliveries(
+        &state,
+        &linux_fs::models::webhook::ListDeliveriesQuery {
+            status: None,
+            webhook_id: None,
+        },
+    );
+    assert_eq!(pending.len(), 1);
+    assert_eq!(pending[0].event, "file_updated");
+}
+
+#[tokio::test]
+async fn test_webhook_retries_persist_and_can_be_retried_by_hand() {
+    use linux_fs::services::webhook_service;
+
+    let tmp = tempfile::tempdir().unwrap();
+    let mut config = test_config(tmp.path().to_str().unwrap());
+    config.webhook_max_attempts = 2;
+    config.webhook_backoff_base_secs = 0;
+    let wal = WalWriter::open(&config.wal_dir()).unwrap();
+    let state = AppState::new(config.clone(), wal).unwrap();
+
+    let status = std::sync::Arc::new(std::sync::atomic::AtomicU16::new(500));
+    let (url, received) = spawn_webhook_receiver(status.clone()).await;
+    let req = Request::builder()
+        .method("POST")
+        .uri("/api/v1/webhooks")
+        .header(header::CONTENT_TYPE, "application/json")
+        .body(Body::from(json!({"url": url, "events": ["file_created"]}).to_string()))
+        .unwrap();
+    let (_, hook) = send_json(&state, req).await;
+    assert!(hook["data"]["secret"].as_str().unwrap().len() >= 32);
+
+    let repo_id = create_test_repo(&state, "flaky").await;
+    upload_test_file(&state, repo_id, "a.txt", b"x").await;
+    assert_eq!(webhook_service::deliver_due(&state).await, 0);
+    assert_eq!(webhook_service::deliver_due(&state).await, 0);
+    assert_eq!(received.lock().unwrap().len(), 2);
+
+    let app = build_router(state.clone());
+    let (key, val) = auth_header();
+    let req = Request::builder()
+        .uri("/api/v1/admin/webhooks/deliveries?status=failed")
+        .header(key, val)
+        .body(Body::empty())
+        .unwrap();
+    let body = body_to_json(app.oneshot(req).await.unwrap().into_body()).await;
+    let failed = body["data"].as_array().unwrap();
+    assert_eq!(failed.len(), 1);
+    assert_eq!(failed[0]["attempts"], 2);
+    assert_eq!(failed[0]["last_status"], 500);
+    let delivery_id = failed[0]["id"].as_str().unwrap().to_string();
+
+    // The queue survives a restart
+    let wal = WalWriter::open(&config.wal_dir()).unwrap();
+    let restarted = AppState::new(config, wal).unwrap();
+    webhook_service::load(&restarted);
+    assert_eq!(webhook_service::list(&restarted, None).len(), 1);
+
+    status.store(200, std::sync::atomic::Ordering::SeqCst);
+    let app = build_router(restarted.clone());
+    let (key, val) = auth_header();
+    let req = Request::builder()
+        .method("POST")
+        .uri(format!(
+            "/api/v1/admin/webhooks/deliveries/{}/retry",
+            delivery_id
+        ))
+        .header(key, val)
+        .body(Body::empty())
+        .unwrap();
+    let resp = app.oneshot(req).await.unwrap();
+    assert_eq!(resp.status(), StatusCode::OK);
+    let body = body_to_json(resp.into_body()).await;
+    assert_eq!(body["data"]["status"], "pending");
+
+    assert_eq!(webhook_service::deliver_due(&restarted).await, 1);
+    assert_eq!(received.lock().unwrap().len(), 3);
+    assert!(std::fs::read_dir(restarted.config.webhooks_dir().join("queue"))
+        .unwrap()
+        .next()
+        .is_none());
+}
+
+#[tokio::test]
+async fn test_webhook_queue_is_written_by_the_dispatcher_and_failures_expire() {
+    use linux_fs::services::webhook_service;
+
+    let tmp = tempfile::tempdir().unwrap();
+    let mut config = test_config(tmp.path().to_str().unwrap());
+    config.webhook_max_attempts = 1;
+    let wal = WalWriter::open(&config.wal_dir()).unwrap();
+    let state = AppState::new(config.clone(), wal).unwrap();
+
+    let status = std::sync::Arc::new(std::sync::atomic::AtomicU16::new(500));
+    let (url, _received) = spawn_webhook_receiver(status).await;
+    let req = Request::builder()
+        .method("POST")
+        .uri("/api/v1/webhooks")
+        .header(header::CONTENT_TYPE, "application/json")
+        .body(Body::from(json!({"url": url, "events": ["file_created"]}).to_string()))
+        .unwrap();
+    send_json(&state, req).await;
+    let repo_id = create_test_repo(&state, "expiring").await;
+    upload_test_file(&state, repo_id, "a.txt", b"x").await;
+
+    let queue = state.config.webhooks_dir().join("queue");
+    assert!(!queue.exists());
+    assert_eq!(webhook_service::deliver_due(&state).await, 0);
+    let path = std::fs::read_dir(&queue).unwrap().next().unwrap().unwrap().path();
+    let mut delivery: Value = serde_json::from_slice(&std::fs::read(&path).unwrap()).unwrap();
+    assert_eq!(delivery["status"], "failed");
+
+    // Last attempted longer ago than the retention
+    delivery["next_attempt_at"] = json!(chrono::Utc::now() - chrono::Duration::days(8));
+    std::fs::write(&path, delivery.to_string()).unwrap();
+    let wal = WalWriter::open(&config.wal_dir()).unwrap();
+    let restarted = AppState::new(config, wal).unwrap();
+    webhook_service::load(&restarted);
+    assert_eq!(webhook_service::deliver_due(&restarted).await, 0);
+    let query = linux_fs::models::webhook::ListDeliveriesQuery {
+        status: None,
+        webhook_id: None,
+    };
+    assert!(webhook_service::list_deliveries(&restarted, &query).is_empty());
+    assert!(!path.exists());
+}
+
+#[tokio::test]
+async fn test_exec_allowed_command() {
+    let (state, _tmp) = setup();
+    let repo_id = create_test_repo(&state, "exec-test").await;
+
+    let app = build_router(state);
+    let (key, val) = auth_header();
+    let req = Request::builder()
+        .method("POST")
+        .uri(format!("/api/v1/repos/{}/exec", repo_id))
+        .header(key, val)
+        .header(header::CONTENT_TYPE, "application/json")
+        .body(Body::from(
+            serde_json::to_string(&json!({
+                "command": "ls",
+                "args": []
+            }))
+            .unwrap(),
+        ))
+        .unwrap();
+
+    let resp = app.oneshot(req).await.unwrap();
+    assert_eq!(resp.status(), StatusCode::OK);
+
+    let body: Value = body_to_json(resp.into_body()).await;
+    assert!(body["data"]["exit_code"].is_number());
+    assert!(body["data"]["stdout"].is_string());
+    assert!(body["data"]["stderr"].is_string());
+    assert!(body["data"]["duration_ms"].is_number());
+}
+
+#[tokio::test]
+async fn test_exec_disallowed_command_returns_403() {
+    let (state, _tmp) = setup();
+    let repo_id = create_test_repo(&state, "exec-forbidden").await;
+
+    let app = build_router(state);
+    let (key, val) = auth_header();
+    let req = Request::builder()
+        .method("POST")
+        .uri(format!("/api/v1/repos/{}/exec", repo_id))
+        .header(key, val)
+        .header(header::CONTENT_TYPE, "application/json")
+        .body(Body::from(
+            serde_json::to_string(&json!({
+                "command": "rm",
+                "args": ["-rf", "/"]
+            }))
+            .unwrap(),
+        ))
+        .unwrap();
+
+    let resp = app.oneshot(req).await.unwrap();
+    assert_eq!(resp.status(), StatusCode::FORBIDDEN);
+}
+
+#[tokio::test]
+async fn test_exec_policy_closes_escape_hatches() {
+    let (state, _tmp) = setup();
+    let repo_id = create_test_repo(&state, "policed").await;
+    upload_test_file(&state, repo_id, "notes/a.txt", b"one two\n").await;
+
+    let rejected: &[(&str, &[&str], &str)] = &[
+        ("find", &[".", "-exec", "cat", "{}", "+"], "option '-exec' is not allowed: runs other programs"),
+        ("find", &["/etc", "-name", "shadow"], "'/etc' is outside the repository"),
+        ("find", &[".", "-delete"], "option '-delete' writes to the repository"),
+        ("find", &["-L", "/", "-name", "shadow"], "'/' is outside the repository"),
+        ("find", &["-P", "-D", "tree", "-O3", "/etc"], "'/etc' is outside the repository"),
+        ("cat", &["/etc/shadow"], "'/etc/shadow' is outside the repository"),
+        ("grep", &["-r", "root", "/etc"], "'/etc' is outside the repository"),
+        ("grep", &["-f", "/etc/passwd", "notes"], "'/etc/passwd' is outside the repository"),
+        ("sed", &["-i", "s/one/1/", "notes/a.txt"], "option '-i' writes to the repository"),
+        ("sed", &["-ni", "p", "notes/a.txt"], "option '-i' writes to the repository"),
+        ("sed", &["1e id", "notes/a.txt"], "the 'e' command runs shell commands"),
+        ("sed", &["s/one/id/e", "notes/a.txt"], "the 'e' flag of 's' runs the result as a command"),
+        ("sed", &["-e", "w out.txt", "notes/a.txt"], "the 'w' command writes to a file"),
+        ("awk", &["BEGIN { system(\"id\") }"], "system() runs shell commands"),
+        ("awk", &["{ print > \"out.txt\" }", "notes/a.txt"], "output redirection"),
+        ("awk", &["@load \"x\" BEGIN {}"], "@load loads native extensions"),
+        ("awk", &["@include \"x.awk\" BEGIN {}"], "@include reads another file"),
+        ("awk", &["BEGIN { getline x < \"/etc/shadow\" }"], "getline with '<' reads another file"),
+        ("awk", &["-l", "x", "BEGIN {}"], "option '-l' is not allowed"),
+        ("sort", &["-o", "sorted.txt", "notes/a.txt"], "option '-o' writes to the repository"),
+        ("sort", &["--out=/tmp/x", "notes/a.txt"], "'/tmp/x' is outside the repository"),
+        ("sort", &["--compress=gzip", "notes/a.txt"], "abbreviates '--compress-program'"),
+        ("rg", &["--pre", "sh", "one"], "option '--pre' is not allowed"),
+        ("uniq", &["notes/a.txt", "notes/b.txt"], "operand 'notes/b.txt' is written to"),
+    ];
+    for (command, args, message) in rejected {
+        let (status, body) = exec_test_command(&state, repo_id, command, args).await;
+        assert_eq!(status, StatusCode::FORBIDDEN, "{} {:?}", command, args);
+        let error = body["error"]["message"].as_str().unwrap();
+        assert!(error.contains(message), "{} {:?}: {}", command, args, error);
+    }
+
+    let allowed: &[(&str, &[&str], &str)] = &[
+        ("sed", &["-n", "s/one/1/p", "notes/a.txt"], "1 two\n"),
+        ("awk", &["{ print (NF > 1) }", "notes/a.txt"], "1\n"),
+        ("grep", &["-rl", "two", "notes"], "notes/a.txt\n"),
+        ("find", &["notes", "-name", "*.txt"], "notes/a.txt\n"),
+        ("find", &["-L", "notes", "-name", "*.txt"], "notes/a.txt\n"),
+        ("awk", &["NR == 1 { while ((getline line) > 0) n++ } END { print n + 0 }", "notes/a.txt"], "0\n"),
+    ];
+    for (command, args, stdout) in allowed {
+        let (status, body) = exec_test_command(&state, repo_id, command, args).await;
+        assert_eq!(status, StatusCode::OK, "{} {:?}: {}", command, args, body);
+        assert_eq!(body["data"]["stdout"], *stdout);
+    }
+
+    // Script files are held to the same rules as inline scripts
+    upload_test_file(&state, repo_id, "prog.awk", b"BEGIN {\n  system(\"id\")\n}\n").await;
+    let (status, body) =
+        exec_test_command(&state, repo_id, "awk", &["-f", "prog.awk", "notes/a.txt"]).await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+    assert!(body["error"]["message"]
+        .as_str()
+        .unwrap()
+        .contains("script file 'prog.awk': system() runs shell commands"));
+
+    // Libraries too, and pipes are caught without the argument check
+    upload_test_file(&state, repo_id, "lib.awk", b"function f() { \"id\" | getline }\n").await;
+    let (status, body) =
+        exec_test_command(&state, repo_id, "awk", &["-i", "lib.awk", "BEGIN {}"]).await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+    assert!(body["error"]["message"]
+        .as_str()
+        .unwrap()
+        .contains("script file 'lib.awk': pipes run shell commands"));
+}
+
+#[tokio::test]
+async fn test_exec_policy_file_and_mutation_opt_in() {
+    let tmp = tempfile::tempdir().unwrap();
+    let mut config = test_config(tmp.path().to_str().unwrap());
+    let policy = tmp.path().join("policy.json");
+    std::fs::write(
+        &policy,
+        json!({"commands": {"echo": {"operands": "text", "allowed_flags": ["-n"]}, "sed": null}})
+            .to_string(),
+    )
+    .unwrap();
+    config.command_policy_file = policy.to_str().unwrap().into();
+    config.exec_allow_mutating = true;
+    let wal = WalWriter::open(&config.wal_dir()).unwrap();
+    let state = AppState::new(config, wal).unwrap();
+    let repo_id = create_test_repo(&state, "custom").await;
+    upload_test_file(&state, repo_id, "notes/a.txt", b"b\na\n").await;
+
+    let (status, body) = exec_test_command(&state, repo_id, "echo", &["-n", "hi"]).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["data"]["stdout"], "hi");
+
+    let (status, body) = exec_test_command(&state, repo_id, "echo", &["-e", "hi"]).await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+    assert_eq!(body["error"]["message"], "echo: option '-e' is not allowed");
+
+    let (status, _) = exec_test_command(&state, repo_id, "sed", &["p", "notes/a.txt"]).await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+
+    // Writes inside the repo are fine once mutation is allowed
+    let (status, _) =
+        exec_test_command(&state, repo_id, "sort", &["-o", "notes/b.txt", "notes/a.txt"]).await;
+    assert_eq!(status, StatusCode::OK);
+    let written = tmp
+        .path()
+        .join(format!("repos/{}/files/notes/b.txt", repo_id));
+    assert_eq!(std::fs::read(written).unwrap(), b"a\nb\n");
+
+    // The file find's print actions write to is checked like any path
+    let (status, body) =
+        exec_test_command(&state, repo_id, "find", &[".", "-fprint", "/tmp/x"]).await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+    assert!(body["error"]["message"]
+        .as_str()
+        .unwrap()
+        .contains("'/tmp/x' is outside the repository"));
+}
+
+#[tokio::test]
+async fn test_repo_command_profile_selects_commands() {
+    let (state, _tmp) = setup();
+    let app = build_router(state.clone());
+    let (key, val) = auth_header();
+    let req = Request::builder()
+        .method("POST")
+        .uri("/api/v1/repos")
+        .header(key, val)
+        .header(header::CONTENT_TYPE, "application/json")
+        .body(Body::from(r#"{"name":"x","command_profile":"nope"}"#))
+        .unwrap();
+    let resp = app.oneshot(req).await.unwrap();
+    assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+    let body = body_to_json(resp.into_body()).await;
+    assert_eq!(body["error"]["message"], "Unknown command profile 'nope'");
+
+    let body =
+        create_test_repo_with(&state, json!({"name": "locked", "command_profile": "read_only"}))
+            .await;
+    assert_eq!(body["data"]["command_profile"], "read_only");
+    let repo_id = uuid::Uuid::parse_str(body["data"]["id"].as_str().unwrap()).unwrap();
+    upload_test_file(&state, repo_id, "b.txt", b"b\na\n").await;
+
+    let (status, body) = exec_test_command(&state, repo_id, "cat", &["b.txt"]).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["data"]["stdout"], "b\na\n");
+    let (status, body) = exec_test_command(&state, repo_id, "grep", &["a", "b.txt"]).await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+    assert_eq!(body["error"]["message"], "Command 'grep' is not allowed");
+
+    let req = Request::builder()
+        .uri(format!("/api/v1/repos/{}/commands", repo_id))
+        .body(Body::empty())
+        .unwrap();
+    let (status, body) = send_json(&state, req).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["data"]["profile"], "read_only");
+    assert_eq!(body["data"]["allow_mutating"], false);
+    let commands = body["data"]["commands"].as_array().unwrap();
+    let names: Vec<&str> = commands.iter().map(|c| c["name"].as_str().unwrap()).collect();
+    assert_eq!(names, ["cat", "ls"]);
+    assert!(commands[0]["path"].as_str().unwrap().starts_with('/'));
+    assert!(commands[0]["version"]
+        .as_str()
+        .unwrap()
+        .contains("coreutils"));
+
+    // Forks keep the profile; null returns to the default one
+    let fork = fork_test_repo(&state, repo_id, json!({})).await;
+    assert_eq!(fork["data"]["command_profile"], "read_only");
+
+    let app = build_router(state.clone());
+    let (key, val) = auth_header();
+    let req = Request::builder()
+        .method("PATCH")
+        .uri(format!("/api/v1/repos/{}", repo_id))
+        .header(key, val)
+        .header(header::CONTENT_TYPE, "application/json")
+        .body(Body::from(r#"{"command_profile":null}"#))
+        .unwrap();
+    let resp = app.oneshot(req).await.unwrap();
+    assert_eq!(resp.status(), StatusCode::OK);
+    let body = body_to_json(resp.into_body()).await;
+    assert!(body["data"]["command_profile"].is_null());
+
+    let (status, _) = exec_test_command(&state, repo_id, "grep", &["a", "b.txt"]).await;
+    assert_eq!(status, StatusCode::OK);
+    let req = Request::builder()
+        .uri(format!("/api/v1/repos/{}/commands", repo_id))
+        .body(Body::empty())
+        .unwrap();
+    let (_, body) = send_json(&state, req).await;
+    assert_eq!(body["data"]["profile"], "default");
+    assert!(body["data"]["commands"].as_array().unwrap().len() > 2);
+}
+
+#[tokio::test]
+async fn test_interpreter_refused_without_full_sandbox() {
+    let tmp = tempfile::tempdir().unwrap();
+    let mut config = test_config(tmp.path().to_str().unwrap());
+    config.sandbox_landlock = true;
+    config.sandbox_seccomp = true;
+    let wal = WalWriter::open(&config.wal_dir()).unwrap();
+    let state = AppState::new(config.clone(), wal).unwrap();
+    let body =
+        create_test_repo_with(&state, json!({"name": "data", "command_profile": "data"})).await;
+    let repo_id = uuid::Uuid::parse_str(body["data"]["id"].as_str().unwrap()).unwrap();
+    upload_test_file(&state, repo_id, "env.py", b"print(open('/proc/1/environ').read())\n").await;
+
+    // Namespaces are off, so python3 would run as the server
+    let (status, body) = exec_test_command(&state, repo_id, "python3", &["env.py"]).await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+    assert!(body["error"]["message"]
+        .as_str()
+        .unwrap()
+        .contains("needs namespaces, Landlock and seccomp"));
+    let (status, _) = exec_test_command(&state, repo_id, "cat", &["env.py"]).await;
+    assert_eq!(status, StatusCode::OK);
+
+    let req = Request::builder()
+        .uri(format!("/api/v1/repos/{}/commands", repo_id))
+        .body(Body::empty())
+        .unwrap();
+    let (_, body) = send_json(&state, req).await;
+    let commands = body["data"]["commands"].as_array().unwrap();
+    let python = commands.iter().find(|c| c["name"] == "python3").unwrap();
+    assert_eq!(python["requires_sandbox"], true);
+    let cat = commands.iter().find(|c| c["name"] == "cat").unwrap();
+    assert_eq!(cat["requires_sandbox"], false);
+}
+
+#[tokio::test]
+async fn test_command_profile_fixed_args_and_limits() {
+    let tmp = tempfile::tempdir().unwrap();
+    let mut config = test_config(tmp.path().to_str().unwrap());
+    let policy = tmp.path().join("policy.json");
+    std::fs::write(
+        &policy,
+        json!({"profiles": {"tight": {
+            "include": ["sort"],
+            "allow_mutating": true,
+            "commands": {
+                "echo": {"operands": "text", "fixed_args": ["-n"], "max_output_bytes": 4},
+                "tail": {"timeout_seconds": 1, "exec_limits": {"pids_max": 8}}
+            }
+        }}})
+        .to_string(),
+    )
+    .unwrap();
+    config.command_policy_file = policy.to_str().unwrap().into();
+    let wal = WalWriter::open(&config.wal_dir()).unwrap();
+    let state = AppState::new(config.clone(), wal).unwrap();
+    let body = create_test_repo_with(
+        &state,
+        json!({"name": "tight", "command_profile": "tight", "exec_limits": {"memory_max_bytes": 1048576}}),
+    )
+    .await;
+    let repo_id = uuid::Uuid::parse_str(body["data"]["id"].as_str().unwrap()).unwrap();
+    upload_test_file(&state, repo_id, "log.txt", b"so far\n").await;
+
+    // The fixed -n drops the newline, and the command's cap beats the request's
+    let resp = build_router(state.clone())
+        .oneshot(exec_request(
+            repo_id,
+            json!({"command": "echo", "args": ["hello"], "max_output_bytes": 1024}),
+        ))
+        .await
+        .unwrap();
+    let body = body_to_json(resp.into_body()).await;
+    assert_eq!(body["data"]["stdout"], "hell");
+    assert_eq!(body["data"]["truncated"], true);
+
+    let resp = build_router(state.clone())
+        .oneshot(exec_request(
+            repo_id,
+            json!({"command": "tail", "args": ["-f", "log.txt"], "timeout_seconds": 30}),
+        ))
+        .await
+        .unwrap();
+    let body = body_to_json(resp.into_body()).await;
+    assert_eq!(body["data"]["killed_by"], "timeout");
+    assert!(body["data"]["duration_ms"].as_u64().unwrap() < 10_000);
+
+    // The profile allows writes even though the server default does not
+    let (status, _) = exec_test_command(&state, repo_id, "sort", &["-o", "out.txt", "log.txt"]).await;
+    assert_eq!(status, StatusCode::OK);
+
+    let req = Request::builder()
+        .uri(format!("/api/v1/repos/{}/commands", repo_id))
+        .body(Body::empty())
+        .unwrap();
+    let (_, body) = send_json(&state, req).await;
+    let commands = body["data"]["commands"].as_array().unwrap();
+    let names: Vec<&str> = commands.iter().map(|c| c["name"].as_str().unwrap()).collect();
+    assert_eq!(names, ["echo", "sort", "tail"]);
+    assert_eq!(commands[0]["fixed_args"], json!(["-n"]));
+    assert_eq!(commands[0]["max_output_bytes"], 4);
+    assert_eq!(commands[0]["timeout_seconds"], 30);
+    assert_eq!(commands[2]["timeout_seconds"], 1);
+    assert_eq!(commands[2]["exec_limits"]["pids_max"], 8);
+    assert_eq!(commands[2]["exec_limits"]["memory_max_bytes"], 1048576);
+
+    // A profile may only include commands the catalogue defines
+    std::fs::write(
+        &policy,
+        json!({"profiles": {"broken": {"include": ["nonexistent"]}}}).to_string(),
+    )
+    .unwrap();
+    let err = linux_fs::sandbox::command_policy::CommandPolicy::load(&config).unwrap_err();
+    assert!(err.to_string().contains("unknown command 'nonexistent'"));
+}
+
+// ==================== Git Tests ====================
+
+async fn exec_test_command(
+    state: &AppState,
+    repo_id: uuid::Uuid,
+    command: &str,
+    args: &[&str],
+) -> (StatusCode, Value) {
+    let req = Request::builder()
+        .method("POST")
+        .uri(format!("/api/v1/repos/{}/exec", repo_id))
+        .header(header::CONTENT_TYPE, "application/json")
+        .body(Body::from(
+            json!({"command": command, "args": args}).to_string(),
+        ))
+        .unwrap();
+    send_json(state, req).await
+}
+
+/// Point `config` at a policy file that lets `commands` take any
+/// arguments, so a test reaches the kernel sandbox layers directly.
+fn without_arg_policy(config: &mut AppConfig, dir: &std::path::Path, commands: &[&str]) {
+    let rules: serde_json::Map<String, Value> = commands
+        .iter()
+        .map(|c| (c.to_string(), json!({"operands": "text"})))
+        .collect();
+    write_test_policy(config, dir, Value::Object(rules));
+}
+
+/// Point `config` at a policy file holding `commands`.
+fn write_test_policy(config: &mut AppConfig, dir: &std::path::Path, commands: Value) {
+    let path = dir.join("policy.json");
+    std::fs::write(&path, json!({ "commands": commands }).to_string()).unwrap();
+    config.command_policy_file = path.to_str().unwrap().into();
+}
+
+#[test]
+fn test_malformed_command_policy_fails_state_creation() {
+    let tmp = tempfile::tempdir().unwrap();
+    let mut config = test_config(tmp.path().to_str().unwrap());
+    let path = tmp.path().join("policy.json");
+    std::fs::write(&path, "{ not json").unwrap();
+    config.command_policy_file = path.to_str().unwrap().into();
+    std::fs::create_dir_all(config.wal_dir()).unwrap();
+    let wal = WalWriter::open(&config.wal_dir()).unwrap();
+    assert!(AppState::new(config, wal).is_err());
+}
+
+#[tokio::test]
+async fn test_exec_in_namespaces_sees_only_the_repo() {
+    let tmp = tempfile::tempdir().unwrap();
+    let mut config = test_config(tmp.path().to_str().unwrap());
+    config.sandbox_namespaces = true;
+    without_arg_policy(&mut config, tmp.path(), &["cat", "ls"]);
+    let wal = WalWriter::open(&config.wal_dir()).unwrap();
+    let state = AppState::new(config, wal).unwrap();
+    let repo_id = create_test_repo(&state, "isolated").await;
+    upload_test_file(&state, repo_id, "notes/a.txt", b"inside").await;
+
+    let (status, body) = exec_test_command(&state, repo_id, "cat", &["notes/a.txt"]).await;
+    if status == StatusCode::INTERNAL_SERVER_ERROR {
+        // Kernel or container without unprivileged user namespaces
+        eprintln!("skipping: {}", body["error"]["message"]);
+        return;
+    }
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["data"]["stdout"], "inside");
+
+    let (_, body) = exec_test_command(&state, repo_id, "cat", &["/etc/passwd"]).await;
+    assert_ne!(body["data"]["exit_code"], 0);
+    assert!(body["data"]["stdout"].as_str().unwrap().is_empty());
+
+    let (_, body) = exec_test_command(&state, repo_id, "ls", &["/"]).await;
+    let mut top: Vec<&str> = body["data"]["stdout"].as_str().unwrap().lines().collect();
+    top.retain(|e| !["bin", "lib", "lib64", "usr"].contains(e));
+    assert_eq!(top, vec!["dev", "repo", "tmp"]);
+
+    let (_, body) = exec_test_command(&state, repo_id, "find", &["/repo", "-type", "f"]).await;
+    assert_eq!(body["data"]["stdout"], "/repo/notes/a.txt\n");
+
+    // Reached through /etc/alternatives on the host
+    let (_, body) = exec_test_command(&state, repo_id, "awk", &["{ print }", "notes/a.txt"]).await;
+    assert_eq!(body["data"]["stdout"], "inside\n");
+}
+
+#[tokio::test]
+async fn test_exec_in_namespaces_mounts_the_repo_read_only_unless_writing() {
+    let tmp = tempfile::tempdir().unwrap();
+    let mut config = test_config(tmp.path().to_str().unwrap());
+    config.sandbox_namespaces = true;
+    config.exec_allow_mutating = true;
+    write_test_policy(
+        &mut config,
+        tmp.path(),
+        json!({
+            "sort": {"operands": "text", "mutating_flags": ["-o"]},
+            "tee": {"operands": "text"}
+        }),
+    );
+    let wal = WalWriter::open(&config.wal_dir()).unwrap();
+    let state = AppState::new(config, wal).unwrap();
+    let repo_id = create_test_repo(&state, "read-only").await;
+    upload_test_file(&state, repo_id, "a.txt", b"inside").await;
+
+    // tee is classified as read-only, so the repo is mounted that way
+    let (status, body) = exec_test_command(&state, repo_id, "tee", &["/repo/b.txt"]).await;
+    if status == StatusCode::INTERNAL_SERVER_ERROR {
+        eprintln!("skipping: {}", body["error"]["message"]);
+        return;
+    }
+    assert_ne!(body["data"]["exit_code"], 0);
+    assert!(body["data"]["stderr"]
+        .as_str()
+        .unwrap()
+        .contains("Read-only file system"));
+    let files_dir = tmp.path().join(format!("repos/{}/files", repo_id));
+    assert!(!files_dir.join("b.txt").exists());
+
+    let (_, body) =
+        exec_test_command(&state, repo_id, "sort", &["-o", "/repo/b.txt", "a.txt"]).await;
+    assert_eq!(body["data"]["exit_code"], 0, "{}", body["data"]["stderr"]);
+    assert_eq!(std::fs::read(files_dir.join("b.txt")).unwrap(), b"inside\n");
+}
+
+#[tokio::test]
+async fn test_exec_under_landlock_only_touches_the_repo() {
+    let tmp = tempfile::tempdir().unwrap();
+    let mut config = test_config(tmp.path().to_str().unwrap());
+    config.sandbox_landlock = true;
+    config.exec_allow_mutating = true;
+    write_test_policy(
+        &mut config,
+        tmp.path(),
+        json!({
+            "cat": {"operands": "text"},
+            "sort": {"operands": "text", "mutating_flags": ["-o"]},
+            "tee": {"operands": "text"}
+        }),
+    );
+    let wal = WalWriter::open(&config.wal_dir()).unwrap();
+    let state = AppState::new(config, wal).unwrap();
+
+    let app = build_router(state.clone());
+    let (key, val) = auth_header();
+    let req = Request::builder()
+        .uri("/api/v1/status")
+        .header(key, val)
+        .body(Body::empty())
+        .unwrap();
+    let body = body_to_json(app.oneshot(req).await.unwrap().into_body()).await;
+    assert_eq!(body["data"]["sandbox_namespaces"], false);
+    if body["data"]["sandbox_landlock_abi"].is_null() {
+        eprintln!("skipping: kernel without Landlock");
+        assert_eq!(body["data"]["sandbox_landlock"], false);
+        return;
+    }
+    assert_eq!(body["data"]["sandbox_landlock"], true);
+
+    let repo_id = create_test_repo(&state, "landlocked").await;
+    upload_test_file(&state, repo_id, "notes/a.txt", b"inside").await;
+
+    let (status, body) = exec_test_command(&state, repo_id, "cat", &["notes/a.txt"]).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["data"]["stdout"], "inside");
+
+    let (_, body) = exec_test_command(&state, repo_id, "cat", &["/etc/hostname"]).await;
+    assert_ne!(body["data"]["exit_code"], 0);
+    assert!(body["data"]["stderr"]
+        .as_str()
+        .unwrap()
+        .contains("Permission denied"));
+
+    let (_, body) =
+        exec_test_command(&state, repo_id, "sort", &["-o", "/tmp/out", "notes/a.txt"]).await;
+    assert_ne!(body["data"]["exit_code"], 0);
+
+    // tee is classified as read-only, so the repo is read-only to it
+    let (_, body) = exec_test_command(&state, repo_id, "tee", &["notes/b.txt"]).await;
+    assert_ne!(body["data"]["exit_code"], 0);
+    assert!(body["data"]["stderr"]
+        .as_str()
+        .unwrap()
+        .contains("Permission denied"));
+
+    let (_, body) =
+        exec_test_command(&state, repo_id, "sort", &["-o", "notes/b.txt", "notes/a.txt"]).await;
+    assert_eq!(body["data"]["exit_code"], 0);
+    let written = tmp
+        .path()
+        .join(format!("repos/{}/files/notes/b.txt", repo_id));
+    assert_eq!(std::fs::read(written).unwrap(), b"inside\n");
+}
+
+#[tokio::test]
+async fn test_exec_under_namespaces_and_landlock_writes_tmp_but_runs_nothing_new() {
+    let tmp = tempfile::tempdir().unwrap();
+    let mut config = test_config(tmp.path().to_str().unwrap());
+    config.sandbox_namespaces = true;
+    config.sandbox_landlock = true;
+    without_arg_policy(&mut config, tmp.path(), &["cat", "find", "sort"]);
+    let wal = WalWriter::open(&config.wal_dir()).unwrap();
+    let state = AppState::new(config, wal).unwrap();
+    let repo_id = create_test_repo(&state, "confined").await;
+    let lines: String = (0..20_000).map(|i| format!("{}\n", 20_000 - i)).collect();
+    upload_test_file(&state, repo_id, "notes/a.txt", lines.as_bytes()).await;
+
+    let (status, body) = exec_test_command(&state, repo_id, "cat", &["/repo/notes/a.txt"]).await;
+    if status == StatusCode::INTERNAL_SERVER_ERROR || linux_fs::sandbox::landlock::abi_version().is_none() {
+        eprintln!("skipping: {}", body["error"]["message"]);
+        return;
+    }
+    assert_eq!(status, StatusCode::OK);
+
+    // A small buffer makes sort spill to the private /tmp
+    let (_, body) = exec_test_command(
+        &state,
+        repo_id,
+        "sort",
+        &["-n", "-S", "64K", "-T", "/tmp", "-o", "/tmp/sorted", "notes/a.txt"],
+    )
+    .await;
+    assert_eq!(body["data"]["exit_code"], 0, "{}", body["data"]["stderr"]);
+    let (_, body) =
+        exec_test_command(&state, repo_id, "sort", &["-n", "-S", "64K", "notes/a.txt"]).await;
+    assert!(body["data"]["stdout"].as_str().unwrap().starts_with("1\n2\n3\n"));
+
+    // Binaries placed in the repo are readable but not executable
+    let tool = tmp.path().join(format!("repos/{}/files/tool", repo_id));
+    std::fs::copy("/bin/true", &tool).unwrap();
+    let (_, body) =
+        exec_test_command(&state, repo_id, "find", &["/repo/tool", "-exec", "{}", "+"]).await;
+    assert_ne!(body["data"]["exit_code"], 0);
+    assert!(body["data"]["stderr"]
+        .as_str()
+        .unwrap()
+        .contains("Permission denied"));
+}
+
+#[tokio::test]
+async fn test_exec_seccomp_kills_denied_syscalls() {
+    let tmp = tempfile::tempdir().unwrap();
+    let mut config = test_config(tmp.path().to_str().unwrap());
+    config.sandbox_seccomp = true;
+    without_arg_policy(&mut config, tmp.path(), &["find", "awk"]);
+    let wal = WalWriter::open(&config.wal_dir()).unwrap();
+    let state = AppState::new(config, wal).unwrap();
+    let repo_id = create_test_repo(&state, "filtered").await;
+    upload_test_file(&state, repo_id, "notes/a.txt", b"inside").await;
+
+    let (status, body) = exec_test_command(&state, repo_id, "find", &[".", "-type", "f"]).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["data"]["stdout"], "./notes/a.txt\n");
+    assert_eq!(body["data"]["seccomp_violation"], false);
+
+    // Asking for -exec lets find start its children
+    let (_, body) = exec_test_command(
+        &state,
+        repo_id,
+        "find",
+        &[".", "-type", "f", "-exec", "cat", "{}", "+"],
+    )
+    .await;
+    assert_eq!(body["data"]["stdout"], "inside");
+    assert_eq!(body["data"]["seccomp_violation"], false);
+
+    // awk's system() has to fork, which no awk invocation may do
+    let (_, body) =
+        exec_test_command(&state, repo_id, "awk", &["BEGIN { system(\"ls\") }"]).await;
+    if body["data"]["exit_code"] == 0 {
+        eprintln!("skipping: kernel without seccomp filters");
+        return;
+    }
+    assert_eq!(body["data"]["seccomp_violation"], true);
+    assert_eq!(body["data"]["killed_by"], "seccomp");
+    assert!(body["data"]["stdout"].as_str().unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn test_exec_rlimits_and_rusage() {
+    let tmp = tempfile::tempdir().unwrap();
+    let mut config = test_config(tmp.path().to_str().unwrap());
+    config.exec_rlimit_fsize_bytes = 4096;
+    config.exec_allow_mutating = true;
+    let wal = WalWriter::open(&config.wal_dir()).unwrap();
+    let state = AppState::new(config, wal).unwrap();
+    let repo_id = create_test_repo(&state, "rlimited").await;
+    upload_test_file(&state, repo_id, "big.txt", "line\n".repeat(4096).as_bytes()).await;
+
+    let (status, body) = exec_test_command(&state, repo_id, "wc", &["-l", "big.txt"]).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["data"]["stdout"], "4096 big.txt\n");
+    assert!(body["data"]["user_cpu_ms"].is_u64());
+    assert!(body["data"]["system_cpu_ms"].is_u64());
+    assert!(body["data"]["max_rss_bytes"].as_u64().unwrap() > 0);
+    assert!(body["data"]["block_reads"].is_u64());
+    assert!(body["data"]["signal"].is_null());
+    assert!(body["data"]["killed_by"].is_null());
+
+    // Writing past the file size limit raises SIGXFSZ
+    let (status, body) =
+        exec_test_command(&state, repo_id, "sort", &["-o", "sorted.txt", "big.txt"]).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["data"]["signal"], libc::SIGXFSZ);
+    assert_eq!(body["data"]["killed_by"], "file_size");
+    assert_eq!(body["data"]["exit_code"], -1);
+}
+
+fn exec_request(repo_id: uuid::Uuid, body: Value) -> Request<Body> {
+    let (key, val) = auth_header();
+    Request::builder()
+        .method("POST")
+        .uri(format!("/api/v1/repos/{}/exec", repo_id))
+        .header(key, val)
+        .header(header::CONTENT_TYPE, "application/json")
+        .body(Body::from(body.to_string()))
+        .unwrap()
+}
+
+/// Processes whose working directory is `dir`.
+fn processes_in(dir: &std::path::Path) -> usize {
+    std::fs::read_dir("/proc")
+        .unwrap()
+        .flatten()
+        .filter(|e| std::fs::read_link(e.path().join("cwd")).is_ok_and(|cwd| cwd == dir))
+        .count()
+}
+
+#[tokio::test]
+async fn test_exec_timeout_kills_process_group_and_keeps_output() {
+    let (state, _tmp) = setup();
+    let repo_id = create_test_repo(&state, "slow").await;
+    upload_test_file(&state, repo_id, "log.txt", b"so far\n").await;
+
+    let app = build_router(state.clone());
+    let req = exec_request(
+        repo_id,
+        json!({"command": "tail", "args": ["-f", "log.txt"], "timeout_seconds": 1}),
+    );
+    let resp = app.oneshot(req).await.unwrap();
+    assert_eq!(resp.status(), StatusCode::OK);
+    let body = body_to_json(resp.into_body()).await;
+    assert_eq!(body["data"]["stdout"], "so far\n");
+    assert_eq!(body["data"]["killed_by"], "timeout");
+    assert_eq!(body["data"]["signal"], libc::SIGTERM);
+    assert_eq!(body["data"]["exit_code"], -1);
+
+    let files = state.config.repos_dir().join(repo_id.to_string()).join("files");
+    assert_eq!(processes_in(&files), 0);
+}
+
+#[tokio::test]
+async fn test_exec_killed_when_client_disconnects() {
+    let (state, _tmp) = setup();
+    let repo_id = create_test_repo(&state, "abandoned").await;
+    upload_test_file(&state, repo_id, "log.txt", b"waiting\n").await;
+    let files = state.config.repos_dir().join(repo_id.to_string()).join("files");
+
+    let app = build_router(state.clone());
+    let req = exec_request(
+        repo_id,
+        json!({"command": "tail", "args": ["-f", "log.txt"], "timeout_seconds": 60}),
+    );
+    let request = tokio::spawn(app.oneshot(req));
+    let wait_for = |count: usize| {
+        let files = files.clone();
+        async move {
+            for _ in 0..50 {
+                if processes_in(&files) == count {
+                    return true;
+                }
+                tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+            }
+            false
+        }
+    };
+    assert!(wait_for(1).await, "tail never started");
+
+    // Aborting drops the handler future, as a disconnect does
+    request.abort();
+    assert!(wait_for(0).await, "tail outlived its request");
+}
+
+#[tokio::test]
+async fn test_repo_exec_limits_override_server_defaults() {
+    let tmp = tempfile::tempdir().unwrap();
+    let mut config = test_config(tmp.path().to_str().unwrap());
+    // No cgroup tree was set up, so commands run and report no usage
+    config.sandbox_cgroups = true;
+    let wal = WalWriter::open(&config.wal_dir()).unwrap();
+    let state = AppState::new(config, wal).unwrap();
+
+    let body = create_test_repo_with(
+        &state,
+        json!({"name": "limited", "exec_limits": {"memory_max_bytes": 1048576, "pids_max": 0}}),
+    )
+    .await;
+    assert_eq!(body["data"]["exec_limits"]["memory_max_bytes"], 1048576);
+    assert!(body["data"]["exec_limits"]["cpu_max_cores"].is_null());
+    let repo_id = uuid::Uuid::parse_str(body["data"]["id"].as_str().unwrap()).unwrap();
+
+    let limits = linux_fs::sandbox::cgroup::Limits::resolve(
+        &state.config,
+        &state.repos.get(&repo_id).unwrap().exec_limits,
+    );
+    assert_eq!(limits.memory_max_bytes, Some(1048576));
+    assert_eq!(limits.cpu_max_cores, Some(1.0));
+    assert_eq!(limits.pids_max, None);
+
+    let app = build_router(state.clone());
+    let (key, val) = auth_header();
+    let req = Request::builder()
+        .method("PATCH")
+        .uri(format!("/api/v1/repos/{}", repo_id))
+        .header(key, val)
+        .header(header::CONTENT_TYPE, "application/json")
+        .body(Body::from(r#"{"exec_limits":{"cpu_max_cores":-1}}"#))
+        .unwrap();
+    let resp = app.oneshot(req).await.unwrap();
+    assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+
+    let app = build_router(state.clone());
+    let (key, val) = auth_header();
+    let req = Request::builder()
+        .method("PATCH")
+        .uri(format!("/api/v1/repos/{}", repo_id))
+        .header(key, val)
+        .header(header::CONTENT_TYPE, "application/json")
+        .body(Body::from(r#"{"exec_limits":{"cpu_max_cores":0.5}}"#))
+        .unwrap();
+    let resp = app.oneshot(req).await.unwrap();
+    assert_eq!(resp.status(), StatusCode::OK);
+    let body = body_to_json(resp.into_body()).await;
+    assert_eq!(body["data"]["exec_limits"]["cpu_max_cores"], 0.5);
+    assert!(body["data"]["exec_limits"]["memory_max_bytes"].is_null());
+
+    upload_test_file(&state, repo_id, "a.txt", b"inside").await;
+    let (status, body) = exec_test_command(&state, repo_id, "cat", &["a.txt"]).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["data"]["stdout"], "inside");
+    assert_eq!(body["data"]["oom_killed"], false);
+    assert!(body["data"]["memory_peak_bytes"].is_null());
+}
+
+async fn export_test_bundle(state: &AppState, repo_id: uuid::Uuid, message: &str) -> Bytes {
+    let app = build_router(state.clone());
+    let (key, val) = auth_header();
+    let req = Request::builder()
+        .method("POST")
+        .uri(format!("/api/v1/repos/{}/git/export", repo_id))
+        .header(key, val)
+        .header(header::CONTENT_TYPE, "application/json")
+        .body(Body::from(
+            serde_json::to_string(&json!({ "message": message })).unwrap(),
+        ))
+        .unwrap();
+    let resp = app.oneshot(req).await.unwrap();
+    assert_eq!(resp.status(), StatusCode::OK);
+    assert!(resp.headers().get("x-git-commit").is_some());
+    body_to_bytes(resp.into_body()).await
+}
+
+#[tokio::test]
+async fn test_git_bundle_round_trip() {
+    let (state, _tmp) = setup();
+    let source = create_test_repo(&state, "git-source").await;
+    upload_test_file(&state, source, "README.md", b"hello\n").await;
+    upload_test_file(&state, source, "src/lib.rs", b"fn a() {}\n").await;
+    export_test_bundle(&state, source, "initial").await;
+    upload_test_file(&state, source, "README.md", b"hello again\n").await;
+    let bundle = export_test_bundle(&state, source, "second").await;
+    assert!(bundle.starts_with(b"# v2 git bundle\n"));
+
+    let target = create_test_repo(&state, "git-target").await;
+    let app = build_router(state.clone());
+    let (key, val) = auth_header();
+    let req = Request::builder()
+        .method("POST")
+        .uri(format!("/api/v1/repos/{}/git/import?ref=main", target))
+        .header(key, val)
+        .body(Body::from(bundle))
+        .unwrap();
+    let resp = app.oneshot(req).await.unwrap();
+    assert_eq!(resp.status(), StatusCode::OK);
+    let body: Value = body_to_json(resp.into_body()).await;
+    assert_eq!(body["data"]["ref"], "refs/heads/main");
+    assert_eq!(body["data"]["files_written"], 2);
+
+    let content = download_test_file(&state, target, "src/lib.rs").await;
+    assert_eq!(&content[..], b"fn a() {}\n");
+
+    let app = build_router(state.clone());
+    let (key, val) = auth_header();
+    let req = Request::builder()
+        .uri(format!("/api/v1/repos/{}/git/log", target))
+        .header(key, val)
+        .body(Body::empty())
+        .unwrap();
+    let resp = app.oneshot(req).await.unwrap();
+    assert_eq!(resp.status(), StatusCode::OK);
+    let body: Value = body_to_json(resp.into_body()).await;
+    let commits = body["data"]["commits"].as_array().unwrap();
+    assert_eq!(commits.len(), 2);
+    assert_eq!(commits[0]["summary"], "second");
+
+    let app = build_router(state);
+    let (key, val) = auth_header();
+    let req = Request::builder()
+        .uri(format!("/api/v1/repos/{}/git/diff?from=HEAD~1&to=HEAD", target))
+        .header(key, val)
+        .body(Body::empty())
+        .unwrap();
+    let resp = app.oneshot(req).await.unwrap();
+    assert_eq!(resp.status(), StatusCode::OK);
+    let body: Value = body_to_json(resp.into_body()).await;
+    assert_eq!(body["data"]["files"][0]["path"], "README.md");
+    assert_eq!(body["data"]["files"][0]["status"], "modified");
+    assert!(body["data"]["patch"].as_str().unwrap().contains("+hello again"));
+}
+
+#[tokio::test]
+async fn test_git_import_replaces_the_tree_or_leaves_it_untouched() {
+    let (state, _tmp) = setup();
+    let source = create_test_repo(&state, "git-source").await;
+    upload_test_file(&state, source, "README.md", b"hello\n").await;
+    upload_test_file(&state, source, "src/lib.rs", b"fn a() {}\n").await;
+    let bundle = export_test_bundle(&state, source, "initial").await;
+
+    // Too big for the target: rejected before anything changes
+    let body = create_test_repo_with(&state, json!({"name": "small", "max_size_bytes": 12})).await;
+    let small = uuid::Uuid::parse_str(body["data"]["id"].as_str().unwrap()).unwrap();
+    upload_test_file(&state, small, "keep.txt", b"kept").await;
+    let req = Request::builder()
+        .method("POST")
+        .uri(format!("/api/v1/repos/{}/git/import", small))
+        .body(Body::from(bundle.clone()))
+        .unwrap();
+    let (status, _) = send_json(&state, req).await;
+    assert_eq!(status, StatusCode::PAYLOAD_TOO_LARGE);
+    assert_eq!(&download_test_file(&state, small, "keep.txt").await[..], b"kept");
+    assert_eq!(state.files.get(&small).unwrap().len(), 1);
+    let main_ref = state.config.repos_dir().join(small.to_string()).join("git/refs/heads/main");
+    assert!(!main_ref.exists());
+
+    // The files would fit, but not with the objects they come in
+    assert!(bundle.len() > 100);
+    let body = create_test_repo_with(&state, json!({"name": "packed", "max_size_bytes": 100})).await;
+    let packed = uuid::Uuid::parse_str(body["data"]["id"].as_str().unwrap()).unwrap();
+    let req = Request::builder()
+        .method("POST")
+        .uri(format!("/api/v1/repos/{}/git/import", packed))
+        .body(Body::from(bundle.clone()))
+        .unwrap();
+    let (status, body) = send_json(&state, req).await;
+    assert_eq!(status, StatusCode::PAYLOAD_TOO_LARGE);
+    assert!(body["error"]["message"].as_str().unwrap().contains("pack"));
+    assert!(!state.config.repos_dir().join(packed.to_string()).join("git").exists());
+
+    // Paths missing from the tree are removed; unchanged ones keep their stats
+    let target = create_test_repo(&state, "git-target").await;
+    upload_test_file(&state, target, "README.md", b"hello\n").await;
+    upload_test_file(&state, target, "stale.txt", b"old").await;
+    download_test_file(&state, target, "README.md").await;
+    let req = Request::builder()
+        .method("POST")
+        .uri(format!("/api/v1/repos/{}/git/import", target))
+        .body(Body::from(bundle))
+        .unwrap();
+    let (status, body) = send_json(&state, req).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["data"]["files_written"], 2);
+    assert_eq!(body["data"]["bytes_written"], 16);
+
+    let files = state.files.get(&target).unwrap().clone();
+    let mut paths: Vec<String> = files.iter().map(|f| f.key().clone()).collect();
+    paths.sort();
+    assert_eq!(paths, vec!["README.md", "src/lib.rs"]);
+    assert_eq!(files.get("README.md").unwrap().access_count, 1);
+    let repo = state.repos.get(&target).unwrap().clone();
+    assert_eq!((repo.current_size_bytes, repo.file_count), (16, 2));
+    let files_dir = state.config.repos_dir().join(target.to_string()).join("files");
+    assert!(!files_dir.join("stale.txt").exists());
+    assert_eq!(std::fs::read(files_dir.join("src/lib.rs")).unwrap(), b"fn a() {}\n");
+    let trashed = linux_fs::services::trash_service::list(&state, Some(target));
+    assert!(matches!(&trashed[0].item, TrashedItem::File { file } if file.path == "stale.txt"));
+
+    let req = Request::builder()
+        .uri(format!("/api/v1/repos/{}/changes?since=0", target))
+        .body(Body::empty())
+        .unwrap();
+    let (_, body) = send_json(&state, req).await;
+    let kinds: Vec<(String, String)> = body["data"]["changes"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .skip(2)
+        .map(|c| (c["kind"].as_str().unwrap().into(), c["path"].as_str().unwrap().into()))
+        .collect();
+    assert_eq!(
+        kinds,
+        vec![
+            ("file_deleted".to_string(), "stale.txt".to_string()),
+            ("file_created".to_string(), "src/lib.rs".to_string()),
+        ]
+    );
+}
+
+#[tokio::test]
+async fn test_git_import_rejects_garbage() {
+    let (state, _tmp) = setup();
+    let repo_id = create_test_repo(&state, "git-garbage").await;
+
+    let app = build_router(state);
+    let (key, val) = auth_header();
+    let req = Request::builder()
+        .method("POST")
+        .uri(format!("/api/v1/repos/{}/git/import", repo_id))
+        .header(key, val)
+        .body(Body::from("not a bundle"))
+        .unwrap();
+    let resp = app.oneshot(req).await.unwrap();
+    assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
 }
 
 // ==================== Archive Tests ====================